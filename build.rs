@@ -6,9 +6,24 @@ use std::{
 
 use image::{AnimationDecoder, codecs::gif::GifDecoder};
 
+// the codec the speaker decodes, shared so that the two cannot differ
+#[allow(dead_code)]
+#[path = "src/hardware/speaker/adpcm.rs"]
+mod adpcm;
+// shared with the tests in `tests/wav_conversion.rs`, which use more
+// of it than this does
+#[allow(dead_code)]
+#[path = "build/wav.rs"]
+mod wav;
+
 const FIRST_POKEMON_ID: u16 = 494;
-const LAST_POKEMON_ID: u16 = 503;
-const POKEMON_NAMES: [&str; 10] = [
+/// The sprites take up far more flash than the cries, so only the
+/// first few Pokemon have one.
+const LAST_SPRITE_ID: u16 = 503;
+const LAST_CRY_ID: u16 = 575;
+const CRY_COUNT: usize =
+    (LAST_CRY_ID - FIRST_POKEMON_ID + 1) as usize;
+const POKEMON_NAMES: [&str; CRY_COUNT] = [
     "Victini",
     "Snivy",
     "Servine",
@@ -19,12 +34,100 @@ const POKEMON_NAMES: [&str; 10] = [
     "Oshawott",
     "Dewott",
     "Samurott",
+    "Patrat",
+    "Watchog",
+    "Lillipup",
+    "Herdier",
+    "Stoutland",
+    "Purrloin",
+    "Liepard",
+    "Pansage",
+    "Simisage",
+    "Pansear",
+    "Simisear",
+    "Panpour",
+    "Simipour",
+    "Munna",
+    "Musharna",
+    "Pidove",
+    "Tranquill",
+    "Unfezant",
+    "Blitzle",
+    "Zebstrika",
+    "Roggenrola",
+    "Boldore",
+    "Gigalith",
+    "Woobat",
+    "Swoobat",
+    "Drilbur",
+    "Excadrill",
+    "Audino",
+    "Timburr",
+    "Gurdurr",
+    "Conkeldurr",
+    "Tympole",
+    "Palpitoad",
+    "Seismitoad",
+    "Throh",
+    "Sawk",
+    "Sewaddle",
+    "Swadloon",
+    "Leavanny",
+    "Venipede",
+    "Whirlipede",
+    "Scolipede",
+    "Cottonee",
+    "Whimsicott",
+    "Petilil",
+    "Lilligant",
+    "Basculin",
+    "Sandile",
+    "Krokorok",
+    "Krookodile",
+    "Darumaka",
+    "Darmanitan",
+    "Maractus",
+    "Dwebble",
+    "Crustle",
+    "Scraggy",
+    "Scrafty",
+    "Sigilyph",
+    "Yamask",
+    "Cofagrigus",
+    "Tirtouga",
+    "Carracosta",
+    "Archen",
+    "Archeops",
+    "Trubbish",
+    "Garbodor",
+    "Zorua",
+    "Zoroark",
+    "Minccino",
+    "Cinccino",
+    "Gothita",
+    "Gothorita",
 ];
 
+/// How much flash the encoded cries may take up, about what the ten
+/// cries took before they were compressed. The cries from
+/// `FIRST_POKEMON_ID` through `LAST_CRY_ID` come to about 1.73 MB.
+const CRY_FLASH_BUDGET: usize = 1_750_000;
+
 fn main() {
-    linker_be_nice();
+    generate_meow();
     generate_cries();
+    generate_songs();
+    generate_ringtones();
+    generate_midi();
     generate_pokemon_sprites();
+
+    // the simulator is linked by the host toolchain, which has no use
+    // for the esp linker scripts
+    if std::env::var_os("CARGO_FEATURE_ESP32S3").is_none() {
+        return;
+    }
+
+    linker_be_nice();
     println!("cargo:rustc-link-arg=-Tdefmt.x");
     // make sure linkall.x is the last linker script (otherwise might
     // cause problems with flip-link)
@@ -40,7 +143,7 @@ fn generate_pokemon_sprites() {
     );
     writeln!(metadata, "&[").unwrap();
 
-    for pokemon_id in FIRST_POKEMON_ID..=LAST_POKEMON_ID {
+    for pokemon_id in FIRST_POKEMON_ID..=LAST_SPRITE_ID {
         let asset_path =
             format!("assets/pokemon-sprites/{pokemon_id}.gif");
         println!("cargo:rerun-if-changed={asset_path}");
@@ -150,6 +253,23 @@ fn write_sprite_deltas(
     (delays_ms, offsets)
 }
 
+/// Encodes `sounds/meow.pcm` into `meow.adpcm`.
+fn generate_meow() {
+    println!("cargo:rerun-if-changed=sounds/meow.pcm");
+
+    let pcm = fs::read("sounds/meow.pcm")
+        .expect("failed to read sounds/meow.pcm");
+    let output_path =
+        PathBuf::from(std::env::var_os("OUT_DIR").unwrap())
+            .join("meow.adpcm");
+    fs::write(output_path, encode_adpcm(&pcm_to_samples(&pcm)))
+        .expect("failed to write meow.adpcm");
+}
+
+/// Bundles a cry for every Pokemon into `cries.rs`, encoded by
+/// `encode_adpcm`. A cry is either a `.pcm` file, which is only mixed
+/// down, or a `.wav` file, which is converted by
+/// `wav::wav_to_samples`.
 fn generate_cries() {
     println!("cargo:rerun-if-changed=sounds/cries");
 
@@ -165,7 +285,9 @@ fn generate_cries() {
             filename
         })
         .filter_map(|filename| {
-            if !filename.ends_with(".pcm") {
+            if !filename.ends_with(".pcm")
+                && !filename.ends_with(".wav")
+            {
                 return None;
             }
 
@@ -176,7 +298,7 @@ fn generate_cries() {
                 .parse::<u16>()
                 .ok()?;
 
-            (FIRST_POKEMON_ID..=LAST_POKEMON_ID)
+            (FIRST_POKEMON_ID..=LAST_CRY_ID)
                 .contains(&id)
                 .then_some((id, filename))
         })
@@ -185,31 +307,577 @@ fn generate_cries() {
     cries.sort_by_key(|(id, _)| *id);
     assert_eq!(
         cries.len(),
-        (LAST_POKEMON_ID - FIRST_POKEMON_ID + 1) as usize,
-        "expected exactly one cry for each Pokemon from {FIRST_POKEMON_ID} through {LAST_POKEMON_ID}"
+        CRY_COUNT,
+        "expected exactly one cry for each Pokemon from {FIRST_POKEMON_ID} through {LAST_CRY_ID}"
     );
     for (offset, (id, _)) in cries.iter().enumerate() {
         assert_eq!(*id, FIRST_POKEMON_ID + offset as u16);
     }
 
-    let output_path =
-        PathBuf::from(std::env::var_os("OUT_DIR").unwrap())
-            .join("cries.rs");
+    let output_dir =
+        PathBuf::from(std::env::var_os("OUT_DIR").unwrap());
     let mut output = BufWriter::new(
-        File::create(output_path).expect("failed to create cries.rs"),
+        File::create(output_dir.join("cries.rs"))
+            .expect("failed to create cries.rs"),
     );
+    fs::create_dir_all(output_dir.join("cries"))
+        .expect("failed to create the encoded cry directory");
 
     writeln!(output, "&[").unwrap();
+    let mut total_bytes = 0;
     for (pokemon_id, filename) in cries {
         let name =
             POKEMON_NAMES[(pokemon_id - FIRST_POKEMON_ID) as usize];
+
+        let path = format!("sounds/cries/{filename}");
+        let bytes = fs::read(&path).unwrap_or_else(|error| {
+            panic!("failed to read {path}: {error}")
+        });
+        let samples = if filename.ends_with(".wav") {
+            wav::wav_to_samples(&bytes)
+                .unwrap_or_else(|error| panic!("{path}: {error}"))
+        } else {
+            pcm_to_samples(&bytes)
+        };
+
+        let encoded = format!("{pokemon_id}.adpcm");
+        let cry = encode_adpcm(&samples);
+        total_bytes += cry.len();
+        fs::write(output_dir.join("cries").join(&encoded), cry)
+            .unwrap_or_else(|error| {
+                panic!("failed to write {encoded}: {error}")
+            });
+
         writeln!(
             output,
-            "Cry {{ pokemon_id: {pokemon_id}, name: {name:?}, samples: include_bytes!(concat!(env!(\"CARGO_MANIFEST_DIR\"), \"/sounds/cries/\", {filename:?})) }},"
+            "Cry {{ pokemon_id: {pokemon_id}, name: {name:?}, samples: include_bytes!(concat!(env!(\"OUT_DIR\"), \"/cries/\", {encoded:?})) }},"
         )
         .unwrap();
     }
     writeln!(output, "]").unwrap();
+
+    assert!(
+        total_bytes <= CRY_FLASH_BUDGET,
+        "the cries come to {total_bytes} bytes, more than the {CRY_FLASH_BUDGET} bytes of flash they may take up"
+    );
+}
+
+/// Mixes 16 bit stereo little endian PCM down to mono.
+fn pcm_to_samples(pcm: &[u8]) -> Vec<i16> {
+    pcm.chunks_exact(4)
+        .map(|frame| {
+            let left = i16::from_le_bytes([frame[0], frame[1]]);
+            let right = i16::from_le_bytes([frame[2], frame[3]]);
+            ((left as i32 + right as i32) / 2) as i16
+        })
+        .collect()
+}
+
+/// Encodes `samples` into the blocks `adpcm` describes.
+fn encode_adpcm(samples: &[i16]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    let mut step_index = 0;
+
+    for chunk in samples.chunks(adpcm::SAMPLES_PER_BLOCK) {
+        let mut block = [0; adpcm::ADPCM_BLOCK_SIZE];
+        let used =
+            adpcm::encode_block(chunk, &mut step_index, &mut block);
+        encoded.extend_from_slice(&block[..used]);
+    }
+
+    encoded
+}
+
+/// Compiles every `.song` file in `sounds/songs` into `songs.rs`, a
+/// list of `Song`s named after the files. See
+/// `hardware::sequencer` for the format.
+fn generate_songs() {
+    println!("cargo:rerun-if-changed=sounds/songs");
+
+    let mut songs = fs::read_dir("sounds/songs")
+        .expect("failed to read sounds/songs")
+        .map(|entry| {
+            entry.expect("failed to read song directory entry").path()
+        })
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "song")
+        })
+        .collect::<Vec<_>>();
+    songs.sort();
+
+    let output_path =
+        PathBuf::from(std::env::var_os("OUT_DIR").unwrap())
+            .join("songs.rs");
+    let mut output = BufWriter::new(
+        File::create(output_path).expect("failed to create songs.rs"),
+    );
+
+    writeln!(output, "&[").unwrap();
+    for path in songs {
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .expect("song filenames must be valid UTF-8");
+        let text =
+            fs::read_to_string(&path).unwrap_or_else(|error| {
+                panic!("failed to read {}: {error}", path.display())
+            });
+        let song = parse_song(&text).unwrap_or_else(|error| {
+            panic!("{}: {error}", path.display())
+        });
+
+        let notes = song
+            .notes
+            .iter()
+            .map(|(pitch, length)| {
+                format!(
+                    "Note {{ pitch: {pitch:?}, length: {length} }}"
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(
+            output,
+            "Song {{ name: {name:?}, tempo_bpm: {}, waveform: Waveform::{}, notes: &[{notes}] }},",
+            song.tempo_bpm, song.waveform
+        )
+        .unwrap();
+    }
+    writeln!(output, "]").unwrap();
+}
+
+/// Bundles every `.rtttl` file in `sounds/ringtones` into
+/// `ringtones.rs`. They are parsed on the device, and checked when
+/// the crate is built, by `hardware::rtttl`.
+fn generate_ringtones() {
+    println!("cargo:rerun-if-changed=sounds/ringtones");
+
+    let mut ringtones = fs::read_dir("sounds/ringtones")
+        .expect("failed to read sounds/ringtones")
+        .map(|entry| {
+            entry
+                .expect("failed to read ringtone directory entry")
+                .file_name()
+                .into_string()
+                .expect("ringtone filenames must be valid UTF-8")
+        })
+        .filter(|filename| filename.ends_with(".rtttl"))
+        .collect::<Vec<_>>();
+    ringtones.sort();
+
+    let output_path =
+        PathBuf::from(std::env::var_os("OUT_DIR").unwrap())
+            .join("ringtones.rs");
+    let mut output = BufWriter::new(
+        File::create(output_path)
+            .expect("failed to create ringtones.rs"),
+    );
+
+    writeln!(output, "&[").unwrap();
+    for filename in ringtones {
+        writeln!(
+            output,
+            "include_str!(concat!(env!(\"CARGO_MANIFEST_DIR\"), \"/sounds/ringtones/\", {filename:?})),"
+        )
+        .unwrap();
+    }
+    writeln!(output, "]").unwrap();
+}
+
+/// Converts every `.mid` file in `sounds/midi` into `midi.rs`, a
+/// list of `MidiFile`s named after the files. See `hardware::midi`.
+fn generate_midi() {
+    println!("cargo:rerun-if-changed=sounds/midi");
+
+    let mut files = fs::read_dir("sounds/midi")
+        .expect("failed to read sounds/midi")
+        .map(|entry| {
+            entry.expect("failed to read MIDI directory entry").path()
+        })
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "mid")
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+
+    let output_path =
+        PathBuf::from(std::env::var_os("OUT_DIR").unwrap())
+            .join("midi.rs");
+    let mut output = BufWriter::new(
+        File::create(output_path).expect("failed to create midi.rs"),
+    );
+
+    writeln!(output, "&[").unwrap();
+    for path in files {
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .expect("MIDI filenames must be valid UTF-8");
+        let bytes = fs::read(&path).unwrap_or_else(|error| {
+            panic!("failed to read {}: {error}", path.display())
+        });
+        let notes = parse_midi(&bytes).unwrap_or_else(|error| {
+            panic!("{}: {error}", path.display())
+        });
+
+        let events = midi_events(&notes)
+            .iter()
+            .map(|(delay_ms, pitch, velocity, length_ms)| {
+                format!(
+                    "MidiEvent {{ delay_ms: {delay_ms}, pitch: {pitch}, velocity: {velocity}, length_ms: {length_ms} }}"
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(
+            output,
+            "MidiFile {{ name: {name:?}, events: &[{events}] }},"
+        )
+        .unwrap();
+    }
+    writeln!(output, "]").unwrap();
+}
+
+/// A note of a MIDI file, with its start and length in milliseconds.
+struct MidiNote {
+    start_ms: u64,
+    length_ms: u64,
+    pitch: u8,
+    velocity: u8,
+}
+
+/// Reads the notes of a Standard MIDI File of format 0 or 1. Every
+/// track and channel is merged, except channel 10, which is for
+/// drums and has no pitch.
+fn parse_midi(bytes: &[u8]) -> Result<Vec<MidiNote>, String> {
+    let mut reader = MidiReader { bytes, position: 0 };
+
+    if reader.take(4)? != b"MThd" || reader.u32()? != 6 {
+        return Err("not a Standard MIDI File".into());
+    }
+    let format = reader.u16()?;
+    let track_count = reader.u16()?;
+    let division = reader.u16()?;
+    if format > 1 {
+        return Err(format!("format {format} is not supported"));
+    }
+    if division & 0x8000 != 0 || division == 0 {
+        return Err("SMPTE time is not supported".into());
+    }
+
+    // in ticks: tempo changes, as microseconds per quarter note, and
+    // note ons and offs, as channel, pitch and velocity
+    let mut tempos = Vec::new();
+    let mut note_events = Vec::new();
+
+    for _ in 0..track_count {
+        if reader.take(4)? != b"MTrk" {
+            return Err("expected a track".into());
+        }
+        let length = reader.u32()? as usize;
+        let mut track = MidiReader {
+            bytes: reader.take(length)?,
+            position: 0,
+        };
+
+        let mut tick = 0u64;
+        let mut running_status = None;
+        while track.position < track.bytes.len() {
+            tick += track.variable_length()? as u64;
+
+            let mut status = track.u8()?;
+            if status < 0x80 {
+                // running status, the byte was the first data byte
+                status = running_status
+                    .ok_or("data byte without a status")?;
+                track.position -= 1;
+            }
+
+            match status {
+                0xFF => {
+                    let kind = track.u8()?;
+                    let length = track.variable_length()? as usize;
+                    let data = track.take(length)?;
+                    match kind {
+                        0x2F => break,
+                        0x51 if length == 3 => tempos.push((
+                            tick,
+                            u32::from_be_bytes([
+                                0, data[0], data[1], data[2],
+                            ]),
+                        )),
+                        _ => {}
+                    }
+                }
+                0xF0 | 0xF7 => {
+                    let length = track.variable_length()? as usize;
+                    track.take(length)?;
+                }
+                0x80..=0xEF => {
+                    running_status = Some(status);
+                    let channel = status & 0x0F;
+                    match status & 0xF0 {
+                        0x80 | 0x90 => {
+                            let pitch = track.u8()?;
+                            let velocity = track.u8()?;
+                            // a note on with no velocity is a note
+                            // off
+                            let velocity = if status & 0xF0 == 0x80 {
+                                0
+                            } else {
+                                velocity
+                            };
+                            if channel != 9 {
+                                note_events.push((
+                                    tick, channel, pitch, velocity,
+                                ));
+                            }
+                        }
+                        0xC0 | 0xD0 => {
+                            track.u8()?;
+                        }
+                        _ => {
+                            track.take(2)?;
+                        }
+                    }
+                }
+                _ => return Err(format!("bad status {status:#04x}")),
+            }
+        }
+    }
+
+    tempos.sort_by_key(|(tick, _)| *tick);
+    // offs before ons, so a note that is played again straight away
+    // ends first
+    note_events.sort_by_key(|(tick, _, _, velocity)| {
+        (*tick, *velocity != 0)
+    });
+
+    let to_ms = |tick: u64| {
+        let mut micros = 0u64;
+        let mut last_tick = 0u64;
+        let mut tempo = 500_000u64;
+        for (change_tick, change_tempo) in &tempos {
+            if *change_tick >= tick {
+                break;
+            }
+            micros +=
+                (change_tick - last_tick) * tempo / division as u64;
+            last_tick = *change_tick;
+            tempo = *change_tempo as u64;
+        }
+        micros += (tick - last_tick) * tempo / division as u64;
+        micros / 1000
+    };
+
+    let mut sounding: Vec<(u8, u8, u64, u8)> = Vec::new();
+    let mut notes = Vec::new();
+    for (tick, channel, pitch, velocity) in note_events {
+        if let Some(index) = sounding
+            .iter()
+            .position(|(c, p, _, _)| (*c, *p) == (channel, pitch))
+        {
+            let (_, _, start_tick, start_velocity) =
+                sounding.remove(index);
+            let start_ms = to_ms(start_tick);
+            notes.push(MidiNote {
+                start_ms,
+                length_ms: to_ms(tick) - start_ms,
+                pitch,
+                velocity: start_velocity,
+            });
+        }
+        if velocity != 0 {
+            sounding.push((channel, pitch, tick, velocity));
+        }
+    }
+    if !sounding.is_empty() {
+        return Err("a note is never released".into());
+    }
+
+    notes.sort_by_key(|note| (note.start_ms, note.pitch));
+    Ok(notes)
+}
+
+/// Turns `notes` into the events of `hardware::midi`: the delay since
+/// the previous event, pitch, velocity and length. Gaps too long for
+/// a delay are filled with silent events.
+fn midi_events(notes: &[MidiNote]) -> Vec<(u16, u8, u8, u16)> {
+    let mut events = Vec::new();
+    let mut last_start_ms = 0;
+
+    for note in notes {
+        let mut delay_ms = note.start_ms - last_start_ms;
+        while delay_ms > u16::MAX as u64 {
+            events.push((u16::MAX, 0, 0, 0));
+            delay_ms -= u16::MAX as u64;
+        }
+        events.push((
+            delay_ms as u16,
+            note.pitch,
+            note.velocity,
+            note.length_ms.min(u16::MAX as u64) as u16,
+        ));
+        last_start_ms = note.start_ms;
+    }
+
+    events
+}
+
+struct MidiReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> MidiReader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + length)
+            .ok_or("unexpected end of file")?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([
+            bytes[0], bytes[1], bytes[2], bytes[3],
+        ]))
+    }
+
+    /// A number of up to four bytes, seven bits at a time, most
+    /// significant first.
+    fn variable_length(&mut self) -> Result<u32, String> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let byte = self.u8()?;
+            value = (value << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("variable length number is too long".into())
+    }
+}
+
+struct ParsedSong {
+    tempo_bpm: u16,
+    waveform: &'static str,
+    /// MIDI note number, None for a rest, and length in 64th notes.
+    notes: Vec<(Option<u8>, u16)>,
+}
+
+fn parse_song(text: &str) -> Result<ParsedSong, String> {
+    let mut song = ParsedSong {
+        tempo_bpm: 120,
+        waveform: "Square",
+        notes: Vec::new(),
+    };
+
+    for (line_index, line) in text.lines().enumerate() {
+        let line_number = line_index + 1;
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let mut words = line.split_whitespace();
+
+        match words.next() {
+            None => {}
+            Some("tempo") => {
+                song.tempo_bpm = words
+                    .next()
+                    .and_then(|tempo| tempo.parse().ok())
+                    .filter(|tempo| *tempo > 0)
+                    .ok_or(format!(
+                        "line {line_number}: bad tempo"
+                    ))?;
+            }
+            Some("waveform") => {
+                song.waveform = match words.next() {
+                    Some("sine") => "Sine",
+                    Some("square") => "Square",
+                    Some("saw") => "Saw",
+                    Some("triangle") => "Triangle",
+                    _ => {
+                        return Err(format!(
+                            "line {line_number}: bad waveform"
+                        ));
+                    }
+                };
+            }
+            Some(first) => {
+                for word in core::iter::once(first).chain(words) {
+                    let note = parse_note(word).ok_or(format!(
+                        "line {line_number}: bad note {word:?}"
+                    ))?;
+                    song.notes.push(note);
+                }
+            }
+        }
+    }
+
+    Ok(song)
+}
+
+/// Parses notes like `C#5/8.`: a pitch and octave (or `r` for a
+/// rest), then optionally `/` and the division of a whole note, and a
+/// `.` to make it half as long again. Quarter notes by default.
+fn parse_note(word: &str) -> Option<(Option<u8>, u16)> {
+    let (pitch, length) = match word.split_once('/') {
+        Some((pitch, length)) => (pitch, length),
+        None => (word, "4"),
+    };
+    let (division, dotted) = match length.strip_suffix('.') {
+        Some(division) => (division, true),
+        None => (length, false),
+    };
+    let division = division.parse::<u16>().ok()?;
+    if !matches!(division, 1 | 2 | 4 | 8 | 16 | 32 | 64) {
+        return None;
+    }
+    let length = 64 / division;
+    let length = if dotted { length + length / 2 } else { length };
+
+    if pitch == "r" {
+        return Some((None, length));
+    }
+
+    let mut characters = pitch.chars();
+    let semitone: i16 = match characters.next()? {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    let rest = characters.as_str();
+    let (semitone, octave) =
+        if let Some(octave) = rest.strip_prefix('#') {
+            (semitone + 1, octave)
+        } else if let Some(octave) = rest.strip_prefix('b') {
+            (semitone - 1, octave)
+        } else {
+            (semitone, rest)
+        };
+    let octave =
+        octave.parse::<i16>().ok().filter(|o| (0..=8).contains(o))?;
+
+    // C4 is middle C, MIDI note 60
+    let midi = (octave + 1) * 12 + semitone;
+    Some((Some(u8::try_from(midi).ok()?), length))
 }
 
 fn linker_be_nice() {
@@ -0,0 +1,267 @@
+//! Converts the `.wav` cries in `sounds/cries` for `build.rs`. Kept
+//! apart from the build script so that `tests/wav_conversion.rs` can
+//! test it on the host.
+
+/// Must match `hardware::speaker::SPEAKER_SAMPLE_RATE`.
+pub const SPEAKER_SAMPLE_RATE: u32 = 44_100;
+
+/// The loudness `.wav` sounds are normalized to, as an RMS level of
+/// full scale. About -14 dBFS.
+pub const TARGET_RMS: f32 = 0.2;
+/// The highest a peak may be after normalizing, so that loud,
+/// spiky sounds are not clipped to reach the target loudness.
+pub const PEAK_LIMIT: f32 = 0.95;
+/// Samples at either end quieter than this fraction of the peak are
+/// trimmed off as silence.
+const SILENCE_THRESHOLD: f32 = 0.01;
+/// How many zero crossings of the sinc filter the resampler uses on
+/// either side of a sample. More is sharper and slower.
+const RESAMPLE_ZERO_CROSSINGS: f64 = 16.0;
+
+/// Converts a `.wav` file of any sample rate and channel count into
+/// mono samples at `SPEAKER_SAMPLE_RATE`. The channels are mixed
+/// down, silence at either end is trimmed off and the result is
+/// normalized to `TARGET_RMS`.
+pub fn wav_to_samples(bytes: &[u8]) -> Result<Vec<i16>, String> {
+    let wav = parse_wav(bytes)?;
+
+    let mono = wav
+        .samples
+        .chunks_exact(wav.channels as usize)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect::<Vec<_>>();
+    let trimmed = trim_silence(&mono);
+    if trimmed.is_empty() {
+        return Err("there is nothing but silence".into());
+    }
+    let mut samples =
+        resample(trimmed, wav.sample_rate, SPEAKER_SAMPLE_RATE);
+    normalize(&mut samples);
+
+    Ok(samples
+        .into_iter()
+        .map(|sample| {
+            (sample * i16::MAX as f32)
+                .round()
+                .clamp(i16::MIN as f32, i16::MAX as f32)
+                as i16
+        })
+        .collect())
+}
+
+/// A decoded `.wav` file.
+struct Wav {
+    sample_rate: u32,
+    channels: u16,
+    /// Interleaved, from -1.0 to 1.0.
+    samples: Vec<f32>,
+}
+
+/// Reads integer PCM of 8 to 32 bits and 32 bit float samples, in
+/// plain or extensible `fmt ` chunks.
+fn parse_wav(bytes: &[u8]) -> Result<Wav, String> {
+    if bytes.len() < 12
+        || &bytes[0..4] != b"RIFF"
+        || &bytes[8..12] != b"WAVE"
+    {
+        return Err("not a RIFF WAVE file".into());
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut position = 12;
+    while position + 8 <= bytes.len() {
+        let id = &bytes[position..position + 4];
+        let length = u32::from_le_bytes(
+            bytes[position + 4..position + 8].try_into().unwrap(),
+        ) as usize;
+        let body = bytes
+            .get(position + 8..position + 8 + length)
+            .ok_or(format!(
+                "the {:?} chunk runs past the end of the file",
+                String::from_utf8_lossy(id)
+            ))?;
+
+        match id {
+            b"fmt " => format = Some(body),
+            b"data" => data = Some(body),
+            _ => {}
+        }
+        // chunks are padded to an even length
+        position += 8 + length + length % 2;
+    }
+    let format = format.ok_or("there is no fmt chunk")?;
+    let data = data.ok_or("there is no data chunk")?;
+
+    if format.len() < 16 {
+        return Err("the fmt chunk is too short".into());
+    }
+    let read_u16 =
+        |at: usize| u16::from_le_bytes([format[at], format[at + 1]]);
+    let mut tag = read_u16(0);
+    let channels = read_u16(2);
+    let sample_rate =
+        u32::from_le_bytes(format[4..8].try_into().unwrap());
+    let bits = read_u16(14);
+    if tag == 0xFFFE {
+        if format.len() < 26 {
+            return Err(
+                "the extensible fmt chunk is too short".into()
+            );
+        }
+        // the subformat starts with the tag it stands for
+        tag = read_u16(24);
+    }
+
+    if channels == 0 {
+        return Err("there are no channels".into());
+    }
+    if sample_rate == 0 {
+        return Err("the sample rate is 0".into());
+    }
+
+    let bytes_per_sample = match (tag, bits) {
+        (1, 8 | 16 | 24 | 32) | (3, 32) => bits as usize / 8,
+        (1 | 3, _) => {
+            return Err(format!(
+                "{bits} bit samples are not supported"
+            ));
+        }
+        _ => {
+            return Err(format!(
+                "format {tag:#06x} is not supported, only PCM and float"
+            ));
+        }
+    };
+    let frame_size = bytes_per_sample * channels as usize;
+    if data.len() % frame_size != 0 {
+        return Err(
+            "the data chunk ends in the middle of a frame".into()
+        );
+    }
+
+    let samples = data
+        .chunks_exact(bytes_per_sample)
+        .map(|sample| match (tag, bytes_per_sample) {
+            // 8 bit samples are the only unsigned ones
+            (1, 1) => (sample[0] as f32 - 128.0) / 128.0,
+            (1, 2) => {
+                i16::from_le_bytes([sample[0], sample[1]]) as f32
+                    / 32_768.0
+            }
+            (1, 3) => {
+                i32::from_le_bytes([
+                    0, sample[0], sample[1], sample[2],
+                ]) as f32
+                    / 2_147_483_648.0
+            }
+            (1, _) => {
+                i32::from_le_bytes(sample.try_into().unwrap()) as f32
+                    / 2_147_483_648.0
+            }
+            _ => f32::from_le_bytes(sample.try_into().unwrap())
+                .clamp(-1.0, 1.0),
+        })
+        .collect();
+
+    Ok(Wav {
+        sample_rate,
+        channels,
+        samples,
+    })
+}
+
+/// The part of `samples` between the first and last that are louder
+/// than `SILENCE_THRESHOLD` of the peak.
+fn trim_silence(samples: &[f32]) -> &[f32] {
+    let peak = samples
+        .iter()
+        .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+    if peak == 0.0 {
+        return &[];
+    }
+
+    let threshold = peak * SILENCE_THRESHOLD;
+    let is_loud = |sample: &f32| sample.abs() > threshold;
+    let start = samples.iter().position(is_loud).unwrap_or_default();
+    let end = samples.iter().rposition(is_loud).unwrap_or_default();
+    &samples[start..=end]
+}
+
+/// Resamples with a Blackman windowed sinc filter, which also takes
+/// out whatever is too high to be played at `to_rate`.
+fn resample(
+    samples: &[f32],
+    from_rate: u32,
+    to_rate: u32,
+) -> Vec<f32> {
+    if from_rate == to_rate {
+        return samples.to_vec();
+    }
+
+    let ratio = to_rate as f64 / from_rate as f64;
+    // as a fraction of the Nyquist frequency of `from_rate`, a little
+    // under the lower of the two
+    let cutoff = ratio.min(1.0) * 0.95;
+    let half_width = RESAMPLE_ZERO_CROSSINGS / cutoff;
+    let output_length =
+        (samples.len() as f64 * ratio).round() as usize;
+
+    (0..output_length)
+        .map(|index| {
+            let center = index as f64 / ratio;
+            let first =
+                (center - half_width).ceil().max(0.0) as usize;
+            let last = ((center + half_width).floor() as usize)
+                .min(samples.len() - 1);
+
+            (first..=last)
+                .map(|input| {
+                    let offset = center - input as f64;
+                    let window = offset / half_width;
+                    let weight = cutoff
+                        * sinc(cutoff * offset)
+                        * (0.42
+                            + 0.5
+                                * (std::f64::consts::PI * window)
+                                    .cos()
+                            + 0.08
+                                * (2.0
+                                    * std::f64::consts::PI
+                                    * window)
+                                    .cos());
+                    samples[input] as f64 * weight
+                })
+                .sum::<f64>() as f32
+        })
+        .collect()
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        let x = std::f64::consts::PI * x;
+        x.sin() / x
+    }
+}
+
+/// Scales `samples` to `TARGET_RMS`, or as close as it can get
+/// without peaks going over `PEAK_LIMIT`.
+fn normalize(samples: &mut [f32]) {
+    let peak = samples
+        .iter()
+        .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+    let rms =
+        (samples.iter().map(|sample| sample * sample).sum::<f32>()
+            / samples.len().max(1) as f32)
+            .sqrt();
+    if peak == 0.0 {
+        return;
+    }
+
+    let gain = (TARGET_RMS / rms).min(PEAK_LIMIT / peak);
+    for sample in samples {
+        *sample *= gain;
+    }
+}
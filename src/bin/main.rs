@@ -44,12 +44,21 @@ use meowbox::{
             self, LED, LED_SHIFTER_CHANNEL, LedCommand,
             led_shifter_listener,
         },
+        midi::midi_player,
         mono_display::{
             MONO_DISPLAY_CH, MonoDisplay, MonoDisplayCommand,
         },
         neopixel,
+        rtttl::ringtone_player,
+        sequencer::sequencer_task,
+        speaker::system::play_startup_sound,
     },
-    input_listener::start_input_listener_listener,
+    input_listener::{
+        auto_repeat::auto_repeater, gestures::gesture_recognizer,
+        recorder::input_replayer, start_input_listener_listener,
+        thumbwheel::thumbwheel_listener,
+    },
+    settings,
     states::{MenuState, Meowbox, Stage, State},
     tasks::{
         display_task, led_rotation, left_button_event,
@@ -57,7 +66,7 @@ use meowbox::{
         neopixel_command_listener, play_sequence_listener,
         right_button_event, right_rotary_rotation_watcher,
         rotary_switch_left_event, rotary_switch_right_event,
-        speaker_task,
+        speaker_task, volume::volume_gestures,
     },
 };
 use micromath::F32Ext;
@@ -77,6 +86,10 @@ static DESCRIPTORS: StaticCell<[DmaDescriptor; 8]> =
     StaticCell::new();
 static BUFFER: StaticCell<[u8; 2048]> = StaticCell::new();
 
+/// Where the settings are saved. The sector after the scratch
+/// space at 0x9000, in the nvs partition, which nothing else uses.
+const SETTINGS_FLASH_OFFSET: u32 = 0xA000;
+
 // RUNTIME VARIABLES
 
 #[esp_rtos::main]
@@ -114,6 +127,12 @@ async fn main(spawner: Spawner) -> ! {
         meow[0], meow[1], meow[2], meow[3], meow[4]
     );
 
+    if let Err(error) =
+        settings::load(&mut flash, SETTINGS_FLASH_OFFSET)
+    {
+        warn!("could not load the settings: {}", error);
+    }
+
     //let neopixel_handle = NeoPixelHandle::new();
     //neopixel_handle.activate_with_hb(235, 30).await;
 
@@ -227,6 +246,10 @@ async fn main(spawner: Spawner) -> ! {
     ));
 
     let _ = spawner.spawn(start_input_listener_listener());
+    let _ = spawner.spawn(gesture_recognizer());
+    let _ = spawner.spawn(auto_repeater());
+    let _ = spawner.spawn(input_replayer());
+    let _ = spawner.spawn(thumbwheel_listener());
 
     let _ = spawner.spawn(right_rotary_rotation_watcher(
         non_mutex_peripherals.right_rotary_a,
@@ -240,6 +263,12 @@ async fn main(spawner: Spawner) -> ! {
 
     let _ =
         spawner.spawn(speaker_task(non_mutex_peripherals.speaker));
+    let _ = spawner.spawn(sequencer_task());
+    let _ = spawner.spawn(ringtone_player());
+    let _ = spawner.spawn(midi_player());
+    let _ = spawner.spawn(volume_gestures());
+    let _ = spawner.spawn(settings_saver(flash));
+    play_startup_sound();
 
     BUZZER_2K3_CH
         .send(BuzzerCommand::Play(Duration::from_millis(50)))
@@ -453,6 +482,12 @@ async fn main(spawner: Spawner) -> ! {
     }
 }
 
+/// Owns the flash from startup on, to save the settings to it.
+#[embassy_executor::task]
+async fn settings_saver(mut flash: FlashStorage<'static>) {
+    settings::save_on_change(&mut flash, SETTINGS_FLASH_OFFSET).await
+}
+
 async fn spawner_stage_one_tasks(spawner: &mut Spawner) {}
 
 /// ALWAYS, UNDER LITERALLY EVERY CIRCUMSTANCE, CALL THIS.
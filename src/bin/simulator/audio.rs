@@ -0,0 +1,198 @@
+//! Stand-ins for the I2S speaker and the two buzzers. Speaker PCM is
+//! written to a WAV file, buzzer activity shows up in the terminal
+//! view.
+
+use std::{
+    fs::File,
+    io::{self, Seek, SeekFrom, Write},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use embassy_time::{Duration, Instant, Timer};
+use meowbox::{
+    hardware::{
+        buzzer::{BUZZER_2K3_CH, BUZZER_400_CH, BuzzerCommand},
+        speaker::{SPEAKER_SAMPLE_RATE, SpeakerOutput, run_speaker},
+    },
+    settings,
+};
+
+use crate::screens::mark_terminal_dirty;
+
+const CHANNELS: u16 = 2;
+const BITS_PER_SAMPLE: u16 = 16;
+const BYTES_PER_FRAME: u32 = (CHANNELS * BITS_PER_SAMPLE / 8) as u32;
+const WAV_HEADER_SIZE: u32 = 44;
+
+const CLICK_DURATION: Duration = Duration::from_micros(1500);
+
+struct AudioStatus {
+    speaker_bytes: u32,
+    buzzer_2k3_until: Option<Instant>,
+    buzzer_400_until: Option<Instant>,
+}
+
+static AUDIO_STATUS: Mutex<AudioStatus> = Mutex::new(AudioStatus {
+    speaker_bytes: 0,
+    buzzer_2k3_until: None,
+    buzzer_400_until: None,
+});
+
+/// Appends everything the speaker plays to a 16-bit stereo WAV file.
+/// Pushes are paced to real time, like the DMA transfer on the board.
+pub struct WavOutput {
+    file: Option<File>,
+    data_bytes: u32,
+}
+
+impl WavOutput {
+    pub fn create(path: &PathBuf) -> Self {
+        let file = File::create(path).and_then(|mut file| {
+            write_header(&mut file, 0)?;
+            Ok(file)
+        });
+
+        match file {
+            Ok(file) => Self {
+                file: Some(file),
+                data_bytes: 0,
+            },
+            Err(e) => {
+                eprintln!("could not create {}: {e}", path.display());
+                Self {
+                    file: None,
+                    data_bytes: 0,
+                }
+            }
+        }
+    }
+
+    fn append(&mut self, buffer: &[u8]) -> io::Result<()> {
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+
+        file.write_all(buffer)?;
+        self.data_bytes += buffer.len() as u32;
+
+        // keep the header valid so the file can be opened while the
+        // simulator is still running
+        file.seek(SeekFrom::Start(0))?;
+        write_header(file, self.data_bytes)?;
+        file.seek(SeekFrom::End(0))?;
+
+        Ok(())
+    }
+}
+
+impl SpeakerOutput for WavOutput {
+    async fn push_all(&mut self, buffer: &[u8]) {
+        if let Err(e) = self.append(buffer) {
+            eprintln!("could not write speaker output: {e}");
+            self.file = None;
+        }
+
+        AUDIO_STATUS.lock().unwrap().speaker_bytes = self.data_bytes;
+        mark_terminal_dirty();
+
+        let frames = buffer.len() as u64 / BYTES_PER_FRAME as u64;
+        Timer::after(Duration::from_micros(
+            frames * 1_000_000 / SPEAKER_SAMPLE_RATE as u64,
+        ))
+        .await;
+    }
+}
+
+fn write_header(file: &mut File, data_bytes: u32) -> io::Result<()> {
+    let byte_rate = SPEAKER_SAMPLE_RATE * BYTES_PER_FRAME;
+
+    let mut header = Vec::with_capacity(WAV_HEADER_SIZE as usize);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(
+        &(WAV_HEADER_SIZE - 8 + data_bytes).to_le_bytes(),
+    );
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    // PCM
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&CHANNELS.to_le_bytes());
+    header.extend_from_slice(&(SPEAKER_SAMPLE_RATE).to_le_bytes());
+    header.extend_from_slice(&byte_rate.to_le_bytes());
+    header.extend_from_slice(&(BYTES_PER_FRAME as u16).to_le_bytes());
+    header.extend_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_bytes.to_le_bytes());
+
+    file.write_all(&header)
+}
+
+#[embassy_executor::task]
+pub async fn speaker_task(wav_path: PathBuf) {
+    let mut output = WavOutput::create(&wav_path);
+    run_speaker(&mut output).await
+}
+
+#[embassy_executor::task]
+pub async fn buzzer_2k3_listener() {
+    loop {
+        let command = BUZZER_2K3_CH.receive().await;
+        if !settings::buzzers_audible() {
+            continue;
+        }
+
+        let duration = command_duration(command);
+        AUDIO_STATUS.lock().unwrap().buzzer_2k3_until =
+            Some(Instant::now() + duration);
+        mark_terminal_dirty();
+
+        // the buzzer blocks for as long as it plays on the board too
+        Timer::after(duration).await;
+        mark_terminal_dirty();
+    }
+}
+
+#[embassy_executor::task]
+pub async fn buzzer_400_listener() {
+    loop {
+        let command = BUZZER_400_CH.receive().await;
+        if !settings::buzzers_audible() {
+            continue;
+        }
+
+        let duration = command_duration(command);
+        AUDIO_STATUS.lock().unwrap().buzzer_400_until =
+            Some(Instant::now() + duration);
+        mark_terminal_dirty();
+
+        Timer::after(duration).await;
+        mark_terminal_dirty();
+    }
+}
+
+fn command_duration(cmd: BuzzerCommand) -> Duration {
+    match cmd {
+        BuzzerCommand::Play(duration) => duration,
+        BuzzerCommand::Click => CLICK_DURATION,
+    }
+}
+
+/// One line describing the speaker and buzzers, for the terminal
+/// view.
+pub fn status() -> String {
+    let status = AUDIO_STATUS.lock().unwrap();
+    let now = Instant::now();
+    let playing = |until: Option<Instant>| match until {
+        Some(until) if until > now => "on ",
+        _ => "off",
+    };
+
+    let seconds = status.speaker_bytes as f32
+        / (SPEAKER_SAMPLE_RATE * BYTES_PER_FRAME) as f32;
+
+    format!(
+        "buzzer 2k3: {}  buzzer 400: {}  speaker: {seconds:.1}s written",
+        playing(status.buzzer_2k3_until),
+        playing(status.buzzer_400_until),
+    )
+}
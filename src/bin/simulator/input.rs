@@ -0,0 +1,346 @@
+//! Feeds `INPUT_CHANNEL` from the keyboard and from script files.
+//!
+//! Both use the same words, separated by whitespace. A word is either
+//! the name of an input (`dpad-top`, `button-right`, ...) or a run of
+//! single key shortcuts (`ssk` is two presses down and one right
+//! button press):
+//!
+//! ```text
+//! w a s d    dpad top, left, bottom, right
+//! j k        left and right big buttons
+//! q e z      left encoder counterclockwise, clockwise, press
+//! u o m      right encoder counterclockwise, clockwise, press
+//! ```
+//!
+//! Scripts can also use `wait <ms>` to pause, `hold <input> <ms>` to
+//! keep a button held down for a while, `chord <input> <input> <ms>`
+//! to hold two buttons down together, `wheel <left|right> <raw>` to
+//! set the ADC reading of a thumbwheel (0 to 4095) and `quit` to stop
+//! the simulator. Everything after a `#` is ignored.
+
+use std::{
+    fs,
+    io::{self, BufRead},
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use embassy_time::{Duration, Timer};
+use meowbox::{
+    hardware::{
+        debounce::debounce_config,
+        thumbwheel::{self, ThumbwheelHandle, ThumbwheelValues},
+    },
+    input_listener::{
+        INPUT_CHANNEL, Input,
+        events::{self, Control, Edge},
+        gestures::holds_back_press,
+        thumbwheel::Thumbwheel,
+    },
+};
+use rotary_encoder_embedded::Direction;
+
+const KEYBOARD_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long buttons are held down for before their release is sent.
+const BUTTON_HOLD: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Step {
+    Press(Input),
+    /// Holds the inputs down together, the second one is for chords.
+    Hold(Input, Option<Input>, Duration),
+    Wait(Duration),
+    /// Sets the raw reading of a thumbwheel.
+    Wheel(Thumbwheel, u16),
+    Quit,
+}
+
+fn parse_line(line: &str) -> Result<Vec<Step>, String> {
+    let line = line.split('#').next().unwrap_or_default();
+    let mut words = line.split_whitespace();
+    let mut steps = Vec::new();
+
+    while let Some(word) = words.next() {
+        if word == "wait" {
+            let millis = words
+                .next()
+                .and_then(|ms| ms.parse().ok())
+                .ok_or("`wait` needs a duration in milliseconds")?;
+            steps.push(Step::Wait(Duration::from_millis(millis)));
+        } else if word == "hold" || word == "chord" {
+            let mut single_input = || {
+                words
+                    .next()
+                    .and_then(single_input)
+                    .ok_or(format!("`{word}` needs single inputs"))
+            };
+            let input = single_input()?;
+            let chorded = if word == "chord" {
+                Some(single_input()?)
+            } else {
+                None
+            };
+            let millis = words
+                .next()
+                .and_then(|ms| ms.parse().ok())
+                .ok_or(format!(
+                    "`{word}` needs a duration in milliseconds"
+                ))?;
+            steps.push(Step::Hold(
+                input,
+                chorded,
+                Duration::from_millis(millis),
+            ));
+        } else if word == "wheel" {
+            let thumbwheel = match words.next() {
+                Some("left") => Thumbwheel::Left,
+                Some("right") => Thumbwheel::Right,
+                _ => {
+                    return Err(
+                        "`wheel` needs `left` or `right`".into()
+                    );
+                }
+            };
+            let raw = words
+                .next()
+                .and_then(|raw| raw.parse().ok())
+                .ok_or("`wheel` needs a raw reading")?;
+            steps.push(Step::Wheel(thumbwheel, raw));
+        } else if word == "quit" {
+            steps.push(Step::Quit);
+        } else if let Some(input) = named_input(word) {
+            steps.push(Step::Press(input));
+        } else {
+            for key in word.chars() {
+                let input = key_input(key).ok_or_else(|| {
+                    format!("unknown input `{word}`")
+                })?;
+                steps.push(Step::Press(input));
+            }
+        }
+    }
+
+    Ok(steps)
+}
+
+fn named_input(name: &str) -> Option<Input> {
+    let input = match name {
+        "dpad-top" => Input::DpadTop,
+        "dpad-bottom" => Input::DpadBottom,
+        "dpad-left" => Input::DpadLeft,
+        "dpad-right" => Input::DpadRight,
+        "button-left" => Input::ButtonLeft,
+        "button-right" => Input::ButtonRight,
+        "left-ccw" => {
+            Input::RotaryEncoderRotateLeft(Direction::Anticlockwise)
+        }
+        "left-cw" => {
+            Input::RotaryEncoderRotateLeft(Direction::Clockwise)
+        }
+        "left-press" => Input::RotaryEncoderPressLeft,
+        "right-ccw" => {
+            Input::RotaryEncoderRotateRight(Direction::Anticlockwise)
+        }
+        "right-cw" => {
+            Input::RotaryEncoderRotateRight(Direction::Clockwise)
+        }
+        "right-press" => Input::RotaryEncoderPressRight,
+        _ => return None,
+    };
+
+    Some(input)
+}
+
+/// An input name, or a single key.
+fn single_input(word: &str) -> Option<Input> {
+    named_input(word).or_else(|| {
+        let mut keys = word.chars();
+        key_input(keys.next()?).filter(|_| keys.next().is_none())
+    })
+}
+
+fn key_input(key: char) -> Option<Input> {
+    let name = match key {
+        'w' => "dpad-top",
+        'a' => "dpad-left",
+        's' => "dpad-bottom",
+        'd' => "dpad-right",
+        'j' => "button-left",
+        'k' => "button-right",
+        'q' => "left-ccw",
+        'e' => "left-cw",
+        'z' => "left-press",
+        'u' => "right-ccw",
+        'o' => "right-cw",
+        'm' => "right-press",
+        _ => return None,
+    };
+
+    named_input(name)
+}
+
+/// Sends the inputs the same way their hardware tasks would,
+/// including the press and release edges and the debounce delays.
+/// All of the inputs are held down together.
+async fn press(inputs: &[Input], hold: Duration) {
+    let controls: Vec<Control> = inputs
+        .iter()
+        .filter_map(|input| Control::from_pressed_input(*input))
+        .collect();
+
+    for &input in inputs {
+        if Control::from_pressed_input(input).is_none() {
+            // encoders are not debounced and have no edges
+            INPUT_CHANNEL.send(input).await;
+        }
+    }
+
+    if controls.is_empty() {
+        return;
+    }
+
+    // the debouncers only let an edge through once the contact has
+    // been steady for long enough
+    let settle = |time: fn(Control) -> Duration| {
+        controls
+            .iter()
+            .map(|control| time(*control))
+            .max()
+            .unwrap_or_default()
+    };
+    let press_settle =
+        settle(|control| debounce_config(control).press);
+    let release_settle =
+        settle(|control| debounce_config(control).release);
+
+    Timer::after(press_settle).await;
+    for &control in &controls {
+        events::publish_edge(control, Edge::Press);
+        if !holds_back_press(control) {
+            INPUT_CHANNEL.send(control.pressed_input()).await;
+        }
+    }
+
+    Timer::after(hold).await;
+
+    Timer::after(release_settle).await;
+    for &control in &controls {
+        events::publish_edge(control, Edge::Release);
+        if control == Control::ButtonRight {
+            INPUT_CHANNEL.send(Input::ButtonRightReleased).await;
+        }
+    }
+}
+
+/// Changes the reading of one wheel, the other one keeps its
+/// reading. Both start out in the middle.
+async fn set_thumbwheel(wheel: Thumbwheel, raw: u16) {
+    let mut values = ThumbwheelHandle::raw_values().await.unwrap_or(
+        ThumbwheelValues {
+            left: 2048,
+            right: 2048,
+        },
+    );
+
+    match wheel {
+        Thumbwheel::Left => values.left = raw,
+        Thumbwheel::Right => values.right = raw,
+    }
+
+    thumbwheel::set_simulated_values(values);
+}
+
+/// Runs the steps, returns false if the simulator should stop.
+async fn run_steps(steps: &[Step]) -> bool {
+    for step in steps {
+        match *step {
+            Step::Press(input) => press(&[input], BUTTON_HOLD).await,
+            Step::Hold(input, None, hold) => {
+                press(&[input], hold).await
+            }
+            Step::Hold(input, Some(chorded), hold) => {
+                press(&[input, chorded], hold).await
+            }
+            Step::Wait(duration) => Timer::after(duration).await,
+            Step::Wheel(wheel, raw) => {
+                set_thumbwheel(wheel, raw).await
+            }
+            Step::Quit => return false,
+        }
+    }
+
+    true
+}
+
+fn spawn_stdin_reader() -> Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    rx
+}
+
+/// Runs `script` if there is one, then reads from the keyboard until
+/// stdin closes. Exits the process on `quit`.
+#[embassy_executor::task]
+pub async fn input_feeder(script: Option<PathBuf>) {
+    if let Some(path) = script {
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                eprintln!("could not read {}: {e}", path.display());
+                std::process::exit(1);
+            }
+        };
+
+        for (number, line) in contents.lines().enumerate() {
+            let steps = match parse_line(line) {
+                Ok(steps) => steps,
+                Err(e) => {
+                    eprintln!(
+                        "{}:{}: {e}",
+                        path.display(),
+                        number + 1
+                    );
+                    std::process::exit(1);
+                }
+            };
+
+            if !run_steps(&steps).await {
+                crate::exit(0);
+            }
+        }
+    }
+
+    let keyboard = spawn_stdin_reader();
+
+    loop {
+        let line = match keyboard.try_recv() {
+            Ok(line) => line,
+            Err(TryRecvError::Empty) => {
+                Timer::after(KEYBOARD_POLL_INTERVAL).await;
+                continue;
+            }
+            // stdin was closed, keep running until killed
+            Err(TryRecvError::Disconnected) => return,
+        };
+
+        match parse_line(&line) {
+            Ok(steps) => {
+                if !run_steps(&steps).await {
+                    crate::exit(0);
+                }
+            }
+            Err(e) => eprintln!("{e}"),
+        }
+    }
+}
@@ -0,0 +1,229 @@
+//! Runs the Meowbox state machine on a desktop, without the board.
+//!
+//! The hardware tasks are replaced with stand-ins that listen on the
+//! same channels. The large display is written to
+//! `<frames>/large_display.ppm`, the mono display and the LEDs are
+//! drawn in the terminal, and the speaker is written to a WAV file.
+//! See `input.rs` for how to press buttons.
+//!
+//! `--record FILE` records every input from startup and writes the
+//! recording to `FILE` when the simulator quits. `--replay FILE`
+//! plays a recording back from startup. Recordings use the same
+//! format as the board's.
+//!
+//! The volume, mute, bindings and thumbwheel calibration are saved
+//! to `--settings FILE`, by default `simulator-out/settings.bin`, and
+//! loaded from it at startup.
+//!
+//! ```text
+//! cargo simulator -- [--frames DIR] [--wav FILE] [--script FILE]
+//!                    [--record FILE] [--replay FILE]
+//!                    [--settings FILE]
+//! ```
+
+use std::{path::PathBuf, process, sync::OnceLock};
+
+use embassy_executor::Spawner;
+use embassy_time::{Duration, Timer};
+use meowbox::{
+    hardware::{
+        midi::midi_player, rtttl::ringtone_player,
+        sequencer::sequencer_task,
+        speaker::system::play_startup_sound,
+    },
+    input_listener::{
+        auto_repeat::auto_repeater,
+        gestures::gesture_recognizer,
+        recorder::{self, MAX_ENCODED_SIZE, input_replayer},
+        start_input_listener_listener,
+        thumbwheel::thumbwheel_listener,
+    },
+    settings,
+    states::{MenuState, Meowbox, Stage, State},
+    tasks::volume::volume_gestures,
+};
+
+use crate::storage::FileStorage;
+
+mod audio;
+mod input;
+mod screens;
+mod storage;
+
+const USAGE: &str = "usage: simulator [--frames DIR] [--wav FILE] \
+                     [--script FILE] [--record FILE] [--replay FILE] \
+                     [--settings FILE]";
+
+/// Where to write the input recording on exit, if recording.
+static RECORD_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+// defmt has nowhere to go on the host, so log frames are dropped.
+#[defmt::global_logger]
+struct Logger;
+
+unsafe impl defmt::Logger for Logger {
+    fn acquire() {}
+    unsafe fn flush() {}
+    unsafe fn release() {}
+    unsafe fn write(_bytes: &[u8]) {}
+}
+
+defmt::timestamp!("{=u64}", 0);
+
+#[defmt::panic_handler]
+fn defmt_panic() -> ! {
+    process::abort()
+}
+
+struct Args {
+    frames_dir: PathBuf,
+    wav_path: PathBuf,
+    script: Option<PathBuf>,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+    settings_path: PathBuf,
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        frames_dir: PathBuf::from("simulator-out"),
+        wav_path: PathBuf::from("simulator-out/speaker.wav"),
+        script: None,
+        record: None,
+        replay: None,
+        settings_path: PathBuf::from("simulator-out/settings.bin"),
+    };
+
+    let mut raw = std::env::args().skip(1);
+    while let Some(flag) = raw.next() {
+        let value = match flag.as_str() {
+            "--frames" | "--wav" | "--script" | "--record"
+            | "--replay" | "--settings" => raw.next(),
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                process::exit(0);
+            }
+            _ => None,
+        };
+
+        let Some(value) = value.map(PathBuf::from) else {
+            eprintln!("{USAGE}");
+            process::exit(2);
+        };
+
+        match flag.as_str() {
+            "--frames" => args.frames_dir = value,
+            "--wav" => args.wav_path = value,
+            "--record" => args.record = Some(value),
+            "--replay" => args.replay = Some(value),
+            "--settings" => args.settings_path = value,
+            _ => args.script = Some(value),
+        }
+    }
+
+    args
+}
+
+/// Writes the input recording if there is one, then exits.
+pub fn exit(code: i32) -> ! {
+    if let Some(path) = RECORD_PATH.get() {
+        recorder::stop_recording();
+
+        let mut buffer = vec![0; MAX_ENCODED_SIZE];
+        let written = recorder::encode(&mut buffer)
+            .map_err(|e| format!("{e:?}"))
+            .and_then(|len| {
+                std::fs::write(path, &buffer[..len])
+                    .map_err(|e| e.to_string())
+            });
+
+        if let Err(e) = written {
+            eprintln!("could not write {}: {e}", path.display());
+        }
+    }
+
+    process::exit(code)
+}
+
+fn load_replay(path: &PathBuf) {
+    let loaded = std::fs::read(path)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| {
+            recorder::decode(&bytes).map_err(|e| format!("{e:?}"))
+        });
+
+    if let Err(e) = loaded {
+        eprintln!("could not load {}: {e}", path.display());
+        process::exit(1);
+    }
+
+    recorder::start_replay();
+}
+
+/// Owns the settings file, to save the settings to it.
+#[embassy_executor::task]
+async fn settings_saver(mut storage: FileStorage) {
+    settings::save_on_change(&mut storage, 0).await
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    let args = parse_args();
+
+    for dir in
+        [Some(args.frames_dir.as_path()), args.wav_path.parent()]
+            .into_iter()
+            .flatten()
+            .filter(|dir| !dir.as_os_str().is_empty())
+    {
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            eprintln!("could not create {}: {e}", dir.display());
+            process::exit(1);
+        }
+    }
+
+    // a missing file just leaves the defaults
+    let mut storage = FileStorage::new(args.settings_path);
+    let _ = settings::load(&mut storage, 0);
+    spawner.must_spawn(settings_saver(storage));
+
+    spawner.must_spawn(screens::led_shifter_listener());
+    spawner.must_spawn(screens::mono_display_listener());
+    spawner.must_spawn(screens::backlight_listener());
+    spawner.must_spawn(screens::large_display_listener());
+    spawner.must_spawn(audio::buzzer_2k3_listener());
+    spawner.must_spawn(audio::buzzer_400_listener());
+    spawner.must_spawn(audio::speaker_task(args.wav_path));
+    spawner.must_spawn(sequencer_task());
+    spawner.must_spawn(ringtone_player());
+    spawner.must_spawn(midi_player());
+    spawner.must_spawn(volume_gestures());
+    play_startup_sound();
+    spawner.must_spawn(screens::dashboard(
+        args.frames_dir,
+        audio::status,
+    ));
+
+    spawner.must_spawn(start_input_listener_listener());
+    spawner.must_spawn(gesture_recognizer());
+    spawner.must_spawn(auto_repeater());
+    spawner.must_spawn(input_replayer());
+    spawner.must_spawn(thumbwheel_listener());
+
+    if let Some(path) = args.replay.as_ref() {
+        load_replay(path);
+    }
+    if let Some(path) = args.record {
+        recorder::start_recording();
+        let _ = RECORD_PATH.set(path);
+    }
+    spawner.must_spawn(input::input_feeder(args.script));
+
+    let state = State::Menu(Stage::Setup, MenuState::default());
+    let mut meowbox = Meowbox::new(state);
+
+    loop {
+        meowbox.tick().await;
+        Timer::after(Duration::from_millis(1)).await;
+    }
+}
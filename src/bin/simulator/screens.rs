@@ -0,0 +1,428 @@
+//! Stand-ins for the large display, the backlight, the mono display
+//! and the LED shift registers. Everything the tasks draw ends up in
+//! the statics below, which `dashboard` turns into an image and a
+//! terminal view.
+
+use std::{
+    convert::Infallible,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering::SeqCst},
+    },
+};
+
+use display_interface::DisplayError;
+use embassy_time::{Duration, Timer};
+use embedded_graphics::{pixelcolor::Rgb565, prelude::*};
+use embedded_hal::digital::{ErrorType, OutputPin};
+use meowbox::hardware::{
+    large_display::{
+        BACKLIGHT_CH, BacklightCommand, LargeDisplayCanvas,
+        run_large_display,
+    },
+    led_shifter::run_led_shifter,
+    mono_display::{MONO_DISPLAY_CH, MonoDisplayCommand},
+};
+
+const LARGE_WIDTH: usize = 240;
+const LARGE_HEIGHT: usize = 320;
+
+// The ssd1306 terminal mode uses an 8x8 font on a 128x64 panel.
+const MONO_COLUMNS: usize = 16;
+const MONO_ROWS: usize = 8;
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(50);
+
+// Index matches the value of the LED enum.
+const LED_NAMES: [&str; 16] = [
+    "White",
+    "YellowCenter",
+    "Red",
+    "Orange",
+    "Green",
+    "Blue",
+    "AmberRight",
+    "AmberLeft",
+    "YellowLeft",
+    "YellowRight",
+    "DpadBottom",
+    "DpadRight",
+    "DpadTop",
+    "DpadLeft",
+    "ButtonRight",
+    "ButtonLeft",
+];
+
+// Drawn left to right, roughly where they sit on the board.
+const LED_LAYOUT: [usize; 16] =
+    [15, 7, 8, 0, 1, 2, 3, 4, 5, 9, 6, 14, 13, 12, 10, 11];
+
+struct LargeFrame {
+    pixels: Vec<u16>,
+    display_on: bool,
+    inverted: bool,
+    backlight: bool,
+}
+
+struct MonoTerminal {
+    cells: [[char; MONO_COLUMNS]; MONO_ROWS],
+    column: usize,
+    row: usize,
+    display_on: bool,
+}
+
+static LARGE_FRAME: Mutex<LargeFrame> = Mutex::new(LargeFrame {
+    pixels: Vec::new(),
+    display_on: true,
+    inverted: false,
+    backlight: false,
+});
+
+static MONO_TERMINAL: Mutex<MonoTerminal> =
+    Mutex::new(MonoTerminal {
+        cells: [[' '; MONO_COLUMNS]; MONO_ROWS],
+        column: 0,
+        row: 0,
+        display_on: false,
+    });
+
+static LEDS: Mutex<[bool; 16]> = Mutex::new([false; 16]);
+
+static LARGE_DIRTY: AtomicBool = AtomicBool::new(true);
+static TERMINAL_DIRTY: AtomicBool = AtomicBool::new(true);
+
+/// Marks the terminal view as needing a redraw. Used by the other
+/// stand-ins that show up in the status line.
+pub fn mark_terminal_dirty() {
+    TERMINAL_DIRTY.store(true, SeqCst);
+}
+
+/// A 240x320 framebuffer that takes the place of the ILI9341.
+pub struct SimLargeDisplay;
+
+impl SimLargeDisplay {
+    fn with_frame<R>(f: impl FnOnce(&mut LargeFrame) -> R) -> R {
+        let mut frame = LARGE_FRAME.lock().unwrap();
+        if frame.pixels.is_empty() {
+            frame.pixels = vec![0; LARGE_WIDTH * LARGE_HEIGHT];
+        }
+        let result = f(&mut frame);
+        LARGE_DIRTY.store(true, SeqCst);
+        result
+    }
+}
+
+impl OriginDimensions for SimLargeDisplay {
+    fn size(&self) -> Size {
+        Size::new(LARGE_WIDTH as u32, LARGE_HEIGHT as u32)
+    }
+}
+
+impl DrawTarget for SimLargeDisplay {
+    type Color = Rgb565;
+    type Error = DisplayError;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        Self::with_frame(|frame| {
+            for Pixel(point, color) in pixels {
+                if let (Ok(x), Ok(y)) = (
+                    usize::try_from(point.x),
+                    usize::try_from(point.y),
+                ) && x < LARGE_WIDTH
+                    && y < LARGE_HEIGHT
+                {
+                    frame.pixels[y * LARGE_WIDTH + x] =
+                        color.into_storage();
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+impl LargeDisplayCanvas for SimLargeDisplay {
+    fn clear_screen(
+        &mut self,
+        color: u16,
+    ) -> Result<(), DisplayError> {
+        Self::with_frame(|frame| frame.pixels.fill(color));
+        Ok(())
+    }
+
+    fn fill_rect(
+        &mut self,
+        x: u16,
+        y: u16,
+        width: u16,
+        height: u16,
+        color: u16,
+    ) -> Result<(), DisplayError> {
+        let x_end = (x as usize + width as usize).min(LARGE_WIDTH);
+        let y_end = (y as usize + height as usize).min(LARGE_HEIGHT);
+
+        Self::with_frame(|frame| {
+            for row in y as usize..y_end {
+                let start = row * LARGE_WIDTH;
+                frame.pixels[start + x as usize..start + x_end]
+                    .fill(color);
+            }
+        });
+
+        Ok(())
+    }
+
+    fn set_display_on(
+        &mut self,
+        on: bool,
+    ) -> Result<(), DisplayError> {
+        Self::with_frame(|frame| frame.display_on = on);
+        Ok(())
+    }
+
+    fn set_inverted(
+        &mut self,
+        inverted: bool,
+    ) -> Result<(), DisplayError> {
+        Self::with_frame(|frame| frame.inverted = inverted);
+        Ok(())
+    }
+
+    fn set_brightness(
+        &mut self,
+        _brightness: u8,
+    ) -> Result<(), DisplayError> {
+        // The ILI9341 on the board ignores this as well, brightness
+        // is only controlled by the backlight pin.
+        Ok(())
+    }
+}
+
+#[embassy_executor::task]
+pub async fn large_display_listener() {
+    run_large_display(Some(SimLargeDisplay)).await
+}
+
+#[embassy_executor::task]
+pub async fn backlight_listener() {
+    loop {
+        let cmd = BACKLIGHT_CH.receive().await;
+        let mut frame = LARGE_FRAME.lock().unwrap();
+
+        frame.backlight = match cmd {
+            BacklightCommand::Toggle => !frame.backlight,
+            BacklightCommand::SetHigh => true,
+            BacklightCommand::SetLow => false,
+            BacklightCommand::SetBrightness(brightness) => {
+                brightness > 0
+            }
+        };
+
+        LARGE_DIRTY.store(true, SeqCst);
+    }
+}
+
+#[embassy_executor::task]
+pub async fn mono_display_listener() {
+    loop {
+        let cmd = MONO_DISPLAY_CH.receive().await;
+        let mut terminal = MONO_TERMINAL.lock().unwrap();
+
+        match cmd {
+            MonoDisplayCommand::Init | MonoDisplayCommand::Clear => {
+                terminal.cells = [[' '; MONO_COLUMNS]; MONO_ROWS];
+                terminal.column = 0;
+                terminal.row = 0;
+            }
+            MonoDisplayCommand::SetDisplayOn(on) => {
+                terminal.display_on = on
+            }
+            // Graphics mode is never drawn to, so there is nothing to
+            // keep track of here.
+            MonoDisplayCommand::SwitchToTerminal
+            | MonoDisplayCommand::SwitchToGraphics => {}
+            MonoDisplayCommand::WriteStr(s) => {
+                for c in s.chars() {
+                    terminal.print_char(c);
+                }
+            }
+        }
+
+        mark_terminal_dirty();
+    }
+}
+
+impl MonoTerminal {
+    // Mirrors the cursor handling of the ssd1306 terminal mode, which
+    // wraps around to the top instead of scrolling.
+    fn print_char(&mut self, c: char) {
+        match c {
+            '\n' => {
+                self.row = (self.row + 1) % MONO_ROWS;
+                self.column = 0;
+            }
+            '\r' => self.column = 0,
+            _ => {
+                self.cells[self.row][self.column] = c;
+                self.column = (self.column + 1) % MONO_COLUMNS;
+                if self.column == 0 {
+                    self.row = (self.row + 1) % MONO_ROWS;
+                }
+            }
+        }
+    }
+}
+
+/// Takes the place of one output of the shift registers.
+pub struct SimLedPin(usize);
+
+impl ErrorType for SimLedPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for SimLedPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        LEDS.lock().unwrap()[self.0] = false;
+        mark_terminal_dirty();
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        LEDS.lock().unwrap()[self.0] = true;
+        mark_terminal_dirty();
+        Ok(())
+    }
+}
+
+#[embassy_executor::task]
+pub async fn led_shifter_listener() {
+    run_led_shifter(core::array::from_fn(SimLedPin)).await
+}
+
+/// Writes the large display to `large_display.ppm` inside
+/// `frames_dir` and redraws the terminal view whenever something
+/// changed.
+#[embassy_executor::task]
+pub async fn dashboard(frames_dir: PathBuf, status: fn() -> String) {
+    if let Err(e) = fs::create_dir_all(&frames_dir) {
+        eprintln!("could not create {}: {e}", frames_dir.display());
+    }
+
+    let image_path = frames_dir.join("large_display.ppm");
+
+    loop {
+        Timer::after(REFRESH_INTERVAL).await;
+
+        if LARGE_DIRTY.swap(false, SeqCst)
+            && let Err(e) = write_large_display(&image_path)
+        {
+            eprintln!(
+                "could not write {}: {e}",
+                image_path.display()
+            );
+        }
+
+        if TERMINAL_DIRTY.swap(false, SeqCst) {
+            draw_terminal(&image_path, &status());
+        }
+    }
+}
+
+fn write_large_display(path: &Path) -> io::Result<()> {
+    let mut image =
+        format!("P6\n{LARGE_WIDTH} {LARGE_HEIGHT}\n255\n")
+            .into_bytes();
+
+    {
+        let frame = LARGE_FRAME.lock().unwrap();
+        let lit = frame.display_on && frame.backlight;
+
+        for i in 0..LARGE_WIDTH * LARGE_HEIGHT {
+            let mut color = frame.pixels.get(i).copied().unwrap_or(0);
+            if frame.inverted {
+                color = !color;
+            }
+
+            let [r, g, b] =
+                if lit { rgb565_to_rgb888(color) } else { [0; 3] };
+            image.extend_from_slice(&[r, g, b]);
+        }
+    }
+
+    // Write then rename, so image viewers that reload on change never
+    // see a half written file.
+    let tmp_path = path.with_extension("ppm.tmp");
+    fs::write(&tmp_path, image)?;
+    fs::rename(tmp_path, path)
+}
+
+fn rgb565_to_rgb888(color: u16) -> [u8; 3] {
+    let r = ((color >> 11) & 0x1f) as u8;
+    let g = ((color >> 5) & 0x3f) as u8;
+    let b = (color & 0x1f) as u8;
+
+    [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+    ]
+}
+
+fn draw_terminal(image_path: &Path, status: &str) {
+    let mut out = String::new();
+
+    // clear the screen and move the cursor to the top left
+    out.push_str("\x1b[2J\x1b[H");
+    out.push_str("meowbox simulator\n\n");
+
+    {
+        let terminal = MONO_TERMINAL.lock().unwrap();
+        out.push('+');
+        out.push_str(&"-".repeat(MONO_COLUMNS));
+        out.push_str("+\n");
+        for row in &terminal.cells {
+            out.push('|');
+            for &c in row {
+                out.push(if terminal.display_on { c } else { ' ' });
+            }
+            out.push_str("|\n");
+        }
+        out.push('+');
+        out.push_str(&"-".repeat(MONO_COLUMNS));
+        out.push_str("+\n\n");
+    }
+
+    {
+        let leds = LEDS.lock().unwrap();
+        for &i in &LED_LAYOUT {
+            out.push_str(if leds[i] {
+                "\x1b[93m●\x1b[0m "
+            } else {
+                "○ "
+            });
+        }
+        out.push('\n');
+        let lit: Vec<&str> = (0..16)
+            .filter(|&i| leds[i])
+            .map(|i| LED_NAMES[i])
+            .collect();
+        out.push_str(&format!("lit: {}\n\n", lit.join(" ")));
+    }
+
+    out.push_str(&format!(
+        "large display: {}\n",
+        image_path.display()
+    ));
+    out.push_str(status);
+    out.push_str("\n\n> ");
+
+    let mut stdout = io::stdout().lock();
+    let _ = stdout.write_all(out.as_bytes());
+    let _ = stdout.flush();
+}
@@ -0,0 +1,80 @@
+//! A stand-in for the board's flash, kept in a file so that the
+//! settings last from one run of the simulator to the next.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+use embedded_storage::{ReadStorage, Storage};
+
+/// One sector of the board's flash, far more than the settings need.
+const CAPACITY: usize = 4096;
+
+pub struct FileStorage {
+    path: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl ReadStorage for FileStorage {
+    type Error = io::Error;
+
+    /// Reads what was written at `offset`. Anything that was never
+    /// written reads as erased flash does, as 0xFF.
+    fn read(
+        &mut self,
+        offset: u32,
+        bytes: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        bytes.fill(0xFF);
+
+        let mut file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+
+        file.seek(SeekFrom::Start(offset as u64))?;
+        let mut read = 0;
+        while read < bytes.len() {
+            match file.read(&mut bytes[read..])? {
+                0 => break,
+                count => read += count,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        CAPACITY
+    }
+}
+
+impl Storage for FileStorage {
+    fn write(
+        &mut self,
+        offset: u32,
+        bytes: &[u8],
+    ) -> Result<(), Self::Error> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&self.path)?;
+        file.seek(SeekFrom::Start(offset as u64))?;
+        file.write_all(bytes)
+    }
+}
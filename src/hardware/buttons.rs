@@ -1,93 +1,147 @@
-use defmt::println;
+//! The GPIO tasks for the buttons, the D-pad and the encoder
+//! switches. None of it is built for the simulator, which feeds the
+//! input listener from the keyboard instead.
+
+#[cfg(feature = "esp32s3")]
 use embassy_executor::task;
-use embassy_time::{Duration, Timer};
+#[cfg(feature = "esp32s3")]
+use embassy_futures::select::select;
+#[cfg(feature = "esp32s3")]
+use embassy_time::{Instant, Timer};
+#[cfg(feature = "esp32s3")]
 use esp_hal::gpio;
 
-use crate::{input_listener, input_listener::INPUT_CHANNEL};
+#[cfg(feature = "esp32s3")]
+use super::debounce::{Debouncer, debounce_config};
+#[cfg(feature = "esp32s3")]
+use crate::{
+    input_listener,
+    input_listener::{
+        INPUT_CHANNEL,
+        events::{self, Control, Edge},
+        gestures::holds_back_press,
+    },
+};
+
+/// Publishes the debounced edges of `pin`, which reads low while
+/// pressed. Sends `pressed` on every press, unless the gesture
+/// recognizer holds it back (see `gestures::holds_back_press`), and
+/// `released` (if there is one) on every release. The timing comes
+/// from `debounce::debounce_config` and is read again on every edge.
+#[cfg(feature = "esp32s3")]
+pub(crate) async fn listen_for_edges(
+    pin: &mut gpio::Input<'static>,
+    control: Control,
+    pressed: input_listener::Input,
+    released: Option<input_listener::Input>,
+) -> ! {
+    let mut debouncer = Debouncer::new(pin.is_low(), Instant::now());
 
-pub const BUTTON_DEBOUNCE: Duration = Duration::from_millis(200);
-pub const DPAD_DEBOUNCE: Duration = Duration::from_millis(150);
+    loop {
+        let config = debounce_config(control);
+
+        match debouncer.deadline(&config) {
+            Some(deadline) => {
+                select(pin.wait_for_any_edge(), Timer::at(deadline))
+                    .await;
+            }
+            None => pin.wait_for_any_edge().await,
+        }
 
+        let Some(edge) =
+            debouncer.update(&config, pin.is_low(), Instant::now())
+        else {
+            continue;
+        };
+
+        events::publish_edge(control, edge);
+        match (edge, released) {
+            (Edge::Press, _) if holds_back_press(control) => {}
+            (Edge::Press, _) => INPUT_CHANNEL.send(pressed).await,
+            (Edge::Release, Some(released)) => {
+                INPUT_CHANNEL.send(released).await
+            }
+            (Edge::Release, None) => {}
+        }
+    }
+}
+
+#[cfg(feature = "esp32s3")]
 #[task]
 pub async fn button_left_listener(
     mut left_button: gpio::Input<'static>,
 ) {
-    loop {
-        left_button.wait_for_falling_edge().await;
-
-        INPUT_CHANNEL.send(input_listener::Input::ButtonLeft).await;
-
-        Timer::after(BUTTON_DEBOUNCE).await;
-    }
+    listen_for_edges(
+        &mut left_button,
+        Control::ButtonLeft,
+        input_listener::Input::ButtonLeft,
+        None,
+    )
+    .await
 }
 
+#[cfg(feature = "esp32s3")]
 #[task]
 pub async fn button_right_listener(
     mut right_button: gpio::Input<'static>,
 ) {
-    loop {
-        right_button.wait_for_falling_edge().await;
-
-        INPUT_CHANNEL.send(input_listener::Input::ButtonRight).await;
-
-        Timer::after(BUTTON_DEBOUNCE).await;
-
-        if right_button.is_low() {
-            right_button.wait_for_rising_edge().await;
-        }
-
-        INPUT_CHANNEL
-            .send(input_listener::Input::ButtonRightReleased)
-            .await;
-
-        Timer::after(BUTTON_DEBOUNCE).await;
-    }
+    listen_for_edges(
+        &mut right_button,
+        Control::ButtonRight,
+        input_listener::Input::ButtonRight,
+        Some(input_listener::Input::ButtonRightReleased),
+    )
+    .await
 }
 
+#[cfg(feature = "esp32s3")]
 #[task]
 pub async fn dpad_bottom_listener(
     mut dpad_bottom: gpio::Input<'static>,
 ) {
-    loop {
-        dpad_bottom.wait_for_falling_edge().await;
-
-        INPUT_CHANNEL.send(input_listener::Input::DpadBottom).await;
-
-        Timer::after(DPAD_DEBOUNCE).await;
-    }
+    listen_for_edges(
+        &mut dpad_bottom,
+        Control::DpadBottom,
+        input_listener::Input::DpadBottom,
+        None,
+    )
+    .await
 }
 
+#[cfg(feature = "esp32s3")]
 #[task]
 pub async fn dpad_top_listener(mut dpad_top: gpio::Input<'static>) {
-    loop {
-        dpad_top.wait_for_falling_edge().await;
-
-        INPUT_CHANNEL.send(input_listener::Input::DpadTop).await;
-
-        Timer::after(DPAD_DEBOUNCE).await;
-    }
+    listen_for_edges(
+        &mut dpad_top,
+        Control::DpadTop,
+        input_listener::Input::DpadTop,
+        None,
+    )
+    .await
 }
 
+#[cfg(feature = "esp32s3")]
 #[task]
 pub async fn dpad_left_listener(mut dpad_left: gpio::Input<'static>) {
-    loop {
-        dpad_left.wait_for_falling_edge().await;
-
-        INPUT_CHANNEL.send(input_listener::Input::DpadLeft).await;
-
-        Timer::after(DPAD_DEBOUNCE).await;
-    }
+    listen_for_edges(
+        &mut dpad_left,
+        Control::DpadLeft,
+        input_listener::Input::DpadLeft,
+        None,
+    )
+    .await
 }
 
+#[cfg(feature = "esp32s3")]
 #[task]
 pub async fn dpad_right_listener(
     mut dpad_right: gpio::Input<'static>,
 ) {
-    loop {
-        dpad_right.wait_for_falling_edge().await;
-
-        INPUT_CHANNEL.send(input_listener::Input::DpadRight).await;
-
-        Timer::after(DPAD_DEBOUNCE).await;
-    }
+    listen_for_edges(
+        &mut dpad_right,
+        Control::DpadRight,
+        input_listener::Input::DpadRight,
+        None,
+    )
+    .await
 }
@@ -3,6 +3,7 @@ use embassy_sync::{
     blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel,
 };
 use embassy_time::{Duration, Timer};
+#[cfg(feature = "esp32s3")]
 use esp_hal::gpio;
 
 use crate::settings;
@@ -27,6 +28,7 @@ pub enum BuzzerCommand {
     Click,
 }
 
+#[cfg(feature = "esp32s3")]
 #[embassy_executor::task]
 pub async fn buzzer_2k3_listener(
     mut buzzer_2k3: gpio::Output<'static>,
@@ -34,7 +36,7 @@ pub async fn buzzer_2k3_listener(
     loop {
         let cmd = BUZZER_2K3_CH.receive().await;
 
-        if settings::MUTE_SOUNDS {
+        if !settings::buzzers_audible() {
             warn!("Sounds are muted.");
             continue;
         }
@@ -50,6 +52,7 @@ pub async fn buzzer_2k3_listener(
     }
 }
 
+#[cfg(feature = "esp32s3")]
 #[embassy_executor::task]
 pub async fn buzzer_400_listener(
     mut buzzer_2k3: gpio::Output<'static>,
@@ -57,7 +60,7 @@ pub async fn buzzer_400_listener(
     loop {
         let cmd = BUZZER_400_CH.receive().await;
 
-        if settings::MUTE_SOUNDS {
+        if !settings::buzzers_audible() {
             warn!("Sounds are muted.");
             continue;
         }
@@ -73,6 +76,7 @@ pub async fn buzzer_400_listener(
     }
 }
 
+#[cfg(feature = "esp32s3")]
 async fn play(
     buzzer: &mut gpio::Output<'static>,
     duration: Duration,
@@ -0,0 +1,272 @@
+//! Integrating debouncer for the buttons, the D-pad and the encoder
+//! switches.
+//!
+//! Instead of sleeping for a fixed time after the first edge, the
+//! debouncer keeps a running total of how long the raw level has
+//! disagreed with the debounced one. Time spent at the other level
+//! adds to it, time spent back at the debounced level takes away
+//! from it, and the debounced level only flips once the total
+//! reaches the stabilization time for that direction. Bounce in
+//! either direction is filtered, and a tap only has to be as long as
+//! the press stabilization time to be seen.
+//!
+//! [`Debouncer`] is pure logic driven by pin levels and timestamps,
+//! so it can be tested on the host with bouncy traces. The GPIO tasks
+//! in `buttons` feed it.
+
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::{
+    Mutex, raw::CriticalSectionRawMutex,
+};
+use embassy_time::{Duration, Instant};
+
+use crate::input_listener::events::{Control, Edge};
+
+/// Indexed by `Control as usize`.
+static DEBOUNCE_CONFIGS: Mutex<
+    CriticalSectionRawMutex,
+    Cell<[DebounceConfig; Control::COUNT]>,
+> = Mutex::new(Cell::new(DEFAULT_CONFIGS));
+
+const DEFAULT_CONFIGS: [DebounceConfig; Control::COUNT] = {
+    let mut configs = [DebounceConfig::BUTTON; Control::COUNT];
+    configs[Control::DpadTop as usize] = DebounceConfig::DPAD;
+    configs[Control::DpadBottom as usize] = DebounceConfig::DPAD;
+    configs[Control::DpadLeft as usize] = DebounceConfig::DPAD;
+    configs[Control::DpadRight as usize] = DebounceConfig::DPAD;
+    configs
+};
+
+/// How long an input has to settle before it counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DebounceConfig {
+    /// How long the pin has to read pressed before a press counts.
+    pub press: Duration,
+    /// How long the pin has to read released before a release
+    /// counts. Contacts usually bounce for longer on release.
+    pub release: Duration,
+}
+
+impl DebounceConfig {
+    /// The big buttons and the encoder switches.
+    pub const BUTTON: Self = Self {
+        press: Duration::from_millis(10),
+        release: Duration::from_millis(30),
+    };
+
+    pub const DPAD: Self = Self {
+        press: Duration::from_millis(8),
+        release: Duration::from_millis(25),
+    };
+}
+
+pub fn debounce_config(control: Control) -> DebounceConfig {
+    DEBOUNCE_CONFIGS.lock(|configs| configs.get()[control as usize])
+}
+
+/// Changes the timing of `control`. Takes effect from its next edge.
+pub fn set_debounce_config(control: Control, config: DebounceConfig) {
+    DEBOUNCE_CONFIGS.lock(|configs| {
+        let mut all = configs.get();
+        all[control as usize] = config;
+        configs.set(all);
+    });
+}
+
+/// Puts back the default timing of every control.
+pub fn reset_debounce_configs() {
+    DEBOUNCE_CONFIGS.lock(|configs| configs.set(DEFAULT_CONFIGS));
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Debouncer {
+    /// The debounced level, true is pressed.
+    pressed: bool,
+    /// The raw level since `last_at`.
+    raw: bool,
+    last_at: Instant,
+    /// How long the raw level has disagreed with `pressed`, net.
+    unsettled: Duration,
+}
+
+impl Debouncer {
+    /// Starts out settled at the current level of the pin.
+    pub const fn new(pressed: bool, at: Instant) -> Self {
+        Self {
+            pressed,
+            raw: pressed,
+            last_at: at,
+            unsettled: Duration::from_ticks(0),
+        }
+    }
+
+    pub const fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// Takes the level of the pin at `at`, which has to be after the
+    /// last update. Returns the edge if the debounced level flipped.
+    /// Has to be called at `deadline` as well when nothing else
+    /// happens before it.
+    pub fn update(
+        &mut self,
+        config: &DebounceConfig,
+        raw: bool,
+        at: Instant,
+    ) -> Option<Edge> {
+        let elapsed =
+            at.as_ticks().saturating_sub(self.last_at.as_ticks());
+        let unsettled = self.unsettled.as_ticks();
+
+        // the level since the last update decides which way it went
+        let unsettled = if self.raw != self.pressed {
+            unsettled.saturating_add(elapsed)
+        } else {
+            unsettled.saturating_sub(elapsed)
+        };
+
+        self.raw = raw;
+        self.last_at = at;
+        self.unsettled = Duration::from_ticks(unsettled);
+
+        if unsettled < self.settle_time(config).as_ticks() {
+            return None;
+        }
+
+        self.pressed = !self.pressed;
+        self.unsettled = Duration::from_ticks(0);
+
+        Some(if self.pressed {
+            Edge::Press
+        } else {
+            Edge::Release
+        })
+    }
+
+    /// When the debounced level will flip if the pin stays where it
+    /// is, None if it is not going to.
+    pub fn deadline(
+        &self,
+        config: &DebounceConfig,
+    ) -> Option<Instant> {
+        if self.raw == self.pressed {
+            return None;
+        }
+
+        let remaining = self
+            .settle_time(config)
+            .as_ticks()
+            .saturating_sub(self.unsettled.as_ticks());
+
+        Some(Instant::from_ticks(
+            self.last_at.as_ticks().saturating_add(remaining),
+        ))
+    }
+
+    fn settle_time(&self, config: &DebounceConfig) -> Duration {
+        if self.pressed {
+            config.release
+        } else {
+            config.press
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 10 ms to press and 30 ms to release.
+    const CONFIG: DebounceConfig = DebounceConfig::BUTTON;
+
+    /// Runs `trace`, a list of (milliseconds, raw level), through a
+    /// debouncer that starts released, calling it at every deadline
+    /// in between like the GPIO tasks do. Returns the debounced edges
+    /// as (milliseconds, pressed).
+    fn run_trace(trace: &[(u64, bool)]) -> Vec<(u64, bool)> {
+        let mut debouncer =
+            Debouncer::new(false, Instant::from_ticks(0));
+        let mut edges = Vec::new();
+
+        // let whatever is left settle at the end
+        let settled =
+            (1_000, trace.last().is_some_and(|&(_, raw)| raw));
+        for &(ms, raw) in trace.iter().chain([&settled]) {
+            let at = Instant::from_millis(ms);
+            while let Some(deadline) = debouncer.deadline(&CONFIG)
+                && deadline <= at
+            {
+                let level = debouncer.raw;
+                if let Some(edge) =
+                    debouncer.update(&CONFIG, level, deadline)
+                {
+                    edges.push((
+                        deadline.as_millis(),
+                        edge == Edge::Press,
+                    ));
+                }
+            }
+
+            if let Some(edge) = debouncer.update(&CONFIG, raw, at) {
+                edges.push((ms, edge == Edge::Press));
+            }
+        }
+
+        edges
+    }
+
+    #[test]
+    fn bouncy_press_and_release_count_once() {
+        // A press that bounces for 4 ms, held for 100 ms, then a
+        // release that bounces for 12 ms. Each is counted once the
+        // level has been steady for long enough.
+        let edges = run_trace(&[
+            (0, true),
+            (1, false),
+            (2, true),
+            (3, false),
+            (4, true),
+            (100, false),
+            (103, true),
+            (105, false),
+            (110, true),
+            (112, false),
+        ]);
+        assert_eq!(edges.len(), 2, "{edges:?}");
+        assert!(edges[0].1 && !edges[1].1);
+        assert!((10..=14).contains(&edges[0].0), "{edges:?}");
+        assert!((130..=150).contains(&edges[1].0), "{edges:?}");
+    }
+
+    #[test]
+    fn quick_tap_counts() {
+        let edges = run_trace(&[(0, true), (25, false)]);
+        assert_eq!(edges.len(), 2, "{edges:?}");
+        assert!(edges[0].1 && !edges[1].1);
+    }
+
+    #[test]
+    fn noise_spikes_never_press() {
+        let edges = run_trace(&[
+            (0, true),
+            (3, false),
+            (20, true),
+            (23, false),
+            (40, true),
+            (43, false),
+        ]);
+        assert_eq!(edges, []);
+    }
+
+    #[test]
+    fn bounce_while_held_is_ignored() {
+        let edges = run_trace(&[
+            (0, true),
+            (50, false),
+            (55, true),
+            (200, false),
+        ]);
+        assert_eq!(edges.len(), 2, "{edges:?}");
+    }
+}
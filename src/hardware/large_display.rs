@@ -20,6 +20,7 @@
 //     tft.println("Hello from ESP32-S3", 100, 40);
 // }
 
+use display_interface::DisplayError;
 use embassy_futures::{
     select::{Either, select},
     yield_now,
@@ -34,8 +35,10 @@ use embedded_graphics::{
     prelude::*,
     text::Text,
 };
+#[cfg(feature = "esp32s3")]
 use ili9341::ModeState;
 
+#[cfg(feature = "esp32s3")]
 use crate::hardware::LargeDisplayType;
 
 struct PokemonSprite {
@@ -62,6 +65,7 @@ pub static LARGE_DISPLAY_CH: Channel<
     8,
 > = Channel::new();
 
+#[cfg(feature = "esp32s3")]
 use esp_hal::gpio::{self, Level};
 
 pub enum BacklightCommand {
@@ -94,6 +98,7 @@ pub enum LargeDisplayCommand {
     StopAnimation,
 }
 
+#[cfg(feature = "esp32s3")]
 #[embassy_executor::task]
 pub async fn backlight_listener(mut bl_pin: gpio::Output<'static>) {
     loop {
@@ -121,10 +126,103 @@ pub async fn backlight_listener(mut bl_pin: gpio::Output<'static>) {
     }
 }
 
+/// The drawing operations the large display listener needs. This is
+/// implemented by the ILI9341 on the board, and by the framebuffer of
+/// the simulator.
+pub trait LargeDisplayCanvas:
+    DrawTarget<Color = Rgb565, Error = DisplayError>
+{
+    fn clear_screen(
+        &mut self,
+        color: u16,
+    ) -> Result<(), DisplayError>;
+
+    fn fill_rect(
+        &mut self,
+        x: u16,
+        y: u16,
+        width: u16,
+        height: u16,
+        color: u16,
+    ) -> Result<(), DisplayError>;
+
+    fn set_display_on(
+        &mut self,
+        on: bool,
+    ) -> Result<(), DisplayError>;
+
+    fn set_inverted(
+        &mut self,
+        inverted: bool,
+    ) -> Result<(), DisplayError>;
+
+    fn set_brightness(
+        &mut self,
+        brightness: u8,
+    ) -> Result<(), DisplayError>;
+}
+
+#[cfg(feature = "esp32s3")]
+impl LargeDisplayCanvas for LargeDisplayType {
+    fn clear_screen(
+        &mut self,
+        color: u16,
+    ) -> Result<(), DisplayError> {
+        ili9341::Ili9341::clear_screen(self, color)
+    }
+
+    fn fill_rect(
+        &mut self,
+        x: u16,
+        y: u16,
+        width: u16,
+        height: u16,
+        color: u16,
+    ) -> Result<(), DisplayError> {
+        fill_rect(self, x, y, width, height, color)
+    }
+
+    fn set_display_on(
+        &mut self,
+        on: bool,
+    ) -> Result<(), DisplayError> {
+        self.display_mode(mode_state(on))
+    }
+
+    fn set_inverted(
+        &mut self,
+        inverted: bool,
+    ) -> Result<(), DisplayError> {
+        self.invert_mode(mode_state(inverted))
+    }
+
+    fn set_brightness(
+        &mut self,
+        brightness: u8,
+    ) -> Result<(), DisplayError> {
+        self.brightness(brightness)
+    }
+}
+
+#[cfg(feature = "esp32s3")]
+fn mode_state(on: bool) -> ModeState {
+    if on { ModeState::On } else { ModeState::Off }
+}
+
+#[cfg(feature = "esp32s3")]
 #[embassy_executor::task]
 pub async fn large_display_listener(
-    mut display: Option<LargeDisplayType>,
+    display: Option<LargeDisplayType>,
 ) {
+    run_large_display(display).await
+}
+
+/// Processes `LARGE_DISPLAY_CH` forever, drawing onto `display`. If
+/// there is no display, commands are still consumed so that senders
+/// never block.
+pub async fn run_large_display<D: LargeDisplayCanvas>(
+    mut display: Option<D>,
+) -> ! {
     let mut pokemon_frame: Option<(usize, usize)> = None;
 
     loop {
@@ -166,25 +264,26 @@ pub async fn large_display_listener(
 
         match cmd {
             LargeDisplayCommand::PlayPokemon(pokemon_id) => {
-                let Some(sprite_index) =
+                // only some of the Pokemon with a cry have a sprite,
+                // the rest get the bars on their own
+                let sprite_index =
                     POKEMON_SPRITES.iter().position(|sprite| {
                         sprite.pokemon_id == pokemon_id
-                    })
-                else {
-                    defmt::error!(
-                        "no sprite for Pokemon {}",
-                        pokemon_id
-                    );
-                    continue;
-                };
-                let sprite = &POKEMON_SPRITES[sprite_index];
-                pokemon_frame = Some((sprite_index, 0));
+                    });
+                pokemon_frame = sprite_index.map(|index| (index, 0));
                 if let Some(display) = display.as_mut() {
                     let result = match draw_bars(display).await {
-                        Ok(()) => {
-                            draw_pokemon_frame(display, sprite, 0)
+                        Ok(()) => match sprite_index {
+                            Some(index) => {
+                                draw_pokemon_frame(
+                                    display,
+                                    &POKEMON_SPRITES[index],
+                                    0,
+                                )
                                 .await
-                        }
+                            }
+                            None => Ok(()),
+                        },
                         Err(error) => Err(error),
                     };
                     if result.is_err() {
@@ -216,21 +315,21 @@ pub async fn large_display_listener(
                 width,
                 height,
                 color,
-            } => fill_rect(display, x, y, width, height, color),
+            } => display.fill_rect(x, y, width, height, color),
             LargeDisplayCommand::DisplayOn => {
-                display.display_mode(ModeState::On)
+                display.set_display_on(true)
             }
             LargeDisplayCommand::DisplayOff => {
-                display.display_mode(ModeState::Off)
+                display.set_display_on(false)
             }
             LargeDisplayCommand::InvertOn => {
-                display.invert_mode(ModeState::On)
+                display.set_inverted(true)
             }
             LargeDisplayCommand::InvertOff => {
-                display.invert_mode(ModeState::Off)
+                display.set_inverted(false)
             }
             LargeDisplayCommand::SetBrightness(brightness) => {
-                display.brightness(brightness)
+                display.set_brightness(brightness)
             }
             LargeDisplayCommand::DrawText90 {
                 text,
@@ -276,11 +375,11 @@ pub async fn large_display_listener(
     }
 }
 
-async fn draw_pokemon_frame(
-    display: &mut LargeDisplayType,
+async fn draw_pokemon_frame<D: LargeDisplayCanvas>(
+    display: &mut D,
     sprite: &PokemonSprite,
     frame_index: usize,
-) -> Result<(), ili9341::DisplayError> {
+) -> Result<(), DisplayError> {
     let start = sprite.offsets[frame_index] as usize;
     let end = sprite.offsets[frame_index + 1] as usize;
     let frame = &sprite.deltas[start..end];
@@ -324,7 +423,7 @@ async fn draw_pokemon_frame(
         if transparent {
             fill_bars_rect(display, x, y, width, height)?;
         } else {
-            fill_rect(display, x, y, width, height, color)?;
+            display.fill_rect(x, y, width, height, color)?;
         }
         yield_now().await;
     }
@@ -335,28 +434,34 @@ async fn draw_pokemon_frame(
 const BAR_WIDTH: u16 = 16;
 const BAR_GRAY: u16 = 0x8410;
 
-async fn draw_bars(
-    display: &mut LargeDisplayType,
-) -> Result<(), ili9341::DisplayError> {
+async fn draw_bars<D: LargeDisplayCanvas>(
+    display: &mut D,
+) -> Result<(), DisplayError> {
     for x in (0..240).step_by(BAR_WIDTH as usize) {
         let color = if (x / BAR_WIDTH) % 2 == 0 {
             BAR_GRAY
         } else {
             0
         };
-        fill_rect(display, x, 0, BAR_WIDTH.min(240 - x), 320, color)?;
+        display.fill_rect(
+            x,
+            0,
+            BAR_WIDTH.min(240 - x),
+            320,
+            color,
+        )?;
         yield_now().await;
     }
     Ok(())
 }
 
-fn fill_bars_rect(
-    display: &mut LargeDisplayType,
+fn fill_bars_rect<D: LargeDisplayCanvas>(
+    display: &mut D,
     x: u16,
     y: u16,
     width: u16,
     height: u16,
-) -> Result<(), ili9341::DisplayError> {
+) -> Result<(), DisplayError> {
     let x_end = x + width;
     let mut bar_x = x;
     while bar_x < x_end {
@@ -367,22 +472,24 @@ fn fill_bars_rect(
         } else {
             0
         };
-        fill_rect(display, bar_x, y, segment_width, height, color)?;
+        display.fill_rect(bar_x, y, segment_width, height, color)?;
         bar_x += segment_width;
     }
     Ok(())
 }
 
-struct RotatedScaledTarget<'a> {
-    display: &'a mut LargeDisplayType,
+struct RotatedScaledTarget<'a, D> {
+    display: &'a mut D,
     origin: Point,
     source_size: Size,
     scale: u32,
 }
 
-impl DrawTarget for RotatedScaledTarget<'_> {
+impl<D: LargeDisplayCanvas> DrawTarget
+    for RotatedScaledTarget<'_, D>
+{
     type Color = Rgb565;
-    type Error = ili9341::DisplayError;
+    type Error = DisplayError;
 
     fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
     where
@@ -408,12 +515,13 @@ impl DrawTarget for RotatedScaledTarget<'_> {
     }
 }
 
-impl OriginDimensions for RotatedScaledTarget<'_> {
+impl<D> OriginDimensions for RotatedScaledTarget<'_, D> {
     fn size(&self) -> Size {
         self.source_size
     }
 }
 
+#[cfg(feature = "esp32s3")]
 fn fill_rect(
     display: &mut LargeDisplayType,
     x: u16,
@@ -421,7 +529,7 @@ fn fill_rect(
     width: u16,
     height: u16,
     color: u16,
-) -> Result<(), ili9341::DisplayError> {
+) -> Result<(), DisplayError> {
     if width == 0 || height == 0 {
         return Ok(());
     }
@@ -1,3 +1,4 @@
+#[cfg(feature = "esp32s3")]
 use adv_shift_registers::wrappers::ShifterPin;
 //use either::Either::Left;
 use embassy_executor::{SendSpawner, task};
@@ -7,10 +8,13 @@ use embassy_sync::{
 };
 use embassy_time::{Duration, Timer};
 use embedded_hal::digital::OutputPin;
-use esp_println::{dbg, println};
 use static_cell::StaticCell;
 
+#[cfg(feature = "esp32s3")]
 use crate::hardware::LedShifterType;
+use crate::input_listener::{
+    acceleration::RotaryEncoder, events::Control,
+};
 
 pub enum RotationalDirection {
     Clockwise,
@@ -71,13 +75,37 @@ pub enum LED {
     DpadRight = 11,
 }
 
+impl LED {
+    /// The LED on or next to `control`. The encoder switches share
+    /// the amber LED of their encoder.
+    pub fn of_control(control: Control) -> Self {
+        match control {
+            Control::ButtonLeft => LED::ButtonLeft,
+            Control::ButtonRight => LED::ButtonRight,
+            Control::DpadTop => LED::DpadTop,
+            Control::DpadBottom => LED::DpadBottom,
+            Control::DpadLeft => LED::DpadLeft,
+            Control::DpadRight => LED::DpadRight,
+            Control::RotarySwitchLeft => LED::AmberLeft,
+            Control::RotarySwitchRight => LED::AmberRight,
+        }
+    }
+
+    pub fn of_encoder(encoder: RotaryEncoder) -> Self {
+        match encoder {
+            RotaryEncoder::Left => LED::AmberLeft,
+            RotaryEncoder::Right => LED::AmberRight,
+        }
+    }
+}
+
 // A wrapper over a pin so that it is possible to save state.
-struct PinWrapper {
-    shifter_pin: ShifterPin,
+struct PinWrapper<P> {
+    shifter_pin: P,
     value: bool,
 }
 
-impl PinWrapper {
+impl<P: OutputPin> PinWrapper<P> {
     fn set_high(&mut self) {
         self.value = true;
         let _ = self.shifter_pin.set_high();
@@ -110,76 +138,40 @@ async fn delay_temporary_high(
         .await;
 }
 
+#[cfg(feature = "esp32s3")]
 #[task]
 pub async fn led_shifter_listener(mut led_shifter: LedShifterType) {
     // array where the index of the LED (defined by the LED enum)
-    let mut led_array: [PinWrapper; 16] = [
-        PinWrapper {
-            shifter_pin: led_shifter.get_pin_mut(0, 0, true),
-            value: false,
-        },
-        PinWrapper {
-            shifter_pin: led_shifter.get_pin_mut(0, 1, true),
-            value: false,
-        },
-        PinWrapper {
-            shifter_pin: led_shifter.get_pin_mut(0, 2, true),
-            value: false,
-        },
-        PinWrapper {
-            shifter_pin: led_shifter.get_pin_mut(0, 3, true),
-            value: false,
-        },
-        PinWrapper {
-            shifter_pin: led_shifter.get_pin_mut(0, 4, true),
-            value: false,
-        },
-        PinWrapper {
-            shifter_pin: led_shifter.get_pin_mut(0, 5, true),
-            value: false,
-        },
-        PinWrapper {
-            shifter_pin: led_shifter.get_pin_mut(0, 6, true),
-            value: false,
-        },
-        PinWrapper {
-            shifter_pin: led_shifter.get_pin_mut(0, 7, true),
-            value: false,
-        },
-        PinWrapper {
-            shifter_pin: led_shifter.get_pin_mut(1, 0, true),
-            value: false,
-        },
-        PinWrapper {
-            shifter_pin: led_shifter.get_pin_mut(1, 1, true),
-            value: false,
-        },
-        PinWrapper {
-            shifter_pin: led_shifter.get_pin_mut(1, 2, true),
-            value: false,
-        },
-        PinWrapper {
-            shifter_pin: led_shifter.get_pin_mut(1, 3, true),
-            value: false,
-        },
-        PinWrapper {
-            shifter_pin: led_shifter.get_pin_mut(1, 4, true),
-            value: false,
-        },
-        PinWrapper {
-            shifter_pin: led_shifter.get_pin_mut(1, 5, true),
-            value: false,
-        },
-        PinWrapper {
-            shifter_pin: led_shifter.get_pin_mut(1, 6, true),
-            value: false,
-        },
-        PinWrapper {
-            shifter_pin: led_shifter.get_pin_mut(1, 7, true),
-            value: false,
-        },
+    let pins: [ShifterPin; 16] = [
+        led_shifter.get_pin_mut(0, 0, true),
+        led_shifter.get_pin_mut(0, 1, true),
+        led_shifter.get_pin_mut(0, 2, true),
+        led_shifter.get_pin_mut(0, 3, true),
+        led_shifter.get_pin_mut(0, 4, true),
+        led_shifter.get_pin_mut(0, 5, true),
+        led_shifter.get_pin_mut(0, 6, true),
+        led_shifter.get_pin_mut(0, 7, true),
+        led_shifter.get_pin_mut(1, 0, true),
+        led_shifter.get_pin_mut(1, 1, true),
+        led_shifter.get_pin_mut(1, 2, true),
+        led_shifter.get_pin_mut(1, 3, true),
+        led_shifter.get_pin_mut(1, 4, true),
+        led_shifter.get_pin_mut(1, 5, true),
+        led_shifter.get_pin_mut(1, 6, true),
+        led_shifter.get_pin_mut(1, 7, true),
     ];
 
+    run_led_shifter(pins).await
+}
+
+/// Processes `LED_SHIFTER_CHANNEL` forever. `pins` is indexed by the
+/// value of the LED enum.
+pub async fn run_led_shifter<P: OutputPin>(pins: [P; 16]) -> ! {
+    let mut led_array = pins.map(|shifter_pin| PinWrapper {
+        shifter_pin,
+        value: false,
+    });
+
     let mut send_spawner = SendSpawner::for_current_executor().await;
     let mut temporary_high_tokens = [0; 16];
 
@@ -231,9 +223,9 @@ pub async fn led_shifter_listener(mut led_shifter: LedShifterType) {
     // true);
 }
 
-fn execute_command(
+fn execute_command<P: OutputPin>(
     send_spawner: &mut SendSpawner,
-    led_array: &mut [PinWrapper; 16],
+    led_array: &mut [PinWrapper<P>; 16],
     temporary_high_tokens: &mut [u16; 16],
     command: LedCommand,
 ) {
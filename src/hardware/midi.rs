@@ -0,0 +1,141 @@
+//! Plays Standard MIDI Files on the waveform voices of the speaker.
+//!
+//! The files in `sounds/midi` are converted by `build.rs` into
+//! [`MIDI_FILES`]. Every track and channel is merged into one list of
+//! notes, each with the time since the one before it and how long it
+//! is held, so the device never has to read MIDI itself. Channel 10
+//! is left out, since its notes are drums rather than pitches.
+//!
+//! `midi_player` starts every note as its own sound on the user
+//! voices, in the waveform it was asked for. There are only as many
+//! notes at a time as there are user voices, and a note beyond that
+//! takes over the voice of the oldest one.
+
+use core::sync::atomic::{AtomicBool, Ordering::Relaxed};
+
+use embassy_futures::select::{Either, select};
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel,
+};
+use embassy_time::{Duration, Instant, Timer};
+
+use super::{
+    sequencer::note_frequency,
+    speaker::{
+        SPEAKER_CHANNEL, SpeakerCommand, Waveform,
+        envelope::Envelope,
+        filter::Filter,
+        mixer::{Sound, SoundId, VoiceGroup},
+    },
+};
+
+pub static MIDI_FILES: &[MidiFile] =
+    include!(concat!(env!("OUT_DIR"), "/midi.rs"));
+
+static MIDI_CH: Channel<CriticalSectionRawMutex, MidiCommand, 2> =
+    Channel::new();
+
+static MIDI_PLAYING: AtomicBool = AtomicBool::new(false);
+
+/// The gain of a note at full velocity.
+const FULL_VELOCITY_GAIN: f32 = 0.8;
+
+pub struct MidiFile {
+    pub name: &'static str,
+    pub events: &'static [MidiEvent],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MidiEvent {
+    /// Since the previous event started.
+    pub delay_ms: u16,
+    /// The MIDI note number.
+    pub pitch: u8,
+    /// From 1 to 127. Events with a velocity of 0 play nothing, and
+    /// only fill gaps too long for `delay_ms`.
+    pub velocity: u8,
+    /// How long the note is held.
+    pub length_ms: u16,
+}
+
+enum MidiCommand {
+    Play(&'static MidiFile, Waveform),
+    Stop,
+}
+
+/// Plays `file` in `waveform`, replacing whatever file was playing.
+pub fn play_midi(file: &'static MidiFile, waveform: Waveform) {
+    let _ = MIDI_CH.try_send(MidiCommand::Play(file, waveform));
+}
+
+pub fn stop_midi() {
+    let _ = MIDI_CH.try_send(MidiCommand::Stop);
+}
+
+pub fn is_midi_playing() -> bool {
+    MIDI_PLAYING.load(Relaxed)
+}
+
+/// Plays the files sent with `play_midi`.
+#[embassy_executor::task]
+pub async fn midi_player() {
+    let mut next = MIDI_CH.receive().await;
+
+    loop {
+        let MidiCommand::Play(file, waveform) = next else {
+            next = MIDI_CH.receive().await;
+            continue;
+        };
+
+        MIDI_PLAYING.store(true, Relaxed);
+        let interrupted =
+            select(play_events(file, waveform), MIDI_CH.receive())
+                .await;
+        MIDI_PLAYING.store(false, Relaxed);
+
+        next = match interrupted {
+            Either::First(()) => MIDI_CH.receive().await,
+            Either::Second(command) => {
+                // cut off the notes that were playing
+                SPEAKER_CHANNEL.send(SpeakerCommand::Silence).await;
+                command
+            }
+        };
+    }
+}
+
+async fn play_events(file: &'static MidiFile, waveform: Waveform) {
+    // every event is timed from the start, so that they do not drift
+    let mut next_event_at = Instant::now();
+    let mut last_note_end = next_event_at;
+
+    for event in file.events {
+        next_event_at += Duration::from_millis(event.delay_ms as u64);
+        Timer::at(next_event_at).await;
+
+        if event.velocity == 0 {
+            continue;
+        }
+        let length = Duration::from_millis(event.length_ms as u64);
+        last_note_end = last_note_end.max(next_event_at + length);
+
+        SPEAKER_CHANNEL
+            .send(SpeakerCommand::Start {
+                id: SoundId::next(),
+                group: VoiceGroup::User,
+                sound: Sound::Waveform {
+                    waveform,
+                    frequency_hz: note_frequency(event.pitch),
+                    duration: length,
+                    envelope: Envelope::GATE,
+                    filter: Filter::OFF,
+                },
+                gain: FULL_VELOCITY_GAIN * event.velocity as f32
+                    / 127.0,
+            })
+            .await;
+    }
+
+    // let the last notes finish
+    Timer::at(last_note_end).await;
+}
@@ -1,12 +1,16 @@
 #[allow(unused_imports)]
 use defmt::{error, info, warn};
+#[cfg(feature = "esp32s3")]
 use display_interface_spi::SPIInterface;
 use embassy_sync::{
     blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex,
 };
 use embassy_time::{Duration, Timer};
+#[cfg(feature = "esp32s3")]
 use embedded_hal::pwm::{ErrorType, SetDutyCycle};
+#[cfg(feature = "esp32s3")]
 use embedded_hal_bus::spi::{ExclusiveDevice, NoDelay};
+#[cfg(feature = "esp32s3")]
 use esp_hal::{
     clock::CpuClock,
     gpio::{
@@ -24,47 +28,69 @@ use esp_hal::{
     time::Rate,
     timer::timg::TimerGroup,
 };
+#[cfg(feature = "esp32s3")]
 use esp_hal_smartled::{SmartLedsAdapter, buffer_size};
+#[cfg(feature = "esp32s3")]
 #[allow(unused_imports)]
 use esp_println::println;
+#[cfg(feature = "esp32s3")]
 use ili9341::{DisplaySize240x320, Ili9341, Orientation};
+#[cfg(feature = "esp32s3")]
 use lcd_ili9341_spi::{Lcd, LcdOrientation, rgb_to_u16};
 use smart_leds::{RGB8, SmartLedsWrite};
+#[cfg(feature = "esp32s3")]
 use ssd1306::{I2CDisplayInterface, Ssd1306Async, prelude::*};
 use static_cell::StaticCell;
 
 pub mod buttons;
 pub mod buzzer;
+pub mod debounce;
 pub mod large_display;
 pub mod led_shifter;
+#[cfg(feature = "esp32s3")]
 pub mod leds;
+pub mod midi;
 pub mod mono_display;
+#[cfg(feature = "esp32s3")]
 pub mod neopixel;
+pub mod quadrature;
+pub mod rtttl;
+pub mod sequencer;
 pub mod speaker;
 pub mod thumbwheel;
 
+#[cfg(feature = "esp32s3")]
 use mono_display::MonoDisplay;
 
+#[cfg(feature = "esp32s3")]
 pub type ButtonType =
     Mutex<CriticalSectionRawMutex, Option<Input<'static>>>;
+#[cfg(feature = "esp32s3")]
 pub static RIGHT_BUTTON: ButtonType = Mutex::new(None);
+#[cfg(feature = "esp32s3")]
 pub static LEFT_BUTTON: ButtonType = Mutex::new(None);
 
+#[cfg(feature = "esp32s3")]
 pub type ButtonLEDType =
     Mutex<CriticalSectionRawMutex, Option<Output<'static>>>;
 //pub static RIGHT_BUTTON_LED: ButtonLEDType = Mutex::new(None);
 //pub static LEFT_BUTTON_LED: ButtonLEDType = Mutex::new(None);
 
+#[cfg(feature = "esp32s3")]
 pub type BuzzerType =
     Mutex<CriticalSectionRawMutex, Option<Output<'static>>>;
 //pub static BUZZER_400: BuzzerType = Mutex::new(None);
 //pub static BUZZER_2K3: BuzzerType = Mutex::new(None);
 
+#[cfg(feature = "esp32s3")]
 pub type RotarySwitchType =
     Mutex<CriticalSectionRawMutex, Option<Input<'static>>>;
+#[cfg(feature = "esp32s3")]
 pub static ROTARY_SWITCH_LEFT: RotarySwitchType = Mutex::new(None);
+#[cfg(feature = "esp32s3")]
 pub static ROTARY_SWITCH_RIGHT: RotarySwitchType = Mutex::new(None);
 
+#[cfg(feature = "esp32s3")]
 use esp_hal::{
     self,
     delay::Delay,
@@ -84,15 +110,19 @@ use esp_hal::{
 // pub static LED_ARRAY: [&'static LEDType; 5] =
 //     [&RED_LED, &GREEN_LED, &BLUE_LED, &YELLOW_LED, &WHITE_LED];
 
+#[cfg(feature = "esp32s3")]
 pub type MonoDisplayType =
     Mutex<CriticalSectionRawMutex, Option<MonoDisplay>>;
 // pub static DISPLAY: MonoDisplayType = Mutex::new(None);
+#[cfg(feature = "esp32s3")]
 static BAR: static_cell::StaticCell<MonoDisplayType> =
     static_cell::StaticCell::new();
 
+#[cfg(feature = "esp32s3")]
 pub type LedShifterType =
     adv_shift_registers::AdvancedShiftRegister<2, Output<'static>>;
 
+#[cfg(feature = "esp32s3")]
 pub type LargeDisplayType = Ili9341<
     SPIInterface<
         ExclusiveDevice<
@@ -105,12 +135,15 @@ pub type LargeDisplayType = Ili9341<
     Output<'static>,
 >;
 
+#[cfg(feature = "esp32s3")]
 struct DummyPwm(Output<'static>);
 
+#[cfg(feature = "esp32s3")]
 impl ErrorType for DummyPwm {
     type Error = core::convert::Infallible;
 }
 
+#[cfg(feature = "esp32s3")]
 impl SetDutyCycle for DummyPwm {
     fn max_duty_cycle(&self) -> u16 {
         255
@@ -129,6 +162,7 @@ impl SetDutyCycle for DummyPwm {
     }
 }
 
+#[cfg(feature = "esp32s3")]
 pub struct NonMutexPeripherals {
     pub mono_display: mono_display::DisplayType,
     pub left_rotary_a: Input<'static>,
@@ -153,6 +187,7 @@ pub struct NonMutexPeripherals {
 
 /// Initializes peripherals and assigns them to their respective
 /// mutexes.
+#[cfg(feature = "esp32s3")]
 pub async fn init_peripherals(
     peripherals: Peripherals,
 ) -> NonMutexPeripherals {
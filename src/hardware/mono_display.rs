@@ -6,12 +6,14 @@ use embassy_sync::{
 };
 use embassy_time::{Duration, Timer};
 use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+#[cfg(feature = "esp32s3")]
 use esp_hal::{
     i2c::master::{Config as I2cConfig, I2c},
     peripherals::{GPIO6, GPIO7, GPIO21, GPIO35, I2C0},
     time::Rate,
 };
 use heapless::String;
+#[cfg(feature = "esp32s3")]
 use ssd1306::{
     I2CDisplayInterface, Ssd1306Async,
     mode::{BufferedGraphicsModeAsync, TerminalModeAsync},
@@ -19,6 +21,7 @@ use ssd1306::{
 };
 
 pub const MONO_DISPLAY_LINE_WIDTH: usize = 16;
+#[cfg(feature = "esp32s3")]
 const I2C_FREQUENCY_KHZ: u32 = 400;
 
 /// A channel to send commands to the display.
@@ -28,6 +31,7 @@ pub static MONO_DISPLAY_CH: Channel<
     20,
 > = Channel::new();
 
+#[cfg(feature = "esp32s3")]
 pub type DisplayType = Ssd1306Async<
     I2CInterface<I2c<'static, esp_hal::Async>>,
     DisplaySize128x64,
@@ -53,6 +57,7 @@ pub enum MonoDisplayCommand {
 // For whatever reason, the compiler requires static here. I am
 // assuming something in the code below contains something that is
 // 'static.
+#[cfg(feature = "esp32s3")]
 pub fn init(
     i2c0: I2C0<'static>,
     scl: GPIO35<'static>,
@@ -80,6 +85,7 @@ pub fn init(
     .into_buffered_graphics_mode()
 }
 
+#[cfg(feature = "esp32s3")]
 #[embassy_executor::task]
 pub async fn display_task(mut display: MonoDisplay) {
     loop {
@@ -101,12 +107,14 @@ pub async fn display_task(mut display: MonoDisplay) {
     }
 }
 
+#[cfg(feature = "esp32s3")]
 pub type GraphicsMonoDisplayType = Ssd1306Async<
     I2CInterface<I2c<'static, esp_hal::Async>>,
     DisplaySize128x64,
     BufferedGraphicsModeAsync<DisplaySize128x64>,
 >;
 
+#[cfg(feature = "esp32s3")]
 pub type TerminalMonoDisplayType = Ssd1306Async<
     I2CInterface<I2c<'static, esp_hal::Async>>,
     DisplaySize128x64,
@@ -116,11 +124,13 @@ pub type TerminalMonoDisplayType = Ssd1306Async<
 /// The struct that represents the 128x64 i2s display. An enum is used
 /// as the display needs to be able to switch between terminal
 /// and graphics mode while the program is running.
+#[cfg(feature = "esp32s3")]
 pub enum MonoDisplay {
     Graphics(GraphicsMonoDisplayType),
     Terminal(TerminalMonoDisplayType),
 }
 
+#[cfg(feature = "esp32s3")]
 impl MonoDisplay {
     pub async fn to_terminal(self) -> Self {
         match self {
@@ -156,6 +166,7 @@ impl MonoDisplay {
     }
 }
 
+#[cfg(feature = "esp32s3")]
 impl MonoDisplay {
     async fn cmd_init(&mut self) {
         loop {
@@ -0,0 +1,238 @@
+//! Full-step decoder for the rotary encoders.
+//!
+//! The encoders go through all four quadrature states for every
+//! detent, and rest with both contacts open. A detent is only counted
+//! once the encoder is back at rest, so contact bounce part way
+//! through a step (which goes back and forth between two neighbouring
+//! states) cancels itself out instead of being counted as extra
+//! steps.
+//!
+//! A transition that skips a state is invalid, as there is no way to
+//! tell which way it went. These are counted as errors and otherwise
+//! ignored. If the rest of the step was seen, the detent still
+//! counts.
+//!
+//! Nothing here touches the hardware, the watcher tasks in
+//! `tasks::rotary` feed it pin levels.
+
+use core::sync::atomic::{AtomicU32, Ordering::SeqCst};
+
+use rotary_encoder_embedded::Direction;
+
+use crate::input_listener::acceleration::RotaryEncoder;
+
+/// The state the encoder sits in between detents, both contacts open.
+pub const REST_STATE: u8 = 0b00;
+
+/// How many invalid transitions each encoder has seen since boot.
+/// Indexed by `RotaryEncoder as usize`.
+static QUADRATURE_ERRORS: [AtomicU32; 2] =
+    [AtomicU32::new(0), AtomicU32::new(0)];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    Stay,
+    /// A quarter step clockwise.
+    Forward,
+    /// A quarter step anticlockwise.
+    Backward,
+    /// Both contacts changed at once.
+    Invalid,
+}
+
+use Transition::{Backward, Forward, Invalid, Stay};
+
+/// `TRANSITIONS[from][to]`, where a state is `(a << 1) | b` and a
+/// contact is 1 when it is closed. Clockwise goes
+/// 00 -> 01 -> 11 -> 10 -> 00.
+pub const TRANSITIONS: [[Transition; 4]; 4] = [
+    // from 00
+    [Stay, Forward, Backward, Invalid],
+    // from 01
+    [Backward, Stay, Invalid, Forward],
+    // from 10
+    [Forward, Invalid, Stay, Backward],
+    // from 11
+    [Invalid, Backward, Forward, Stay],
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct QuadratureDecoder {
+    state: u8,
+    /// Quarter steps taken since the encoder was last at rest.
+    quarter_steps: i8,
+    errors: u32,
+}
+
+impl QuadratureDecoder {
+    /// Starts from whatever state the contacts are in right now.
+    pub const fn new(a: bool, b: bool) -> Self {
+        Self {
+            state: state_of(a, b),
+            quarter_steps: 0,
+            errors: 0,
+        }
+    }
+
+    /// Takes the current contact levels, true is closed. Returns a
+    /// direction when a detent has been completed.
+    pub fn update(&mut self, a: bool, b: bool) -> Direction {
+        let next = state_of(a, b);
+
+        match TRANSITIONS[self.state as usize][next as usize] {
+            Stay => return Direction::None,
+            Forward => {
+                self.quarter_steps =
+                    self.quarter_steps.saturating_add(1)
+            }
+            Backward => {
+                self.quarter_steps =
+                    self.quarter_steps.saturating_sub(1)
+            }
+            Invalid => self.errors = self.errors.wrapping_add(1),
+        }
+
+        self.state = next;
+
+        if next != REST_STATE {
+            return Direction::None;
+        }
+
+        // At least half of the step has to have been seen going the
+        // same way. Anything less is bounce around the rest state.
+        let quarter_steps = core::mem::take(&mut self.quarter_steps);
+        if quarter_steps >= 2 {
+            Direction::Clockwise
+        } else if quarter_steps <= -2 {
+            Direction::Anticlockwise
+        } else {
+            Direction::None
+        }
+    }
+
+    /// How many invalid transitions this decoder has seen.
+    pub fn errors(&self) -> u32 {
+        self.errors
+    }
+}
+
+const fn state_of(a: bool, b: bool) -> u8 {
+    ((a as u8) << 1) | b as u8
+}
+
+/// Adds to the error count of `encoder`, for the watcher tasks.
+pub fn record_quadrature_errors(encoder: RotaryEncoder, errors: u32) {
+    QUADRATURE_ERRORS[encoder as usize].fetch_add(errors, SeqCst);
+}
+
+/// How many invalid transitions `encoder` has seen since boot. A
+/// steadily climbing count points to a worn encoder or a bad
+/// connection.
+pub fn quadrature_errors(encoder: RotaryEncoder) -> u32 {
+    QUADRATURE_ERRORS[encoder as usize].load(SeqCst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Steps clockwise from rest, as `(a, b)`.
+    const CLOCKWISE: [(bool, bool); 4] =
+        [(false, true), (true, true), (true, false), (false, false)];
+
+    /// Feeds `levels` to a decoder starting at rest and returns the
+    /// detents it counted, clockwise positive, and its errors.
+    fn feed(levels: &[(bool, bool)]) -> (i32, u32) {
+        let mut decoder = QuadratureDecoder::new(false, false);
+        let mut detents = 0;
+        for &(a, b) in levels {
+            match decoder.update(a, b) {
+                Direction::Clockwise => detents += 1,
+                Direction::Anticlockwise => detents -= 1,
+                Direction::None => {}
+            }
+        }
+        (detents, decoder.errors())
+    }
+
+    #[test]
+    fn transitions_are_symmetric() {
+        for (from, row) in TRANSITIONS.iter().enumerate() {
+            for (to, &transition) in row.iter().enumerate() {
+                assert!(
+                    matches!(
+                        (transition, TRANSITIONS[to][from]),
+                        (Stay, Stay)
+                            | (Forward, Backward)
+                            | (Backward, Forward)
+                            | (Invalid, Invalid)
+                    ),
+                    "{from:02b} -> {to:02b} is not undone by the \
+                     opposite move"
+                );
+                assert_eq!(
+                    transition == Stay,
+                    from == to,
+                    "only staying put can be a stay"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn four_steps_forward_is_a_full_cycle() {
+        let mut state = REST_STATE as usize;
+        for steps in 1..=4 {
+            state = (0..4)
+                .find(|&next| TRANSITIONS[state][next] == Forward)
+                .unwrap();
+            assert_eq!(state == REST_STATE as usize, steps == 4);
+        }
+    }
+
+    #[test]
+    fn clean_detents() {
+        assert_eq!(feed(&CLOCKWISE), (1, 0));
+
+        let mut anticlockwise = CLOCKWISE;
+        anticlockwise[..3].reverse();
+        assert_eq!(feed(&anticlockwise), (-1, 0));
+
+        assert_eq!(feed(&[CLOCKWISE, CLOCKWISE].concat()), (2, 0));
+    }
+
+    #[test]
+    fn bounce_cancels_out() {
+        // A opens and closes again half way through a detent.
+        let bouncing = [
+            (false, true),
+            (true, true),
+            (false, true),
+            (true, true),
+            (true, false),
+            (false, false),
+        ];
+        assert_eq!(feed(&bouncing), (1, 0));
+
+        // A bounces at rest without the encoder being turned.
+        let at_rest = [
+            (true, false),
+            (false, false),
+            (true, false),
+            (false, false),
+        ];
+        assert_eq!(feed(&at_rest), (0, 0));
+    }
+
+    #[test]
+    fn skipped_states_are_errors() {
+        // 11 was missed, but the rest of the detent was seen.
+        let skipped_one =
+            [(false, true), (true, false), (false, false)];
+        assert_eq!(feed(&skipped_one), (1, 1));
+
+        // Nothing tells which way this went.
+        let skipped_two = [(true, true), (false, false)];
+        assert_eq!(feed(&skipped_two), (0, 2));
+    }
+}
@@ -0,0 +1,442 @@
+//! Plays ringtones in the RTTTL format of old Nokia phones:
+//!
+//! ```text
+//! Nokia:d=4,o=5,b=225:8e6,8d6,f#,g#,8c#6,8b,d,e,8b,8a,c#,e,2a
+//! ```
+//!
+//! A name, then the defaults (`d` the note value, `o` the octave and
+//! `b` the tempo in quarter notes per minute), then the notes. Each
+//! note is an optional value, a letter (`p` is a rest), an optional
+//! `#`, an optional octave and an optional `.` to make it half as
+//! long again.
+//!
+//! [`Rtttl`] parses a ringtone into the same notes the sequencer
+//! plays, without allocating.
+//!
+//! The ringtones in `sounds/ringtones` are bundled by `build.rs` into
+//! [`RINGTONES`], and `ringtone_player` plays them on the speaker.
+
+use core::sync::atomic::{AtomicBool, Ordering::Relaxed};
+
+use embassy_futures::select::{Either, select};
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel,
+};
+use embassy_time::{Instant, Timer};
+
+use super::{
+    sequencer::{Note, note_frequency},
+    speaker::{SPEAKER_CHANNEL, SpeakerCommand, Waveform},
+};
+
+/// Every ringtone in `sounds/ringtones`, in filename order.
+pub const RINGTONES: &[&str] =
+    include!(concat!(env!("OUT_DIR"), "/ringtones.rs"));
+
+static RINGTONE_CH: Channel<
+    CriticalSectionRawMutex,
+    RingtoneCommand,
+    2,
+> = Channel::new();
+
+static RINGTONE_PLAYING: AtomicBool = AtomicBool::new(false);
+
+/// How much of its length a note is held for, like in the sequencer.
+const HOLD_NUMERATOR: u32 = 7;
+const HOLD_DENOMINATOR: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RtttlError {
+    /// The name, defaults and notes have to be split by colons.
+    MissingSection,
+    /// A default that is not `d`, `o` or `b`, or has a bad value.
+    BadDefault,
+    /// The note starting at this byte could not be read.
+    BadNote(usize),
+}
+
+/// A parsed ringtone. Iterating over it gives its notes.
+#[derive(Debug, Clone, Copy)]
+pub struct Rtttl<'a> {
+    name: &'a str,
+    tempo_bpm: u16,
+    /// The note value of notes without one, as a division of a whole
+    /// note.
+    default_division: u16,
+    default_octave: u8,
+    text: &'a [u8],
+    /// The byte the next note starts at.
+    position: usize,
+}
+
+impl<'a> Rtttl<'a> {
+    /// Reads the name and defaults of `text`. The notes are read as
+    /// they are asked for.
+    pub fn parse(text: &'a str) -> Result<Self, RtttlError> {
+        let bytes = text.as_bytes();
+
+        let Some(name_end) = find(bytes, 0, b':') else {
+            return Err(RtttlError::MissingSection);
+        };
+        let Some(defaults_end) = find(bytes, name_end + 1, b':')
+        else {
+            return Err(RtttlError::MissingSection);
+        };
+
+        let name =
+            match core::str::from_utf8(bytes.split_at(name_end).0) {
+                Ok(name) => name.trim_ascii(),
+                Err(_) => return Err(RtttlError::MissingSection),
+            };
+
+        let mut rtttl = Self {
+            name,
+            tempo_bpm: 63,
+            default_division: 4,
+            default_octave: 6,
+            text: bytes,
+            position: defaults_end + 1,
+        };
+
+        let mut position = name_end + 1;
+        while position < defaults_end {
+            position = skip_whitespace(bytes, position);
+            if position >= defaults_end {
+                break;
+            }
+
+            let key = bytes[position].to_ascii_lowercase();
+            position = skip_whitespace(bytes, position + 1);
+            if position >= defaults_end || bytes[position] != b'=' {
+                return Err(RtttlError::BadDefault);
+            }
+
+            let (value, end) = read_number(bytes, position + 1);
+            let Some(value) = value else {
+                return Err(RtttlError::BadDefault);
+            };
+
+            match key {
+                b'd' if is_division(value) => {
+                    rtttl.default_division = value
+                }
+                b'o' if value <= 8 => {
+                    rtttl.default_octave = value as u8
+                }
+                b'b' if value > 0 => rtttl.tempo_bpm = value,
+                _ => return Err(RtttlError::BadDefault),
+            }
+
+            position = skip_whitespace(bytes, end);
+            if position < defaults_end {
+                if bytes[position] != b',' {
+                    return Err(RtttlError::BadDefault);
+                }
+                position += 1;
+            }
+        }
+
+        Ok(rtttl)
+    }
+
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Quarter notes per minute.
+    pub fn tempo_bpm(&self) -> u16 {
+        self.tempo_bpm
+    }
+
+    /// Reads the next note. Nothing more is read after an error.
+    pub fn next_note(&mut self) -> Option<Result<Note, RtttlError>> {
+        let text = self.text;
+        let start = skip_whitespace(text, self.position);
+        if start >= text.len() {
+            return None;
+        }
+
+        let (division, mut position) = read_number(text, start);
+        let division = match division {
+            Some(division) if is_division(division) => division,
+            Some(_) => return self.fail(start),
+            None => self.default_division,
+        };
+
+        if position >= text.len() {
+            return self.fail(start);
+        }
+        let semitone: i16 = match text[position].to_ascii_lowercase()
+        {
+            b'c' => 0,
+            b'd' => 2,
+            b'e' => 4,
+            b'f' => 5,
+            b'g' => 7,
+            b'a' => 9,
+            b'b' | b'h' => 11,
+            b'p' => -1,
+            _ => return self.fail(start),
+        };
+        position += 1;
+
+        let mut sharp = false;
+        if position < text.len() && text[position] == b'#' {
+            sharp = true;
+            position += 1;
+        }
+
+        // the dot is allowed on either side of the octave
+        let mut dotted = false;
+        if position < text.len() && text[position] == b'.' {
+            dotted = true;
+            position += 1;
+        }
+
+        let (octave, end) = read_number(text, position);
+        position = end;
+        let octave = match octave {
+            Some(octave) if octave <= 8 => octave as i16,
+            Some(_) => return self.fail(start),
+            None => self.default_octave as i16,
+        };
+
+        if position < text.len() && text[position] == b'.' {
+            dotted = true;
+            position += 1;
+        }
+
+        position = skip_whitespace(text, position);
+        if position < text.len() {
+            if text[position] != b',' {
+                return self.fail(start);
+            }
+            position += 1;
+        }
+        self.position = position;
+
+        let length = 64 / division;
+        let length =
+            if dotted { length + length / 2 } else { length };
+
+        let pitch = if semitone < 0 {
+            None
+        } else {
+            // C4 is middle C, MIDI note 60
+            let midi = (octave + 1) * 12 + semitone + sharp as i16;
+            if midi > 127 {
+                return self.fail(start);
+            }
+            Some(midi as u8)
+        };
+
+        Some(Ok(Note { pitch, length }))
+    }
+
+    fn fail(
+        &mut self,
+        at: usize,
+    ) -> Option<Result<Note, RtttlError>> {
+        self.position = self.text.len();
+        Some(Err(RtttlError::BadNote(at)))
+    }
+}
+
+impl Iterator for Rtttl<'_> {
+    type Item = Result<Note, RtttlError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_note()
+    }
+}
+
+fn find(bytes: &[u8], from: usize, byte: u8) -> Option<usize> {
+    bytes
+        .get(from..)?
+        .iter()
+        .position(|other| *other == byte)
+        .map(|index| from + index)
+}
+
+fn skip_whitespace(bytes: &[u8], from: usize) -> usize {
+    let mut index = from;
+    while bytes.get(index).is_some_and(u8::is_ascii_whitespace) {
+        index += 1;
+    }
+    index
+}
+
+/// Reads the decimal number starting at `from`. Returns None if there
+/// are no digits there, and the index after the number.
+fn read_number(bytes: &[u8], from: usize) -> (Option<u16>, usize) {
+    let mut index = from;
+    let mut value: u16 = 0;
+
+    while index < bytes.len() && bytes[index].is_ascii_digit() {
+        value = value
+            .saturating_mul(10)
+            .saturating_add((bytes[index] - b'0') as u16);
+        index += 1;
+    }
+
+    if index == from {
+        (None, index)
+    } else {
+        (Some(value), index)
+    }
+}
+
+fn is_division(value: u16) -> bool {
+    matches!(value, 1 | 2 | 4 | 8 | 16 | 32 | 64)
+}
+
+enum RingtoneCommand {
+    Play(&'static str, Waveform),
+    Stop,
+}
+
+/// Plays `ringtone` in `waveform`, replacing whatever ringtone was
+/// playing.
+pub fn play_ringtone(ringtone: &'static str, waveform: Waveform) {
+    let _ = RINGTONE_CH
+        .try_send(RingtoneCommand::Play(ringtone, waveform));
+}
+
+pub fn stop_ringtone() {
+    let _ = RINGTONE_CH.try_send(RingtoneCommand::Stop);
+}
+
+pub fn is_ringtone_playing() -> bool {
+    RINGTONE_PLAYING.load(Relaxed)
+}
+
+/// Plays the ringtones sent with `play_ringtone`, one note at a time
+/// through `SpeakerCommand::PlayWaveform`.
+#[embassy_executor::task]
+pub async fn ringtone_player() {
+    let mut next = RINGTONE_CH.receive().await;
+
+    loop {
+        let RingtoneCommand::Play(ringtone, waveform) = next else {
+            next = RINGTONE_CH.receive().await;
+            continue;
+        };
+
+        RINGTONE_PLAYING.store(true, Relaxed);
+        let interrupted = select(
+            play_notes(ringtone, waveform),
+            RINGTONE_CH.receive(),
+        )
+        .await;
+        RINGTONE_PLAYING.store(false, Relaxed);
+
+        next = match interrupted {
+            Either::First(()) => RINGTONE_CH.receive().await,
+            Either::Second(command) => {
+                // cut off the note that was playing
+                SPEAKER_CHANNEL.send(SpeakerCommand::Silence).await;
+                command
+            }
+        };
+    }
+}
+
+async fn play_notes(ringtone: &'static str, waveform: Waveform) {
+    let Ok(rtttl) = Rtttl::parse(ringtone) else {
+        return;
+    };
+    let tempo_bpm = rtttl.tempo_bpm();
+
+    // every note is timed from the start, so that they do not drift
+    let mut next_note_at = Instant::now();
+
+    for note in rtttl {
+        let Ok(note) = note else {
+            return;
+        };
+        let duration = note.duration(tempo_bpm);
+
+        if let Some(pitch) = note.pitch {
+            SPEAKER_CHANNEL
+                .send(SpeakerCommand::PlayWaveform {
+                    waveform,
+                    frequency_hz: note_frequency(pitch) as u16,
+                    duration: duration * HOLD_NUMERATOR
+                        / HOLD_DENOMINATOR,
+                })
+                .await;
+        }
+
+        next_note_at += duration;
+        Timer::at(next_note_at).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(
+        pitch: Option<u8>,
+        length: u16,
+    ) -> Option<Result<Note, RtttlError>> {
+        Some(Ok(Note { pitch, length }))
+    }
+
+    #[test]
+    fn bundled_ringtones_parse() {
+        for ringtone in RINGTONES {
+            let rtttl = Rtttl::parse(ringtone).unwrap();
+            for note in rtttl {
+                assert!(note.is_ok(), "{ringtone}: {note:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn notes() {
+        // the defaults, sharps, dots on either side of the octave,
+        // rests and the end
+        let mut rtttl = Rtttl::parse(
+            " Test : d=8, o=5, b=100 : c, 4a#4, e.6, g6., 16p",
+        )
+        .unwrap();
+        assert_eq!(rtttl.tempo_bpm(), 100);
+        assert_eq!(rtttl.name(), "Test");
+        assert_eq!(rtttl.next_note(), note(Some(72), 8));
+        assert_eq!(rtttl.next_note(), note(Some(70), 16));
+        assert_eq!(rtttl.next_note(), note(Some(88), 12));
+        assert_eq!(rtttl.next_note(), note(Some(91), 12));
+        assert_eq!(rtttl.next_note(), note(None, 4));
+        assert_eq!(rtttl.next_note(), None);
+    }
+
+    #[test]
+    fn missing_defaults() {
+        // d=4, o=6, b=63
+        let mut rtttl = Rtttl::parse("x::a").unwrap();
+        assert_eq!(rtttl.tempo_bpm(), 63);
+        assert_eq!(rtttl.next_note(), note(Some(93), 16));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Rtttl::parse("no sections").err(),
+            Some(RtttlError::MissingSection)
+        );
+        assert_eq!(
+            Rtttl::parse("x:d=3:a").err(),
+            Some(RtttlError::BadDefault)
+        );
+
+        // nothing more is read after a bad note
+        let mut rtttl = Rtttl::parse("x:d=4:a,q,b").unwrap();
+        assert!(matches!(rtttl.next_note(), Some(Ok(_))));
+        assert_eq!(
+            rtttl.next_note(),
+            Some(Err(RtttlError::BadNote(8)))
+        );
+        assert_eq!(rtttl.next_note(), None);
+    }
+}
@@ -0,0 +1,240 @@
+//! Plays songs, note by note, on the speaker or one of the buzzers.
+//!
+//! Songs are written as text in `sounds/songs/*.song` and compiled
+//! into [`SONGS`] by `build.rs`, named after their files:
+//!
+//! ```text
+//! # comments start with a hash
+//! tempo 180          # quarter notes per minute, 120 by default
+//! waveform square    # sine, square, saw or triangle
+//! C5/8 E5/8 G5/8 r/8 C6/4.
+//! ```
+//!
+//! A note is a pitch and an octave (C4 is middle C) with an optional
+//! `#` or `b`, or `r` for a rest. After a `/` comes the note value (8
+//! is an eighth note) and a trailing `.` makes the note half as long
+//! again. Notes without a value are quarter notes.
+//!
+//! Songs are queued with [`play_song`] or [`play_system_song`], which
+//! return straight away, and played by `sequencer_task`. System songs
+//! and app songs each have their own queue, and a new song replaces
+//! the one playing in the same queue.
+
+use embassy_futures::{
+    join::join,
+    select::{Either, select},
+};
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel,
+};
+use embassy_time::{Duration, Instant, Timer};
+
+use super::{
+    buzzer::{BUZZER_2K3_CH, BUZZER_400_CH, BuzzerCommand},
+    speaker::{
+        SPEAKER_CHANNEL, SpeakerCommand, Waveform,
+        envelope::Envelope,
+        filter::Filter,
+        mixer::{Sound, SoundId, VoiceGroup},
+    },
+};
+
+pub static SONGS: &[Song] =
+    include!(concat!(env!("OUT_DIR"), "/songs.rs"));
+
+static SYSTEM_SONG_CH: Channel<
+    CriticalSectionRawMutex,
+    SequencerCommand,
+    2,
+> = Channel::new();
+
+static USER_SONG_CH: Channel<
+    CriticalSectionRawMutex,
+    SequencerCommand,
+    2,
+> = Channel::new();
+
+/// How much of its length a note is held for. The rest is left
+/// silent so that repeated notes can be told apart.
+const HOLD_NUMERATOR: u32 = 7;
+const HOLD_DENOMINATOR: u32 = 8;
+
+/// The frequencies of the notes in the lowest MIDI octave, C-1 to
+/// B-1. Every octave up doubles them.
+const LOWEST_OCTAVE_HZ: [f32; 12] = [
+    8.175_799, 8.661_957, 9.177_024, 9.722_718, 10.300_861,
+    10.913_382, 11.562_326, 12.249_857, 12.978_272, 13.75,
+    14.567_618, 15.433_853,
+];
+
+pub struct Song {
+    pub name: &'static str,
+    /// Quarter notes per minute.
+    pub tempo_bpm: u16,
+    /// What the song sounds like on the speaker.
+    pub waveform: Waveform,
+    pub notes: &'static [Note],
+}
+
+impl Song {
+    /// How long `note` lasts at the tempo of the song.
+    pub fn note_duration(&self, note: &Note) -> Duration {
+        note.duration(self.tempo_bpm)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Note {
+    /// The MIDI note number, None for a rest.
+    pub pitch: Option<u8>,
+    /// In 64th notes.
+    pub length: u16,
+}
+
+impl Note {
+    /// How long the note lasts at `tempo_bpm` quarter notes per
+    /// minute.
+    pub fn duration(&self, tempo_bpm: u16) -> Duration {
+        // a quarter note is 16 64th notes
+        Duration::from_micros(
+            self.length as u64 * 3_750_000 / tempo_bpm.max(1) as u64,
+        )
+    }
+}
+
+/// The frequency of MIDI note `pitch` in equal temperament.
+pub fn note_frequency(pitch: u8) -> f32 {
+    let octave = pitch / 12;
+    LOWEST_OCTAVE_HZ[(pitch % 12) as usize] * (1u32 << octave) as f32
+}
+
+/// The bundled song named `name`, which is its filename without the
+/// extension.
+pub fn find_song(name: &str) -> Option<&'static Song> {
+    SONGS.iter().find(|song| song.name == name)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SongOutput {
+    Speaker,
+    /// The buzzers only have one pitch each, so they only follow the
+    /// rhythm.
+    Buzzer2k3,
+    Buzzer400,
+}
+
+enum SequencerCommand {
+    Play(&'static Song, SongOutput),
+    Stop,
+}
+
+/// Queues `song` for an app. It plays on the user voices, so system
+/// sounds duck it.
+pub fn play_song(song: &'static Song, output: SongOutput) {
+    let _ =
+        USER_SONG_CH.try_send(SequencerCommand::Play(song, output));
+}
+
+/// Stops the song an app is playing.
+pub fn stop_song() {
+    let _ = USER_SONG_CH.try_send(SequencerCommand::Stop);
+}
+
+/// Queues `song` for a system event, like starting up. It plays on
+/// the system voices of the speaker.
+pub fn play_system_song(song: &'static Song) {
+    let _ = SYSTEM_SONG_CH
+        .try_send(SequencerCommand::Play(song, SongOutput::Speaker));
+}
+
+/// Plays the songs sent with `play_song` and `play_system_song`.
+#[embassy_executor::task]
+pub async fn sequencer_task() {
+    join(
+        run_queue(&SYSTEM_SONG_CH, VoiceGroup::System),
+        run_queue(&USER_SONG_CH, VoiceGroup::User),
+    )
+    .await;
+}
+
+async fn run_queue(
+    channel: &Channel<CriticalSectionRawMutex, SequencerCommand, 2>,
+    group: VoiceGroup,
+) -> ! {
+    let mut next = channel.receive().await;
+
+    loop {
+        let SequencerCommand::Play(song, output) = next else {
+            next = channel.receive().await;
+            continue;
+        };
+
+        let id = SoundId::next();
+        match select(
+            play_notes(song, output, id, group),
+            channel.receive(),
+        )
+        .await
+        {
+            Either::First(()) => next = channel.receive().await,
+            Either::Second(command) => {
+                if output == SongOutput::Speaker {
+                    SPEAKER_CHANNEL
+                        .send(SpeakerCommand::StopSound(id))
+                        .await;
+                }
+                next = command;
+            }
+        }
+    }
+}
+
+async fn play_notes(
+    song: &'static Song,
+    output: SongOutput,
+    id: SoundId,
+    group: VoiceGroup,
+) {
+    // every note is timed from the start of the song, so that they
+    // do not drift
+    let mut next_note_at = Instant::now();
+
+    for note in song.notes {
+        let duration = song.note_duration(note);
+        let hold = duration * HOLD_NUMERATOR / HOLD_DENOMINATOR;
+
+        if let Some(pitch) = note.pitch {
+            match output {
+                SongOutput::Speaker => {
+                    SPEAKER_CHANNEL
+                        .send(SpeakerCommand::Start {
+                            id,
+                            group,
+                            sound: Sound::Waveform {
+                                waveform: song.waveform,
+                                frequency_hz: note_frequency(pitch),
+                                duration: hold,
+                                envelope: Envelope::GATE,
+                                filter: Filter::OFF,
+                            },
+                            gain: 0.8,
+                        })
+                        .await;
+                }
+                SongOutput::Buzzer2k3 => {
+                    BUZZER_2K3_CH
+                        .send(BuzzerCommand::Play(hold))
+                        .await
+                }
+                SongOutput::Buzzer400 => {
+                    BUZZER_400_CH
+                        .send(BuzzerCommand::Play(hold))
+                        .await
+                }
+            }
+        }
+
+        next_note_at += duration;
+        Timer::at(next_note_at).await;
+    }
+}
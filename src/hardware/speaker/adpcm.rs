@@ -0,0 +1,381 @@
+//! IMA ADPCM, which stores every 16 bit sample in 4 bits as the
+//! difference from the one before it.
+//!
+//! The bundled sounds are encoded by `build.rs`, which includes this
+//! file as well, and decoded by the mixer as it plays them. They are
+//! mono, at `SPEAKER_SAMPLE_RATE`, in blocks of [`ADPCM_BLOCK_SIZE`]
+//! bytes, like the IMA ADPCM in `.wav` files:
+//!
+//! ```text
+//! first sample (i16 le), step index (u8), 0 (u8), 4 bit codes
+//! ```
+//!
+//! The codes hold two samples a byte, the earlier one in the low
+//! nibble. The last block is cut short after its last sample. Every
+//! block starts over from its own header, so a decoder can start at
+//! any of them.
+
+pub const ADPCM_BLOCK_SIZE: usize = 256;
+
+const HEADER_SIZE: usize = 4;
+
+/// The sample in the header, then two for every other byte.
+pub const SAMPLES_PER_BLOCK: usize =
+    1 + (ADPCM_BLOCK_SIZE - HEADER_SIZE) * 2;
+
+const STEP_SIZES: [i16; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34,
+    37, 41, 45, 50, 55, 60, 66, 73, 80, 88, 97, 107, 118, 130, 143,
+    157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449, 494,
+    544, 598, 658, 724, 796, 876, 963, 1060, 1166, 1282, 1411, 1552,
+    1707, 1878, 2066, 2272, 2499, 2749, 3024, 3327, 3660, 4026, 4428,
+    4871, 5358, 5894, 6484, 7132, 7845, 8630, 9493, 10442, 11487,
+    12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086,
+    29794, 32767,
+];
+
+/// How far the step index moves after each code, by the size of the
+/// code without its sign.
+const INDEX_ADJUSTMENTS: [i8; 8] = [-1, -1, -1, -1, 2, 4, 6, 8];
+
+const MAX_STEP_INDEX: u8 = STEP_SIZES.len() as u8 - 1;
+
+/// What the encoder and decoder keep between samples.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AdpcmState {
+    pub predictor: i16,
+    pub step_index: u8,
+}
+
+impl AdpcmState {
+    /// The sample `code` stands for.
+    pub fn decode(&mut self, code: u8) -> i16 {
+        let step = STEP_SIZES[self.step_index as usize] as i32;
+
+        // step * (magnitude + 0.5) / 4, rounded the same way by every
+        // IMA ADPCM decoder
+        let mut difference = step >> 3;
+        if code & 4 != 0 {
+            difference += step;
+        }
+        if code & 2 != 0 {
+            difference += step >> 1;
+        }
+        if code & 1 != 0 {
+            difference += step >> 2;
+        }
+        if code & 8 != 0 {
+            difference = -difference;
+        }
+
+        self.predictor = (self.predictor as i32 + difference)
+            .clamp(i16::MIN as i32, i16::MAX as i32)
+            as i16;
+
+        let step_index = self.step_index as i8
+            + INDEX_ADJUSTMENTS[(code & 7) as usize];
+        self.step_index =
+            step_index.clamp(0, MAX_STEP_INDEX as i8) as u8;
+
+        self.predictor
+    }
+
+    /// The code that gets closest to `sample`. Moves on as `decode`
+    /// would, so that the two stay in step.
+    pub fn encode(&mut self, sample: i16) -> u8 {
+        let step = STEP_SIZES[self.step_index as usize] as i32;
+        let mut difference = sample as i32 - self.predictor as i32;
+
+        let mut code = 0;
+        if difference < 0 {
+            code = 8;
+            difference = -difference;
+        }
+        if difference >= step {
+            code |= 4;
+            difference -= step;
+        }
+        if difference >= step >> 1 {
+            code |= 2;
+            difference -= step >> 1;
+        }
+        if difference >= step >> 2 {
+            code |= 1;
+        }
+
+        self.decode(code);
+        code
+    }
+}
+
+/// Encodes up to `SAMPLES_PER_BLOCK` of `samples` into `block`, and
+/// returns how many bytes of it were used. `step_index` carries over
+/// from one block to the next, so that the start of a block does not
+/// have to adapt again.
+pub fn encode_block(
+    samples: &[i16],
+    step_index: &mut u8,
+    block: &mut [u8; ADPCM_BLOCK_SIZE],
+) -> usize {
+    if samples.is_empty() {
+        return 0;
+    }
+
+    let mut state = AdpcmState {
+        predictor: samples[0],
+        step_index: *step_index,
+    };
+    block[..2].copy_from_slice(&samples[0].to_le_bytes());
+    block[2] = state.step_index;
+    block[3] = 0;
+
+    let count = samples.len().min(SAMPLES_PER_BLOCK);
+    for (index, sample) in samples[..count].iter().enumerate().skip(1)
+    {
+        let code = state.encode(*sample);
+        let byte = HEADER_SIZE + (index - 1) / 2;
+        if index % 2 == 1 {
+            block[byte] = code;
+        } else {
+            block[byte] |= code << 4;
+        }
+    }
+
+    *step_index = state.step_index;
+    HEADER_SIZE + count / 2
+}
+
+/// How many samples `data` decodes to.
+pub fn sample_count(data: &[u8]) -> usize {
+    let full_blocks = data.len() / ADPCM_BLOCK_SIZE;
+    let rest = data.len() % ADPCM_BLOCK_SIZE;
+    let last_block = if rest >= HEADER_SIZE {
+        1 + (rest - HEADER_SIZE) * 2
+    } else {
+        0
+    };
+
+    full_blocks * SAMPLES_PER_BLOCK + last_block
+}
+
+/// Decodes ADPCM one sample at a time.
+#[derive(Clone, Copy, Debug)]
+pub struct AdpcmDecoder<'a> {
+    data: &'a [u8],
+    /// The byte the next code is in.
+    position: usize,
+    /// Whether the next code is in the high nibble.
+    high_nibble: bool,
+    state: AdpcmState,
+}
+
+impl<'a> AdpcmDecoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            position: 0,
+            high_nibble: false,
+            state: AdpcmState {
+                predictor: 0,
+                step_index: 0,
+            },
+        }
+    }
+
+    pub fn next_sample(&mut self) -> Option<i16> {
+        let data = self.data;
+
+        if self.position.is_multiple_of(ADPCM_BLOCK_SIZE) {
+            if self.position + HEADER_SIZE > data.len() {
+                return None;
+            }
+
+            let header = self.position;
+            let step_index = data[header + 2];
+            self.state = AdpcmState {
+                predictor: i16::from_le_bytes([
+                    data[header],
+                    data[header + 1],
+                ]),
+                step_index: step_index.min(MAX_STEP_INDEX),
+            };
+            self.position += HEADER_SIZE;
+            self.high_nibble = false;
+            return Some(self.state.predictor);
+        }
+
+        if self.position >= data.len() {
+            return None;
+        }
+
+        let byte = data[self.position];
+        let code = if self.high_nibble {
+            self.position += 1;
+            byte >> 4
+        } else {
+            byte & 0x0F
+        };
+        self.high_nibble = !self.high_nibble;
+
+        Some(self.state.decode(code))
+    }
+
+    /// How many samples `data` decodes to.
+    pub fn length(&self) -> usize {
+        sample_count(self.data)
+    }
+
+    /// How many samples have been decoded so far.
+    pub fn position(&self) -> usize {
+        let block = self.position / ADPCM_BLOCK_SIZE;
+        let offset = self.position % ADPCM_BLOCK_SIZE;
+        let in_block = if offset == 0 {
+            0
+        } else {
+            1 + (offset - HEADER_SIZE) * 2 + self.high_nibble as usize
+        };
+
+        block * SAMPLES_PER_BLOCK + in_block
+    }
+
+    /// Carries on from `sample`, or from the end if it is past it.
+    /// Decodes from the start of its block, since that is the closest
+    /// place the decoder can start from.
+    pub fn seek(&mut self, sample: usize) {
+        let sample = sample.min(self.length());
+
+        self.position = sample / SAMPLES_PER_BLOCK * ADPCM_BLOCK_SIZE;
+        self.high_nibble = false;
+
+        let mut skip = sample % SAMPLES_PER_BLOCK;
+        while skip > 0 && self.next_sample().is_some() {
+            skip -= 1;
+        }
+    }
+}
+
+impl Iterator for AdpcmDecoder<'_> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        self.next_sample()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Three full blocks.
+    const LENGTH: usize = 3 * SAMPLES_PER_BLOCK;
+
+    /// A triangle wave with a period of `period` samples.
+    fn triangle(amplitude: i32, period: i32) -> Vec<i16> {
+        (0..LENGTH as i32)
+            .map(|index| {
+                let phase = index % period;
+                let rising =
+                    phase * 4 * amplitude / period - amplitude;
+                let sample = if rising > amplitude {
+                    3 * amplitude - phase * 4 * amplitude / period
+                } else {
+                    rising
+                };
+                sample as i16
+            })
+            .collect()
+    }
+
+    /// Encodes `samples` a block at a time, like `build.rs` does.
+    fn encode(samples: &[i16]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut step_index = 0;
+        for chunk in samples.chunks(SAMPLES_PER_BLOCK) {
+            let mut block = [0; ADPCM_BLOCK_SIZE];
+            let used =
+                encode_block(chunk, &mut step_index, &mut block);
+            data.extend_from_slice(&block[..used]);
+        }
+        data
+    }
+
+    /// Encodes `samples`, decodes them again and returns the largest
+    /// difference, after the first `settle` samples of every block.
+    fn round_trip_error(samples: &[i16], settle: usize) -> i32 {
+        let data = encode(samples);
+        assert_eq!(sample_count(&data), samples.len());
+
+        let decoded = AdpcmDecoder::new(&data).collect::<Vec<_>>();
+        assert_eq!(decoded.len(), samples.len());
+
+        decoded
+            .iter()
+            .zip(samples)
+            .enumerate()
+            .filter(|(index, _)| index % SAMPLES_PER_BLOCK >= settle)
+            .map(|(_, (decoded, sample))| {
+                (*decoded as i32 - *sample as i32).abs()
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn silence_stays_silent() {
+        assert_eq!(round_trip_error(&[0; LENGTH], 0), 0);
+    }
+
+    #[test]
+    fn loud_tone_is_within_one_percent() {
+        // once the step size has caught up with it at the start
+        let error = round_trip_error(&triangle(16_000, 101), 40);
+        assert!(error < i16::MAX as i32 / 100, "{error}");
+    }
+
+    #[test]
+    fn quiet_tone_is_within_a_few_steps() {
+        let error = round_trip_error(&triangle(200, 60), 20);
+        assert!(error < 16, "{error}");
+    }
+
+    #[test]
+    fn short_blocks_decode_every_sample() {
+        // and one more if the codes end halfway through a byte
+        let samples = triangle(1_000, 50);
+        let mut block = [0; ADPCM_BLOCK_SIZE];
+
+        let used = encode_block(&samples[..101], &mut 0, &mut block);
+        assert_eq!(used, HEADER_SIZE + 50);
+        assert_eq!(sample_count(&block[..used]), 101);
+
+        let used = encode_block(&samples[..100], &mut 0, &mut block);
+        assert_eq!(used, HEADER_SIZE + 50);
+        assert_eq!(sample_count(&block[..used]), 101);
+    }
+
+    #[test]
+    fn seeking_matches_decoding() {
+        // in the first block, in a later one and at the very end
+        let data =
+            encode(&triangle(3_000, 70)[..2 * SAMPLES_PER_BLOCK]);
+
+        for target in
+            [0, 7, SAMPLES_PER_BLOCK, SAMPLES_PER_BLOCK + 300]
+        {
+            let mut straight = AdpcmDecoder::new(&data);
+            while straight.position() < target {
+                straight.next_sample();
+            }
+
+            let mut seeked = AdpcmDecoder::new(&data);
+            seeked.seek(target);
+            assert_eq!(seeked.position(), target);
+            assert_eq!(seeked.next_sample(), straight.next_sample());
+        }
+
+        let mut end = AdpcmDecoder::new(&data);
+        end.seek(usize::MAX);
+        assert_eq!(end.position(), 2 * SAMPLES_PER_BLOCK);
+        assert_eq!(end.next_sample(), None);
+    }
+}
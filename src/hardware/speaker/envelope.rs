@@ -0,0 +1,142 @@
+//! Attack, decay, sustain and release envelopes for the waveform
+//! voices of the mixer.
+//!
+//! A note fades in over the attack to full level, falls over the
+//! decay to the sustain level, and stays there until it is let go,
+//! then fades out over the release. Without one, a waveform starts
+//! and stops at full amplitude, which clicks.
+
+use embassy_time::Duration;
+
+use super::duration_to_frames;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Envelope {
+    pub attack: Duration,
+    pub decay: Duration,
+    /// The level held after the decay, from 0.0 to 1.0.
+    pub sustain: f32,
+    pub release: Duration,
+}
+
+impl Envelope {
+    /// Just long enough at either end not to click. For tones that
+    /// should sound like they were switched on and off.
+    pub const GATE: Self = Self {
+        attack: Duration::from_millis(3),
+        decay: Duration::from_ticks(0),
+        sustain: 1.0,
+        release: Duration::from_millis(15),
+    };
+
+    /// A soft attack that settles a little below full level and
+    /// rings out after it is let go.
+    pub const PLUCK: Self = Self {
+        attack: Duration::from_millis(10),
+        decay: Duration::from_millis(150),
+        sustain: 0.7,
+        release: Duration::from_millis(200),
+    };
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Self::GATE
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// Where a voice is in its envelope.
+#[derive(Clone, Copy, Debug)]
+pub struct EnvelopeState {
+    stage: Stage,
+    level: f32,
+    sustain: f32,
+    /// How much the level changes every frame in each stage.
+    attack_step: f32,
+    decay_step: f32,
+    release_step: f32,
+}
+
+impl EnvelopeState {
+    /// Starts a note from silence.
+    pub fn new(envelope: &Envelope) -> Self {
+        let mut state = Self {
+            stage: Stage::Attack,
+            level: 0.0,
+            sustain: 0.0,
+            attack_step: 0.0,
+            decay_step: 0.0,
+            release_step: 0.0,
+        };
+        state.retrigger(envelope);
+        state
+    }
+
+    /// Switches to `envelope` without jumping in level. A note that
+    /// is being held carries on where it is, and one that is being
+    /// released rises again from wherever it has got to.
+    pub fn retrigger(&mut self, envelope: &Envelope) {
+        let sustain = envelope.sustain.clamp(0.0, 1.0);
+
+        self.sustain = sustain;
+        self.attack_step = 1.0 / frames(envelope.attack);
+        self.decay_step = (1.0 - sustain) / frames(envelope.decay);
+        self.release_step = 1.0 / frames(envelope.release);
+
+        if self.stage == Stage::Release {
+            self.stage = Stage::Attack;
+        }
+    }
+
+    /// Lets go of the note, which then fades out over the release.
+    pub fn release(&mut self) {
+        self.stage = Stage::Release;
+    }
+
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+
+    /// The level of the next frame, or None once the release has
+    /// finished.
+    pub fn next_level(&mut self) -> Option<f32> {
+        match self.stage {
+            Stage::Attack => {
+                self.level += self.attack_step;
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.level -= self.decay_step;
+                if self.level <= self.sustain {
+                    self.level = self.sustain;
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => self.level = self.sustain,
+            Stage::Release => {
+                self.level -= self.release_step;
+                if self.level <= 0.0 {
+                    self.level = 0.0;
+                    return None;
+                }
+            }
+        }
+
+        Some(self.level)
+    }
+}
+
+fn frames(duration: Duration) -> f32 {
+    duration_to_frames(duration).max(1) as f32
+}
@@ -0,0 +1,287 @@
+//! A resonant filter for the waveform voices of the mixer.
+//!
+//! The filter is a state-variable filter, integrated with the
+//! trapezoidal rule so that it stays stable and in tune right up to
+//! the highest cutoff. It lets through everything below the cutoff
+//! in [`FilterMode::LowPass`] or everything above it in
+//! [`FilterMode::HighPass`], falling away at 12 dB an octave past it.
+//! Resonance makes it ring at the cutoff, which is what gives a synth
+//! its wah.
+//!
+//! Changing the cutoff or the resonance of a [`FilterState`] keeps
+//! what it has integrated so far, so a sweep does not click.
+
+use core::f32::consts::{FRAC_1_SQRT_2, PI};
+
+use super::SPEAKER_SAMPLE_RATE;
+
+/// The lowest cutoff, below which a waveform is mostly silence.
+pub const MIN_CUTOFF_HZ: f32 = 40.0;
+
+/// The highest cutoff. Any higher and the cutoff is too close to
+/// half the sample rate to follow.
+pub const MAX_CUTOFF_HZ: f32 = 12_000.0;
+
+/// The Q at a resonance of 0.0, which is as flat as the filter can
+/// be without ringing.
+const MIN_Q: f32 = FRAC_1_SQRT_2;
+
+/// The Q at a resonance of 1.0.
+const MAX_Q: f32 = 8.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterMode {
+    /// Lets everything through unchanged.
+    Off,
+    LowPass,
+    HighPass,
+}
+
+impl FilterMode {
+    pub const COUNT: usize = 3;
+
+    pub fn index(self) -> usize {
+        match self {
+            Self::Off => 0,
+            Self::LowPass => 1,
+            Self::HighPass => 2,
+        }
+    }
+
+    pub fn from_index(index: usize) -> Self {
+        match index % Self::COUNT {
+            0 => Self::Off,
+            1 => Self::LowPass,
+            _ => Self::HighPass,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::LowPass => "Low pass",
+            Self::HighPass => "High pass",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Filter {
+    pub mode: FilterMode,
+    /// From `MIN_CUTOFF_HZ` to `MAX_CUTOFF_HZ`.
+    pub cutoff_hz: f32,
+    /// From 0.0, which does not ring at all, to 1.0.
+    pub resonance: f32,
+}
+
+impl Filter {
+    pub const OFF: Self = Self {
+        mode: FilterMode::Off,
+        cutoff_hz: MAX_CUTOFF_HZ,
+        resonance: 0.0,
+    };
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::OFF
+    }
+}
+
+/// A filter and what it has integrated so far.
+#[derive(Clone, Copy, Debug)]
+pub struct FilterState {
+    mode: FilterMode,
+    /// 1/Q, how quickly the ringing dies away.
+    damping: f32,
+    /// How much every sample moves the integrators, from the cutoff
+    /// and the damping.
+    a1: f32,
+    a2: f32,
+    a3: f32,
+    /// Brings the ringing of a resonant filter back down to about
+    /// the level of the waveform.
+    level: f32,
+    /// The two integrators.
+    band: f32,
+    low: f32,
+}
+
+impl FilterState {
+    /// Starts `filter` from silence.
+    pub fn new(filter: &Filter) -> Self {
+        let mut state = Self {
+            mode: FilterMode::Off,
+            damping: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+            a3: 0.0,
+            level: 1.0,
+            band: 0.0,
+            low: 0.0,
+        };
+        state.retune(filter);
+        state
+    }
+
+    /// Switches to `filter`, carrying on from what has been
+    /// integrated so far.
+    pub fn retune(&mut self, filter: &Filter) {
+        let cutoff_hz =
+            filter.cutoff_hz.clamp(MIN_CUTOFF_HZ, MAX_CUTOFF_HZ);
+        let resonance = filter.resonance.clamp(0.0, 1.0);
+
+        // the cutoff the trapezoidal rule needs to land on the one
+        // asked for
+        let g = micromath::F32Ext::tan(
+            PI * cutoff_hz / SPEAKER_SAMPLE_RATE as f32,
+        );
+        let damping = 1.0 / (MIN_Q + (MAX_Q - MIN_Q) * resonance);
+
+        self.mode = filter.mode;
+        self.damping = damping;
+        self.a1 = 1.0 / (1.0 + g * (g + damping));
+        self.a2 = g * self.a1;
+        self.a3 = g * self.a2;
+        self.level = 1.0 / (1.0 + 2.0 * resonance);
+    }
+
+    /// Filters the next sample. Both integrators keep running while
+    /// the filter is off, so switching it on does not click.
+    pub fn process(&mut self, input: f32) -> f32 {
+        let v3 = input - self.low;
+        let v1 = self.a1 * self.band + self.a2 * v3;
+        let v2 = self.low + self.a2 * self.band + self.a3 * v3;
+        self.band = 2.0 * v1 - self.band;
+        self.low = 2.0 * v2 - self.low;
+
+        match self.mode {
+            FilterMode::Off => input,
+            FilterMode::LowPass => v2 * self.level,
+            FilterMode::HighPass => {
+                (input - self.damping * v1 - v2) * self.level
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAMES: usize = 4096;
+
+    /// How long the filter is given to settle before it is measured.
+    const SETTLE_FRAMES: usize = 2048;
+
+    /// sin(2π `phase`) of every frame of a sine at `frequency_hz`.
+    fn sine(frequency_hz: f32) -> impl Iterator<Item = f32> {
+        let step = frequency_hz / SPEAKER_SAMPLE_RATE as f32;
+        (0..FRAMES)
+            .map(move |index| {
+                (index as f32 * step).fract() * 2.0 * PI
+            })
+            .map(f32::sin)
+    }
+
+    /// Plays a sine at `frequency_hz` through `filter` and returns
+    /// the peak it settles down to, compared to the sine.
+    fn gain(filter: Filter, frequency_hz: f32) -> f32 {
+        let mut state = FilterState::new(&filter);
+        sine(frequency_hz)
+            .map(|input| state.process(input))
+            .skip(SETTLE_FRAMES)
+            .fold(0.0, |peak, output| output.abs().max(peak))
+    }
+
+    fn example(mode: FilterMode, resonance: f32) -> Filter {
+        Filter {
+            mode,
+            cutoff_hz: 1000.0,
+            resonance,
+        }
+    }
+
+    #[test]
+    fn off_is_unchanged() {
+        let mut state =
+            FilterState::new(&example(FilterMode::Off, 1.0));
+        for input in sine(1_234.0).take(100) {
+            assert_eq!(state.process(input), input);
+        }
+    }
+
+    #[test]
+    fn low_pass() {
+        // without resonance, it lets through a decade below the
+        // cutoff, is 3 dB down at the cutoff, and takes out three
+        // octaves above it
+        let filter = example(FilterMode::LowPass, 0.0);
+        assert!((gain(filter, 100.0) - 1.0).abs() < 0.02);
+        assert!((gain(filter, 1000.0) - FRAC_1_SQRT_2).abs() < 0.02);
+        assert!(gain(filter, 8000.0) < 1.0 / 64.0);
+    }
+
+    #[test]
+    fn high_pass() {
+        // the low pass the other way round, give or take the cutoff
+        // landing about 1% low, as `micromath` rounds off tan
+        let filter = example(FilterMode::HighPass, 0.0);
+        assert!((gain(filter, 10_000.0) - 1.0).abs() < 0.02);
+        assert!((gain(filter, 1000.0) - FRAC_1_SQRT_2).abs() < 0.02);
+        assert!(gain(filter, 125.0) < 1.0 / 60.0);
+    }
+
+    #[test]
+    fn resonance_rings_at_the_cutoff() {
+        // at Q times the level, and still takes out what is far
+        // from it. The peak is sharp enough that the cutoff landing
+        // about 1% low takes a little off it.
+        let ringing = MAX_Q / 3.0;
+        let low = example(FilterMode::LowPass, 1.0);
+        let high = example(FilterMode::HighPass, 1.0);
+        assert!((gain(low, 1000.0) - ringing).abs() < 0.1);
+        assert!((gain(high, 1000.0) - ringing).abs() < 0.1);
+        assert!(gain(low, 8000.0) < 1.0 / 64.0);
+        assert!(gain(high, 125.0) < 1.0 / 64.0);
+    }
+
+    #[test]
+    fn stable_at_the_highest_cutoff_and_resonance() {
+        let filter = Filter {
+            mode: FilterMode::LowPass,
+            cutoff_hz: MAX_CUTOFF_HZ,
+            resonance: 1.0,
+        };
+        assert!(gain(filter, MAX_CUTOFF_HZ) < MAX_Q);
+        assert!(gain(filter, 20_000.0) < 1.0);
+    }
+
+    #[test]
+    fn sweeps_carry_on_smoothly() {
+        // a semitone at a time in the middle of a note. Starting over
+        // from silence every time would jump by as much as the level
+        // of the note.
+        let mut filter = example(FilterMode::LowPass, 0.5);
+        let mut state = FilterState::new(&filter);
+        let mut previous = 0.0;
+        let mut largest_step = 0.0f32;
+
+        for (index, input) in sine(440.0).enumerate() {
+            if index >= SETTLE_FRAMES && index % 256 == 0 {
+                filter.cutoff_hz *= 1.059_463_1;
+                state.retune(&filter);
+            }
+
+            let output = state.process(input);
+            if index >= SETTLE_FRAMES / 2 {
+                largest_step =
+                    largest_step.max((output - previous).abs());
+            }
+            previous = output;
+        }
+
+        // as far as the note itself moves from one frame to the next
+        assert!(largest_step < 0.05, "{largest_step}");
+    }
+}
@@ -0,0 +1,728 @@
+//! Mixes several sounds into the one stream the speaker plays.
+//!
+//! The mixer has a fixed number of voices. The first two are for
+//! system sounds and the others are for sounds the apps play
+//! themselves, so a menu sound never has to wait for a cry or a synth
+//! tone to finish. Each voice plays either PCM or a waveform at its
+//! own gain, and a waveform can go through a `filter`. Every block,
+//! the voices are summed in 32 bits and saturated back to 16 bits, so
+//! loud sounds playing at the same time clip instead of wrapping
+//! around.
+//!
+//! While a system voice is playing, the user voices are ducked, so a
+//! system sound can always be heard over whatever an app is playing.
+//! Everything is then scaled by `settings::master_gain`.
+//!
+//! Sounds started with `SpeakerCommand::Start` get a [`SoundId`],
+//! which can be used to stop or replace them later on whichever voice
+//! they ended up on. PCM and ADPCM sounds can also be paused, moved
+//! to another point, played faster or slower, or set to start over
+//! when they reach the end.
+//! How far through every sound is gets published after each block,
+//! see [`sound_position`]. See `system` and `user` for the APIs on
+//! top.
+
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicU32, Ordering::Relaxed},
+};
+
+use embassy_sync::blocking_mutex::{
+    Mutex, raw::CriticalSectionRawMutex,
+};
+use embassy_time::Duration;
+
+use super::{
+    SPEAKER_SAMPLE_RATE, SpeakerCommand, WAVEFORM_AMPLITUDE,
+    Waveform,
+    adpcm::AdpcmDecoder,
+    duration_to_frames,
+    envelope::{Envelope, EnvelopeState},
+    filter::{Filter, FilterState},
+    speed::{FrameSource, Speed, SpeedMode},
+    waveform_sample,
+};
+use crate::settings;
+
+pub const VOICE_COUNT: usize = 6;
+
+/// How many frames are mixed at a time. Every frame is a left and a
+/// right 16 bit sample.
+pub const MIX_BLOCK_FRAMES: usize = 512;
+
+/// The size of a mixed block in bytes.
+pub const MIX_BLOCK_SIZE: usize = MIX_BLOCK_FRAMES * 4;
+
+/// How many frames are summed on the stack at a time.
+const MIX_CHUNK_FRAMES: usize = 128;
+
+/// The gain of the user voices while a system voice is playing.
+const DUCKED_GAIN: f32 = 0.3;
+
+/// How much the ducking gain can change from one frame to the next,
+/// so that ducking fades over about 10 ms instead of clicking.
+const DUCK_STEP: f32 = (1.0 - DUCKED_GAIN) / 441.0;
+
+/// How much the master gain can change from one frame to the next,
+/// so that muting fades over about 10 ms.
+const MASTER_STEP: f32 = 1.0 / 441.0;
+
+static NEXT_SOUND_ID: AtomicU32 = AtomicU32::new(0);
+
+/// The sound every voice is playing and how far through it is.
+/// Indexed by `Voice::index`.
+type PlayingSounds = [Option<(SoundId, SoundPosition)>; VOICE_COUNT];
+
+/// The sounds that were playing as of the last mixed block.
+static PLAYING_SOUNDS: Mutex<
+    CriticalSectionRawMutex,
+    Cell<PlayingSounds>,
+> = Mutex::new(Cell::new([None; VOICE_COUNT]));
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VoiceGroup {
+    System,
+    User,
+}
+
+/// One of the voices of the mixer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Voice(u8);
+
+impl Voice {
+    pub const SYSTEM: [Voice; 2] = [Voice(0), Voice(1)];
+    pub const USER: [Voice; 4] =
+        [Voice(2), Voice(3), Voice(4), Voice(5)];
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn group(self) -> VoiceGroup {
+        if Self::SYSTEM.contains(&self) {
+            VoiceGroup::System
+        } else {
+            VoiceGroup::User
+        }
+    }
+}
+
+/// Identifies a sound started with `SpeakerCommand::Start`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SoundId(u32);
+
+impl SoundId {
+    /// An id no other sound has had.
+    pub fn next() -> Self {
+        Self(NEXT_SOUND_ID.fetch_add(1, Relaxed))
+    }
+}
+
+/// How far through a sound its voice is.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SoundPosition {
+    /// The next frame to be played.
+    pub frame: u32,
+    /// How many frames the sound has. 0 for waveforms, which last
+    /// for as long as they are held.
+    pub length: u32,
+    pub paused: bool,
+    pub looping: bool,
+}
+
+/// Whether the sound `id` was still playing when the last block was
+/// mixed. A paused sound still counts as playing.
+pub fn is_sound_playing(id: SoundId) -> bool {
+    sound_position(id).is_some()
+}
+
+/// Where the sound `id` was when the last block was mixed, or None if
+/// it was not playing.
+pub fn sound_position(id: SoundId) -> Option<SoundPosition> {
+    PLAYING_SOUNDS.lock(|sounds| {
+        sounds.get().into_iter().flatten().find_map(
+            |(sound, position)| (sound == id).then_some(position),
+        )
+    })
+}
+
+/// Something a voice can play.
+#[derive(Clone, Copy, Debug)]
+pub enum Sound {
+    /// 16 bit stereo little endian PCM at `SPEAKER_SAMPLE_RATE`.
+    Pcm(&'static [u8]),
+    /// Mono IMA ADPCM at `SPEAKER_SAMPLE_RATE`, see `adpcm`. Played
+    /// on both sides.
+    Adpcm(&'static [u8]),
+    /// Holds the note for `duration`, then releases it.
+    Waveform {
+        waveform: Waveform,
+        frequency_hz: f32,
+        duration: Duration,
+        envelope: Envelope,
+        filter: Filter,
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Playback {
+    Idle,
+    Pcm {
+        samples: &'static [u8],
+        /// In bytes.
+        position: usize,
+    },
+    Adpcm(AdpcmDecoder<'static>),
+    Waveform {
+        waveform: Waveform,
+        frequency_hz: f32,
+        phase: f32,
+        /// How long until the note is released.
+        remaining_frames: u32,
+        envelope: EnvelopeState,
+        filter: FilterState,
+    },
+}
+
+/// PCM and ADPCM. Everything else has no frames.
+impl FrameSource for Playback {
+    fn next_frame(&mut self) -> Option<[i16; 2]> {
+        match self {
+            Playback::Pcm { samples, position } => {
+                let bytes = samples.get(*position..*position + 4)?;
+                *position += 4;
+
+                Some([
+                    i16::from_le_bytes([bytes[0], bytes[1]]),
+                    i16::from_le_bytes([bytes[2], bytes[3]]),
+                ])
+            }
+            Playback::Adpcm(decoder) => {
+                decoder.next_sample().map(|sample| [sample, sample])
+            }
+            _ => None,
+        }
+    }
+
+    fn frame(&self) -> u32 {
+        match self {
+            Playback::Pcm { position, .. } => (*position / 4) as u32,
+            Playback::Adpcm(decoder) => decoder.position() as u32,
+            _ => 0,
+        }
+    }
+
+    fn length(&self) -> u32 {
+        match self {
+            Playback::Pcm { samples, .. } => {
+                (samples.len() / 4) as u32
+            }
+            Playback::Adpcm(decoder) => decoder.length() as u32,
+            _ => 0,
+        }
+    }
+
+    fn seek(&mut self, frame: u32) {
+        let frame = frame.min(self.length());
+
+        match self {
+            Playback::Pcm { position, .. } => {
+                *position = frame as usize * 4
+            }
+            Playback::Adpcm(decoder) => decoder.seek(frame as usize),
+            _ => {}
+        }
+    }
+}
+
+/// Adds the next `mix.len() / 2` frames of PCM or ADPCM to `mix`, at
+/// `speed`, starting over at the end if `looping`. Returns true once
+/// it has all played.
+fn render_samples(
+    playback: &mut Playback,
+    speed: &mut Speed<Playback>,
+    mix: &mut [i32],
+    gain: f32,
+    looping: bool,
+) -> bool {
+    for frame in mix.chunks_exact_mut(2) {
+        let Some([left, right]) = speed.next_frame(playback, looping)
+        else {
+            return true;
+        };
+
+        frame[0] += (left * gain) as i32;
+        frame[1] += (right * gain) as i32;
+    }
+
+    !looping && speed.position(playback) >= playback.length()
+}
+
+#[derive(Clone, Copy, Debug)]
+struct VoiceState {
+    playback: Playback,
+    gain: f32,
+    /// When the voice started playing, compared to the other voices.
+    started: u32,
+    /// None if the voice was started without an id.
+    sound: Option<SoundId>,
+    /// A paused voice holds its place and plays silence.
+    paused: bool,
+    /// Whether PCM and ADPCM start over when they reach the end.
+    looping: bool,
+    /// How fast PCM and ADPCM play.
+    speed: Speed<Playback>,
+}
+
+impl VoiceState {
+    const IDLE: Self = Self {
+        playback: Playback::Idle,
+        gain: 1.0,
+        started: 0,
+        sound: None,
+        paused: false,
+        looping: false,
+        speed: Speed::NORMAL,
+    };
+
+    fn is_idle(&self) -> bool {
+        matches!(self.playback, Playback::Idle)
+    }
+
+    /// Where the voice is, or None if it is not playing.
+    fn position(&self) -> Option<SoundPosition> {
+        if self.is_idle() {
+            return None;
+        }
+
+        Some(SoundPosition {
+            frame: self.speed.position(&self.playback),
+            length: self.playback.length(),
+            paused: self.paused,
+            looping: self.looping,
+        })
+    }
+
+    /// Adds the next `mix.len() / 2` frames of the voice to `mix`.
+    fn render(&mut self, mix: &mut [i32]) {
+        if self.paused {
+            return;
+        }
+        let gain = self.gain;
+
+        let finished = match &mut self.playback {
+            Playback::Idle => return,
+            Playback::Waveform {
+                waveform,
+                frequency_hz,
+                phase,
+                remaining_frames,
+                envelope,
+                filter,
+            } => {
+                let step = *frequency_hz / SPEAKER_SAMPLE_RATE as f32;
+                let mut released = false;
+
+                for frame in mix.chunks_exact_mut(2) {
+                    if *remaining_frames == 0 {
+                        envelope.release();
+                    } else {
+                        *remaining_frames -= 1;
+                    }
+
+                    let Some(level) = envelope.next_level() else {
+                        released = true;
+                        break;
+                    };
+
+                    let sample = (filter
+                        .process(waveform_sample(*waveform, *phase))
+                        * WAVEFORM_AMPLITUDE
+                        * gain
+                        * level)
+                        as i32;
+                    frame[0] += sample;
+                    frame[1] += sample;
+
+                    *phase += step;
+                    while *phase >= 1.0 {
+                        *phase -= 1.0;
+                    }
+                }
+
+                released
+            }
+            samples => render_samples(
+                samples,
+                &mut self.speed,
+                mix,
+                gain,
+                self.looping,
+            ),
+        };
+
+        if finished {
+            self.playback = Playback::Idle;
+            self.sound = None;
+        }
+    }
+}
+
+/// The speaker contains 6 channels that will be mixed.
+/// The first two are used for system sounds. The last
+/// four are used for playing extra sounds specified by
+/// other parts of the program.
+pub struct SpeakerChannelPool {
+    voices: [VoiceState; VOICE_COUNT],
+    /// Counts up every time a voice starts.
+    started: u32,
+    /// The gain the user voices are at right now, see `DUCKED_GAIN`.
+    duck: f32,
+    /// The gain everything is at right now, following
+    /// `settings::master_gain`.
+    master: f32,
+}
+
+impl SpeakerChannelPool {
+    pub const fn new() -> Self {
+        Self {
+            voices: [VoiceState::IDLE; VOICE_COUNT],
+            started: 0,
+            duck: 1.0,
+            master: 0.0,
+        }
+    }
+
+    /// Plays `sound` on `voice`, replacing whatever it was playing. A
+    /// waveform played on a voice that is already playing one carries
+    /// on from the same phase, level and filter state, so a note can
+    /// be changed or held for longer without a click.
+    pub fn play(&mut self, voice: Voice, sound: Sound, gain: f32) {
+        self.started = self.started.wrapping_add(1);
+        let state = &mut self.voices[voice.index()];
+
+        state.playback = match sound {
+            Sound::Pcm(samples) => Playback::Pcm {
+                samples,
+                position: 0,
+            },
+            Sound::Adpcm(data) => {
+                Playback::Adpcm(AdpcmDecoder::new(data))
+            }
+            Sound::Waveform {
+                waveform,
+                frequency_hz,
+                duration,
+                envelope,
+                filter,
+            } => {
+                let (phase, envelope, filter) = match state.playback {
+                    Playback::Waveform {
+                        phase,
+                        envelope: mut envelope_state,
+                        filter: mut filter_state,
+                        ..
+                    } => {
+                        envelope_state.retrigger(&envelope);
+                        filter_state.retune(&filter);
+                        (phase, envelope_state, filter_state)
+                    }
+                    _ => (
+                        0.0,
+                        EnvelopeState::new(&envelope),
+                        FilterState::new(&filter),
+                    ),
+                };
+
+                Playback::Waveform {
+                    waveform,
+                    frequency_hz,
+                    phase,
+                    remaining_frames: duration_to_frames(duration),
+                    envelope,
+                    filter,
+                }
+            }
+        };
+        state.gain = gain;
+        state.started = self.started;
+        state.sound = None;
+        state.paused = false;
+        state.looping = false;
+        state.speed = Speed::NORMAL;
+    }
+
+    /// Plays `sound` as `id`. If `id` is still playing, it is
+    /// replaced where it is, otherwise it gets a voice of `group`.
+    pub fn start(
+        &mut self,
+        id: SoundId,
+        group: VoiceGroup,
+        sound: Sound,
+        gain: f32,
+    ) {
+        let voice = self
+            .voice_of(id)
+            .unwrap_or_else(|| self.free_voice(group));
+
+        self.play(voice, sound, gain);
+        self.voices[voice.index()].sound = Some(id);
+    }
+
+    /// Stops `voice`. A waveform is released and fades out over the
+    /// release of its envelope, even if it was paused, anything else
+    /// stops right away.
+    pub fn stop(&mut self, voice: Voice) {
+        let state = &mut self.voices[voice.index()];
+        state.paused = false;
+
+        match &mut state.playback {
+            Playback::Waveform { envelope, .. } => envelope.release(),
+            _ => {
+                state.playback = Playback::Idle;
+                state.sound = None;
+            }
+        }
+    }
+
+    /// Stops `id` if it is still playing.
+    pub fn stop_sound(&mut self, id: SoundId) {
+        if let Some(voice) = self.voice_of(id) {
+            self.stop(voice);
+        }
+    }
+
+    /// Pauses `id` where it is, or carries on from there if
+    /// `paused` is false.
+    pub fn set_paused(&mut self, id: SoundId, paused: bool) {
+        if let Some(voice) = self.voice_of(id) {
+            self.voices[voice.index()].paused = paused;
+        }
+    }
+
+    /// Moves `id` to `frame` frames from its start. Only PCM and
+    /// ADPCM can be moved.
+    pub fn seek(&mut self, id: SoundId, frame: u32) {
+        if let Some(voice) = self.voice_of(id) {
+            let state = &mut self.voices[voice.index()];
+            state.playback.seek(frame);
+            state.speed.restart();
+        }
+    }
+
+    /// Plays `id` at `speed` times its normal speed, in `mode`. Only
+    /// PCM and ADPCM can change speed.
+    pub fn set_speed(
+        &mut self,
+        id: SoundId,
+        speed: f32,
+        mode: SpeedMode,
+    ) {
+        if let Some(voice) = self.voice_of(id) {
+            let state = &mut self.voices[voice.index()];
+            state.speed.set(speed, mode, &mut state.playback);
+        }
+    }
+
+    /// Sets whether `id` starts over when it reaches the end.
+    pub fn set_looping(&mut self, id: SoundId, looping: bool) {
+        if let Some(voice) = self.voice_of(id) {
+            self.voices[voice.index()].looping = looping;
+        }
+    }
+
+    /// The voice playing `id`, if it is still playing.
+    pub fn voice_of(&self, id: SoundId) -> Option<Voice> {
+        (0..VOICE_COUNT).map(|index| Voice(index as u8)).find(
+            |voice| self.voices[voice.index()].sound == Some(id),
+        )
+    }
+
+    pub fn stop_group(&mut self, group: VoiceGroup) {
+        for voice in group_voices(group) {
+            self.stop(*voice);
+        }
+    }
+
+    pub fn is_playing(&self, voice: Voice) -> bool {
+        !self.voices[voice.index()].is_idle()
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.voices.iter().all(VoiceState::is_idle)
+    }
+
+    /// A voice of `group` that is not playing anything, or the one
+    /// that has been playing the longest if they all are.
+    pub fn free_voice(&self, group: VoiceGroup) -> Voice {
+        let voices = group_voices(group);
+
+        voices
+            .iter()
+            .copied()
+            .find(|voice| !self.is_playing(*voice))
+            .or_else(|| {
+                voices.iter().copied().max_by_key(|voice| {
+                    self.started.wrapping_sub(
+                        self.voices[voice.index()].started,
+                    )
+                })
+            })
+            .unwrap_or(voices[0])
+    }
+
+    /// The voice of `group` that is playing a waveform, if any.
+    pub fn waveform_voice(&self, group: VoiceGroup) -> Option<Voice> {
+        group_voices(group).iter().copied().find(|voice| {
+            matches!(
+                self.voices[voice.index()].playback,
+                Playback::Waveform { .. }
+            )
+        })
+    }
+
+    /// Carries out `cmd`. Commands that do not name a voice get the
+    /// one that suits them: the old tone and meow commands go to the
+    /// system voices, cries and synth tones to the user voices.
+    pub fn apply(&mut self, cmd: SpeakerCommand) {
+        match cmd {
+            SpeakerCommand::Play { voice, sound, gain } => {
+                self.play(voice, sound, gain)
+            }
+            SpeakerCommand::Stop(voice) => self.stop(voice),
+            SpeakerCommand::Start {
+                id,
+                group,
+                sound,
+                gain,
+            } => self.start(id, group, sound, gain),
+            SpeakerCommand::StopSound(id) => self.stop_sound(id),
+            SpeakerCommand::Pause(id) => self.set_paused(id, true),
+            SpeakerCommand::Resume(id) => self.set_paused(id, false),
+            SpeakerCommand::Seek { id, frame } => {
+                self.seek(id, frame)
+            }
+            SpeakerCommand::SetLooping { id, looping } => {
+                self.set_looping(id, looping)
+            }
+            SpeakerCommand::SetSpeed { id, speed, mode } => {
+                self.set_speed(id, speed, mode)
+            }
+            SpeakerCommand::Sine440Hz(duration) => self.play(
+                self.free_voice(VoiceGroup::System),
+                Sound::Waveform {
+                    waveform: Waveform::Sine,
+                    frequency_hz: 440.0,
+                    duration,
+                    envelope: Envelope::GATE,
+                    filter: Filter::OFF,
+                },
+                1.0,
+            ),
+            SpeakerCommand::PlayWaveform {
+                waveform,
+                frequency_hz,
+                duration,
+            } => self.play(
+                self.waveform_voice(VoiceGroup::User)
+                    .unwrap_or(self.free_voice(VoiceGroup::User)),
+                Sound::Waveform {
+                    waveform,
+                    frequency_hz: frequency_hz as f32,
+                    duration,
+                    envelope: Envelope::GATE,
+                    filter: Filter::OFF,
+                },
+                1.0,
+            ),
+            SpeakerCommand::Silence => {
+                self.stop_group(VoiceGroup::User)
+            }
+            SpeakerCommand::PlayPcm(samples) => self.play(
+                self.free_voice(VoiceGroup::System),
+                Sound::Pcm(samples),
+                1.0,
+            ),
+            SpeakerCommand::PlayPcmWithVolume {
+                samples,
+                volume_multiplier,
+            } => self.play(
+                self.free_voice(VoiceGroup::User),
+                Sound::Pcm(samples),
+                volume_multiplier,
+            ),
+        }
+    }
+
+    /// Fills `buffer` with the next frames of every voice, as 16 bit
+    /// stereo little endian PCM.
+    pub fn mix(&mut self, buffer: &mut [u8]) {
+        let ducked =
+            Voice::SYSTEM.iter().any(|voice| self.is_playing(*voice));
+        let duck_target = if ducked { DUCKED_GAIN } else { 1.0 };
+        let master_target = settings::master_gain();
+
+        for chunk in buffer.chunks_mut(MIX_CHUNK_FRAMES * 4) {
+            let mut system = [0i32; MIX_CHUNK_FRAMES * 2];
+            let mut user = [0i32; MIX_CHUNK_FRAMES * 2];
+            let system = &mut system[..chunk.len() / 2];
+            let user = &mut user[..chunk.len() / 2];
+
+            for (index, voice) in self.voices.iter_mut().enumerate() {
+                match Voice(index as u8).group() {
+                    VoiceGroup::System => voice.render(system),
+                    VoiceGroup::User => voice.render(user),
+                }
+            }
+
+            for ((system, user), bytes) in system
+                .chunks_exact(2)
+                .zip(user.chunks_exact(2))
+                .zip(chunk.chunks_exact_mut(4))
+            {
+                self.duck += (duck_target - self.duck)
+                    .clamp(-DUCK_STEP, DUCK_STEP);
+                self.master += (master_target - self.master)
+                    .clamp(-MASTER_STEP, MASTER_STEP);
+
+                for channel in 0..2 {
+                    let sample = ((system[channel] as f32
+                        + user[channel] as f32 * self.duck)
+                        * self.master)
+                        as i32;
+                    let saturated = sample
+                        .clamp(i16::MIN as i32, i16::MAX as i32)
+                        as i16;
+                    bytes[channel * 2..channel * 2 + 2]
+                        .copy_from_slice(&saturated.to_le_bytes());
+                }
+            }
+        }
+
+        self.publish();
+    }
+
+    /// Makes the sounds that are playing and where they are visible
+    /// to `is_sound_playing` and `sound_position`.
+    pub fn publish(&self) {
+        let mut sounds = [None; VOICE_COUNT];
+        for (sound, voice) in sounds.iter_mut().zip(&self.voices) {
+            *sound = voice.sound.zip(voice.position());
+        }
+
+        PLAYING_SOUNDS.lock(|playing| playing.set(sounds));
+    }
+}
+
+impl Default for SpeakerChannelPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn group_voices(group: VoiceGroup) -> &'static [Voice] {
+    match group {
+        VoiceGroup::System => &Voice::SYSTEM,
+        VoiceGroup::User => &Voice::USER,
+    }
+}
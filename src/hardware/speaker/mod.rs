@@ -4,7 +4,10 @@ use defmt::warn;
 use embassy_sync::{
     blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel,
 };
-use embassy_time::{Duration, Instant};
+use embassy_time::Duration;
+#[cfg(feature = "esp32s3")]
+use embassy_time::Instant;
+#[cfg(feature = "esp32s3")]
 use esp_hal::{
     Async,
     dma::DmaDescriptor,
@@ -25,17 +28,27 @@ use static_cell::StaticCell;
 pub static _A: Channel<CriticalSectionRawMutex, (), 20> =
     Channel::new();
 
+pub mod adpcm;
+pub mod envelope;
+pub mod filter;
+pub mod mixer;
+pub mod polyphony;
+pub mod speed;
 // NOTE: the system module is used for playing system sounds, and the
 // user channel is used for playing user sounds (i.e. programs playing
 // their own sounds).
 pub mod system;
 pub mod user;
 
-// NOTE: later on, it would be nice to have some kind of mixer to
-// where multiple sounds can be played at once.
+use mixer::{
+    MIX_BLOCK_SIZE, Sound, SoundId, SpeakerChannelPool, Voice,
+    VoiceGroup,
+};
+use speed::SpeedMode;
 
-/// The speaker can buffer two sounds.
-const SPEAKER_BUFFER_CMD_SIZE: usize = 2;
+/// How many commands can be waiting for the speaker. They are picked
+/// up between mixed blocks, so this only has to cover bursts.
+const SPEAKER_BUFFER_CMD_SIZE: usize = 8;
 
 /// A channel to send commands to the speaker.
 pub static SPEAKER_CHANNEL: Channel<
@@ -44,12 +57,14 @@ pub static SPEAKER_CHANNEL: Channel<
     SPEAKER_BUFFER_CMD_SIZE,
 > = Channel::new();
 
-pub static MEOW_PCM: &[u8] =
-    include_bytes!("../../../sounds/meow.pcm");
+/// `sounds/meow.pcm`, encoded by `build.rs`. See `adpcm`.
+pub static MEOW_ADPCM: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/meow.adpcm"));
 
 pub struct Cry {
     pub pokemon_id: u16,
     pub name: &'static str,
+    /// Encoded by `build.rs`. See `adpcm`.
     pub samples: &'static [u8],
 }
 
@@ -58,12 +73,55 @@ pub static CRIES: &[Cry] =
 
 #[derive(Clone)]
 pub enum SpeakerCommand {
+    /// Plays `sound` on `voice` at `gain`, replacing whatever the
+    /// voice was playing.
+    Play {
+        voice: Voice,
+        sound: Sound,
+        gain: f32,
+    },
+    Stop(Voice),
+    /// Plays `sound` as `id`, replacing it if it is still playing,
+    /// or on a voice of `group` if it is not.
+    Start {
+        id: SoundId,
+        group: VoiceGroup,
+        sound: Sound,
+        gain: f32,
+    },
+    /// Stops `id` if it is still playing.
+    StopSound(SoundId),
+    /// Pauses `id` where it is, if it is still playing.
+    Pause(SoundId),
+    /// Carries on playing `id` from where it was paused.
+    Resume(SoundId),
+    /// Moves `id` to `frame` frames from its start. Past the end, it
+    /// finishes. Only PCM and ADPCM can be moved.
+    Seek {
+        id: SoundId,
+        frame: u32,
+    },
+    /// Sets whether `id` starts over when it reaches the end. Sounds
+    /// start out not looping.
+    SetLooping {
+        id: SoundId,
+        looping: bool,
+    },
+    /// Plays `id` at `speed` times its normal speed, from
+    /// `speed::MIN_SPEED` to `speed::MAX_SPEED`. Sounds start out at
+    /// 1.0. Only PCM and ADPCM can change speed.
+    SetSpeed {
+        id: SoundId,
+        speed: f32,
+        mode: SpeedMode,
+    },
     Sine440Hz(embassy_time::Duration),
     PlayWaveform {
         waveform: Waveform,
         frequency_hz: u16,
         duration: embassy_time::Duration,
     },
+    /// Stops every user voice. System sounds carry on.
     Silence,
     PlayPcm(&'static [u8]),
     PlayPcmWithVolume {
@@ -111,20 +169,33 @@ impl Waveform {
     }
 }
 
+#[cfg(feature = "esp32s3")]
 pub(super) type SpeakerType = I2s<'static, Async>;
+#[cfg(feature = "esp32s3")]
 type SpeakerTxType = I2sTx<'static, Async>;
 
 pub const SPEAKER_SAMPLE_RATE: u32 = 44_100;
 
+/// The peak of a waveform played at a gain of 1.0.
+pub const WAVEFORM_AMPLITUDE: f32 = 14000.0;
+
+/// How many frames `duration` lasts at `SPEAKER_SAMPLE_RATE`.
+pub fn duration_to_frames(duration: Duration) -> u32 {
+    (duration.as_micros() * SPEAKER_SAMPLE_RATE as u64 / 1_000_000)
+        .min(u32::MAX as u64) as u32
+}
+
 // From my understanding, this involves the memory that we write to
 // that the i2s speaker directly reads from. So basically we are
 // occassionally filling a buffer.
+#[cfg(feature = "esp32s3")]
 static DESCRIPTORS: StaticCell<[DmaDescriptor; 8]> =
     StaticCell::new();
 
 // The 'a lifetime is used because the I2s interface the function
 // returns can only last for as long as the gpio interfaces do.
 /// Intiailize the i2s speaker.``
+#[cfg(feature = "esp32s3")]
 pub(super) fn init(
     i2s0: I2S0<'static>,
     dma: DMA_CH0<'static>,
@@ -163,14 +234,24 @@ pub(super) fn init(
     I2s::new(i2s0, dma, config).unwrap().into_async()
 }
 
-/// The speaker contains 4 channels that will be mixed.
-/// The first two are used for system sounds. The last
-/// two are used for playing extra sounds specified by
-/// other parts of the program.
-struct SpeakerChannelPool {}
+/// Where the speaker writes its 16 bit stereo PCM. On the board this
+/// is the I2S DMA buffer, and in the simulator it is a WAV file.
+#[allow(async_fn_in_trait)]
+pub trait SpeakerOutput {
+    /// Writes all of `buffer`, waiting until there is room for it.
+    async fn push_all(&mut self, buffer: &[u8]);
+}
+
+#[cfg(feature = "esp32s3")]
+impl SpeakerOutput for I2sWriteDmaTransferAsync<'_, &mut [u8; 4096]> {
+    async fn push_all(&mut self, buffer: &[u8]) {
+        push_all(self, buffer).await;
+    }
+}
 
 /// A task that waits until a speaker command is sent. After receiving
 /// a channel input, it will play that sound.
+#[cfg(feature = "esp32s3")]
 #[embassy_executor::task]
 pub async fn speaker_task(speaker: SpeakerType) {
     // TODO: clean up all of this cause like, what the hell
@@ -185,96 +266,45 @@ pub async fn speaker_task(speaker: SpeakerType) {
     let mut transfer = speaker_tx
         .write_dma_circular_async(&mut dma_buffer)
         .unwrap();
-    let mut waveform_phase = 0.0f32;
+
+    run_speaker(&mut transfer).await
+}
+
+/// Plays every command sent through `SPEAKER_CHANNEL` on `output`,
+/// forever. Commands are picked up between mixed blocks, so a new
+/// sound starts while the others are still playing.
+pub async fn run_speaker<O: SpeakerOutput>(output: &mut O) -> ! {
+    let mut pool = SpeakerChannelPool::new();
+    let mut buffer = [0u8; MIX_BLOCK_SIZE];
 
     loop {
-        let cmd = SPEAKER_CHANNEL.receive().await;
-
-        match cmd {
-            SpeakerCommand::Sine440Hz(duration) => {
-                let mut buffer = [0u8; 2048];
-                let mut phase = 0.0f32;
-                let sample_rate = SPEAKER_SAMPLE_RATE as f32;
-                let start = Instant::now();
-
-                while start.elapsed() < duration {
-                    fill_sine(
-                        &mut buffer,
-                        &mut phase,
-                        440.0,
-                        sample_rate,
-                    );
-                    push_all(&mut transfer, &buffer).await;
-                }
-
-                buffer.fill(0);
-                for _ in 0..2 {
-                    push_all(&mut transfer, &buffer).await;
-                }
-            }
-            SpeakerCommand::PlayWaveform {
-                waveform,
-                frequency_hz,
-                duration,
-            } => {
-                let mut buffer = [0u8; 2048];
-                let sample_rate = SPEAKER_SAMPLE_RATE as f32;
-                let start = Instant::now();
-
-                while start.elapsed() < duration {
-                    fill_waveform(
-                        &mut buffer,
-                        &mut waveform_phase,
-                        waveform,
-                        frequency_hz as f32,
-                        sample_rate,
-                    );
-                    push_all(&mut transfer, &buffer).await;
-                }
-            }
-            SpeakerCommand::Silence => {
-                waveform_phase = 0.0;
-                let silence = [0u8; 2048];
-                for _ in 0..2 {
-                    push_all(&mut transfer, &silence).await;
-                }
-            }
-            SpeakerCommand::PlayPcm(samples) => {
-                for buffer in samples.chunks(2048) {
-                    push_all(&mut transfer, buffer).await;
-                }
-
-                let silence = [0u8; 2048];
-                for _ in 0..2 {
-                    push_all(&mut transfer, &silence).await;
-                }
-            }
-            SpeakerCommand::PlayPcmWithVolume {
-                samples,
-                volume_multiplier,
-            } => {
-                let mut scaled_buffer = [0u8; 2048];
-
-                for buffer in samples.chunks(scaled_buffer.len()) {
-                    let output = &mut scaled_buffer[..buffer.len()];
-                    scale_pcm_s16le(
-                        buffer,
-                        output,
-                        volume_multiplier,
-                    );
-                    push_all(&mut transfer, output).await;
-                }
-
-                let silence = [0u8; 2048];
-                for _ in 0..2 {
-                    push_all(&mut transfer, &silence).await;
-                }
+        while let Ok(cmd) = SPEAKER_CHANNEL.try_receive() {
+            pool.apply(cmd);
+        }
+        pool.publish();
+
+        if pool.is_idle() {
+            // push silence through so that the end of the last sound
+            // is not left looping in the circular DMA buffer
+            buffer.fill(0);
+            for _ in 0..2 {
+                output.push_all(&buffer).await;
             }
+
+            pool.apply(SPEAKER_CHANNEL.receive().await);
+            continue;
         }
+
+        pool.mix(&mut buffer);
+        output.push_all(&buffer).await;
     }
 }
 
-fn scale_pcm_s16le(input: &[u8], output: &mut [u8], multiplier: f32) {
+pub fn scale_pcm_s16le(
+    input: &[u8],
+    output: &mut [u8],
+    multiplier: f32,
+) {
     output.copy_from_slice(input);
 
     for (input_sample, output_sample) in
@@ -289,6 +319,7 @@ fn scale_pcm_s16le(input: &[u8], output: &mut [u8], multiplier: f32) {
     }
 }
 
+#[cfg(feature = "esp32s3")]
 async fn push_all(
     transfer: &mut I2sWriteDmaTransferAsync<'_, &mut [u8; 4096]>,
     buffer: &[u8],
@@ -307,6 +338,7 @@ async fn push_all(
     }
 }
 
+#[cfg(feature = "esp32s3")]
 fn play_sine440hz(
     speaker_tx: &mut SpeakerTxType,
     buffer: &mut [u8; 32000],
@@ -350,7 +382,7 @@ fn play_sine440hz(
     }
 }
 
-fn fill_sine(
+pub fn fill_sine(
     buffer: &mut [u8],
     phase: &mut f32,
     freq: f32,
@@ -372,30 +404,6 @@ fn fill_sine(
     }
 }
 
-fn fill_waveform(
-    buffer: &mut [u8],
-    phase: &mut f32,
-    waveform: Waveform,
-    freq: f32,
-    sample_rate: f32,
-) {
-    for chunk in buffer.chunks_exact_mut(4) {
-        let sample =
-            (waveform_sample(waveform, *phase) * 14000.0) as i16;
-        let s = sample.to_le_bytes();
-
-        chunk[0] = s[0];
-        chunk[1] = s[1];
-        chunk[2] = s[0];
-        chunk[3] = s[1];
-
-        *phase += freq / sample_rate;
-        while *phase >= 1.0 {
-            *phase -= 1.0;
-        }
-    }
-}
-
 pub fn waveform_sample(waveform: Waveform, phase: f32) -> f32 {
     let phase = phase - phase as u32 as f32;
 
@@ -418,23 +426,3 @@ pub fn waveform_sample(waveform: Waveform, phase: f32) -> f32 {
         }
     }
 }
-
-fn apply_fade_edges(buffer: &mut [u8], fade_samples: usize) {
-    let samples = buffer.len() / 4;
-
-    for i in 0..fade_samples {
-        let gain = i as f32 / fade_samples as f32;
-
-        let idx = i * 4;
-
-        let sample =
-            i16::from_le_bytes([buffer[idx], buffer[idx + 1]]);
-        let scaled = (sample as f32 * gain) as i16;
-
-        let bytes = scaled.to_le_bytes();
-        buffer[idx] = bytes[0];
-        buffer[idx + 1] = bytes[1];
-        buffer[idx + 2] = bytes[0];
-        buffer[idx + 3] = bytes[1];
-    }
-}
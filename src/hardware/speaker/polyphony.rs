@@ -0,0 +1,153 @@
+//! Shares a fixed number of voices between the keys of a keyboard.
+//!
+//! Every key that is pressed gets a voice of its own, for as long as
+//! it is held. A key pressed when every voice is held takes the voice
+//! of the key that has been held the longest, which goes quiet even
+//! though it is still held. Otherwise it gets the voice that was let
+//! go of the longest ago, so that the release of the note before it
+//! has had the most time to fade.
+//!
+//! [`VoiceAllocator`] only hands out voice numbers. What a voice
+//! plays, and on which voice of the mixer, is up to whoever holds
+//! it, usually one `UserSound` per voice.
+
+#[derive(Clone, Copy, Debug)]
+struct Slot {
+    /// The key holding the voice, if it is held.
+    key: Option<u8>,
+    /// When the voice was last pressed or let go of, by the clock of
+    /// the allocator.
+    since: u32,
+}
+
+/// Hands out `N` voices to keys numbered from 0 to 255.
+#[derive(Clone, Copy, Debug)]
+pub struct VoiceAllocator<const N: usize> {
+    slots: [Slot; N],
+    /// Counts up every time a key is pressed or let go of.
+    clock: u32,
+}
+
+impl<const N: usize> VoiceAllocator<N> {
+    /// Every voice free.
+    pub const fn new() -> Self {
+        Self {
+            slots: [Slot {
+                key: None,
+                since: 0,
+            }; N],
+            clock: 0,
+        }
+    }
+
+    /// Gives `key` a voice and returns it. A key that is already held
+    /// keeps the voice it has.
+    pub fn press(&mut self, key: u8) -> usize {
+        if let Some(voice) = self.voice_of(key) {
+            return voice;
+        }
+
+        let chosen = (1..N).fold(0, |chosen, voice| {
+            if self.better_to_take(voice, chosen) {
+                voice
+            } else {
+                chosen
+            }
+        });
+
+        self.clock = self.clock.wrapping_add(1);
+        self.slots[chosen] = Slot {
+            key: Some(key),
+            since: self.clock,
+        };
+        chosen
+    }
+
+    /// Lets go of `key` and returns the voice it had, if it still
+    /// had one.
+    pub fn release(&mut self, key: u8) -> Option<usize> {
+        let voice = self.voice_of(key)?;
+
+        self.clock = self.clock.wrapping_add(1);
+        self.slots[voice] = Slot {
+            key: None,
+            since: self.clock,
+        };
+        Some(voice)
+    }
+
+    /// The voice `key` is holding, if any.
+    pub fn voice_of(&self, key: u8) -> Option<usize> {
+        self.slots.iter().position(|slot| slot.key == Some(key))
+    }
+
+    /// The key holding `voice`, if any.
+    pub fn key_of(&self, voice: usize) -> Option<u8> {
+        self.slots[voice].key
+    }
+
+    /// Whether `voice` should be taken before `other`: free voices
+    /// first, then whichever has been in the state it is in for
+    /// longer.
+    fn better_to_take(&self, voice: usize, other: usize) -> bool {
+        let slot = self.slots[voice];
+        let other_slot = self.slots[other];
+
+        match (slot.key.is_none(), other_slot.key.is_none()) {
+            (true, false) => true,
+            (false, true) => false,
+            _ => {
+                self.clock.wrapping_sub(slot.since)
+                    > self.clock.wrapping_sub(other_slot.since)
+            }
+        }
+    }
+}
+
+impl<const N: usize> Default for VoiceAllocator<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn held_keys_keep_their_voices() {
+        // every key gets a voice of its own
+        let mut voices = VoiceAllocator::<3>::new();
+        let a = voices.press(10);
+        let b = voices.press(20);
+        let c = voices.press(30);
+        assert!(a != b && b != c && a != c);
+        assert_eq!(voices.press(20), b);
+        assert_eq!(voices.key_of(c), Some(30));
+    }
+
+    #[test]
+    fn longest_held_key_loses_its_voice() {
+        // with every voice held
+        let mut voices = VoiceAllocator::<2>::new();
+        let a = voices.press(10);
+        voices.press(20);
+        assert_eq!(voices.press(30), a);
+        assert_eq!(voices.voice_of(10), None);
+        assert_eq!(voices.release(10), None);
+    }
+
+    #[test]
+    fn free_voices_are_taken_first() {
+        // the one let go of the longest ago first
+        let mut voices = VoiceAllocator::<3>::new();
+        let a = voices.press(10);
+        let b = voices.press(20);
+        let c = voices.press(30);
+        assert_eq!(voices.release(20), Some(b));
+        assert_eq!(voices.release(30), Some(c));
+        assert_eq!(voices.press(40), b);
+        assert_eq!(voices.press(50), c);
+        assert_eq!(voices.press(60), a);
+    }
+}
@@ -0,0 +1,532 @@
+//! Plays PCM and ADPCM faster or slower than it was recorded.
+//!
+//! In [`SpeedMode::Turntable`], the pitch follows the speed, like a
+//! record played at the wrong speed. [`Resampler`] reads the sound
+//! at a fractional rate and fills in between its frames, with
+//! straight lines or with a cubic curve, which keeps high notes
+//! cleaner.
+//!
+//! In [`SpeedMode::TimeStretch`], the pitch stays where it is.
+//! [`TimeStretcher`] plays short overlapping grains of the sound at
+//! their own speed, and starts them closer together or further apart
+//! to change how long the sound lasts. Sharp sounds get smeared a
+//! little, which is why it is not the default.
+//!
+//! The voices of the mixer play every sound through a [`Speed`].
+
+/// The slowest a sound can be played.
+pub const MIN_SPEED: f32 = 0.25;
+
+/// The fastest a sound can be played.
+pub const MAX_SPEED: f32 = 4.0;
+
+/// How many frames every grain of a stretched sound lasts, about
+/// 46 ms. Shorter grains smear less, but sound rougher.
+const GRAIN_FRAMES: u32 = 2048;
+
+/// How a sound is filled in between its frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// A straight line between the frames on either side.
+    Linear,
+    /// A Catmull-Rom spline through the two frames on either side.
+    Cubic,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpeedMode {
+    /// Faster is higher and slower is lower.
+    Turntable(Interpolation),
+    /// The pitch stays the same at every speed.
+    TimeStretch,
+}
+
+/// Something made of frames that can be played at another speed.
+pub trait FrameSource: Copy {
+    /// The next frame, None once every frame has been read.
+    fn next_frame(&mut self) -> Option<[i16; 2]>;
+
+    /// The next frame to be read.
+    fn frame(&self) -> u32;
+
+    /// How many frames there are in all.
+    fn length(&self) -> u32;
+
+    /// Carries on from `frame`, or from the end if it is past it.
+    fn seek(&mut self, frame: u32);
+
+    /// Like `next_frame`, but starts over at the end if `looping`.
+    fn next_frame_looping(
+        &mut self,
+        looping: bool,
+    ) -> Option<[i16; 2]> {
+        let next = self.next_frame();
+        if next.is_none() && looping && self.length() > 0 {
+            self.seek(0);
+            return self.next_frame();
+        }
+
+        next
+    }
+}
+
+/// The value `t` of the way from `points[1]` to `points[2]`, where
+/// the points are evenly spaced and `t` is from 0.0 to 1.0.
+pub fn interpolate(
+    interpolation: Interpolation,
+    points: [f32; 4],
+    t: f32,
+) -> f32 {
+    let [p0, p1, p2, p3] = points;
+
+    match interpolation {
+        Interpolation::Linear => p1 + (p2 - p1) * t,
+        Interpolation::Cubic => {
+            let a = -0.5 * p0 + 1.5 * p1 - 1.5 * p2 + 0.5 * p3;
+            let b = p0 - 2.5 * p1 + 2.0 * p2 - 0.5 * p3;
+            let c = 0.5 * (p2 - p0);
+
+            ((a * t + b) * t + c) * t + p1
+        }
+    }
+}
+
+/// Reads a sound at a fractional rate. The frames go in through
+/// `push` whenever `needs_frame`, and come out of `frame`, which is
+/// then moved on with `advance`.
+#[derive(Clone, Copy, Debug)]
+pub struct Resampler {
+    /// The last four frames pushed, oldest first.
+    history: [[f32; 2]; 4],
+    /// How far the output is from `history[1]` to `history[2]`.
+    fraction: f32,
+    /// How many frames have to be pushed before the next output.
+    needed: u8,
+    /// Whether nothing has been pushed yet.
+    empty: bool,
+    /// How many frames have been pushed since the sound ended.
+    padding: u8,
+}
+
+impl Resampler {
+    pub fn new() -> Self {
+        Self {
+            history: [[0.0; 2]; 4],
+            fraction: 0.0,
+            needed: 3,
+            empty: true,
+            padding: 0,
+        }
+    }
+
+    pub fn needs_frame(&self) -> bool {
+        self.needed > 0
+    }
+
+    /// Adds the next frame of the sound, or None once it has ended.
+    pub fn push(&mut self, frame: Option<[i16; 2]>) {
+        let frame = match frame {
+            Some([left, right]) => [left as f32, right as f32],
+            None => {
+                self.padding = self.padding.saturating_add(1);
+                [0.0; 2]
+            }
+        };
+
+        if self.empty {
+            // there is nothing before the first frame, so it stands
+            // in for the frame before it as well
+            self.history = [frame; 4];
+            self.empty = false;
+        } else {
+            self.history = [
+                self.history[1],
+                self.history[2],
+                self.history[3],
+                frame,
+            ];
+        }
+        self.needed -= 1;
+    }
+
+    /// Whether every frame of the sound has come out.
+    pub fn is_finished(&self) -> bool {
+        self.padding >= 3
+    }
+
+    /// The frame at the output, once no more frames are needed.
+    pub fn frame(&self, interpolation: Interpolation) -> [f32; 2] {
+        core::array::from_fn(|channel| {
+            interpolate(
+                interpolation,
+                self.history.map(|frame| frame[channel]),
+                self.fraction,
+            )
+        })
+    }
+
+    /// Moves the output on by `rate` frames of the sound.
+    pub fn advance(&mut self, rate: f32) {
+        self.fraction += rate;
+        while self.fraction >= 1.0 {
+            self.fraction -= 1.0;
+            self.needed += 1;
+        }
+    }
+
+    /// The next frame of `source` played at `rate` times its speed,
+    /// None once it has all played.
+    pub fn next<S: FrameSource>(
+        &mut self,
+        source: &mut S,
+        rate: f32,
+        interpolation: Interpolation,
+        looping: bool,
+    ) -> Option<[f32; 2]> {
+        while self.needs_frame() {
+            self.push(source.next_frame_looping(looping));
+        }
+        if self.is_finished() {
+            return None;
+        }
+
+        let frame = self.frame(interpolation);
+        self.advance(rate);
+        Some(frame)
+    }
+}
+
+impl Default for Resampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How loud a grain is `played` frames in. It fades in over the first
+/// half and out over the second, so that two grains half a grain
+/// apart always add up to full volume.
+pub fn grain_window(played: u32) -> f32 {
+    let half = (GRAIN_FRAMES / 2) as f32;
+
+    1.0 - (played as f32 - half).abs() / half
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Grain<S> {
+    source: S,
+    /// How many frames of the grain have been played.
+    played: u32,
+}
+
+/// Plays a sound at another speed without changing its pitch.
+#[derive(Clone, Copy, Debug)]
+pub struct TimeStretcher<S> {
+    /// Half a grain apart, so that one is always fading in while the
+    /// other is fading out.
+    grains: [Grain<S>; 2],
+    /// Where the sound is, in frames. Moves on at the speed, while
+    /// the grains move on at the normal speed.
+    position: f32,
+}
+
+impl<S: FrameSource> TimeStretcher<S> {
+    /// Starts from where `source` is.
+    pub fn new(source: &S) -> Self {
+        Self {
+            grains: [
+                Grain {
+                    source: *source,
+                    played: 0,
+                },
+                Grain {
+                    source: *source,
+                    played: GRAIN_FRAMES / 2,
+                },
+            ],
+            position: source.frame() as f32,
+        }
+    }
+
+    /// Where the sound is, in frames.
+    pub fn position(&self) -> u32 {
+        self.position as u32
+    }
+
+    /// The next frame at `speed` times the speed of `source`, None
+    /// once it has all played. New grains are read from copies of
+    /// `source`, which itself is left where it is.
+    pub fn next(
+        &mut self,
+        source: &S,
+        speed: f32,
+        looping: bool,
+    ) -> Option<[f32; 2]> {
+        let length = source.length() as f32;
+        if self.position >= length {
+            if !looping || length == 0.0 {
+                return None;
+            }
+            while self.position >= length {
+                self.position -= length;
+            }
+        }
+
+        let mut frame = [0.0; 2];
+        for grain in &mut self.grains {
+            if grain.played >= GRAIN_FRAMES {
+                grain.source = *source;
+                grain.source.seek(self.position as u32);
+                grain.played = 0;
+            }
+
+            let weight = grain_window(grain.played);
+            if let Some([left, right]) =
+                grain.source.next_frame_looping(looping)
+            {
+                frame[0] += left as f32 * weight;
+                frame[1] += right as f32 * weight;
+            }
+            grain.played += 1;
+        }
+
+        self.position += speed;
+        Some(frame)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum SpeedState<S> {
+    /// Not started yet, or started over after the source moved.
+    Start,
+    /// At the normal speed, straight from the source.
+    Direct,
+    Resampling(Resampler),
+    Stretching(TimeStretcher<S>),
+}
+
+/// The speed a voice plays at, and what it needs to keep between
+/// frames to play at it.
+#[derive(Clone, Copy, Debug)]
+pub struct Speed<S> {
+    speed: f32,
+    mode: SpeedMode,
+    state: SpeedState<S>,
+}
+
+impl<S: FrameSource> Speed<S> {
+    pub const NORMAL: Self = Self {
+        speed: 1.0,
+        mode: SpeedMode::Turntable(Interpolation::Cubic),
+        state: SpeedState::Start,
+    };
+
+    /// Plays at `speed` times the normal speed from now on, kept
+    /// between `MIN_SPEED` and `MAX_SPEED`. `source` is the sound
+    /// being played.
+    pub fn set(
+        &mut self,
+        speed: f32,
+        mode: SpeedMode,
+        source: &mut S,
+    ) {
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        self.mode = mode;
+
+        // a stretched sound leaves `source` where it started, so it
+        // is moved to where the sound got to
+        if let SpeedState::Stretching(stretcher) = self.state {
+            source.seek(stretcher.position());
+        }
+        self.state = SpeedState::Start;
+    }
+
+    /// Starts over from where `source` is, after it has been moved.
+    pub fn restart(&mut self) {
+        self.state = SpeedState::Start;
+    }
+
+    /// Where the sound is, in frames of `source`.
+    pub fn position(&self, source: &S) -> u32 {
+        match &self.state {
+            SpeedState::Stretching(stretcher) => stretcher.position(),
+            _ => source.frame(),
+        }
+    }
+
+    /// The next frame of `source`, None once it has all played.
+    pub fn next_frame(
+        &mut self,
+        source: &mut S,
+        looping: bool,
+    ) -> Option<[f32; 2]> {
+        if let SpeedState::Start = self.state {
+            self.state = match self.mode {
+                _ if self.speed == 1.0 => SpeedState::Direct,
+                SpeedMode::Turntable(_) => {
+                    SpeedState::Resampling(Resampler::new())
+                }
+                SpeedMode::TimeStretch => {
+                    SpeedState::Stretching(TimeStretcher::new(source))
+                }
+            };
+        }
+
+        match &mut self.state {
+            SpeedState::Start => None,
+            SpeedState::Direct => source
+                .next_frame_looping(looping)
+                .map(|[left, right]| [left as f32, right as f32]),
+            SpeedState::Resampling(resampler) => {
+                let interpolation = match self.mode {
+                    SpeedMode::Turntable(interpolation) => {
+                        interpolation
+                    }
+                    SpeedMode::TimeStretch => Interpolation::Cubic,
+                };
+                resampler.next(
+                    source,
+                    self.speed,
+                    interpolation,
+                    looping,
+                )
+            }
+            SpeedState::Stretching(stretcher) => {
+                stretcher.next(source, self.speed, looping)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::f32::consts::TAU;
+
+    use super::*;
+
+    const AMPLITUDE: f32 = 16_000.0;
+    const FRAMES: usize = 300;
+
+    /// A sine with a period of `period` frames, rounded to whole
+    /// samples.
+    fn sine_frames(period: f32) -> Vec<i16> {
+        (0..FRAMES)
+            .map(|index| {
+                let phase = index as f32 * TAU / period;
+                (AMPLITUDE * phase.sin()).round() as i16
+            })
+            .collect()
+    }
+
+    /// Plays a sine with a period of `period` frames at `rate` and
+    /// returns the largest difference from the sine itself, at the
+    /// same point in it. Skips the first frame, which has nothing
+    /// before it.
+    fn resampling_error(
+        period: f32,
+        rate: f32,
+        interpolation: Interpolation,
+    ) -> f32 {
+        let frames = sine_frames(period);
+        let mut frames = frames.iter();
+        let mut resampler = Resampler::new();
+        let mut largest = 0.0f32;
+
+        // multiplied rather than added up, so that it does not drift
+        let positions = (0..)
+            .map(|outputs| outputs as f32 * rate)
+            .take_while(|position| *position < (FRAMES - 4) as f32);
+        for position in positions {
+            while resampler.needs_frame() {
+                let frame = *frames.next().unwrap();
+                resampler.push(Some([frame; 2]));
+            }
+
+            let expected =
+                AMPLITUDE * (position * TAU / period).sin();
+            let error =
+                (resampler.frame(interpolation)[0] - expected).abs();
+            if position >= 1.0 {
+                largest = largest.max(error);
+            }
+
+            resampler.advance(rate);
+        }
+
+        largest
+    }
+
+    #[test]
+    fn normal_speed_is_unchanged() {
+        // apart from rounding to whole samples
+        for interpolation in
+            [Interpolation::Linear, Interpolation::Cubic]
+        {
+            assert!(resampling_error(40.0, 1.0, interpolation) < 0.6);
+        }
+    }
+
+    #[test]
+    fn cubic_follows_a_sine_more_closely() {
+        // slower and faster
+        for rate in [0.37, 0.8, 1.7, 3.3] {
+            let linear =
+                resampling_error(40.0, rate, Interpolation::Linear);
+            let cubic =
+                resampling_error(40.0, rate, Interpolation::Cubic);
+
+            assert!(linear < 60.0, "{rate}: {linear}");
+            assert!(cubic < 2.0, "{rate}: {cubic}");
+            assert!(cubic * 20.0 < linear, "{rate}");
+        }
+    }
+
+    #[test]
+    fn interpolation_is_exact_on_lines_and_parabolas() {
+        // both on a straight line, and the cubic curve on a parabola
+        // too
+        let parabola = |x: f32| 3.0 * x * x - 2.0 * x + 5.0;
+        let line = [-1.0, 1.0, 3.0, 5.0];
+        let points = [-1.0, 0.0, 1.0, 2.0].map(parabola);
+
+        let linear = interpolate(Interpolation::Linear, line, 0.25);
+        let cubic = interpolate(Interpolation::Cubic, line, 0.25);
+        let curve = interpolate(Interpolation::Cubic, points, 0.25);
+
+        assert!((linear - 1.5).abs() < 1e-5);
+        assert!((cubic - 1.5).abs() < 1e-5);
+        assert!((curve - parabola(0.25)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn last_frame_comes_out_before_finishing() {
+        let frames = [100, 200, 300];
+        let mut pushed = frames.iter();
+        let mut resampler = Resampler::new();
+        let mut outputs = Vec::new();
+
+        loop {
+            while resampler.needs_frame() {
+                resampler
+                    .push(pushed.next().map(|frame| [*frame; 2]));
+            }
+            if resampler.is_finished() {
+                break;
+            }
+
+            outputs.push(resampler.frame(Interpolation::Cubic)[0]);
+            resampler.advance(1.0);
+        }
+
+        assert_eq!(outputs, frames.map(|frame| frame as f32));
+    }
+
+    #[test]
+    fn overlapping_grains_add_up_to_full_volume() {
+        // two grains half a grain apart
+        for played in 0..GRAIN_FRAMES / 2 {
+            let sum = grain_window(played)
+                + grain_window(played + GRAIN_FRAMES / 2);
+            assert!((sum - 1.0).abs() < 1e-5, "{played}");
+        }
+    }
+}
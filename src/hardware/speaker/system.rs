@@ -1 +1,96 @@
-pub fn play_startup_sound() {}
+//! Sounds the firmware plays for its own events, like moving through
+//! the menu or starting up.
+//!
+//! They play on the system voices of the mixer, which apps cannot
+//! take, and the user voices are ducked while one is playing, so a
+//! system sound is always heard.
+
+use embassy_time::Duration;
+
+use super::{
+    MEOW_ADPCM, SPEAKER_CHANNEL, SpeakerCommand, Waveform,
+    envelope::Envelope,
+    filter::Filter,
+    mixer::{Sound, SoundId, VoiceGroup},
+};
+use crate::hardware::sequencer::{find_song, play_system_song};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SystemSound {
+    /// Moving through a list.
+    Click,
+    /// Picking something.
+    Confirm,
+    /// Something that cannot be done.
+    Error,
+    /// The device has started.
+    Boot,
+}
+
+impl SystemSound {
+    fn sound(self) -> (Sound, f32) {
+        match self {
+            SystemSound::Click => (
+                Sound::Waveform {
+                    waveform: Waveform::Square,
+                    frequency_hz: 1800.0,
+                    duration: Duration::from_millis(12),
+                    envelope: Envelope::GATE,
+                    filter: Filter::OFF,
+                },
+                0.3,
+            ),
+            SystemSound::Confirm => (Sound::Adpcm(MEOW_ADPCM), 1.0),
+            SystemSound::Error => (
+                Sound::Waveform {
+                    waveform: Waveform::Square,
+                    frequency_hz: 220.0,
+                    duration: Duration::from_millis(250),
+                    envelope: Envelope::GATE,
+                    filter: Filter::OFF,
+                },
+                0.5,
+            ),
+            SystemSound::Boot => (
+                Sound::Waveform {
+                    waveform: Waveform::Sine,
+                    frequency_hz: 440.0,
+                    duration: Duration::from_millis(500),
+                    envelope: Envelope::GATE,
+                    filter: Filter::OFF,
+                },
+                1.0,
+            ),
+        }
+    }
+
+    fn command(self) -> SpeakerCommand {
+        let (sound, gain) = self.sound();
+
+        SpeakerCommand::Start {
+            id: SoundId::next(),
+            group: VoiceGroup::System,
+            sound,
+            gain,
+        }
+    }
+}
+
+pub async fn play_system_sound(sound: SystemSound) {
+    SPEAKER_CHANNEL.send(sound.command()).await;
+}
+
+/// Plays `sound` unless the speaker is too busy to take it. For
+/// places that cannot wait.
+pub fn try_play_system_sound(sound: SystemSound) {
+    let _ = SPEAKER_CHANNEL.try_send(sound.command());
+}
+
+/// Plays the `boot` song, or the boot sound if there is no such
+/// song.
+pub fn play_startup_sound() {
+    match find_song("boot") {
+        Some(song) => play_system_song(song),
+        None => try_play_system_sound(SystemSound::Boot),
+    }
+}
@@ -1 +1,137 @@
+//! Sounds the apps play themselves.
+//!
+//! An app plays its sounds through a [`UserSound`], a handle that can
+//! later stop the sound or replace it with another one, even halfway
+//! through. They play on the user voices of the mixer, so if more
+//! sounds are playing than there are user voices, the one that has
+//! been playing the longest is cut off. System sounds never are.
+//!
+//! PCM and ADPCM sounds can be paused, moved to another point, sped
+//! up or slowed down and looped through their handle, and `position`
+//! tells how far through they are, for progress bars and scrubbing.
 
+use super::{
+    SPEAKER_CHANNEL, SpeakerCommand,
+    mixer::{
+        Sound, SoundId, SoundPosition, VoiceGroup, is_sound_playing,
+        sound_position,
+    },
+    speed::SpeedMode,
+};
+
+/// A handle to one sound of an app. Playing through the same handle
+/// again replaces the sound where it is, which is how a tone is
+/// changed or kept going without a gap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UserSound {
+    id: SoundId,
+}
+
+impl UserSound {
+    /// A handle that is not playing anything yet.
+    pub fn new() -> Self {
+        Self {
+            id: SoundId::next(),
+        }
+    }
+
+    /// Plays `sound` at `gain`, replacing whatever this handle was
+    /// playing.
+    pub async fn play(self, sound: Sound, gain: f32) {
+        SPEAKER_CHANNEL.send(self.start(sound, gain)).await;
+    }
+
+    /// Like `play`, but gives up if the speaker is too busy. Returns
+    /// true if the sound was sent.
+    pub fn try_play(self, sound: Sound, gain: f32) -> bool {
+        SPEAKER_CHANNEL.try_send(self.start(sound, gain)).is_ok()
+    }
+
+    pub async fn stop(self) {
+        SPEAKER_CHANNEL
+            .send(SpeakerCommand::StopSound(self.id))
+            .await;
+    }
+
+    /// Like `stop`, for places that cannot wait.
+    pub fn try_stop(self) -> bool {
+        SPEAKER_CHANNEL
+            .try_send(SpeakerCommand::StopSound(self.id))
+            .is_ok()
+    }
+
+    /// Pauses the sound where it is. It still counts as playing.
+    pub async fn pause(self) {
+        SPEAKER_CHANNEL.send(SpeakerCommand::Pause(self.id)).await;
+    }
+
+    /// Carries on from where the sound was paused.
+    pub async fn resume(self) {
+        SPEAKER_CHANNEL.send(SpeakerCommand::Resume(self.id)).await;
+    }
+
+    /// Moves the sound to `frame` frames from its start.
+    pub async fn seek(self, frame: u32) {
+        SPEAKER_CHANNEL
+            .send(SpeakerCommand::Seek { id: self.id, frame })
+            .await;
+    }
+
+    /// Plays the sound at `speed` times its normal speed, in `mode`.
+    /// Playing a new sound through the handle puts it back to 1.0.
+    pub async fn set_speed(self, speed: f32, mode: SpeedMode) {
+        SPEAKER_CHANNEL
+            .send(SpeakerCommand::SetSpeed {
+                id: self.id,
+                speed,
+                mode,
+            })
+            .await;
+    }
+
+    /// Sets whether the sound starts over when it reaches the end.
+    /// Playing a new sound through the handle turns this off again.
+    pub async fn set_looping(self, looping: bool) {
+        SPEAKER_CHANNEL
+            .send(SpeakerCommand::SetLooping {
+                id: self.id,
+                looping,
+            })
+            .await;
+    }
+
+    /// Where the sound was when the speaker last mixed a block, or
+    /// None if it was not playing.
+    pub fn position(self) -> Option<SoundPosition> {
+        sound_position(self.id)
+    }
+
+    /// Whether the sound was still playing when the speaker last
+    /// mixed a block. A sound that was only just sent may not have
+    /// started yet.
+    pub fn is_playing(self) -> bool {
+        is_sound_playing(self.id)
+    }
+
+    fn start(self, sound: Sound, gain: f32) -> SpeakerCommand {
+        SpeakerCommand::Start {
+            id: self.id,
+            group: VoiceGroup::User,
+            sound,
+            gain,
+        }
+    }
+}
+
+impl Default for UserSound {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Plays `sound` at `gain` on a new handle.
+pub async fn play_user_sound(sound: Sound, gain: f32) -> UserSound {
+    let handle = UserSound::new();
+    handle.play(sound, gain).await;
+    handle
+}
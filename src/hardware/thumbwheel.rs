@@ -1,23 +1,32 @@
+#[cfg(not(feature = "esp32s3"))]
+use core::cell::Cell;
+
+#[cfg(not(feature = "esp32s3"))]
+use embassy_sync::blocking_mutex::Mutex as BlockingMutex;
 use embassy_sync::{
     blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex,
 };
+#[cfg(feature = "esp32s3")]
 use esp_hal::{
     Blocking,
     analog::adc::{Adc, AdcConfig, AdcPin, Attenuation},
     peripherals::{ADC2, GPIO17, GPIO18},
 };
 
+#[cfg(feature = "esp32s3")]
 pub static THUMBWHEELS: Mutex<
     CriticalSectionRawMutex,
     Option<Thumbwheels>,
 > = Mutex::new(None);
 
+#[cfg(feature = "esp32s3")]
 pub struct Thumbwheels {
     adc: Adc<'static, ADC2<'static>, Blocking>,
     left: AdcPin<GPIO17<'static>, ADC2<'static>>,
     right: AdcPin<GPIO18<'static>, ADC2<'static>>,
 }
 
+#[cfg(feature = "esp32s3")]
 impl Thumbwheels {
     pub fn new(
         adc2: ADC2<'static>,
@@ -54,8 +63,24 @@ pub struct ThumbwheelValues {
     pub right: u16,
 }
 
+/// Reads the thumbwheels from any task. Every read returns `None` if
+/// the thumbwheels have not been initialized. In the simulator they
+/// read whatever was last given to `set_simulated_values`.
 pub struct ThumbwheelHandle;
 
+#[cfg(not(feature = "esp32s3"))]
+static SIMULATED_VALUES: BlockingMutex<
+    CriticalSectionRawMutex,
+    Cell<Option<ThumbwheelValues>>,
+> = BlockingMutex::new(Cell::new(None));
+
+/// Sets the raw readings the simulated thumbwheels return.
+#[cfg(not(feature = "esp32s3"))]
+pub fn set_simulated_values(values: ThumbwheelValues) {
+    SIMULATED_VALUES.lock(|current| current.set(Some(values)));
+}
+
+#[cfg(feature = "esp32s3")]
 impl ThumbwheelHandle {
     pub async fn left_raw() -> Option<u16> {
         THUMBWHEELS.lock().await.as_mut().map(Thumbwheels::left_raw)
@@ -77,3 +102,18 @@ impl ThumbwheelHandle {
             .map(Thumbwheels::raw_values)
     }
 }
+
+#[cfg(not(feature = "esp32s3"))]
+impl ThumbwheelHandle {
+    pub async fn left_raw() -> Option<u16> {
+        Self::raw_values().await.map(|values| values.left)
+    }
+
+    pub async fn right_raw() -> Option<u16> {
+        Self::raw_values().await.map(|values| values.right)
+    }
+
+    pub async fn raw_values() -> Option<ThumbwheelValues> {
+        SIMULATED_VALUES.lock(|values| values.get())
+    }
+}
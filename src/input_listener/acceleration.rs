@@ -0,0 +1,376 @@
+//! Acceleration for the rotary encoders. Turning an encoder quickly
+//! counts each detent more than once, so that large ranges can be
+//! crossed without dozens of turns, while slow turns still move one
+//! step at a time.
+//!
+//! Every detent is recorded by the input listener task as it comes
+//! in, whether or not anything asks for the accelerated value. States
+//! pick between the raw and the accelerated count with
+//! `InputListener::take_rotation`.
+//!
+//! The curve itself ([`AccelerationCurve`] and [`RotaryAccelerator`])
+//! does not touch any statics or hardware, so it can be tried out on
+//! the host.
+
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicI32, Ordering::SeqCst},
+};
+
+use embassy_sync::blocking_mutex::{
+    Mutex, raw::CriticalSectionRawMutex,
+};
+use embassy_time::{Duration, Instant};
+use rotary_encoder_embedded::Direction;
+
+use super::Input;
+
+static ACCELERATION_CURVE: Mutex<
+    CriticalSectionRawMutex,
+    Cell<AccelerationCurve>,
+> = Mutex::new(Cell::new(AccelerationCurve::DEFAULT));
+
+/// Indexed by `RotaryEncoder as usize`.
+static ACCELERATORS: Mutex<
+    CriticalSectionRawMutex,
+    Cell<[RotaryAccelerator; 2]>,
+> = Mutex::new(Cell::new([RotaryAccelerator::new(); 2]));
+
+/// The accelerated detents that have not been taken yet, clockwise
+/// is positive. Indexed by `RotaryEncoder as usize`.
+static ACCELERATED_DETENTS: [AtomicI32; 2] =
+    [AtomicI32::new(0), AtomicI32::new(0)];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotaryEncoder {
+    Left,
+    Right,
+}
+
+impl RotaryEncoder {
+    /// The encoder and direction of a rotation input, or None if
+    /// `input` is not a rotation.
+    pub fn rotated_by(input: Input) -> Option<(Self, Direction)> {
+        match input {
+            Input::RotaryEncoderRotateLeft(direction) => {
+                Some((RotaryEncoder::Left, direction))
+            }
+            Input::RotaryEncoderRotateRight(direction) => {
+                Some((RotaryEncoder::Right, direction))
+            }
+            _ => None,
+        }
+    }
+
+    /// The input sent when this encoder is turned in `direction`.
+    pub fn rotation_input(self, direction: Direction) -> Input {
+        match self {
+            RotaryEncoder::Left => {
+                Input::RotaryEncoderRotateLeft(direction)
+            }
+            RotaryEncoder::Right => {
+                Input::RotaryEncoderRotateRight(direction)
+            }
+        }
+    }
+}
+
+/// How a state wants the rotation of an encoder counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationMode {
+    /// One per detent.
+    Raw,
+    /// Scaled by how fast the encoder is being turned.
+    Accelerated,
+}
+
+/// Maps the time between two detents to how many steps the second
+/// detent counts as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccelerationCurve {
+    /// Detents this far apart or further count as one step.
+    pub slow_interval: Duration,
+    /// Detents this close together or closer count as
+    /// `max_multiplier` steps.
+    pub fast_interval: Duration,
+    pub max_multiplier: u16,
+    /// How much of the previous interval is kept when a new one
+    /// comes in, out of 256. Smooths out uneven turning.
+    pub smoothing: u16,
+}
+
+impl AccelerationCurve {
+    pub const DEFAULT: Self = Self {
+        slow_interval: Duration::from_millis(60),
+        fast_interval: Duration::from_millis(8),
+        max_multiplier: 12,
+        smoothing: 160,
+    };
+
+    /// How many steps a detent counts as, `interval` after the one
+    /// before it. Linear between the slow and fast intervals.
+    pub fn multiplier(&self, interval: Duration) -> u16 {
+        if interval >= self.slow_interval {
+            return 1;
+        }
+
+        if interval <= self.fast_interval {
+            return self.max_multiplier.max(1);
+        }
+
+        let span =
+            (self.slow_interval - self.fast_interval).as_micros();
+        let into = (self.slow_interval - interval).as_micros();
+        let extra = self.max_multiplier.saturating_sub(1) as u64;
+
+        1 + (extra * into / span) as u16
+    }
+}
+
+impl Default for AccelerationCurve {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Keeps track of the last detent of one encoder.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RotaryAccelerator {
+    last_detent: Option<(Instant, Direction)>,
+    smoothed_interval: Option<Duration>,
+}
+
+impl RotaryAccelerator {
+    pub const fn new() -> Self {
+        Self {
+            last_detent: None,
+            smoothed_interval: None,
+        }
+    }
+
+    /// Records a detent at `at`, returns how many steps it counts as,
+    /// negative for anticlockwise. Turning the other way starts over
+    /// from one step.
+    pub fn detent(
+        &mut self,
+        curve: &AccelerationCurve,
+        direction: Direction,
+        at: Instant,
+    ) -> i32 {
+        let sign = match direction {
+            Direction::Clockwise => 1,
+            Direction::Anticlockwise => -1,
+            Direction::None => return 0,
+        };
+
+        let interval = match self.last_detent {
+            Some((last_at, last_direction))
+                if last_direction == direction && at >= last_at =>
+            {
+                Some(at - last_at)
+            }
+            _ => None,
+        };
+
+        self.last_detent = Some((at, direction));
+
+        // A pause or a change of direction starts over.
+        let Some(interval) = interval
+            .filter(|interval| *interval < curve.slow_interval)
+        else {
+            self.smoothed_interval = None;
+            return sign;
+        };
+
+        let smoothed = match self.smoothed_interval {
+            Some(previous) => {
+                let keep = curve.smoothing.min(256) as u64;
+                Duration::from_micros(
+                    (previous.as_micros() * keep
+                        + interval.as_micros() * (256 - keep))
+                        / 256,
+                )
+            }
+            None => interval,
+        };
+        self.smoothed_interval = Some(smoothed);
+
+        sign * curve.multiplier(smoothed) as i32
+    }
+}
+
+pub fn acceleration_curve() -> AccelerationCurve {
+    ACCELERATION_CURVE.lock(|curve| curve.get())
+}
+
+pub fn set_acceleration_curve(curve: AccelerationCurve) {
+    ACCELERATION_CURVE.lock(|current| current.set(curve));
+}
+
+/// Called by the input listener task for every input. Rotations are
+/// run through the accelerator of their encoder.
+pub(super) fn record_rotation(input: Input) {
+    let Some((encoder, direction)) = RotaryEncoder::rotated_by(input)
+    else {
+        return;
+    };
+
+    let curve = acceleration_curve();
+    let steps = ACCELERATORS.lock(|accelerators| {
+        let mut all = accelerators.get();
+        let steps = all[encoder as usize].detent(
+            &curve,
+            direction,
+            Instant::now(),
+        );
+        accelerators.set(all);
+        steps
+    });
+
+    let _ = ACCELERATED_DETENTS[encoder as usize].fetch_update(
+        SeqCst,
+        SeqCst,
+        |detents| Some(detents.saturating_add(steps)),
+    );
+}
+
+/// Takes every accelerated step of `encoder` that has built up.
+pub(super) fn take_accelerated(encoder: RotaryEncoder) -> i32 {
+    ACCELERATED_DETENTS[encoder as usize].swap(0, SeqCst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Round numbers so that the points in between come out exact.
+    const CURVE: AccelerationCurve = AccelerationCurve {
+        slow_interval: Duration::from_millis(50),
+        fast_interval: Duration::from_millis(10),
+        max_multiplier: 5,
+        smoothing: 0,
+    };
+
+    /// Feeds detents at the given times in ms, returns the steps each
+    /// one counted as.
+    fn run(
+        curve: &AccelerationCurve,
+        detents: &[(u64, Direction)],
+    ) -> Vec<i32> {
+        let mut accelerator = RotaryAccelerator::new();
+        detents
+            .iter()
+            .map(|&(at, direction)| {
+                accelerator.detent(
+                    curve,
+                    direction,
+                    Instant::from_millis(at),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn slow_and_fast_ends() {
+        let ms = Duration::from_millis;
+
+        assert_eq!(CURVE.multiplier(ms(50)), 1);
+        assert_eq!(CURVE.multiplier(ms(500)), 1);
+        assert_eq!(CURVE.multiplier(ms(10)), 5);
+        assert_eq!(CURVE.multiplier(ms(0)), 5);
+    }
+
+    #[test]
+    fn linear_in_between() {
+        let ms = Duration::from_millis;
+
+        assert_eq!(CURVE.multiplier(ms(40)), 2);
+        assert_eq!(CURVE.multiplier(ms(30)), 3);
+        assert_eq!(CURVE.multiplier(ms(20)), 4);
+    }
+
+    #[test]
+    fn zero_max_multiplier_still_steps() {
+        let curve = AccelerationCurve {
+            max_multiplier: 0,
+            ..CURVE
+        };
+        let ms = Duration::from_millis;
+
+        assert_eq!(curve.multiplier(ms(5)), 1);
+        assert_eq!(curve.multiplier(ms(30)), 1);
+        assert_eq!(curve.multiplier(ms(100)), 1);
+    }
+
+    #[test]
+    fn no_direction_is_no_step() {
+        use Direction::*;
+
+        assert_eq!(
+            run(
+                &CURVE,
+                &[(0, Clockwise), (5, None), (10, Clockwise)]
+            ),
+            [1, 0, 5]
+        );
+    }
+
+    #[test]
+    fn reversal_starts_over() {
+        use Direction::*;
+
+        assert_eq!(
+            run(
+                &CURVE,
+                &[
+                    (0, Clockwise),
+                    (10, Clockwise),
+                    (20, Anticlockwise),
+                    (30, Anticlockwise),
+                ]
+            ),
+            [1, 5, -1, -5]
+        );
+    }
+
+    #[test]
+    fn pause_starts_over() {
+        use Direction::*;
+
+        assert_eq!(
+            run(
+                &CURVE,
+                &[
+                    (0, Anticlockwise),
+                    (10, Anticlockwise),
+                    (60, Anticlockwise),
+                    (70, Anticlockwise),
+                ]
+            ),
+            [-1, -5, -1, -5]
+        );
+    }
+
+    #[test]
+    fn smoothing_damps_a_single_fast_detent() {
+        use Direction::*;
+
+        // Three detents 40 ms apart, then one only 10 ms later.
+        let detents = [
+            (0, Clockwise),
+            (40, Clockwise),
+            (80, Clockwise),
+            (90, Clockwise),
+        ];
+
+        assert_eq!(run(&CURVE, &detents), [1, 2, 2, 5]);
+
+        // Keeping half of the 40 ms interval puts the last one at
+        // 25 ms, between 30 ms and 20 ms on the curve.
+        let smoothed = AccelerationCurve {
+            smoothing: 128,
+            ..CURVE
+        };
+        assert_eq!(run(&smoothed, &detents), [1, 2, 2, 3]);
+    }
+}
@@ -0,0 +1,155 @@
+//! Typematic repeat for held controls. While a control is held down,
+//! its input is sent to `INPUT_CHANNEL` again after `delay`, then
+//! once every `interval`, the same as holding a key on a keyboard.
+//!
+//! Repeats are opted into per control. The D-pad repeats by default
+//! so that lists can be scrolled by holding it, everything else does
+//! not. The state machine puts the defaults back whenever the state
+//! changes, so a state only has to set what it wants in its setup.
+
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicU8, Ordering::SeqCst},
+};
+
+use embassy_executor::task;
+use embassy_futures::select::{Either, select};
+use embassy_sync::blocking_mutex::{
+    Mutex, raw::CriticalSectionRawMutex,
+};
+use embassy_time::{Duration, Instant, Timer};
+
+use super::{
+    INPUT_CHANNEL,
+    events::{Control, Edge, INPUT_EVENTS},
+};
+
+/// One bit per control, indexed by `Control as usize`.
+static ENABLED: AtomicU8 = AtomicU8::new(DEFAULT_ENABLED);
+
+const DEFAULT_ENABLED: u8 = (1 << Control::DpadTop as u8)
+    | (1 << Control::DpadBottom as u8)
+    | (1 << Control::DpadLeft as u8)
+    | (1 << Control::DpadRight as u8);
+
+static AUTO_REPEAT_CONFIG: Mutex<
+    CriticalSectionRawMutex,
+    Cell<AutoRepeatConfig>,
+> = Mutex::new(Cell::new(AutoRepeatConfig::DEFAULT));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutoRepeatConfig {
+    /// How long a control is held down for before it starts
+    /// repeating.
+    pub delay: Duration,
+    /// The time between repeats once it has started.
+    pub interval: Duration,
+}
+
+impl AutoRepeatConfig {
+    pub const DEFAULT: Self = Self {
+        delay: Duration::from_millis(400),
+        interval: Duration::from_millis(100),
+    };
+}
+
+impl Default for AutoRepeatConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+pub fn auto_repeat_config() -> AutoRepeatConfig {
+    AUTO_REPEAT_CONFIG.lock(|config| config.get())
+}
+
+/// Changes the delay and interval. Takes effect from the next press.
+pub fn set_auto_repeat_config(config: AutoRepeatConfig) {
+    AUTO_REPEAT_CONFIG.lock(|current| current.set(config));
+}
+
+/// Turns repeating on or off for `control`.
+pub fn set_auto_repeat(control: Control, enabled: bool) {
+    let bit = 1 << control as u8;
+
+    if enabled {
+        ENABLED.fetch_or(bit, SeqCst);
+    } else {
+        ENABLED.fetch_and(!bit, SeqCst);
+    }
+}
+
+pub fn auto_repeat_enabled(control: Control) -> bool {
+    ENABLED.load(SeqCst) & (1 << control as u8) != 0
+}
+
+/// Puts back the default controls, delay and interval.
+pub fn reset_auto_repeat() {
+    ENABLED.store(DEFAULT_ENABLED, SeqCst);
+    set_auto_repeat_config(AutoRepeatConfig::DEFAULT);
+}
+
+/// Watches the input events and sends repeated inputs for the
+/// controls that are held down.
+#[task]
+pub async fn auto_repeater() {
+    let mut events = INPUT_EVENTS
+        .subscriber()
+        .expect("no input event subscribers left");
+
+    // When each held control should next repeat.
+    let mut next_repeat: [Option<Instant>; Control::COUNT] =
+        [None; Control::COUNT];
+
+    loop {
+        let deadline = Control::ALL
+            .into_iter()
+            .filter(|control| auto_repeat_enabled(*control))
+            .filter_map(|control| next_repeat[control as usize])
+            .min();
+
+        let event = match deadline {
+            Some(deadline) => match select(
+                events.next_message_pure(),
+                Timer::at(deadline),
+            )
+            .await
+            {
+                Either::First(event) => Some(event),
+                Either::Second(()) => None,
+            },
+            None => Some(events.next_message_pure().await),
+        };
+
+        if let Some(event) = event {
+            next_repeat[event.control as usize] = match event.edge {
+                Edge::Press => {
+                    Some(event.timestamp + auto_repeat_config().delay)
+                }
+                Edge::Release => None,
+            };
+            continue;
+        }
+
+        let now = Instant::now();
+        let interval = auto_repeat_config().interval;
+
+        for control in Control::ALL {
+            let Some(at) = next_repeat[control as usize] else {
+                continue;
+            };
+
+            if at > now || !auto_repeat_enabled(control) {
+                continue;
+            }
+
+            INPUT_CHANNEL.send(control.pressed_input()).await;
+
+            // If the channel was full for a while, do not send all of
+            // the repeats that were missed at once.
+            let next = at + interval;
+            next_repeat[control as usize] =
+                Some(if next < now { now + interval } else { next });
+        }
+    }
+}
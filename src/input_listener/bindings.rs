@@ -0,0 +1,234 @@
+//! Logical actions and the physical controls they are bound to. Apps
+//! that ask for an [`Action`] or a [`Dial`] instead of a specific
+//! `Input` follow whatever the user has set up, so the buttons and
+//! encoders can be swapped around (for example for left-handed use)
+//! without the apps knowing.
+//!
+//! Every action is bound to exactly one control and every control to
+//! exactly one action, so binding an action to a control swaps it
+//! with the action that had that control before. The same goes for
+//! the dials and the encoders.
+//!
+//! The bindings are stored in `settings`, globally and per app, and
+//! saved with the rest of the settings. The state machine picks the
+//! ones for whatever is running each tick, see `active_bindings`.
+
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::{
+    Mutex, raw::CriticalSectionRawMutex,
+};
+
+use super::{Input, acceleration::RotaryEncoder, events::Control};
+
+static ACTIVE_BINDINGS: Mutex<
+    CriticalSectionRawMutex,
+    Cell<Bindings>,
+> = Mutex::new(Cell::new(Bindings::DEFAULT));
+
+/// Something an app can be asked to do with a press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Back,
+    Confirm,
+    Up,
+    Down,
+    Previous,
+    Next,
+    /// Pressing the primary dial.
+    PrimaryPress,
+    /// Pressing the secondary dial.
+    SecondaryPress,
+}
+
+impl Action {
+    pub const COUNT: usize = 8;
+
+    pub const ALL: [Action; Self::COUNT] = [
+        Action::Back,
+        Action::Confirm,
+        Action::Up,
+        Action::Down,
+        Action::Previous,
+        Action::Next,
+        Action::PrimaryPress,
+        Action::SecondaryPress,
+    ];
+
+    /// A short name that fits on the mono display next to a control.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Back => "Back",
+            Action::Confirm => "Confirm",
+            Action::Up => "Up",
+            Action::Down => "Down",
+            Action::Previous => "Prev",
+            Action::Next => "Next",
+            Action::PrimaryPress => "Pri press",
+            Action::SecondaryPress => "Sec press",
+        }
+    }
+
+    /// The action that `input` is bound to in the active bindings,
+    /// if any.
+    pub fn from_input(input: Input) -> Option<Self> {
+        let control = Control::from_pressed_input(input)?;
+        Some(active_bindings().action(control))
+    }
+}
+
+/// Something an app can be asked to change by turning an encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dial {
+    Primary,
+    Secondary,
+}
+
+impl Dial {
+    pub const ALL: [Dial; 2] = [Dial::Primary, Dial::Secondary];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Dial::Primary => "Pri dial",
+            Dial::Secondary => "Sec dial",
+        }
+    }
+}
+
+/// Which control every action is bound to, and which encoder every
+/// dial is bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bindings {
+    /// Indexed by `Action as usize`.
+    controls: [Control; Action::COUNT],
+    /// Indexed by `Dial as usize`.
+    encoders: [RotaryEncoder; 2],
+}
+
+impl Bindings {
+    /// How many bytes `encode` takes: the index in `Control::ALL` of
+    /// the control of every action, then 1 if the dials are swapped.
+    pub const ENCODED_SIZE: usize = Action::COUNT + 1;
+
+    /// The layout the apps were written for.
+    pub const DEFAULT: Self = Self {
+        controls: [
+            Control::ButtonLeft,
+            Control::ButtonRight,
+            Control::DpadTop,
+            Control::DpadBottom,
+            Control::DpadLeft,
+            Control::DpadRight,
+            Control::RotarySwitchLeft,
+            Control::RotarySwitchRight,
+        ],
+        encoders: [RotaryEncoder::Left, RotaryEncoder::Right],
+    };
+
+    /// The default layout with the big buttons and the encoders
+    /// swapped. The D-pad stays the same.
+    pub const LEFT_HANDED: Self = Self {
+        controls: [
+            Control::ButtonRight,
+            Control::ButtonLeft,
+            Control::DpadTop,
+            Control::DpadBottom,
+            Control::DpadLeft,
+            Control::DpadRight,
+            Control::RotarySwitchRight,
+            Control::RotarySwitchLeft,
+        ],
+        encoders: [RotaryEncoder::Right, RotaryEncoder::Left],
+    };
+
+    pub fn control(&self, action: Action) -> Control {
+        self.controls[action as usize]
+    }
+
+    /// The action `control` is bound to.
+    pub fn action(&self, control: Control) -> Action {
+        Action::ALL
+            .into_iter()
+            .find(|action| self.control(*action) == control)
+            // every control is bound to something, see `bind`
+            .unwrap_or(Action::Back)
+    }
+
+    pub fn encoder(&self, dial: Dial) -> RotaryEncoder {
+        self.encoders[dial as usize]
+    }
+
+    /// Binds `action` to `control`. Whichever action had `control`
+    /// before gets the control `action` used to have.
+    pub fn bind(&mut self, action: Action, control: Control) {
+        let previous = self.control(action);
+        let displaced = self.action(control);
+
+        self.controls[displaced as usize] = previous;
+        self.controls[action as usize] = control;
+    }
+
+    /// Binds `dial` to `encoder`, swapping it with the other dial if
+    /// that one had it.
+    pub fn bind_dial(&mut self, dial: Dial, encoder: RotaryEncoder) {
+        if self.encoder(dial) != encoder {
+            self.encoders.swap(0, 1);
+        }
+    }
+
+    pub fn encode(&self) -> [u8; Self::ENCODED_SIZE] {
+        let mut bytes = [0; Self::ENCODED_SIZE];
+        for (byte, control) in bytes.iter_mut().zip(self.controls) {
+            *byte = Control::ALL
+                .iter()
+                .position(|other| *other == control)
+                .unwrap_or_default() as u8;
+        }
+        bytes[Action::COUNT] =
+            (self.encoders != Self::DEFAULT.encoders) as u8;
+        bytes
+    }
+
+    /// Reads what `encode` wrote. None if it does not bind every
+    /// control to exactly one action.
+    pub fn decode(bytes: &[u8; Self::ENCODED_SIZE]) -> Option<Self> {
+        let mut bindings = Self::DEFAULT;
+        for (control, byte) in
+            bindings.controls.iter_mut().zip(&bytes[..Action::COUNT])
+        {
+            *control = *Control::ALL.get(*byte as usize)?;
+        }
+
+        let every_control_bound = Control::ALL
+            .iter()
+            .all(|control| bindings.controls.contains(control));
+        if !every_control_bound {
+            return None;
+        }
+
+        match bytes[Action::COUNT] {
+            0 => {}
+            1 => bindings.encoders.swap(0, 1),
+            _ => return None,
+        }
+
+        Some(bindings)
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The bindings of whatever is running right now.
+pub fn active_bindings() -> Bindings {
+    ACTIVE_BINDINGS.lock(|bindings| bindings.get())
+}
+
+/// Called by the state machine with the bindings of the state it is
+/// running.
+pub fn set_active_bindings(bindings: Bindings) {
+    ACTIVE_BINDINGS.lock(|current| current.set(bindings));
+}
@@ -0,0 +1,227 @@
+//! Timestamped press and release edges for every button, D-pad
+//! direction and rotary switch.
+//!
+//! The counters in the parent module only say that something was
+//! pressed, which is enough for menus but not for anything that
+//! cares about how long a control is held for, or when it is let go.
+//! The hardware tasks publish an [`InputEvent`] here for both edges,
+//! alongside the usual `Input` they send to `INPUT_CHANNEL`.
+//!
+//! Events go out over a `PubSubChannel`, so every subscriber sees
+//! every event. If a subscriber falls behind, the oldest events are
+//! dropped rather than the hardware tasks being held up.
+
+use core::cell::Cell;
+
+use embassy_futures::select::{Either, select};
+use embassy_sync::{
+    blocking_mutex::{Mutex, raw::CriticalSectionRawMutex},
+    pubsub::{PubSubChannel, Subscriber, WaitResult},
+};
+use embassy_time::{Duration, Instant};
+
+use super::{Input, InputListener, KILL_WAKER, KillSignal};
+
+const INPUT_EVENTS_SIZE: usize = 32;
+/// The state machine holds one subscriber, the rest are for tasks
+/// that build on top of the raw edges, like the gesture recognizer.
+const INPUT_EVENTS_SUBSCRIBERS: usize = 4;
+
+/// Only immediate publishers are used, so no publisher slots are
+/// needed.
+pub static INPUT_EVENTS: PubSubChannel<
+    CriticalSectionRawMutex,
+    InputEvent,
+    INPUT_EVENTS_SIZE,
+    INPUT_EVENTS_SUBSCRIBERS,
+    0,
+> = PubSubChannel::new();
+
+/// When each control was pressed, or None if it is not held down.
+/// Indexed by `Control as usize`.
+static PRESSED_AT: Mutex<
+    CriticalSectionRawMutex,
+    Cell<[Option<Instant>; Control::COUNT]>,
+> = Mutex::new(Cell::new([None; Control::COUNT]));
+
+/// A physical control that has a press and a release. The rotary
+/// encoders' rotation is not here, as it has no edges to speak of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    ButtonLeft,
+    ButtonRight,
+    DpadTop,
+    DpadBottom,
+    DpadLeft,
+    DpadRight,
+    RotarySwitchLeft,
+    RotarySwitchRight,
+}
+
+impl Control {
+    pub const COUNT: usize = 8;
+
+    pub const ALL: [Control; Self::COUNT] = [
+        Control::ButtonLeft,
+        Control::ButtonRight,
+        Control::DpadTop,
+        Control::DpadBottom,
+        Control::DpadLeft,
+        Control::DpadRight,
+        Control::RotarySwitchLeft,
+        Control::RotarySwitchRight,
+    ];
+
+    /// The input that is sent to `INPUT_CHANNEL` when the control is
+    /// pressed.
+    pub fn pressed_input(self) -> Input {
+        match self {
+            Control::ButtonLeft => Input::ButtonLeft,
+            Control::ButtonRight => Input::ButtonRight,
+            Control::DpadTop => Input::DpadTop,
+            Control::DpadBottom => Input::DpadBottom,
+            Control::DpadLeft => Input::DpadLeft,
+            Control::DpadRight => Input::DpadRight,
+            Control::RotarySwitchLeft => {
+                Input::RotaryEncoderPressLeft
+            }
+            Control::RotarySwitchRight => {
+                Input::RotaryEncoderPressRight
+            }
+        }
+    }
+
+    /// The control whose press sends `input`, if there is one.
+    pub fn from_pressed_input(input: Input) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|control| control.pressed_input() == input)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Press,
+    Release,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub control: Control,
+    pub edge: Edge,
+    /// When the edge was published, after debouncing. That is at
+    /// least the `DebounceConfig` time of the control after the
+    /// contact first moved.
+    pub timestamp: Instant,
+}
+
+/// Stamps the edge with the current time and publishes it. Called by
+/// the hardware tasks as soon as an edge has been debounced.
+pub fn publish_edge(control: Control, edge: Edge) {
+    let timestamp = Instant::now();
+
+    PRESSED_AT.lock(|pressed_at| {
+        let mut pressed = pressed_at.get();
+        pressed[control as usize] = match edge {
+            Edge::Press => Some(timestamp),
+            Edge::Release => None,
+        };
+        pressed_at.set(pressed);
+    });
+
+    INPUT_EVENTS.immediate_publisher().publish_immediate(
+        InputEvent {
+            control,
+            edge,
+            timestamp,
+        },
+    );
+}
+
+/// When `control` was pressed, if it is being held down right now.
+pub fn pressed_at(control: Control) -> Option<Instant> {
+    PRESSED_AT.lock(|pressed_at| pressed_at.get()[control as usize])
+}
+
+/// How long `control` has been held down for, if it is being held
+/// down right now.
+pub fn held_for(control: Control) -> Option<Duration> {
+    pressed_at(control).map(|pressed_at| pressed_at.elapsed())
+}
+
+pub fn is_held(control: Control) -> bool {
+    pressed_at(control).is_some()
+}
+
+/// A subscription to the input events. Events that were dropped
+/// because the subscriber fell behind are skipped over.
+pub type InputEvents = Subscription<
+    InputEvent,
+    INPUT_EVENTS_SIZE,
+    INPUT_EVENTS_SUBSCRIBERS,
+>;
+
+/// A subscriber to one of the event channels in `input_listener`,
+/// for use by states. Waiting stops when the input listener is
+/// killed.
+pub struct Subscription<
+    T: Clone + 'static,
+    const CAP: usize,
+    const SUBS: usize,
+> {
+    subscriber:
+        Subscriber<'static, CriticalSectionRawMutex, T, CAP, SUBS, 0>,
+}
+
+impl<T: Clone + 'static, const CAP: usize, const SUBS: usize>
+    Subscription<T, CAP, SUBS>
+{
+    /// Subscribes to `channel`. Panics if every subscriber slot is
+    /// already taken.
+    pub fn subscribe(
+        channel: &'static PubSubChannel<
+            CriticalSectionRawMutex,
+            T,
+            CAP,
+            SUBS,
+            0,
+        >,
+    ) -> Self {
+        Self {
+            subscriber: channel
+                .subscriber()
+                .expect("no event subscribers left"),
+        }
+    }
+
+    /// Takes the next event if there is one.
+    pub fn try_next(&mut self) -> Option<T> {
+        loop {
+            match self.subscriber.try_next_message()? {
+                WaitResult::Message(event) => return Some(event),
+                WaitResult::Lagged(_) => continue,
+            }
+        }
+    }
+
+    /// Waits for the next event. Stops waiting if the input listener
+    /// is killed, the same as `InputListener::wait_for`.
+    pub async fn next(&mut self) -> Result<T, KillSignal> {
+        InputListener::check_kill_signal()?;
+
+        match select(
+            self.subscriber.next_message_pure(),
+            KILL_WAKER.wait(),
+        )
+        .await
+        {
+            Either::First(event) => Ok(event),
+            Either::Second(()) => Err(KillSignal),
+        }
+    }
+
+    /// Throws away every event that has not been taken yet.
+    pub fn drain(&mut self) {
+        while self.try_next().is_some() {}
+    }
+}
@@ -0,0 +1,387 @@
+//! Turns the press and release edges from `events` into gestures:
+//! long presses, double presses and chords.
+//!
+//! Gestures are recognized on top of the normal inputs, not instead
+//! of them. The first press of a double press and both presses of a
+//! chord still show up in the input counters, so states that care
+//! about gestures should not act on those presses straight away.
+//!
+//! The encoder switches are the exception, as their long presses and
+//! chords change the volume in every app (see `tasks::volume`). Their
+//! presses are held back until they are let go of, and dropped if
+//! they were used for a long press or a chord, see
+//! [`holds_back_press`].
+//!
+//! Once a press has been used for a gesture it is not used again, so
+//! a chord that is held for a long time does not also give two long
+//! presses, and a long press cannot be the first half of a double
+//! press.
+
+use core::cell::Cell;
+
+use embassy_executor::task;
+use embassy_futures::select::{Either, select};
+use embassy_sync::{
+    blocking_mutex::{Mutex, raw::CriticalSectionRawMutex},
+    pubsub::PubSubChannel,
+};
+use embassy_time::{Duration, Instant, Timer};
+
+use super::{
+    INPUT_CHANNEL, Input,
+    events::{Control, Edge, INPUT_EVENTS, InputEvent, Subscription},
+};
+
+const GESTURES_SIZE: usize = 16;
+/// The state machine and `tasks::volume`.
+const GESTURES_SUBSCRIBERS: usize = 2;
+
+pub static GESTURES: PubSubChannel<
+    CriticalSectionRawMutex,
+    GestureEvent,
+    GESTURES_SIZE,
+    GESTURES_SUBSCRIBERS,
+    0,
+> = PubSubChannel::new();
+
+/// A subscription to the recognized gestures.
+pub type Gestures =
+    Subscription<GestureEvent, GESTURES_SIZE, GESTURES_SUBSCRIBERS>;
+
+static GESTURE_CONFIG: Mutex<
+    CriticalSectionRawMutex,
+    Cell<GestureConfig>,
+> = Mutex::new(Cell::new(GestureConfig::DEFAULT));
+
+/// The timing thresholds used by the recognizer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GestureConfig {
+    /// How long a control has to be held down for before it is a
+    /// long press.
+    pub long_press: Duration,
+    /// The longest a control can be released for between the two
+    /// presses of a double press.
+    pub double_press_gap: Duration,
+    /// The longest time between the two presses of a chord.
+    pub chord_window: Duration,
+}
+
+impl GestureConfig {
+    pub const DEFAULT: Self = Self {
+        long_press: Duration::from_millis(600),
+        double_press_gap: Duration::from_millis(400),
+        chord_window: Duration::from_millis(100),
+    };
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The thresholds the recognizer is currently using.
+pub fn gesture_config() -> GestureConfig {
+    GESTURE_CONFIG.lock(|config| config.get())
+}
+
+/// Changes the thresholds. Takes effect from the next input event.
+pub fn set_gesture_config(config: GestureConfig) {
+    GESTURE_CONFIG.lock(|current| current.set(config));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gesture {
+    /// Sent while the control is still held down, as soon as it has
+    /// been held for `long_press`.
+    LongPress(Control),
+    /// Sent on the second press.
+    DoublePress(Control),
+    /// Two controls pressed within `chord_window` of each other,
+    /// in the order they were pressed.
+    Chord(Control, Control),
+}
+
+impl Gesture {
+    /// Returns true if this is a chord of `a` and `b`, in either
+    /// order.
+    pub fn is_chord(&self, a: Control, b: Control) -> bool {
+        match *self {
+            Gesture::Chord(first, second) => {
+                (first, second) == (a, b) || (first, second) == (b, a)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Whether the press of `control` is sent by the recognizer when it
+/// is let go of, instead of by its hardware task as soon as it is
+/// pressed. The press is not sent at all if it was used for a long
+/// press or a chord.
+pub fn holds_back_press(control: Control) -> bool {
+    matches!(
+        control,
+        Control::RotarySwitchLeft | Control::RotarySwitchRight
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GestureEvent {
+    pub gesture: Gesture,
+    pub timestamp: Instant,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ControlState {
+    pressed_at: Option<Instant>,
+    /// When the last press that was not used for a gesture was
+    /// released.
+    released_at: Option<Instant>,
+    /// Set once the current press has been used for a gesture.
+    used: bool,
+    /// Set once the current press has been used for a long press or
+    /// a chord, after which a held back press is not sent.
+    swallowed: bool,
+}
+
+struct Recognizer {
+    controls: [ControlState; Control::COUNT],
+    config: GestureConfig,
+}
+
+impl Recognizer {
+    fn new() -> Self {
+        Self {
+            controls: [ControlState::default(); Control::COUNT],
+            config: gesture_config(),
+        }
+    }
+
+    /// Returns the held back press to send, if `event` lets go of
+    /// one.
+    fn handle_event(&mut self, event: InputEvent) -> Option<Input> {
+        self.config = gesture_config();
+
+        match event.edge {
+            Edge::Press => {
+                self.handle_press(event);
+                None
+            }
+            Edge::Release => {
+                let state =
+                    &mut self.controls[event.control as usize];
+                let held_back = holds_back_press(event.control)
+                    && state.pressed_at.is_some()
+                    && !state.swallowed;
+                state.released_at =
+                    (!state.used).then_some(event.timestamp);
+                state.pressed_at = None;
+                state.used = false;
+                state.swallowed = false;
+
+                held_back.then(|| event.control.pressed_input())
+            }
+        }
+    }
+
+    fn handle_press(&mut self, event: InputEvent) {
+        let index = event.control as usize;
+        let released_at = self.controls[index].released_at;
+
+        self.controls[index] = ControlState {
+            pressed_at: Some(event.timestamp),
+            released_at: None,
+            used: false,
+            swallowed: false,
+        };
+
+        if released_at.is_some_and(|released_at| {
+            event.timestamp - released_at
+                <= self.config.double_press_gap
+        }) {
+            self.controls[index].used = true;
+            publish(
+                Gesture::DoublePress(event.control),
+                event.timestamp,
+            );
+            return;
+        }
+
+        let chorded_with = Control::ALL.into_iter().find(|other| {
+            let other_state = self.controls[*other as usize];
+
+            *other != event.control
+                && !other_state.used
+                && other_state.pressed_at.is_some_and(|pressed_at| {
+                    event.timestamp - pressed_at
+                        <= self.config.chord_window
+                })
+        });
+
+        if let Some(other) = chorded_with {
+            for control in [index, other as usize] {
+                self.controls[control].used = true;
+                self.controls[control].swallowed = true;
+            }
+            publish(
+                Gesture::Chord(other, event.control),
+                event.timestamp,
+            );
+        }
+    }
+
+    /// The next time a held control turns into a long press.
+    fn next_deadline(&self) -> Option<Instant> {
+        self.controls
+            .iter()
+            .filter(|state| !state.used)
+            .filter_map(|state| state.pressed_at)
+            .map(|pressed_at| pressed_at + self.config.long_press)
+            .min()
+    }
+
+    fn handle_deadline(&mut self, now: Instant) {
+        for control in Control::ALL {
+            let state = &mut self.controls[control as usize];
+
+            let Some(pressed_at) = state.pressed_at else {
+                continue;
+            };
+
+            if !state.used
+                && now - pressed_at >= self.config.long_press
+            {
+                state.used = true;
+                state.swallowed = true;
+                publish(
+                    Gesture::LongPress(control),
+                    pressed_at + self.config.long_press,
+                );
+            }
+        }
+    }
+}
+
+fn publish(gesture: Gesture, timestamp: Instant) {
+    GESTURES
+        .immediate_publisher()
+        .publish_immediate(GestureEvent { gesture, timestamp });
+}
+
+/// Watches the input events and publishes gestures to `GESTURES`.
+/// Also sends the presses that are held back to `INPUT_CHANNEL`.
+#[task]
+pub async fn gesture_recognizer() {
+    let mut events = INPUT_EVENTS
+        .subscriber()
+        .expect("no input event subscribers left");
+    let mut recognizer = Recognizer::new();
+
+    loop {
+        let event = match recognizer.next_deadline() {
+            Some(deadline) => match select(
+                events.next_message_pure(),
+                Timer::at(deadline),
+            )
+            .await
+            {
+                Either::First(event) => Some(event),
+                Either::Second(()) => None,
+            },
+            None => Some(events.next_message_pure().await),
+        };
+
+        match event {
+            Some(event) => {
+                if let Some(input) = recognizer.handle_event(event) {
+                    INPUT_CHANNEL.send(input).await;
+                }
+            }
+            None => recognizer.handle_deadline(Instant::now()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `edges`, a list of (milliseconds, control, pressed),
+    /// through a recognizer with the default thresholds, calling it
+    /// at every deadline in between like the task does. Returns the
+    /// held back presses it sends as (milliseconds, control).
+    fn run(edges: &[(u64, Control, bool)]) -> Vec<(u64, Control)> {
+        let mut recognizer = Recognizer::new();
+        let mut sent = Vec::new();
+
+        for &(ms, control, pressed) in edges {
+            let timestamp = Instant::from_millis(ms);
+            while let Some(deadline) = recognizer.next_deadline()
+                && deadline <= timestamp
+            {
+                recognizer.handle_deadline(deadline);
+            }
+
+            let edge =
+                if pressed { Edge::Press } else { Edge::Release };
+            let event = InputEvent {
+                control,
+                edge,
+                timestamp,
+            };
+            if let Some(input) = recognizer.handle_event(event) {
+                assert_eq!(input, control.pressed_input());
+                sent.push((ms, control));
+            }
+        }
+
+        sent
+    }
+
+    const LEFT: Control = Control::RotarySwitchLeft;
+    const RIGHT: Control = Control::RotarySwitchRight;
+
+    #[test]
+    fn short_presses_are_sent_on_release() {
+        let sent = run(&[(0, LEFT, true), (150, LEFT, false)]);
+        assert_eq!(sent, [(150, LEFT)]);
+    }
+
+    #[test]
+    fn long_presses_are_not_sent() {
+        let sent = run(&[(0, RIGHT, true), (900, RIGHT, false)]);
+        assert_eq!(sent, []);
+    }
+
+    #[test]
+    fn chords_are_not_sent() {
+        let sent = run(&[
+            (0, LEFT, true),
+            (50, RIGHT, true),
+            (200, LEFT, false),
+            (220, RIGHT, false),
+        ]);
+        assert_eq!(sent, []);
+    }
+
+    #[test]
+    fn double_presses_send_both_presses() {
+        let sent = run(&[
+            (0, RIGHT, true),
+            (100, RIGHT, false),
+            (300, RIGHT, true),
+            (400, RIGHT, false),
+        ]);
+        assert_eq!(sent, [(100, RIGHT), (400, RIGHT)]);
+    }
+
+    #[test]
+    fn other_controls_are_not_held_back() {
+        let sent = run(&[
+            (0, Control::ButtonLeft, true),
+            (900, Control::ButtonLeft, false),
+        ]);
+        assert_eq!(sent, []);
+    }
+}
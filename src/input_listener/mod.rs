@@ -21,15 +21,28 @@ use core::{
 
 use defmt::{dbg, println};
 use embassy_executor::task;
-use embassy_futures::select::Either;
+use embassy_futures::select::{Either, select};
 use embassy_sync::{
     blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel,
-    mutex::Mutex,
+    mutex::Mutex, signal::Signal,
 };
 use rotary_encoder_embedded::Direction;
 use static_cell::StaticCell;
 
+use self::{
+    acceleration::{RotaryEncoder, RotationMode},
+    bindings::{Action, Dial, active_bindings},
+    thumbwheel::Thumbwheel,
+};
+
+pub mod acceleration;
+pub mod auto_repeat;
+pub mod bindings;
+pub mod events;
+pub mod gestures;
+pub mod recorder;
 mod signal_acceptor;
+pub mod thumbwheel;
 
 const BUFFERED_INPUTS_SIZE: usize = 32;
 
@@ -63,6 +76,9 @@ pub static DPAD_TOP: AtomicU16 = AtomicU16::new(0);
 pub static DPAD_LEFT: AtomicU16 = AtomicU16::new(0);
 pub static DPAD_RIGHT: AtomicU16 = AtomicU16::new(0);
 
+pub static THUMBWHEEL_LEFT_MOVED: AtomicU16 = AtomicU16::new(0);
+pub static THUMBWHEEL_RIGHT_MOVED: AtomicU16 = AtomicU16::new(0);
+
 /// This is marked as Some with the specified input if there is
 /// an external source waiting on a signal. It basically says
 /// to start "forwarding" a signal to a waiter, instead of
@@ -72,6 +88,40 @@ pub static EXTERNAL_WAIT_FOR_SIGNAL: Mutex<
     Option<Either<Input, AllInputs>>,
 > = Mutex::new(None);
 
+/// Set by `InputListener::kill`. While this is set, every input
+/// listener function returns `Err(KillSignal)`.
+static KILL_SIGNAL: AtomicBool = AtomicBool::new(false);
+
+/// Wakes up anything waiting on an input when the kill signal is
+/// given.
+static KILL_WAKER: Signal<CriticalSectionRawMutex, ()> =
+    Signal::new();
+
+/// Where the listener task sends an input that a waiter asked for
+/// through `EXTERNAL_WAIT_FOR_SIGNAL`.
+static FORWARDED_INPUT: Signal<CriticalSectionRawMutex, Input> =
+    Signal::new();
+
+/// Every input that has a counter, in the order `wait_for_any` takes
+/// buffered inputs in.
+const ALL_INPUTS: [Input; 15] = [
+    Input::ButtonLeft,
+    Input::ButtonRight,
+    Input::ButtonRightReleased,
+    Input::DpadTop,
+    Input::DpadBottom,
+    Input::DpadLeft,
+    Input::DpadRight,
+    Input::RotaryEncoderPressLeft,
+    Input::RotaryEncoderRotateLeft(Direction::Clockwise),
+    Input::RotaryEncoderRotateLeft(Direction::Anticlockwise),
+    Input::RotaryEncoderPressRight,
+    Input::RotaryEncoderRotateRight(Direction::Clockwise),
+    Input::RotaryEncoderRotateRight(Direction::Anticlockwise),
+    Input::ThumbwheelMoved(Thumbwheel::Left),
+    Input::ThumbwheelMoved(Thumbwheel::Right),
+];
+
 #[derive(Clone, Copy)]
 pub struct AllInputs;
 
@@ -103,17 +153,33 @@ pub async fn start_input_listener_listener() {
     loop {
         let input = INPUT_CHANNEL.receive().await;
 
-        let external_wait_for_signal =
-            EXTERNAL_WAIT_FOR_SIGNAL.lock().await.clone();
-
-        match external_wait_for_signal {
-            // check to make sure that the input doesnt need to be
-            // forwarded
-            Some(input_kind) => match input_kind {
-                Either::First(waited_input) => todo!(),
-                Either::Second(_any_input) => todo!(),
-            },
-            None => InputListener::handle_no_wait_for_signal(input),
+        acceleration::record_rotation(input);
+        recorder::record(input);
+
+        let mut external_wait_for_signal =
+            EXTERNAL_WAIT_FOR_SIGNAL.lock().await;
+
+        // check to make sure that the input doesnt need to be
+        // forwarded
+        let forwarded = match *external_wait_for_signal {
+            Some(Either::First(waited_input)) => {
+                InputListener::handle_wait_for_signal(
+                    input,
+                    waited_input,
+                )
+            }
+            Some(Either::Second(_any_input)) => {
+                InputListener::handle_wait_for_any_signal(input)
+            }
+            None => {
+                InputListener::handle_no_wait_for_signal(input);
+                false
+            }
+        };
+
+        // the waiter only ever wants one input
+        if forwarded {
+            *external_wait_for_signal = None;
         }
     }
 }
@@ -135,6 +201,9 @@ pub enum Input {
     DpadTop,
     DpadLeft,
     DpadRight,
+    /// The thumbwheel has a new position, see
+    /// `thumbwheel::thumbwheel_position`.
+    ThumbwheelMoved(Thumbwheel),
 }
 
 /// If this is found in a Result, the program should exit and change
@@ -150,15 +219,83 @@ impl InputListener {
     /// Wait for any input, including inputs that have already
     /// happened. Each call to this function will "take" one instance
     /// of the keypress.
-    pub fn wait_for_any() -> Result<Input, KillSignal> {
-        todo!()
+    pub async fn wait_for_any() -> Result<Input, KillSignal> {
+        if let Some(input) = Self::take_any()? {
+            return Ok(input);
+        }
+
+        FORWARDED_INPUT.reset();
+        *EXTERNAL_WAIT_FOR_SIGNAL.lock().await =
+            Some(Either::Second(AllInputs));
+        let _waiting = StopWaitingOnDrop;
+
+        // An input could have been counted right before the wait was
+        // registered. A kill signal given in the meantime is picked
+        // up by the wait below.
+        if let Some(input) = Self::take_any().ok().flatten() {
+            return Ok(input);
+        }
+
+        Self::wait_for_forwarded_input().await
     }
 
     /// Wait for a specific kind of input, including inputs that have
     /// already happened. Each call to this function will "take" one
     /// instance of the keypress.
-    pub fn wait_for(input_kind: Input) -> Result<(), KillSignal> {
-        todo!()
+    pub async fn wait_for(
+        input_kind: Input,
+    ) -> Result<(), KillSignal> {
+        if Self::take_input(input_kind, false)?.is_some() {
+            return Ok(());
+        }
+
+        FORWARDED_INPUT.reset();
+        *EXTERNAL_WAIT_FOR_SIGNAL.lock().await =
+            Some(Either::First(input_kind));
+        let _waiting = StopWaitingOnDrop;
+
+        // An input could have been counted right before the wait was
+        // registered. A kill signal given in the meantime is picked
+        // up by the wait below.
+        if Self::take_input(input_kind, false)
+            .ok()
+            .flatten()
+            .is_some()
+        {
+            return Ok(());
+        }
+
+        Self::wait_for_forwarded_input().await.map(|_| ())
+    }
+
+    /// Waits for whichever control `action` is bound to right now.
+    pub async fn wait_for_action(
+        action: Action,
+    ) -> Result<(), KillSignal> {
+        Self::wait_for(
+            active_bindings().control(action).pressed_input(),
+        )
+        .await
+    }
+
+    async fn wait_for_forwarded_input() -> Result<Input, KillSignal> {
+        match select(FORWARDED_INPUT.wait(), KILL_WAKER.wait()).await
+        {
+            Either::First(input) => Ok(input),
+            Either::Second(()) => Err(KillSignal),
+        }
+    }
+
+    /// Takes one of whichever buffered input comes first in
+    /// `ALL_INPUTS`.
+    fn take_any() -> Result<Option<Input>, KillSignal> {
+        for input in ALL_INPUTS {
+            if Self::take_input(input, false)?.is_some() {
+                return Ok(Some(input));
+            }
+        }
+
+        Ok(None)
     }
 
     /// "Takes" one of the inputs that already exists. It is an option
@@ -171,47 +308,15 @@ impl InputListener {
         input: Input,
         take_total: bool,
     ) -> Result<Option<u16>, KillSignal> {
-        let counter = match input {
-            Input::RotaryEncoderPressLeft => {
-                &ROTARY_ENCODER_PRESS_LEFT
-            }
+        Self::check_kill_signal()?;
 
-            Input::RotaryEncoderRotateLeft(dir) => match dir {
-                Direction::Clockwise => {
-                    &ROTARY_ENCODER_ROTATE_LEFT_CW
-                }
-                Direction::Anticlockwise => {
-                    &ROTARY_ENCODER_ROTATE_LEFT_CCW
-                }
-                Direction::None => {
-                    panic!("Direction should not be None.")
-                }
-            },
-
-            Input::RotaryEncoderPressRight => {
-                &ROTARY_ENCODER_PRESS_RIGHT
-            }
+        // Whatever built up for the accelerated count is now out of
+        // date.
+        if let Some((encoder, _)) = RotaryEncoder::rotated_by(input) {
+            let _ = acceleration::take_accelerated(encoder);
+        }
 
-            Input::RotaryEncoderRotateRight(dir) => match dir {
-                Direction::Clockwise => {
-                    &ROTARY_ENCODER_ROTATE_RIGHT_CW
-                }
-                Direction::Anticlockwise => {
-                    &ROTARY_ENCODER_ROTATE_RIGHT_CCW
-                }
-                Direction::None => {
-                    panic!("Direction should not be None.")
-                }
-            },
-
-            Input::ButtonLeft => &BUTTON_LEFT,
-            Input::ButtonRight => &BUTTON_RIGHT,
-            Input::ButtonRightReleased => &BUTTON_RIGHT_RELEASED,
-            Input::DpadBottom => &DPAD_BOTTOM,
-            Input::DpadTop => &DPAD_TOP,
-            Input::DpadLeft => &DPAD_LEFT,
-            Input::DpadRight => &DPAD_RIGHT,
-        };
+        let counter = counter_for(input);
 
         if take_total {
             // Atomically take everything
@@ -234,18 +339,175 @@ impl InputListener {
         }
     }
 
+    /// `take_input` for whichever control `action` is bound to right
+    /// now.
+    pub fn take_action(
+        action: Action,
+        take_total: bool,
+    ) -> Result<Option<u16>, KillSignal> {
+        Self::take_input(
+            active_bindings().control(action).pressed_input(),
+            take_total,
+        )
+    }
+
+    /// `take_rotation` for whichever encoder `dial` is bound to right
+    /// now.
+    pub fn take_dial(
+        dial: Dial,
+        mode: RotationMode,
+    ) -> Result<i32, KillSignal> {
+        Self::take_rotation(active_bindings().encoder(dial), mode)
+    }
+
+    /// Takes every buffered rotation of `encoder`, clockwise is
+    /// positive. Both the raw and the accelerated counts are taken,
+    /// whichever one `mode` asks for is returned.
+    pub fn take_rotation(
+        encoder: RotaryEncoder,
+        mode: RotationMode,
+    ) -> Result<i32, KillSignal> {
+        Self::check_kill_signal()?;
+
+        let accelerated = acceleration::take_accelerated(encoder);
+
+        let (clockwise, anticlockwise) = match encoder {
+            RotaryEncoder::Left => (
+                &ROTARY_ENCODER_ROTATE_LEFT_CW,
+                &ROTARY_ENCODER_ROTATE_LEFT_CCW,
+            ),
+            RotaryEncoder::Right => (
+                &ROTARY_ENCODER_ROTATE_RIGHT_CW,
+                &ROTARY_ENCODER_ROTATE_RIGHT_CCW,
+            ),
+        };
+        let raw = clockwise.swap(0, SeqCst) as i32
+            - anticlockwise.swap(0, SeqCst) as i32;
+
+        Ok(match mode {
+            RotationMode::Raw => raw,
+            RotationMode::Accelerated => accelerated,
+        })
+    }
+
+    /// Takes every buffered move of `thumbwheel`. Returns the
+    /// position of the wheel if it has moved since the last take.
+    pub fn take_thumbwheel(
+        thumbwheel: Thumbwheel,
+    ) -> Result<Option<f32>, KillSignal> {
+        let moved = Self::take_input(
+            Input::ThumbwheelMoved(thumbwheel),
+            true,
+        )?;
+
+        Ok(moved.and(thumbwheel::thumbwheel_position(thumbwheel)))
+    }
+
     /// Returns true if there are inputs that are able to be taken.
     pub fn inputs_available() -> Result<bool, KillSignal> {
-        todo!()
+        Self::check_kill_signal()?;
+
+        Ok(ALL_INPUTS
+            .iter()
+            .any(|input| counter_for(*input).load(SeqCst) > 0))
     }
 
     /// Returns Ok(()) if there is no kill signal
     pub fn check_kill_signal() -> Result<(), KillSignal> {
-        todo!()
+        if KILL_SIGNAL.load(SeqCst) {
+            Err(KillSignal)
+        } else {
+            Ok(())
+        }
     }
 
-    /// Clears all signals and flags.
+    /// Gives the kill signal. Anything waiting on an input stops
+    /// waiting, and every function here returns `Err(KillSignal)`
+    /// until `clear` is called.
+    pub fn kill() {
+        KILL_SIGNAL.store(true, SeqCst);
+        KILL_WAKER.signal(());
+    }
+
+    /// Clears all signals and flags. Buffered inputs are kept.
     pub fn clear() {
-        todo!()
+        KILL_SIGNAL.store(false, SeqCst);
+        KILL_WAKER.reset();
+        FORWARDED_INPUT.reset();
+
+        // Waits always clean up after themselves, even when dropped
+        // halfway through, so this is only a precaution.
+        if let Ok(mut external_wait_for_signal) =
+            EXTERNAL_WAIT_FOR_SIGNAL.try_lock()
+        {
+            *external_wait_for_signal = None;
+        }
+    }
+}
+
+/// Stops forwarding inputs when a wait is over, however it ends. A
+/// wait that loses a `select` is dropped without ever finishing, and
+/// would otherwise keep every input from being counted until the next
+/// state change.
+struct StopWaitingOnDrop;
+
+impl Drop for StopWaitingOnDrop {
+    fn drop(&mut self) {
+        // The listener task never awaits while it holds the lock, so
+        // it is always free here.
+        if let Ok(mut external_wait_for_signal) =
+            EXTERNAL_WAIT_FOR_SIGNAL.try_lock()
+        {
+            *external_wait_for_signal = None;
+        }
+
+        // An input forwarded after the waiter stopped listening would
+        // otherwise be lost, so it goes back on its counter.
+        if let Some(input) = FORWARDED_INPUT.try_take() {
+            signal_acceptor::increment_counter(counter_for(input));
+        }
+    }
+}
+
+/// The counter that buffers `input`.
+fn counter_for(input: Input) -> &'static AtomicU16 {
+    match input {
+        Input::RotaryEncoderPressLeft => &ROTARY_ENCODER_PRESS_LEFT,
+
+        Input::RotaryEncoderRotateLeft(dir) => match dir {
+            Direction::Clockwise => &ROTARY_ENCODER_ROTATE_LEFT_CW,
+            Direction::Anticlockwise => {
+                &ROTARY_ENCODER_ROTATE_LEFT_CCW
+            }
+            Direction::None => {
+                panic!("Direction should not be None.")
+            }
+        },
+
+        Input::RotaryEncoderPressRight => &ROTARY_ENCODER_PRESS_RIGHT,
+
+        Input::RotaryEncoderRotateRight(dir) => match dir {
+            Direction::Clockwise => &ROTARY_ENCODER_ROTATE_RIGHT_CW,
+            Direction::Anticlockwise => {
+                &ROTARY_ENCODER_ROTATE_RIGHT_CCW
+            }
+            Direction::None => {
+                panic!("Direction should not be None.")
+            }
+        },
+
+        Input::ButtonLeft => &BUTTON_LEFT,
+        Input::ButtonRight => &BUTTON_RIGHT,
+        Input::ButtonRightReleased => &BUTTON_RIGHT_RELEASED,
+        Input::DpadBottom => &DPAD_BOTTOM,
+        Input::DpadTop => &DPAD_TOP,
+        Input::DpadLeft => &DPAD_LEFT,
+        Input::DpadRight => &DPAD_RIGHT,
+        Input::ThumbwheelMoved(Thumbwheel::Left) => {
+            &THUMBWHEEL_LEFT_MOVED
+        }
+        Input::ThumbwheelMoved(Thumbwheel::Right) => {
+            &THUMBWHEEL_RIGHT_MOVED
+        }
     }
 }
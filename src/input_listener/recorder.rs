@@ -0,0 +1,375 @@
+//! Records everything that goes through `INPUT_CHANNEL`, with the
+//! time it came in, and plays it back later with the same timing.
+//! Used to reproduce bugs and to run scripted demos.
+//!
+//! A recording also keeps the seeds that were in use when it was
+//! started, and puts them back before it is replayed, so that apps
+//! launched during the replay generate the same lights and palettes.
+//!
+//! Only `Input`s are recorded. The press and release edges in
+//! `events` are not, so gestures are not recognized during a replay.
+//! Auto-repeated inputs were sent to `INPUT_CHANNEL`, so they are in
+//! the recording and replay as they happened.
+//!
+//! Recordings can be printed over defmt with `dump`, or turned into
+//! bytes and back with `encode` and `decode`, which is how the
+//! simulator keeps them in files. The format is a small header
+//! followed by one entry per input:
+//!
+//! ```text
+//! "MREC" version:u8 light_show_seed:u16 palette_seed:u32 count:u16
+//! (offset_ms:u32 input:u8) * count
+//! ```
+//!
+//! All numbers are little endian, `input` is the index of the input
+//! in `ALL_INPUTS`.
+
+use core::cell::RefCell;
+
+use defmt::{println, warn};
+use embassy_executor::task;
+use embassy_futures::select::{Either, select};
+use embassy_sync::{
+    blocking_mutex::{Mutex, raw::CriticalSectionRawMutex},
+    signal::Signal,
+};
+use embassy_time::{Duration, Instant, Timer};
+use heapless::Vec;
+
+use super::{ALL_INPUTS, INPUT_CHANNEL, Input};
+use crate::seeds::{Seeds, seeds, set_seeds};
+
+/// How many inputs fit in a recording. Recording stops by itself once
+/// it is full.
+pub const RECORDING_CAPACITY: usize = 512;
+
+const MAGIC: [u8; 4] = *b"MREC";
+const FORMAT_VERSION: u8 = 1;
+const HEADER_SIZE: usize = 13;
+const ENTRY_SIZE: usize = 5;
+
+/// The most space an encoded recording can take up.
+pub const MAX_ENCODED_SIZE: usize =
+    HEADER_SIZE + RECORDING_CAPACITY * ENTRY_SIZE;
+
+static RECORDER: Mutex<CriticalSectionRawMutex, RefCell<Recorder>> =
+    Mutex::new(RefCell::new(Recorder {
+        recording: false,
+        replaying: false,
+        started_at: Instant::from_ticks(0),
+        seeds: Seeds::DEFAULT,
+        inputs: Vec::new(),
+    }));
+
+static START_REPLAY: Signal<CriticalSectionRawMutex, ()> =
+    Signal::new();
+static STOP_REPLAY: Signal<CriticalSectionRawMutex, ()> =
+    Signal::new();
+
+struct Recorder {
+    recording: bool,
+    replaying: bool,
+    started_at: Instant,
+    seeds: Seeds,
+    inputs: Vec<RecordedInput, RECORDING_CAPACITY>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecordedInput {
+    /// Milliseconds since the recording was started.
+    pub offset_ms: u32,
+    pub input: Input,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum RecordingError {
+    /// The buffer passed to `encode` cannot fit the recording.
+    BufferTooSmall,
+    /// The data does not start with a recording header, or was
+    /// written by a different version of the format.
+    BadHeader,
+    /// The recording has more inputs than `RECORDING_CAPACITY`.
+    TooLong,
+    /// An entry names an input that does not exist.
+    UnknownInput,
+}
+
+/// Throws away the current recording and starts a new one. The
+/// seeds in use right now are kept with it. Stops any replay.
+pub fn start_recording() {
+    stop_replay();
+
+    RECORDER.lock(|recorder| {
+        let mut recorder = recorder.borrow_mut();
+        recorder.recording = true;
+        recorder.started_at = Instant::now();
+        recorder.seeds = seeds();
+        recorder.inputs.clear();
+    });
+}
+
+/// Stops recording, returns how many inputs were recorded.
+pub fn stop_recording() -> usize {
+    RECORDER.lock(|recorder| {
+        let mut recorder = recorder.borrow_mut();
+        recorder.recording = false;
+        recorder.inputs.len()
+    })
+}
+
+pub fn is_recording() -> bool {
+    RECORDER.lock(|recorder| recorder.borrow().recording)
+}
+
+/// How many inputs are in the current recording.
+pub fn recorded_len() -> usize {
+    RECORDER.lock(|recorder| recorder.borrow().inputs.len())
+}
+
+/// Called by the input listener task for every input. Thumbwheel
+/// moves are left out, as the positions that go with them are not
+/// part of a recording.
+pub(super) fn record(input: Input) {
+    if matches!(input, Input::ThumbwheelMoved(_)) {
+        return;
+    }
+
+    RECORDER.lock(|recorder| {
+        let mut recorder = recorder.borrow_mut();
+        if !recorder.recording {
+            return;
+        }
+
+        let offset_ms =
+            recorder.started_at.elapsed().as_millis() as u32;
+        if recorder
+            .inputs
+            .push(RecordedInput { offset_ms, input })
+            .is_err()
+        {
+            warn!("input recording is full, stopped recording");
+            recorder.recording = false;
+        }
+    });
+}
+
+/// Replays the current recording from the start. Stops recording
+/// first, so that the replay does not record itself.
+pub fn start_replay() {
+    STOP_REPLAY.reset();
+    START_REPLAY.signal(());
+}
+
+pub fn stop_replay() {
+    START_REPLAY.reset();
+    if is_replaying() {
+        STOP_REPLAY.signal(());
+    }
+}
+
+pub fn is_replaying() -> bool {
+    RECORDER.lock(|recorder| recorder.borrow().replaying)
+}
+
+/// Writes the current recording into `buffer`, returns how many bytes
+/// were written.
+pub fn encode(buffer: &mut [u8]) -> Result<usize, RecordingError> {
+    let (seeds, count) = RECORDER.lock(|recorder| {
+        let recorder = recorder.borrow();
+        (recorder.seeds, recorder.inputs.len())
+    });
+
+    let len = HEADER_SIZE + count * ENTRY_SIZE;
+    let buffer = buffer
+        .get_mut(..len)
+        .ok_or(RecordingError::BufferTooSmall)?;
+
+    buffer[..HEADER_SIZE]
+        .copy_from_slice(&encode_header(seeds, count));
+    encode_entries(0, &mut buffer[HEADER_SIZE..]);
+
+    Ok(len)
+}
+
+/// Replaces the current recording with the one in `bytes`, returns
+/// how many inputs it has.
+pub fn decode(bytes: &[u8]) -> Result<usize, RecordingError> {
+    let header: &[u8; HEADER_SIZE] = bytes
+        .get(..HEADER_SIZE)
+        .and_then(|header| header.try_into().ok())
+        .ok_or(RecordingError::BadHeader)?;
+    let (seeds, count) = decode_header(header)?;
+
+    let entries = bytes
+        .get(HEADER_SIZE..HEADER_SIZE + count * ENTRY_SIZE)
+        .ok_or(RecordingError::BadHeader)?;
+
+    replace_recording(seeds);
+    append_entries(entries).inspect_err(|_| clear_inputs())?;
+
+    Ok(count)
+}
+
+/// Prints the current recording over defmt, in the same format as
+/// `encode`, as lines of hex.
+pub fn dump() {
+    let (seeds, count) = RECORDER.lock(|recorder| {
+        let recorder = recorder.borrow();
+        (recorder.seeds, recorder.inputs.len())
+    });
+
+    println!("input recording, {} inputs", count);
+    println!("{=[u8]:02x}", encode_header(seeds, count));
+
+    let mut bytes = [0; 8 * ENTRY_SIZE];
+    for start in (0..count).step_by(8) {
+        let len = encode_entries(start, &mut bytes);
+        println!("{=[u8]:02x}", bytes[..len]);
+    }
+}
+
+/// Encodes as many entries as fit in `bytes`, starting at the entry
+/// `start`. Returns how many bytes were written.
+fn encode_entries(start: usize, bytes: &mut [u8]) -> usize {
+    RECORDER.lock(|recorder| {
+        let recorder = recorder.borrow();
+        let inputs = recorder.inputs.get(start..).unwrap_or_default();
+
+        bytes
+            .chunks_exact_mut(ENTRY_SIZE)
+            .zip(inputs)
+            .map(|(entry, recorded)| {
+                entry.copy_from_slice(&encode_entry(recorded));
+                ENTRY_SIZE
+            })
+            .sum()
+    })
+}
+
+/// Starts an empty recording with `seeds`, for entries to be appended
+/// to.
+fn replace_recording(seeds: Seeds) {
+    stop_replay();
+
+    RECORDER.lock(|recorder| {
+        let mut recorder = recorder.borrow_mut();
+        recorder.recording = false;
+        recorder.seeds = seeds;
+        recorder.inputs.clear();
+    });
+}
+
+fn append_entries(entries: &[u8]) -> Result<(), RecordingError> {
+    for entry in entries.chunks_exact(ENTRY_SIZE) {
+        let recorded = decode_entry(entry)?;
+        RECORDER.lock(|recorder| {
+            recorder
+                .borrow_mut()
+                .inputs
+                .push(recorded)
+                .map_err(|_| RecordingError::TooLong)
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Leaves nothing behind of a recording that failed to decode.
+fn clear_inputs() {
+    RECORDER.lock(|recorder| recorder.borrow_mut().inputs.clear());
+}
+
+fn encode_header(seeds: Seeds, count: usize) -> [u8; HEADER_SIZE] {
+    let mut header = [0; HEADER_SIZE];
+    header[..4].copy_from_slice(&MAGIC);
+    header[4] = FORMAT_VERSION;
+    header[5..7].copy_from_slice(&seeds.light_show.to_le_bytes());
+    header[7..11].copy_from_slice(&seeds.palette.to_le_bytes());
+    header[11..13].copy_from_slice(&(count as u16).to_le_bytes());
+    header
+}
+
+fn decode_header(
+    header: &[u8; HEADER_SIZE],
+) -> Result<(Seeds, usize), RecordingError> {
+    if header[..4] != MAGIC || header[4] != FORMAT_VERSION {
+        return Err(RecordingError::BadHeader);
+    }
+
+    let seeds = Seeds {
+        light_show: u16::from_le_bytes([header[5], header[6]]),
+        palette: u32::from_le_bytes([
+            header[7], header[8], header[9], header[10],
+        ]),
+    };
+    let count = u16::from_le_bytes([header[11], header[12]]) as usize;
+
+    if count > RECORDING_CAPACITY {
+        return Err(RecordingError::TooLong);
+    }
+
+    Ok((seeds, count))
+}
+
+fn encode_entry(recorded: &RecordedInput) -> [u8; ENTRY_SIZE] {
+    let code = ALL_INPUTS
+        .iter()
+        .position(|input| *input == recorded.input)
+        .unwrap_or_default() as u8;
+
+    let mut entry = [0; ENTRY_SIZE];
+    entry[..4].copy_from_slice(&recorded.offset_ms.to_le_bytes());
+    entry[4] = code;
+    entry
+}
+
+fn decode_entry(
+    entry: &[u8],
+) -> Result<RecordedInput, RecordingError> {
+    let offset_ms =
+        u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]);
+    let input = *ALL_INPUTS
+        .get(entry[4] as usize)
+        .ok_or(RecordingError::UnknownInput)?;
+
+    Ok(RecordedInput { offset_ms, input })
+}
+
+/// Waits for `start_replay`, then sends the recorded inputs to
+/// `INPUT_CHANNEL` with the same timing they were recorded with.
+#[task]
+pub async fn input_replayer() {
+    loop {
+        START_REPLAY.wait().await;
+
+        let seeds = RECORDER.lock(|recorder| {
+            let mut recorder = recorder.borrow_mut();
+            recorder.recording = false;
+            recorder.replaying = true;
+            recorder.seeds
+        });
+        set_seeds(seeds);
+
+        let started_at = Instant::now();
+        let mut index = 0;
+
+        while let Some(recorded) = RECORDER.lock(|recorder| {
+            recorder.borrow().inputs.get(index).copied()
+        }) {
+            let at = started_at
+                + Duration::from_millis(recorded.offset_ms as u64);
+            if let Either::Second(()) =
+                select(Timer::at(at), STOP_REPLAY.wait()).await
+            {
+                break;
+            }
+
+            INPUT_CHANNEL.send(recorded.input).await;
+            index += 1;
+        }
+
+        RECORDER.lock(|recorder| {
+            recorder.borrow_mut().replaying = false;
+        });
+    }
+}
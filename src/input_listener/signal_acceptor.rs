@@ -1,94 +1,37 @@
 use core::sync::atomic::{AtomicU16, Ordering::SeqCst};
 
-use rotary_encoder_embedded::Direction;
-
-use super::{Input, InputListener};
+use super::{FORWARDED_INPUT, Input, InputListener, counter_for};
 
 impl InputListener {
     pub(super) fn handle_no_wait_for_signal(input: Input) {
-        match input {
-            Input::RotaryEncoderPressLeft => {
-                increment_counter(&super::ROTARY_ENCODER_PRESS_LEFT);
-            }
-
-            Input::RotaryEncoderRotateLeft(dir) => match dir {
-                Direction::Clockwise => {
-                    increment_counter(
-                        &super::ROTARY_ENCODER_ROTATE_LEFT_CW,
-                    );
-                }
-                Direction::Anticlockwise => {
-                    increment_counter(
-                        &super::ROTARY_ENCODER_ROTATE_LEFT_CCW,
-                    );
-                }
-                Direction::None => {
-                    panic!("Direction should not be None.")
-                }
-            },
-
-            Input::RotaryEncoderPressRight => {
-                increment_counter(&super::ROTARY_ENCODER_PRESS_RIGHT);
-            }
-
-            Input::RotaryEncoderRotateRight(dir) => match dir {
-                Direction::Clockwise => {
-                    increment_counter(
-                        &super::ROTARY_ENCODER_ROTATE_RIGHT_CW,
-                    );
-                }
-                Direction::Anticlockwise => {
-                    increment_counter(
-                        &super::ROTARY_ENCODER_ROTATE_RIGHT_CCW,
-                    );
-                }
-                Direction::None => {
-                    panic!("Direction should not be None.")
-                }
-            },
-
-            Input::ButtonLeft => {
-                increment_counter(&super::BUTTON_LEFT);
-            }
-            Input::ButtonRight => {
-                increment_counter(&super::BUTTON_RIGHT);
-            }
-            Input::ButtonRightReleased => {
-                increment_counter(&super::BUTTON_RIGHT_RELEASED);
-            }
-
-            Input::DpadBottom => {
-                increment_counter(&super::DPAD_BOTTOM);
-            }
-            Input::DpadTop => {
-                increment_counter(&super::DPAD_TOP);
-            }
-            Input::DpadLeft => {
-                increment_counter(&super::DPAD_LEFT);
-            }
-            Input::DpadRight => {
-                increment_counter(&super::DPAD_RIGHT);
-            }
-        }
+        increment_counter(counter_for(input));
     }
 
+    /// Forwards `input` to the waiter if it is the input being waited
+    /// on, otherwise it is buffered like normal. Returns true if it
+    /// was forwarded.
     pub(super) fn handle_wait_for_signal(
-        &mut self,
         input: Input,
         waited_input: Input,
-    ) {
-        todo!()
+    ) -> bool {
+        if input == waited_input {
+            FORWARDED_INPUT.signal(input);
+            true
+        } else {
+            Self::handle_no_wait_for_signal(input);
+            false
+        }
     }
 
-    pub(super) fn handle_wait_for_any_signal(
-        &mut self,
-        input: Input,
-    ) {
-        todo!()
+    /// Forwards `input` to the waiter. Always returns true.
+    pub(super) fn handle_wait_for_any_signal(input: Input) -> bool {
+        FORWARDED_INPUT.signal(input);
+        true
     }
 }
 
-fn increment_counter(counter: &AtomicU16) {
+/// Adds one to `counter`, stopping at `u16::MAX` instead of wrapping.
+pub(super) fn increment_counter(counter: &AtomicU16) {
     let _ = counter.fetch_update(SeqCst, SeqCst, |value| {
         Some(value.saturating_add(1))
     });
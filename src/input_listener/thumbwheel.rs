@@ -0,0 +1,462 @@
+//! Turns the raw ADC readings of the thumbwheels into positions from
+//! 0.0 to 1.0 that apps can use as analog controls.
+//!
+//! Every reading goes through the same steps:
+//! 1. Smoothing, so that ADC noise does not make the position jitter.
+//! 2. Scaling between the calibrated minimum and maximum of the
+//!    wheel, as the wheels do not reach either end of the ADC range.
+//! 3. A dead zone at each end, so that 0.0 and 1.0 can be reached
+//!    without rolling the wheel hard against its stop.
+//! 4. Hysteresis, so that a new position is only reported once it has
+//!    moved far enough from the last one.
+//!
+//! Whenever a wheel reports a new position, the listener task sends
+//! `Input::ThumbwheelMoved` for it. States can take that input like
+//! any other, or use `InputListener::take_thumbwheel` to get the
+//! position along with it.
+//!
+//! The filter itself ([`ThumbwheelFilter`]) does not touch any
+//! statics or hardware.
+
+use core::cell::Cell;
+
+use embassy_executor::task;
+use embassy_sync::blocking_mutex::{
+    Mutex, raw::CriticalSectionRawMutex,
+};
+use embassy_time::{Duration, Timer};
+
+use super::{INPUT_CHANNEL, Input};
+use crate::hardware::thumbwheel::ThumbwheelHandle;
+
+/// Calibrations closer together than this are too narrow to have
+/// come from rolling the wheel end to end, and are thrown away.
+const MIN_CALIBRATION_SPAN: u16 = 256;
+
+static THUMBWHEEL_CONFIG: Mutex<
+    CriticalSectionRawMutex,
+    Cell<ThumbwheelConfig>,
+> = Mutex::new(Cell::new(ThumbwheelConfig::DEFAULT));
+
+/// Indexed by `Thumbwheel as usize`.
+static CALIBRATIONS: Mutex<
+    CriticalSectionRawMutex,
+    Cell<[ThumbwheelCalibration; 2]>,
+> = Mutex::new(Cell::new([ThumbwheelCalibration::DEFAULT; 2]));
+
+/// The calibration from before `start_thumbwheel_calibration`, while
+/// a new one is being taken.
+static CALIBRATING_FROM: Mutex<
+    CriticalSectionRawMutex,
+    Cell<Option<[ThumbwheelCalibration; 2]>>,
+> = Mutex::new(Cell::new(None));
+
+/// The last reported position of each wheel, None until the first
+/// reading. Indexed by `Thumbwheel as usize`.
+static POSITIONS: Mutex<
+    CriticalSectionRawMutex,
+    Cell<[Option<f32>; 2]>,
+> = Mutex::new(Cell::new([None; 2]));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Thumbwheel {
+    Left,
+    Right,
+}
+
+impl Thumbwheel {
+    pub const ALL: [Self; 2] = [Thumbwheel::Left, Thumbwheel::Right];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThumbwheelConfig {
+    /// How often the wheels are read.
+    pub poll_interval: Duration,
+    /// How much of the smoothed reading is kept when a new one comes
+    /// in, out of 256.
+    pub smoothing: u16,
+    /// How far the position has to move before it is reported again.
+    pub hysteresis: f32,
+    /// How much of each end of the range counts as that end.
+    pub dead_zone: f32,
+}
+
+impl ThumbwheelConfig {
+    pub const DEFAULT: Self = Self {
+        poll_interval: Duration::from_millis(20),
+        smoothing: 192,
+        hysteresis: 0.01,
+        dead_zone: 0.03,
+    };
+}
+
+impl Default for ThumbwheelConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The raw readings at either end of a wheel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThumbwheelCalibration {
+    pub min: u16,
+    pub max: u16,
+}
+
+impl ThumbwheelCalibration {
+    /// The full range of the 12 bit ADC.
+    pub const DEFAULT: Self = Self { min: 0, max: 4095 };
+
+    /// Where `raw` sits between the ends, from 0.0 to 1.0.
+    pub fn normalize(&self, raw: f32) -> f32 {
+        let span = self.max.saturating_sub(self.min).max(1) as f32;
+        ((raw - self.min as f32) / span).clamp(0.0, 1.0)
+    }
+
+    /// Widens the range to take in `raw`.
+    fn widen(&mut self, raw: u16) {
+        self.min = self.min.min(raw);
+        self.max = self.max.max(raw);
+    }
+}
+
+impl Default for ThumbwheelCalibration {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Keeps track of the readings of one wheel.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThumbwheelFilter {
+    smoothed: Option<f32>,
+    reported: Option<f32>,
+}
+
+impl ThumbwheelFilter {
+    pub const fn new() -> Self {
+        Self {
+            smoothed: None,
+            reported: None,
+        }
+    }
+
+    /// Takes a raw reading, returns the new position if it has moved
+    /// far enough to be reported.
+    pub fn update(
+        &mut self,
+        config: &ThumbwheelConfig,
+        calibration: &ThumbwheelCalibration,
+        raw: u16,
+    ) -> Option<f32> {
+        let raw = raw as f32;
+        let smoothed = match self.smoothed {
+            Some(previous) => {
+                let keep = config.smoothing.min(256) as f32 / 256.0;
+                previous * keep + raw * (1.0 - keep)
+            }
+            None => raw,
+        };
+        self.smoothed = Some(smoothed);
+
+        let position = apply_dead_zone(
+            calibration.normalize(smoothed),
+            config.dead_zone,
+        );
+
+        let moved = match self.reported {
+            Some(reported) => {
+                (position - reported).abs() >= config.hysteresis
+                    // the ends are always reachable
+                    || (position != reported
+                        && (position == 0.0 || position == 1.0))
+            }
+            None => true,
+        };
+
+        if !moved {
+            return None;
+        }
+
+        self.reported = Some(position);
+        Some(position)
+    }
+}
+
+/// Snaps the ends of `position` to 0.0 and 1.0 and stretches what is
+/// left in between back over the whole range.
+fn apply_dead_zone(position: f32, dead_zone: f32) -> f32 {
+    let dead_zone = dead_zone.clamp(0.0, 0.49);
+    ((position - dead_zone) / (1.0 - 2.0 * dead_zone)).clamp(0.0, 1.0)
+}
+
+pub fn thumbwheel_config() -> ThumbwheelConfig {
+    THUMBWHEEL_CONFIG.lock(|config| config.get())
+}
+
+pub fn set_thumbwheel_config(config: ThumbwheelConfig) {
+    THUMBWHEEL_CONFIG.lock(|current| current.set(config));
+}
+
+pub fn thumbwheel_calibration(
+    thumbwheel: Thumbwheel,
+) -> ThumbwheelCalibration {
+    CALIBRATIONS
+        .lock(|calibrations| calibrations.get()[thumbwheel as usize])
+}
+
+pub fn set_thumbwheel_calibration(
+    thumbwheel: Thumbwheel,
+    calibration: ThumbwheelCalibration,
+) {
+    CALIBRATIONS.lock(|calibrations| {
+        let mut all = calibrations.get();
+        all[thumbwheel as usize] = calibration;
+        calibrations.set(all);
+    });
+}
+
+/// Starts a new calibration of both wheels. Until it is finished,
+/// the range of each wheel is whatever it has been rolled across, so
+/// both should be rolled from one end to the other.
+pub fn start_thumbwheel_calibration() {
+    let previous = CALIBRATIONS.lock(|calibrations| {
+        let previous = calibrations.get();
+        calibrations.set(
+            [ThumbwheelCalibration {
+                min: u16::MAX,
+                max: 0,
+            }; 2],
+        );
+        previous
+    });
+
+    CALIBRATING_FROM.lock(|from| {
+        // a calibration that was already running keeps the
+        // calibration from before it
+        if from.get().is_none() {
+            from.set(Some(previous));
+        }
+    });
+}
+
+/// Finishes the calibration. A wheel that was not rolled far enough
+/// keeps its old calibration. Returns true if both wheels got a new
+/// one.
+pub fn finish_thumbwheel_calibration() -> bool {
+    let Some(previous) = CALIBRATING_FROM.lock(|from| from.take())
+    else {
+        return false;
+    };
+
+    CALIBRATIONS.lock(|calibrations| {
+        let mut all = calibrations.get();
+        let mut calibrated = true;
+
+        for (calibration, previous) in all.iter_mut().zip(previous) {
+            if calibration.max.saturating_sub(calibration.min)
+                < MIN_CALIBRATION_SPAN
+            {
+                *calibration = previous;
+                calibrated = false;
+            }
+        }
+
+        calibrations.set(all);
+        calibrated
+    })
+}
+
+pub fn is_calibrating_thumbwheels() -> bool {
+    CALIBRATING_FROM.lock(|from| from.get().is_some())
+}
+
+/// The last reported position of `thumbwheel`, from 0.0 to 1.0. None
+/// if the wheel has not been read yet.
+pub fn thumbwheel_position(thumbwheel: Thumbwheel) -> Option<f32> {
+    POSITIONS.lock(|positions| positions.get()[thumbwheel as usize])
+}
+
+/// Reads the thumbwheels and sends `Input::ThumbwheelMoved` whenever
+/// one of them has moved.
+#[task]
+pub async fn thumbwheel_listener() {
+    let mut filters = [ThumbwheelFilter::new(); 2];
+
+    loop {
+        let config = thumbwheel_config();
+        Timer::after(config.poll_interval).await;
+
+        let Some(values) = ThumbwheelHandle::raw_values().await
+        else {
+            continue;
+        };
+
+        let calibrating = is_calibrating_thumbwheels();
+
+        for (thumbwheel, raw) in Thumbwheel::ALL
+            .into_iter()
+            .zip([values.left, values.right])
+        {
+            if calibrating {
+                CALIBRATIONS.lock(|calibrations| {
+                    let mut all = calibrations.get();
+                    all[thumbwheel as usize].widen(raw);
+                    calibrations.set(all);
+                });
+            }
+
+            let Some(position) = filters[thumbwheel as usize].update(
+                &config,
+                &thumbwheel_calibration(thumbwheel),
+                raw,
+            ) else {
+                continue;
+            };
+
+            POSITIONS.lock(|positions| {
+                let mut all = positions.get();
+                all[thumbwheel as usize] = Some(position);
+                positions.set(all);
+            });
+
+            INPUT_CHANNEL
+                .send(Input::ThumbwheelMoved(thumbwheel))
+                .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALIBRATION: ThumbwheelCalibration =
+        ThumbwheelCalibration {
+            min: 1000,
+            max: 3000,
+        };
+
+    /// No smoothing, so that every reading counts in full.
+    const CONFIG: ThumbwheelConfig = ThumbwheelConfig {
+        smoothing: 0,
+        hysteresis: 0.05,
+        dead_zone: 0.1,
+        ..ThumbwheelConfig::DEFAULT
+    };
+
+    /// Feeds the readings through one filter and checks what each
+    /// one reported, give or take rounding.
+    fn assert_reports(
+        config: &ThumbwheelConfig,
+        readings: &[u16],
+        expected: &[Option<f32>],
+    ) {
+        let mut filter = ThumbwheelFilter::new();
+        let reported: Vec<_> = readings
+            .iter()
+            .map(|&raw| filter.update(config, &CALIBRATION, raw))
+            .collect();
+
+        let matches = reported.len() == expected.len()
+            && reported.iter().zip(expected).all(|pair| match pair {
+                (Some(reported), Some(expected)) => {
+                    (reported - expected).abs() < 1e-4
+                }
+                (reported, expected) => reported == expected,
+            });
+        assert!(
+            matches,
+            "reported {reported:?}, expected {expected:?}"
+        );
+    }
+
+    #[test]
+    fn normalize_scales_between_the_ends() {
+        assert_eq!(CALIBRATION.normalize(1000.0), 0.0);
+        assert_eq!(CALIBRATION.normalize(2000.0), 0.5);
+        assert_eq!(CALIBRATION.normalize(3000.0), 1.0);
+    }
+
+    #[test]
+    fn normalize_clamps_past_the_ends() {
+        assert_eq!(CALIBRATION.normalize(0.0), 0.0);
+        assert_eq!(CALIBRATION.normalize(4095.0), 1.0);
+    }
+
+    #[test]
+    fn normalize_with_min_at_max() {
+        let calibration = ThumbwheelCalibration {
+            min: 2000,
+            max: 2000,
+        };
+
+        assert_eq!(calibration.normalize(1999.0), 0.0);
+        assert_eq!(calibration.normalize(2000.0), 0.0);
+        assert_eq!(calibration.normalize(2001.0), 1.0);
+
+        // the ends swapped, as they are while calibrating
+        let calibration = ThumbwheelCalibration {
+            min: u16::MAX,
+            max: 0,
+        };
+        assert_eq!(calibration.normalize(2000.0), 0.0);
+    }
+
+    #[test]
+    fn dead_zone_reaches_the_ends() {
+        // 5% of the range in from either end
+        assert_reports(&CONFIG, &[1100], &[Some(0.0)]);
+        assert_reports(&CONFIG, &[2900], &[Some(1.0)]);
+        // what is left is stretched over the whole range
+        assert_reports(&CONFIG, &[2000], &[Some(0.5)]);
+        assert_reports(&CONFIG, &[1400], &[Some(0.125)]);
+    }
+
+    #[test]
+    fn hysteresis_holds_back_small_moves() {
+        // 0.5, then 0.025 and 0.0625 past it
+        assert_reports(
+            &CONFIG,
+            &[2000, 2040, 2100],
+            &[Some(0.5), None, Some(0.5625)],
+        );
+        // and the same back the other way
+        assert_reports(
+            &CONFIG,
+            &[2000, 1960, 1900],
+            &[Some(0.5), None, Some(0.4375)],
+        );
+    }
+
+    #[test]
+    fn hysteresis_lets_the_ends_through() {
+        // 1.0 is within the hysteresis of 0.975, but the end is still
+        // reported, and only once
+        assert_reports(
+            &CONFIG,
+            &[2760, 2900, 3000],
+            &[Some(0.975), Some(1.0), None],
+        );
+        assert_reports(
+            &CONFIG,
+            &[1240, 1100, 1000],
+            &[Some(0.025), Some(0.0), None],
+        );
+    }
+
+    #[test]
+    fn smoothing_follows_slowly() {
+        let config = ThumbwheelConfig {
+            smoothing: 128,
+            ..CONFIG
+        };
+
+        // halfway from the middle to the top, then halfway again
+        assert_reports(
+            &config,
+            &[2000, 2800, 2800],
+            &[Some(0.5), Some(0.75), Some(0.875)],
+        );
+    }
+}
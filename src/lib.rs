@@ -1,9 +1,38 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(all(feature = "esp32s3", feature = "simulator"))]
+compile_error!(
+    "the `esp32s3` and `simulator` features are mutually exclusive"
+);
 
 pub mod hardware;
 pub mod input_listener;
 pub mod leds;
 pub mod physics;
+pub mod seeds;
 pub mod settings;
 pub mod states;
 pub mod tasks;
+
+// The host tests have nowhere to send defmt logs to, the same as the
+// simulator.
+#[cfg(test)]
+#[defmt::global_logger]
+struct TestLogger;
+
+#[cfg(test)]
+unsafe impl defmt::Logger for TestLogger {
+    fn acquire() {}
+    unsafe fn flush() {}
+    unsafe fn release() {}
+    unsafe fn write(_bytes: &[u8]) {}
+}
+
+#[cfg(test)]
+defmt::timestamp!("{=u64}", 0);
+
+#[cfg(test)]
+#[defmt::panic_handler]
+fn defmt_panic() -> ! {
+    panic!("defmt panic")
+}
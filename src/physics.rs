@@ -1,5 +1,6 @@
 use core::f32::consts::PI;
 
+#[cfg(feature = "esp32s3")]
 use esp_hal::rng::Rng;
 use micromath::F32Ext;
 use static_cell::StaticCell;
@@ -137,10 +138,12 @@ impl FlowField {
 }
 
 /// Generates a value between 0.0 and 1.0
+#[cfg(feature = "esp32s3")]
 pub fn random(rng: &Rng) -> f32 {
     (rng.random() as u8) as f32 / 255.0
 }
 
+#[cfg(feature = "esp32s3")]
 pub fn random_angle(rng: &Rng) -> f32 {
     random(rng) * 2.0 * PI
 }
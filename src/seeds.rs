@@ -0,0 +1,45 @@
+//! Seeds for everything that is generated pseudo-randomly, kept in
+//! one place so that a run can be reproduced. Input recordings store
+//! the seeds that were in use when recording started, and put them
+//! back before they are replayed.
+
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::{
+    Mutex, raw::CriticalSectionRawMutex,
+};
+
+static SEEDS: Mutex<CriticalSectionRawMutex, Cell<Seeds>> =
+    Mutex::new(Cell::new(Seeds::DEFAULT));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Seeds {
+    /// The starting seed of the light show's random blink.
+    pub light_show: u16,
+    /// Mixed into the palette index by the automata and langton
+    /// color scheme generators.
+    pub palette: u32,
+}
+
+impl Seeds {
+    pub const DEFAULT: Self = Self {
+        light_show: 0xace1,
+        palette: 0,
+    };
+}
+
+impl Default for Seeds {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+pub fn seeds() -> Seeds {
+    SEEDS.lock(|seeds| seeds.get())
+}
+
+/// Changes the seeds. Apps pick them up the next time they are
+/// launched.
+pub fn set_seeds(seeds: Seeds) {
+    SEEDS.lock(|current| current.set(seeds));
+}
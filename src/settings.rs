@@ -1 +1,286 @@
-pub const MUTE_SOUNDS: bool = false;
+//! Settings that can be changed while the device is running.
+//!
+//! The volume, mute, bindings and thumbwheel calibration are kept
+//! across reboots: `load` reads them at startup and `save_on_change`
+//! writes them back to storage whenever they change. They are stored
+//! as
+//!
+//! ```text
+//! "MSET" version:u8 volume:u8 muted:u8 bindings
+//! (overridden:u8 bindings) * AppId::COUNT
+//! (min:u16 max:u16) * 2
+//! ```
+//!
+//! where `bindings` is written by `Bindings::encode`, and the
+//! override of an app that uses the global bindings is written as
+//! zeros. The calibration is little endian, left wheel first. Adding
+//! an app changes the size, so it needs a new version of the format.
+
+use core::cell::Cell;
+
+use defmt::warn;
+use embassy_futures::select::{Either, select};
+use embassy_sync::{
+    blocking_mutex::{Mutex, raw::CriticalSectionRawMutex},
+    signal::Signal,
+};
+use embassy_time::{Duration, Timer};
+use embedded_storage::{ReadStorage, Storage};
+
+use crate::{
+    input_listener::{
+        bindings::Bindings,
+        thumbwheel::{
+            Thumbwheel, ThumbwheelCalibration,
+            set_thumbwheel_calibration, thumbwheel_calibration,
+        },
+    },
+    states::AppId,
+};
+
+/// The loudest the volume goes. Every step is a little louder than
+/// the one before.
+pub const MAX_VOLUME: u8 = 10;
+
+/// The most space the saved settings take up in storage.
+pub const ENCODED_SIZE: usize = HEADER_SIZE
+    + Bindings::ENCODED_SIZE
+    + APPS_SIZE
+    + Thumbwheel::ALL.len() * CALIBRATION_SIZE;
+
+const MAGIC: [u8; 4] = *b"MSET";
+const FORMAT_VERSION: u8 = 4;
+/// The magic, the version, the volume and the mute.
+const HEADER_SIZE: usize = 7;
+/// The overrides of every app.
+const APPS_SIZE: usize = AppId::COUNT * (1 + Bindings::ENCODED_SIZE);
+/// The minimum and maximum of one thumbwheel.
+const CALIBRATION_SIZE: usize = 4;
+
+/// How long the settings have to stay the same before they are
+/// saved, so that turning the volume a step at a time writes it once.
+const SAVE_DELAY: Duration = Duration::from_secs(2);
+
+static VOLUME: Mutex<CriticalSectionRawMutex, Cell<u8>> =
+    Mutex::new(Cell::new(MAX_VOLUME));
+
+static MUTED: Mutex<CriticalSectionRawMutex, Cell<bool>> =
+    Mutex::new(Cell::new(false));
+
+/// Signaled whenever a setting that is saved changes.
+static SETTINGS_CHANGED: Signal<CriticalSectionRawMutex, ()> =
+    Signal::new();
+
+/// The bindings used by everything without its own.
+static BINDINGS: Mutex<CriticalSectionRawMutex, Cell<Bindings>> =
+    Mutex::new(Cell::new(Bindings::DEFAULT));
+
+/// Per app overrides of `BINDINGS`. Indexed by `AppId as usize`.
+static APP_BINDINGS: Mutex<
+    CriticalSectionRawMutex,
+    Cell<[Option<Bindings>; AppId::COUNT]>,
+> = Mutex::new(Cell::new([None; AppId::COUNT]));
+
+pub fn bindings() -> Bindings {
+    BINDINGS.lock(|bindings| bindings.get())
+}
+
+pub fn set_bindings(bindings: Bindings) {
+    BINDINGS.lock(|current| current.set(bindings));
+    SETTINGS_CHANGED.signal(());
+}
+
+/// The override for `app`, None if it uses the global bindings.
+pub fn app_bindings(app: AppId) -> Option<Bindings> {
+    APP_BINDINGS.lock(|overrides| overrides.get()[app as usize])
+}
+
+/// Sets or clears (with None) the override for `app`.
+pub fn set_app_bindings(app: AppId, bindings: Option<Bindings>) {
+    APP_BINDINGS.lock(|overrides| {
+        let mut all = overrides.get();
+        all[app as usize] = bindings;
+        overrides.set(all);
+    });
+    SETTINGS_CHANGED.signal(());
+}
+
+/// The bindings `app` runs with, or the global ones for None.
+pub fn bindings_for(app: Option<AppId>) -> Bindings {
+    app.and_then(app_bindings).unwrap_or_else(bindings)
+}
+
+/// The master volume, from 0 to `MAX_VOLUME`.
+pub fn volume() -> u8 {
+    VOLUME.lock(|volume| volume.get())
+}
+
+pub fn set_volume(volume: u8) {
+    VOLUME.lock(|current| current.set(volume.min(MAX_VOLUME)));
+    SETTINGS_CHANGED.signal(());
+}
+
+/// Whether every sound is muted, whatever the volume.
+pub fn muted() -> bool {
+    MUTED.lock(|muted| muted.get())
+}
+
+pub fn set_muted(muted: bool) {
+    MUTED.lock(|current| current.set(muted));
+    SETTINGS_CHANGED.signal(());
+}
+
+/// Has the thumbwheel calibration saved. The calibration itself is
+/// kept by `input_listener::thumbwheel`, so call this once a new one
+/// is finished.
+pub fn thumbwheels_calibrated() {
+    SETTINGS_CHANGED.signal(());
+}
+
+/// What the speaker multiplies everything it plays by, from 0.0 to
+/// 1.0. Squared, so that the steps of the volume sound about even.
+pub fn master_gain() -> f32 {
+    if muted() {
+        return 0.0;
+    }
+
+    let level = volume() as f32 / MAX_VOLUME as f32;
+    level * level
+}
+
+/// Whether the buzzers should sound. They only have the one loudness,
+/// so they are silent at volume 0 and loud at every other.
+pub fn buzzers_audible() -> bool {
+    !muted() && volume() > 0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum SettingsError {
+    /// Nothing was saved there, or it was saved by a different
+    /// version of the format.
+    BadHeader,
+    /// A saved layout does not bind every control to exactly one
+    /// action.
+    BadBindings,
+    /// Reading from or writing to storage failed.
+    Storage,
+}
+
+/// Writes the settings to `storage`, starting at `offset`. Takes up
+/// `ENCODED_SIZE` bytes.
+pub fn save<S: Storage>(
+    storage: &mut S,
+    offset: u32,
+) -> Result<(), SettingsError> {
+    let mut bytes = [0; ENCODED_SIZE];
+    bytes[..4].copy_from_slice(&MAGIC);
+    bytes[4] = FORMAT_VERSION;
+    bytes[5] = volume();
+    bytes[6] = muted() as u8;
+
+    let (global, rest) =
+        bytes[HEADER_SIZE..].split_at_mut(Bindings::ENCODED_SIZE);
+    let (apps, wheels) = rest.split_at_mut(APPS_SIZE);
+    global.copy_from_slice(&bindings().encode());
+
+    let overrides = APP_BINDINGS.lock(|overrides| overrides.get());
+    for (entry, bindings) in apps
+        .chunks_exact_mut(1 + Bindings::ENCODED_SIZE)
+        .zip(overrides)
+    {
+        if let Some(bindings) = bindings {
+            entry[0] = 1;
+            entry[1..].copy_from_slice(&bindings.encode());
+        }
+    }
+
+    for (entry, thumbwheel) in wheels
+        .chunks_exact_mut(CALIBRATION_SIZE)
+        .zip(Thumbwheel::ALL)
+    {
+        let calibration = thumbwheel_calibration(thumbwheel);
+        entry[..2].copy_from_slice(&calibration.min.to_le_bytes());
+        entry[2..].copy_from_slice(&calibration.max.to_le_bytes());
+    }
+
+    storage
+        .write(offset, &bytes)
+        .map_err(|_| SettingsError::Storage)
+}
+
+/// Puts back the settings written by `save`. Leaves them all as they
+/// are if nothing was saved or any of it is bad.
+pub fn load<S: ReadStorage>(
+    storage: &mut S,
+    offset: u32,
+) -> Result<(), SettingsError> {
+    let mut bytes = [0; ENCODED_SIZE];
+    storage
+        .read(offset, &mut bytes)
+        .map_err(|_| SettingsError::Storage)?;
+
+    if bytes[..4] != MAGIC || bytes[4] != FORMAT_VERSION {
+        return Err(SettingsError::BadHeader);
+    }
+
+    let (global, rest) =
+        bytes[HEADER_SIZE..].split_at(Bindings::ENCODED_SIZE);
+    let (apps, wheels) = rest.split_at(APPS_SIZE);
+    let global = decode_bindings(global)?;
+
+    let mut overrides = [None; AppId::COUNT];
+    for (entry, bindings) in apps
+        .chunks_exact(1 + Bindings::ENCODED_SIZE)
+        .zip(&mut overrides)
+    {
+        if entry[0] != 0 {
+            *bindings = Some(decode_bindings(&entry[1..])?);
+        }
+    }
+
+    VOLUME.lock(|volume| volume.set(bytes[5].min(MAX_VOLUME)));
+    MUTED.lock(|muted| muted.set(bytes[6] != 0));
+    BINDINGS.lock(|bindings| bindings.set(global));
+    APP_BINDINGS.lock(|current| current.set(overrides));
+    for (entry, thumbwheel) in
+        wheels.chunks_exact(CALIBRATION_SIZE).zip(Thumbwheel::ALL)
+    {
+        set_thumbwheel_calibration(
+            thumbwheel,
+            ThumbwheelCalibration {
+                min: u16::from_le_bytes([entry[0], entry[1]]),
+                max: u16::from_le_bytes([entry[2], entry[3]]),
+            },
+        );
+    }
+    Ok(())
+}
+
+fn decode_bindings(bytes: &[u8]) -> Result<Bindings, SettingsError> {
+    bytes
+        .try_into()
+        .ok()
+        .and_then(Bindings::decode)
+        .ok_or(SettingsError::BadBindings)
+}
+
+/// Saves the settings to `storage` at `offset` a little while after
+/// every change, forever. Run it from a task that owns the storage.
+pub async fn save_on_change<S: Storage>(
+    storage: &mut S,
+    offset: u32,
+) -> ! {
+    loop {
+        SETTINGS_CHANGED.wait().await;
+
+        // wait for the changes to settle
+        while let Either::Second(()) =
+            select(Timer::after(SAVE_DELAY), SETTINGS_CHANGED.wait())
+                .await
+        {}
+
+        if let Err(error) = save(storage, offset) {
+            warn!("could not save the settings: {}", error);
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Apps are the programs that can be launched from the menu. Each app
+//! lives in its own module under `states`, implements [`App`], and is
+//! listed once in the registry at the bottom of this file. The
+//! registry generates the [`AppId`] that is stored inside
+//! `State::App` and the [`AppState`] enum that holds the running app,
+//! and the menu is built from [`APPS`].
+//!
+//! Some apps have a lot of state, so it is kept in one static instead
+//! of inside `State`, which is copied around on every state change.
+
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex,
+};
+
+use super::{
+    MenuState, Resources, Stage, State, automata::AutomataState,
+    buzzer_test::BuzzerTestState, controls::ControlsState,
+    cries::CriesState, langton::LangtonState,
+    light_show::LightShowState, menu_state::menu::MenuFolder,
+    recorder::RecorderState, ringtones::RingtonesState,
+    synth::SynthState,
+};
+
+/// Where an app shows up in the menu.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuPlacement {
+    /// Shown on the first layer of the menu.
+    Root,
+    /// Shown inside a folder.
+    Folder(MenuFolder),
+    /// Not shown in the menu. The app can still be switched to.
+    Hidden,
+}
+
+/// A program that runs inside the state machine. The value
+/// implementing this trait is the state of the app, which is kept
+/// in `RUNNING_APP` between ticks.
+///
+/// The state machine calls `setup` once, then `execute` every tick
+/// until the app (or something else) asks for a state change, then
+/// `shutdown` once before switching.
+#[allow(async_fn_in_trait)]
+pub trait App: Copy + Default {
+    /// The name shown in the menu. Has to fit on one line of the
+    /// mono display, alongside the `#` prefix.
+    const NAME: &'static str;
+
+    const MENU_PLACEMENT: MenuPlacement;
+
+    async fn setup(&mut self, ctx: &mut AppContext<'_>);
+
+    async fn execute(&mut self, ctx: &mut AppContext<'_>);
+
+    /// Put the hardware back the way the menu expects it. The next
+    /// state is switched to after this returns.
+    async fn shutdown(&mut self, ctx: &mut AppContext<'_>);
+}
+
+/// What an app gets access to while it is running.
+pub struct AppContext<'a> {
+    pub resources: &'a mut Resources,
+    next_state: &'a mut Option<State>,
+    needs_to_shutdown: &'a mut bool,
+}
+
+impl<'a> AppContext<'a> {
+    pub(super) fn new(
+        resources: &'a mut Resources,
+        next_state: &'a mut Option<State>,
+        needs_to_shutdown: &'a mut bool,
+    ) -> Self {
+        Self {
+            resources,
+            next_state,
+            needs_to_shutdown,
+        }
+    }
+
+    /// Shuts down the app and goes to `state` afterwards.
+    pub fn switch_to(&mut self, state: State) {
+        *self.next_state = Some(state);
+        *self.needs_to_shutdown = true;
+    }
+
+    /// Shuts down the app and goes back to the menu afterwards.
+    pub fn exit_to_menu(&mut self) {
+        self.switch_to(State::Menu(
+            Stage::Setup,
+            MenuState::default(),
+        ));
+    }
+}
+
+/// An entry in the app registry.
+#[derive(Debug, Clone, Copy)]
+pub struct AppEntry {
+    pub id: AppId,
+    pub name: &'static str,
+    pub menu_placement: MenuPlacement,
+}
+
+/// The state of the app that is running, or of the last one that
+/// ran. Only the state machine uses it.
+static RUNNING_APP: Mutex<CriticalSectionRawMutex, Option<AppState>> =
+    Mutex::new(None);
+
+/// Runs `stage` of the app `id`. Setup starts it from its default
+/// state, the other stages carry on with the state it was left in.
+pub(super) async fn run_app(
+    id: AppId,
+    stage: Stage,
+    ctx: &mut AppContext<'_>,
+) {
+    let mut running = RUNNING_APP.lock().await;
+
+    if matches!(stage, Stage::Setup)
+        || running.as_ref().map(AppState::id) != Some(id)
+    {
+        *running = Some(id.launch());
+    }
+
+    if let Some(app_state) = running.as_mut() {
+        app_state.run_stage(stage, ctx).await;
+    }
+}
+
+// Generates `AppId`, `AppState` and `APPS` from the list of apps, so
+// that none of them have to be written by hand.
+macro_rules! app_registry {
+    ($($id:ident => $app:ty,)+) => {
+        /// Identifies a registered app.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum AppId {
+            $($id,)+
+        }
+
+        /// The state of whichever registered app is running. There
+        /// is only ever the one in `RUNNING_APP`, so it does not
+        /// matter how much bigger some apps are than others.
+        #[derive(Debug)]
+        #[allow(clippy::large_enum_variant)]
+        pub enum AppState {
+            $($id($app),)+
+        }
+
+        /// Every registered app, in menu order.
+        pub static APPS: &[AppEntry] = &[
+            $(AppEntry {
+                id: AppId::$id,
+                name: <$app as App>::NAME,
+                menu_placement: <$app as App>::MENU_PLACEMENT,
+            },)+
+        ];
+
+        impl AppId {
+            /// How many apps are registered.
+            pub const COUNT: usize = [$(AppId::$id,)+].len();
+
+            /// The state the app starts in when launched.
+            fn launch(self) -> AppState {
+                match self {
+                    $(AppId::$id => {
+                        AppState::$id(<$app as Default>::default())
+                    })+
+                }
+            }
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(AppId::$id => <$app as App>::NAME,)+
+                }
+            }
+        }
+
+        impl AppState {
+            pub fn id(&self) -> AppId {
+                match self {
+                    $(AppState::$id(_) => AppId::$id,)+
+                }
+            }
+
+            async fn run_stage(
+                &mut self,
+                stage: Stage,
+                ctx: &mut AppContext<'_>,
+            ) {
+                match self {
+                    $(AppState::$id(app) => match stage {
+                        Stage::Setup => app.setup(ctx).await,
+                        Stage::Execution => app.execute(ctx).await,
+                        Stage::Shutdown => app.shutdown(ctx).await,
+                    },)+
+                }
+            }
+        }
+    };
+}
+
+// The registry. Adding an app means adding its module to `states` and
+// a line here.
+app_registry! {
+    LightShow => LightShowState,
+    Cries => CriesState,
+    Automata => AutomataState,
+    Langton => LangtonState,
+    Synth => SynthState,
+    Ringtones => RingtonesState,
+    Recorder => RecorderState,
+    Controls => ControlsState,
+    BuzzerTest => BuzzerTestState,
+}
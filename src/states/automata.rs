@@ -2,9 +2,8 @@ use core::fmt::Write;
 
 use embassy_time::{Duration, Timer};
 use heapless::String;
-use rotary_encoder_embedded::Direction;
 
-use super::{AutomataState, MenuState, Meowbox, Stage, State};
+use super::app::{App, AppContext, MenuPlacement};
 use crate::{
     hardware::{
         buzzer::{BUZZER_2K3_CH, BuzzerCommand},
@@ -18,7 +17,13 @@ use crate::{
             MonoDisplayCommand,
         },
     },
-    input_listener::{Input, InputListener},
+    input_listener::{
+        InputListener,
+        acceleration::RotationMode,
+        bindings::{Action, Dial, active_bindings},
+        thumbwheel::{Thumbwheel, thumbwheel_position},
+    },
+    seeds::seeds,
 };
 
 const BLACK: u16 = 0x0000;
@@ -32,30 +37,47 @@ const MAX_KERNEL_SIZE: usize = 5;
 const CONVOLUTION_LIGHTNESS: u16 = 1;
 const INPUT_LED_TIME: Duration = Duration::from_millis(100);
 const KERNEL_HIGHLIGHT: u16 = 0xf800;
+/// The tick delay with the left thumbwheel at either end.
+const FASTEST_TICK: Duration = Duration::from_millis(10);
+const SLOWEST_TICK: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Copy, Debug)]
+pub struct AutomataState {
+    pub rule: u8,
+    pub palette_index: i32,
+    pub kernel_index: usize,
+    pub kernel_running: bool,
+    pub kernel_pass: usize,
+    /// From 0.0 (slowest) to 1.0 (fastest), set by the left
+    /// thumbwheel.
+    pub speed: f32,
+}
 
-impl Meowbox {
-    pub(super) async fn tick_automata(&mut self) {
-        let State::Automata(stage, automata_state) = self.state
-        else {
-            return;
-        };
-
-        match stage {
-            Stage::Setup => self.setup_automata(automata_state).await,
-            Stage::Execution => {
-                self.execute_automata(automata_state).await
-            }
-            Stage::Shutdown => self.shutdown_automata().await,
+impl Default for AutomataState {
+    fn default() -> Self {
+        Self {
+            rule: 1,
+            palette_index: 0,
+            kernel_index: 0,
+            kernel_running: false,
+            kernel_pass: 0,
+            speed: 1.0,
         }
     }
+}
+
+impl App for AutomataState {
+    const NAME: &'static str = "Automata";
+    const MENU_PLACEMENT: MenuPlacement = MenuPlacement::Root;
 
-    async fn setup_automata(
-        &mut self,
-        mut automata_state: AutomataState,
-    ) {
+    async fn setup(&mut self, _ctx: &mut AppContext<'_>) {
+        let automata_state = self;
         automata_state.kernel_index = 0;
         automata_state.kernel_running = false;
         automata_state.kernel_pass = 0;
+        if let Some(speed) = thumbwheel_position(Thumbwheel::Left) {
+            automata_state.speed = speed;
+        }
 
         LED_SHIFTER_CHANNEL.send(LedCommand::SetAllLow).await;
         LED_SHIFTER_CHANNEL
@@ -83,38 +105,35 @@ impl Meowbox {
 
         LARGE_DISPLAY_CH.send(LargeDisplayCommand::DisplayOn).await;
         BACKLIGHT_CH.send(BacklightCommand::SetHigh).await;
-        draw_rule(automata_state).await;
-
-        self.state =
-            State::Automata(Stage::Execution, automata_state);
+        draw_rule(*automata_state).await;
     }
 
-    async fn execute_automata(
-        &mut self,
-        mut automata_state: AutomataState,
-    ) {
-        if InputListener::take_input(Input::ButtonLeft, true)
+    async fn execute(&mut self, ctx: &mut AppContext<'_>) {
+        if InputListener::take_action(Action::Back, true)
             .ok()
             .flatten()
             .is_some()
         {
-            self.next_state =
-                Some(State::Menu(Stage::Setup, MenuState::default()));
-            self.needs_to_shutdown = true;
+            ctx.exit_to_menu();
             return;
         }
 
+        let mut automata_state = *self;
+
         let right_button_pressed =
-            InputListener::take_input(Input::ButtonRight, true)
+            InputListener::take_action(Action::Confirm, true)
                 .ok()
                 .flatten()
                 .is_some();
-        let _ = InputListener::take_input(
-            Input::RotaryEncoderPressRight,
-            true,
-        );
+        let _ = take_action_count(Action::SecondaryPress);
 
-        drain_dpad_inputs();
+        drain_unused_actions();
+
+        if let Ok(Some(speed)) =
+            InputListener::take_thumbwheel(Thumbwheel::Left)
+        {
+            automata_state.speed = speed;
+        }
 
         let rule_delta = take_rule_delta();
         let palette_delta = take_palette_delta();
@@ -159,83 +178,65 @@ impl Meowbox {
             tick_blur_kernel(&mut automata_state).await;
         }
 
-        self.state =
-            State::Automata(Stage::Execution, automata_state);
+        *self = automata_state;
 
-        Timer::after(Duration::from_millis(10)).await;
+        Timer::after(tick_delay(automata_state.speed)).await;
     }
 
-    async fn shutdown_automata(&mut self) {
+    async fn shutdown(&mut self, _ctx: &mut AppContext<'_>) {
         LED_SHIFTER_CHANNEL.send(LedCommand::SetAllLow).await;
         BACKLIGHT_CH.send(BacklightCommand::SetLow).await;
-
-        self.state = self.next_state.take().unwrap_or(State::Menu(
-            Stage::Setup,
-            MenuState::default(),
-        ));
     }
 }
 
-fn take_rule_delta() -> i16 {
-    let left_cw = take_input_count(Input::RotaryEncoderRotateLeft(
-        Direction::Clockwise,
-    ));
-    let left_ccw = take_input_count(Input::RotaryEncoderRotateLeft(
-        Direction::Anticlockwise,
-    ));
+fn tick_delay(speed: f32) -> Duration {
+    let range = (SLOWEST_TICK - FASTEST_TICK).as_millis() as f32;
+    let slowdown = (1.0 - speed.clamp(0.0, 1.0)) * range;
 
-    left_cw as i16 - left_ccw as i16
+    FASTEST_TICK + Duration::from_millis(slowdown as u64)
 }
 
-fn take_palette_delta() -> i16 {
-    let right_cw = take_input_count(Input::RotaryEncoderRotateRight(
-        Direction::Clockwise,
-    ));
-    let right_ccw = take_input_count(
-        Input::RotaryEncoderRotateRight(Direction::Anticlockwise),
-    );
+fn take_rule_delta() -> i16 {
+    InputListener::take_dial(Dial::Primary, RotationMode::Accelerated)
+        .unwrap_or_default() as i16
+}
 
-    right_cw as i16 - right_ccw as i16
+fn take_palette_delta() -> i16 {
+    InputListener::take_dial(Dial::Secondary, RotationMode::Raw)
+        .unwrap_or_default() as i16
 }
 
 fn drain_automata_inputs() {
-    let _ = take_input_count(Input::RotaryEncoderRotateLeft(
-        Direction::Clockwise,
-    ));
-    let _ = take_input_count(Input::RotaryEncoderRotateLeft(
-        Direction::Anticlockwise,
-    ));
-    let _ = take_input_count(Input::RotaryEncoderRotateRight(
-        Direction::Clockwise,
-    ));
-    let _ = take_input_count(Input::RotaryEncoderRotateRight(
-        Direction::Anticlockwise,
-    ));
-    let _ = take_input_count(Input::ButtonRight);
-    let _ = take_input_count(Input::RotaryEncoderPressRight);
-    drain_dpad_inputs();
-}
-
-fn drain_dpad_inputs() {
-    let _ = take_input_count(Input::DpadLeft);
-    let _ = take_input_count(Input::DpadRight);
-    let _ = take_input_count(Input::DpadTop);
-    let _ = take_input_count(Input::DpadBottom);
-}
-
-fn take_input_count(input: Input) -> u16 {
-    InputListener::take_input(input, true)
+    for dial in Dial::ALL {
+        let _ = InputListener::take_dial(dial, RotationMode::Raw);
+    }
+    let _ = take_action_count(Action::Confirm);
+    let _ = take_action_count(Action::SecondaryPress);
+    drain_unused_actions();
+}
+
+fn drain_unused_actions() {
+    let _ = take_action_count(Action::Previous);
+    let _ = take_action_count(Action::Next);
+    let _ = take_action_count(Action::Up);
+    let _ = take_action_count(Action::Down);
+}
+
+fn take_action_count(action: Action) -> u16 {
+    InputListener::take_action(action, true)
         .ok()
         .flatten()
         .unwrap_or_default()
 }
 
 async fn flash_input_led(rule_delta: i16, palette_delta: i16) {
+    let bindings = active_bindings();
+
     if rule_delta != 0 {
         let led = if rule_delta > 0 {
-            LED::AmberLeft
+            LED::of_encoder(bindings.encoder(Dial::Primary))
         } else {
-            LED::ButtonLeft
+            LED::of_control(bindings.control(Action::Back))
         };
 
         LED_SHIFTER_CHANNEL
@@ -245,9 +246,9 @@ async fn flash_input_led(rule_delta: i16, palette_delta: i16) {
 
     if palette_delta != 0 {
         let led = if palette_delta > 0 {
-            LED::AmberRight
+            LED::of_encoder(bindings.encoder(Dial::Secondary))
         } else {
-            LED::ButtonRight
+            LED::of_control(bindings.control(Action::Confirm))
         };
 
         LED_SHIFTER_CHANNEL
@@ -259,7 +260,7 @@ async fn flash_input_led(rule_delta: i16, palette_delta: i16) {
 async fn flash_right_button() {
     LED_SHIFTER_CHANNEL
         .send(LedCommand::TemporaryToggle(
-            LED::ButtonRight,
+            LED::of_control(active_bindings().control(Action::Confirm)),
             INPUT_LED_TIME,
         ))
         .await;
@@ -644,7 +645,7 @@ fn color_scheme(index: i32) -> (u16, u16) {
         return (WHITE, BLACK);
     }
 
-    let seed = index as u32;
+    let seed = index as u32 ^ seeds().palette;
     let background = random_rgb565(seed ^ 0x4d45_4f57);
     let mut foreground = random_rgb565(seed ^ 0x4341_5453);
 
@@ -0,0 +1,104 @@
+//! Sounds each of the buzzers on its own, to check that they work.
+//!
+//! ```text
+//! previous    the 400 Hz buzzer
+//! next        the 2.3 kHz buzzer
+//! back        back to the menu
+//! ```
+//!
+//! The buzzers follow the volume like everything else, so they stay
+//! silent while muted or at volume 0.
+
+use embassy_time::Duration;
+use heapless::String;
+
+use super::{
+    app::{App, AppContext, MenuPlacement},
+    menu_state::menu::MenuFolder,
+};
+use crate::{
+    hardware::{
+        buzzer::{BUZZER_2K3_CH, BUZZER_400_CH, BuzzerCommand},
+        led_shifter::{LED, LED_SHIFTER_CHANNEL, LedCommand},
+        mono_display::{MONO_DISPLAY_CH, MonoDisplayCommand},
+    },
+    input_listener::{
+        InputListener,
+        bindings::{Action, active_bindings},
+    },
+    settings,
+};
+
+/// How long each buzzer sounds for, long enough to tell them apart.
+const BEEP: Duration = Duration::from_millis(300);
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BuzzerTestState;
+
+impl App for BuzzerTestState {
+    const NAME: &'static str = "BuzzerTest";
+    const MENU_PLACEMENT: MenuPlacement =
+        MenuPlacement::Folder(MenuFolder::Tests);
+
+    async fn setup(&mut self, _ctx: &mut AppContext<'_>) {
+        let bindings = active_bindings();
+
+        LED_SHIFTER_CHANNEL.send(LedCommand::SetAllLow).await;
+        for action in [Action::Back, Action::Previous, Action::Next] {
+            LED_SHIFTER_CHANNEL
+                .send(LedCommand::SetHigh(LED::of_control(
+                    bindings.control(action),
+                )))
+                .await;
+        }
+
+        MONO_DISPLAY_CH
+            .send(MonoDisplayCommand::SwitchToTerminal)
+            .await;
+        MONO_DISPLAY_CH
+            .send(MonoDisplayCommand::SetDisplayOn(true))
+            .await;
+        MONO_DISPLAY_CH.send(MonoDisplayCommand::Clear).await;
+        for line in [
+            "BuzzerTest",
+            "Prev: 400 Hz",
+            "Next: 2.3 kHz",
+            if settings::buzzers_audible() {
+                ""
+            } else {
+                "(muted)"
+            },
+        ] {
+            let mut line = String::try_from(line).unwrap_or_default();
+            let _ = line.push('\n');
+            MONO_DISPLAY_CH
+                .send(MonoDisplayCommand::WriteStr(line))
+                .await;
+        }
+
+        for action in [Action::Previous, Action::Next] {
+            let _ = InputListener::take_action(action, true);
+        }
+    }
+
+    async fn execute(&mut self, ctx: &mut AppContext<'_>) {
+        let Ok(input) = InputListener::wait_for_any().await else {
+            return;
+        };
+
+        match Action::from_input(input) {
+            Some(Action::Back) => ctx.exit_to_menu(),
+            Some(Action::Previous) => {
+                BUZZER_400_CH.send(BuzzerCommand::Play(BEEP)).await
+            }
+            Some(Action::Next) => {
+                BUZZER_2K3_CH.send(BuzzerCommand::Play(BEEP)).await
+            }
+            _ => {}
+        }
+    }
+
+    async fn shutdown(&mut self, _ctx: &mut AppContext<'_>) {
+        LED_SHIFTER_CHANNEL.send(LedCommand::SetAllLow).await;
+    }
+}
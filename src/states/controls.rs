@@ -0,0 +1,390 @@
+//! Edits the bindings in `settings`, globally or for one app. See
+//! `input_listener::bindings`.
+//!
+//! This app reads the physical controls directly instead of going
+//! through the bindings, so that it always works the same way and a
+//! bad binding can always be undone:
+//!
+//! ```text
+//! up / down     pick a row
+//! left / right  pick what is being edited: global or an app
+//! right button  rebind the row, then press the new control (or turn
+//!               the new encoder for a dial)
+//! left button   back to the menu
+//! ```
+//!
+//! The last row calibrates the thumbwheels instead: roll both of them
+//! from one end to the other, then press the right button again.
+
+use core::fmt::Write;
+
+use heapless::String;
+
+use super::app::{APPS, App, AppContext, AppId, MenuPlacement};
+use crate::{
+    hardware::{
+        led_shifter::{LED, LED_SHIFTER_CHANNEL, LedCommand},
+        mono_display::{
+            MONO_DISPLAY_CH, MONO_DISPLAY_LINE_WIDTH,
+            MonoDisplayCommand,
+        },
+        speaker::system::{SystemSound, play_system_sound},
+    },
+    input_listener::{
+        Input, InputListener,
+        acceleration::RotaryEncoder,
+        bindings::{Action, Bindings, Dial},
+        events::Control,
+        thumbwheel::{
+            Thumbwheel, finish_thumbwheel_calibration,
+            start_thumbwheel_calibration, thumbwheel_calibration,
+        },
+    },
+    settings,
+};
+
+/// Every action, then every dial, then the presets, then the
+/// thumbwheel calibration.
+const ROW_COUNT: usize = Action::COUNT + Dial::ALL.len() + 3;
+const LEFT_HANDED_ROW: usize = ROW_COUNT - 3;
+const RESET_ROW: usize = ROW_COUNT - 2;
+const CALIBRATE_ROW: usize = ROW_COUNT - 1;
+
+/// How many rows fit under the title.
+const VISIBLE_ROWS: usize = 6;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ControlsState {
+    /// The app whose bindings are being edited, None for the global
+    /// ones.
+    pub scope: Option<AppId>,
+    pub row: usize,
+    /// Waiting for the control to bind the row to.
+    pub capturing: bool,
+    /// Waiting for the thumbwheels to be rolled end to end.
+    pub calibrating: bool,
+}
+
+impl App for ControlsState {
+    const NAME: &'static str = "Controls";
+    const MENU_PLACEMENT: MenuPlacement = MenuPlacement::Root;
+
+    async fn setup(&mut self, _ctx: &mut AppContext<'_>) {
+        self.capturing = false;
+        self.calibrating = false;
+
+        LED_SHIFTER_CHANNEL.send(LedCommand::SetAllLow).await;
+        for led in [
+            LED::ButtonLeft,
+            LED::ButtonRight,
+            LED::DpadTop,
+            LED::DpadBottom,
+            LED::DpadLeft,
+            LED::DpadRight,
+        ] {
+            LED_SHIFTER_CHANNEL.send(LedCommand::SetHigh(led)).await;
+        }
+
+        MONO_DISPLAY_CH
+            .send(MonoDisplayCommand::SwitchToTerminal)
+            .await;
+        MONO_DISPLAY_CH
+            .send(MonoDisplayCommand::SetDisplayOn(true))
+            .await;
+
+        let _ = InputListener::take_input(Input::ButtonRight, true);
+
+        show_bindings(*self).await;
+    }
+
+    async fn execute(&mut self, ctx: &mut AppContext<'_>) {
+        let Ok(input) = InputListener::wait_for_any().await else {
+            return;
+        };
+
+        // the release of the button that started capturing, and
+        // anything else that is not a press or a turn
+        let ignored = matches!(
+            input,
+            Input::ButtonRightReleased | Input::ThumbwheelMoved(_)
+        );
+
+        if self.calibrating {
+            match input {
+                Input::ButtonLeft => {
+                    ctx.exit_to_menu();
+                    return;
+                }
+                Input::ButtonRight => finish_calibration(self).await,
+                // show the new range
+                Input::ThumbwheelMoved(_) => {}
+                _ => return,
+            }
+
+            show_bindings(*self).await;
+            return;
+        }
+
+        if self.capturing && !ignored {
+            self.capturing = false;
+            capture(*self, input);
+            show_bindings(*self).await;
+            return;
+        }
+
+        match input {
+            Input::ButtonLeft => {
+                ctx.exit_to_menu();
+                return;
+            }
+            Input::DpadTop => {
+                self.row = (self.row + ROW_COUNT - 1) % ROW_COUNT;
+            }
+            Input::DpadBottom => {
+                self.row = (self.row + 1) % ROW_COUNT
+            }
+            Input::DpadLeft => {
+                self.scope = shift_scope(self.scope, -1)
+            }
+            Input::DpadRight => {
+                self.scope = shift_scope(self.scope, 1)
+            }
+            Input::ButtonRight => match self.row {
+                LEFT_HANDED_ROW => {
+                    save(self.scope, Some(Bindings::LEFT_HANDED))
+                }
+                RESET_ROW => save(self.scope, None),
+                CALIBRATE_ROW => {
+                    start_thumbwheel_calibration();
+                    self.calibrating = true;
+                }
+                _ => self.capturing = true,
+            },
+            _ => return,
+        }
+
+        show_bindings(*self).await;
+    }
+
+    async fn shutdown(&mut self, _ctx: &mut AppContext<'_>) {
+        if self.calibrating {
+            finish_calibration(self).await;
+        }
+
+        LED_SHIFTER_CHANNEL.send(LedCommand::SetAllLow).await;
+    }
+}
+
+/// Ends the thumbwheel calibration and has it saved. A wheel that
+/// was not rolled far enough keeps its old calibration, which is
+/// told apart by the error sound.
+async fn finish_calibration(controls_state: &mut ControlsState) {
+    controls_state.calibrating = false;
+
+    let sound = if finish_thumbwheel_calibration() {
+        SystemSound::Confirm
+    } else {
+        SystemSound::Error
+    };
+    settings::thumbwheels_calibrated();
+    play_system_sound(sound).await;
+}
+
+/// Binds the row to the control `input` came from, if it is the
+/// right kind of input for the row.
+fn capture(controls_state: ControlsState, input: Input) {
+    let mut bindings = settings::bindings_for(controls_state.scope);
+
+    match (
+        row_action(controls_state.row),
+        row_dial(controls_state.row),
+    ) {
+        (Some(action), _) => {
+            let Some(control) = Control::from_pressed_input(input)
+            else {
+                return;
+            };
+            bindings.bind(action, control);
+        }
+        (None, Some(dial)) => {
+            let Some((encoder, _)) = RotaryEncoder::rotated_by(input)
+            else {
+                return;
+            };
+            bindings.bind_dial(dial, encoder);
+        }
+        (None, None) => return,
+    }
+
+    save(controls_state.scope, Some(bindings));
+}
+
+/// Stores `bindings` for `scope`. None puts back the defaults for the
+/// global bindings, and the global bindings for an app.
+fn save(scope: Option<AppId>, bindings: Option<Bindings>) {
+    match scope {
+        Some(app) => settings::set_app_bindings(app, bindings),
+        None => settings::set_bindings(bindings.unwrap_or_default()),
+    }
+}
+
+fn row_action(row: usize) -> Option<Action> {
+    Action::ALL.get(row).copied()
+}
+
+fn row_dial(row: usize) -> Option<Dial> {
+    row.checked_sub(Action::COUNT)
+        .and_then(|index| Dial::ALL.get(index).copied())
+}
+
+/// Moves through the global bindings and then every app but this one.
+fn shift_scope(scope: Option<AppId>, delta: isize) -> Option<AppId> {
+    let scopes = APPS
+        .iter()
+        .map(|entry| entry.id)
+        .filter(|id| *id != AppId::Controls);
+    let count = scopes.clone().count() as isize + 1;
+
+    let index = match scope {
+        Some(app) => {
+            scopes.clone().position(|id| id == app).unwrap_or(0)
+                as isize
+                + 1
+        }
+        None => 0,
+    };
+
+    match (index + delta).rem_euclid(count) {
+        0 => None,
+        index => scopes.clone().nth(index as usize - 1),
+    }
+}
+
+fn control_name(control: Control) -> &'static str {
+    match control {
+        Control::ButtonLeft => "BtnL",
+        Control::ButtonRight => "BtnR",
+        Control::DpadTop => "Up",
+        Control::DpadBottom => "Down",
+        Control::DpadLeft => "Left",
+        Control::DpadRight => "Right",
+        Control::RotarySwitchLeft => "SwL",
+        Control::RotarySwitchRight => "SwR",
+    }
+}
+
+fn encoder_name(encoder: RotaryEncoder) -> &'static str {
+    match encoder {
+        RotaryEncoder::Left => "EncL",
+        RotaryEncoder::Right => "EncR",
+    }
+}
+
+async fn show_bindings(controls_state: ControlsState) {
+    if controls_state.calibrating {
+        show_calibration().await;
+        return;
+    }
+
+    let scope = controls_state.scope;
+    let bindings = settings::bindings_for(scope);
+
+    let mut title: String<MONO_DISPLAY_LINE_WIDTH> = String::new();
+    let _ = match scope {
+        Some(app) if settings::app_bindings(app).is_some() => {
+            write!(title, "<{}*>", app.name())
+        }
+        Some(app) => write!(title, "<{}>", app.name()),
+        None => write!(title, "<Global>"),
+    };
+
+    MONO_DISPLAY_CH.send(MonoDisplayCommand::Clear).await;
+    write_line(title.as_str()).await;
+
+    if controls_state.capturing {
+        let prompt = if row_dial(controls_state.row).is_some() {
+            "Turn a dial"
+        } else {
+            "Press a control"
+        };
+        write_line(prompt).await;
+        return;
+    }
+
+    let first_row = controls_state
+        .row
+        .saturating_sub(VISIBLE_ROWS - 1)
+        .min(ROW_COUNT - VISIBLE_ROWS);
+
+    for row in first_row..first_row + VISIBLE_ROWS {
+        let cursor =
+            if row == controls_state.row { '>' } else { ' ' };
+
+        let mut line: String<MONO_DISPLAY_LINE_WIDTH> = String::new();
+        let _ = match (row_action(row), row_dial(row)) {
+            (Some(action), _) => write!(
+                line,
+                "{cursor}{:<9}{}",
+                action.name(),
+                control_name(bindings.control(action))
+            ),
+            (None, Some(dial)) => write!(
+                line,
+                "{cursor}{:<9}{}",
+                dial.name(),
+                encoder_name(bindings.encoder(dial))
+            ),
+            (None, None) if row == CALIBRATE_ROW => {
+                write!(line, "{cursor}Calib. wheels")
+            }
+            (None, None) if row == LEFT_HANDED_ROW => {
+                write!(line, "{cursor}Left-handed")
+            }
+            (None, None) if scope.is_some() => {
+                write!(line, "{cursor}Use global")
+            }
+            (None, None) => write!(line, "{cursor}Reset"),
+        };
+
+        write_line(line.as_str()).await;
+    }
+}
+
+/// The range each wheel has been rolled across so far.
+async fn show_calibration() {
+    MONO_DISPLAY_CH.send(MonoDisplayCommand::Clear).await;
+    write_line("<Calibrate>").await;
+    write_line("Roll each wheel").await;
+    write_line("end to end").await;
+
+    for (thumbwheel, name) in
+        Thumbwheel::ALL.into_iter().zip(['L', 'R'])
+    {
+        let calibration = thumbwheel_calibration(thumbwheel);
+
+        let mut line: String<MONO_DISPLAY_LINE_WIDTH> = String::new();
+        let _ = if calibration.min > calibration.max {
+            // not read since the calibration started
+            write!(line, "{name} -")
+        } else {
+            write!(
+                line,
+                "{name} {}-{}",
+                calibration.min, calibration.max
+            )
+        };
+        write_line(line.as_str()).await;
+    }
+
+    write_line("BtnR: done").await;
+}
+
+async fn write_line(text: &str) {
+    let mut line: String<MONO_DISPLAY_LINE_WIDTH> =
+        String::try_from(text).unwrap_or_default();
+    let _ = line.push('\n');
+
+    MONO_DISPLAY_CH
+        .send(MonoDisplayCommand::WriteStr(line))
+        .await;
+}
@@ -1,9 +1,27 @@
+//! Shows each Pokemon and plays its cry, with how far through the
+//! cry is on the mono display.
+//!
+//! ```text
+//! previous / next                pick a Pokemon
+//! confirm                        play the cry from the start
+//! up                             pause or carry on
+//! down                           loop the cry or not
+//! primary dial                   scrub through the cry
+//! secondary dial                 play it slower or faster
+//! double press secondary dial    keep the pitch at every speed or
+//!                                not
+//! back                           back to the menu
+//! ```
+//!
+//! Every step of the secondary dial is a semitone: slower is lower
+//! and faster is higher, like a turntable, unless the pitch is kept.
+
 use core::fmt::Write;
 
 use heapless::String;
-use rotary_encoder_embedded::Direction;
+use micromath::F32Ext;
 
-use super::{MenuState, Meowbox, Stage, State};
+use super::app::{App, AppContext, MenuPlacement};
 use crate::{
     hardware::{
         large_display::{
@@ -11,41 +29,121 @@ use crate::{
             LargeDisplayCommand,
         },
         led_shifter::{LED, LED_SHIFTER_CHANNEL, LedCommand},
-        mono_display::{MONO_DISPLAY_CH, MonoDisplayCommand},
-        speaker::{CRIES, SPEAKER_CHANNEL, SpeakerCommand},
+        mono_display::{
+            MONO_DISPLAY_CH, MONO_DISPLAY_LINE_WIDTH,
+            MonoDisplayCommand,
+        },
+        speaker::{
+            CRIES, SPEAKER_SAMPLE_RATE,
+            adpcm::sample_count,
+            mixer::{Sound, SoundPosition},
+            speed::{Interpolation, SpeedMode},
+            user::UserSound,
+        },
+    },
+    input_listener::{
+        InputListener,
+        acceleration::RotationMode,
+        auto_repeat::set_auto_repeat,
+        bindings::{Action, Dial, active_bindings},
+        gestures::Gesture,
     },
-    input_listener::{Input, InputListener},
 };
 
 const CRIES_VOLUME_MULTIPLIER: f32 = 0.5;
 
-impl Meowbox {
-    pub(super) async fn tick_cries(&mut self) {
-        let State::Cries(stage, cry_index) = self.state else {
-            return;
-        };
+/// How far one step of the primary dial moves through the cry.
+const SCRUB_STEP_FRAMES: i32 = SPEAKER_SAMPLE_RATE as i32 / 20;
 
-        match stage {
-            Stage::Setup => self.setup_cries(cry_index).await,
-            Stage::Execution => self.execute_cries(cry_index).await,
-            Stage::Shutdown => self.shutdown_cries().await,
+/// How far the secondary dial goes either way, in semitones. An
+/// octave is half or twice the speed.
+const MAX_SEMITONES: i8 = 12;
+
+/// How many cells the progress bar has, leaving room for its ends.
+const PROGRESS_CELLS: usize = MONO_DISPLAY_LINE_WIDTH - 3;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CriesState {
+    /// Index into `CRIES` of the pokemon being shown.
+    pub cry_index: usize,
+    /// Pressing play again restarts the cry instead of playing it
+    /// over itself.
+    pub cry: UserSound,
+    /// Whether the cry starts over when it reaches the end.
+    pub looping: bool,
+    /// How much faster or slower the cry plays, in semitones.
+    pub semitones: i8,
+    /// Whether the pitch stays the same at every speed.
+    pub stretch: bool,
+    /// What the mono display shows, so that it is only redrawn when
+    /// that changes. None when it has to be redrawn.
+    progress: Option<Progress>,
+}
+
+/// How far through the cry is, as it is shown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Progress {
+    /// How many cells of the bar are filled.
+    filled: usize,
+    /// In tenths of a second.
+    elapsed: u32,
+    /// In tenths of a second.
+    length: u32,
+    playing: bool,
+    paused: bool,
+    looping: bool,
+    semitones: i8,
+    stretch: bool,
+}
+
+impl Progress {
+    fn new(
+        cries: &CriesState,
+        position: Option<SoundPosition>,
+    ) -> Self {
+        let cry = &CRIES[cries.cry_index];
+        let length = sample_count(cry.samples) as u32;
+        let frame = position.map_or(0, |position| position.frame);
+        let tenths = |frames: u32| frames * 10 / SPEAKER_SAMPLE_RATE;
+
+        Self {
+            filled: (frame as usize * PROGRESS_CELLS)
+                .checked_div(length as usize)
+                .unwrap_or(0)
+                .min(PROGRESS_CELLS),
+            elapsed: tenths(frame.min(length)),
+            length: tenths(length),
+            playing: position.is_some(),
+            paused: position.is_some_and(|position| position.paused),
+            looping: cries.looping,
+            semitones: cries.semitones,
+            stretch: cries.stretch,
         }
     }
+}
+
+impl App for CriesState {
+    const NAME: &'static str = "Cries";
+    const MENU_PLACEMENT: MenuPlacement = MenuPlacement::Root;
+
+    async fn setup(&mut self, _ctx: &mut AppContext<'_>) {
+        let bindings = active_bindings();
 
-    async fn setup_cries(&mut self, cry_index: usize) {
         LED_SHIFTER_CHANNEL.send(LedCommand::SetAllLow).await;
-        LED_SHIFTER_CHANNEL
-            .send(LedCommand::SetHigh(LED::ButtonLeft))
-            .await;
-        LED_SHIFTER_CHANNEL
-            .send(LedCommand::SetHigh(LED::ButtonRight))
-            .await;
-        LED_SHIFTER_CHANNEL
-            .send(LedCommand::SetHigh(LED::DpadLeft))
-            .await;
-        LED_SHIFTER_CHANNEL
-            .send(LedCommand::SetHigh(LED::DpadRight))
-            .await;
+        for action in [
+            Action::Back,
+            Action::Confirm,
+            Action::Previous,
+            Action::Next,
+            Action::Up,
+            Action::Down,
+        ] {
+            LED_SHIFTER_CHANNEL
+                .send(LedCommand::SetHigh(LED::of_control(
+                    bindings.control(action),
+                )))
+                .await;
+        }
 
         MONO_DISPLAY_CH
             .send(MonoDisplayCommand::SwitchToTerminal)
@@ -53,136 +151,227 @@ impl Meowbox {
         MONO_DISPLAY_CH
             .send(MonoDisplayCommand::SetDisplayOn(true))
             .await;
-        let _ = InputListener::take_input(Input::DpadLeft, true);
-        let _ = InputListener::take_input(Input::DpadRight, true);
-        let _ = InputListener::take_input(
-            Input::RotaryEncoderRotateLeft(Direction::Clockwise),
-            true,
-        );
-        let _ = InputListener::take_input(
-            Input::RotaryEncoderRotateLeft(Direction::Anticlockwise),
-            true,
-        );
-        let _ = InputListener::take_input(
-            Input::RotaryEncoderRotateRight(Direction::Clockwise),
-            true,
-        );
-        let _ = InputListener::take_input(
-            Input::RotaryEncoderRotateRight(Direction::Anticlockwise),
-            true,
-        );
+
+        // one pause or loop per press, holding the D-pad should not
+        // flick between them
+        set_auto_repeat(bindings.control(Action::Up), false);
+        set_auto_repeat(bindings.control(Action::Down), false);
+
+        for action in [
+            Action::Confirm,
+            Action::Previous,
+            Action::Next,
+            Action::Up,
+            Action::Down,
+        ] {
+            let _ = take_total(action);
+        }
+        for dial in Dial::ALL {
+            let _ = take_turns(dial);
+        }
         LARGE_DISPLAY_CH.send(LargeDisplayCommand::DisplayOn).await;
         BACKLIGHT_CH.send(BacklightCommand::SetHigh).await;
-        show_cry(cry_index).await;
-
-        self.state = State::Cries(Stage::Execution, cry_index);
+        show_cry(self.cry_index).await;
+        self.progress = None;
+        self.show_progress().await;
     }
 
-    async fn execute_cries(&mut self, cry_index: usize) {
-        if InputListener::take_input(Input::ButtonLeft, true)
-            .ok()
-            .flatten()
-            .is_some()
-        {
-            self.next_state =
-                Some(State::Menu(Stage::Setup, MenuState::default()));
-            self.needs_to_shutdown = true;
+    async fn execute(&mut self, ctx: &mut AppContext<'_>) {
+        if take_total(Action::Back) != 0 {
+            ctx.exit_to_menu();
             return;
         }
 
-        if InputListener::take_input(Input::ButtonRight, true)
-            .ok()
-            .flatten()
-            .is_some()
+        if take_total(Action::Confirm) != 0 {
+            self.play_cry().await;
+        }
+
+        for _ in 0..take_total(Action::Up) {
+            match self.cry.position() {
+                Some(position) if position.paused => {
+                    self.cry.resume().await
+                }
+                Some(_) => self.cry.pause().await,
+                None => self.play_cry().await,
+            }
+        }
+
+        for _ in 0..take_total(Action::Down) {
+            self.looping = !self.looping;
+            self.cry.set_looping(self.looping).await;
+        }
+
+        let semitones = (self.semitones as i32
+            + take_turns(Dial::Secondary))
+        .clamp(-MAX_SEMITONES as i32, MAX_SEMITONES as i32)
+            as i8;
+        let mut stretch = self.stretch;
+        while let Some(event) = ctx.resources.gestures.try_next() {
+            if event.gesture
+                == Gesture::DoublePress(
+                    active_bindings().control(Action::SecondaryPress),
+                )
+            {
+                stretch = !stretch;
+            }
+        }
+        if semitones != self.semitones || stretch != self.stretch {
+            self.semitones = semitones;
+            self.stretch = stretch;
+            self.cry.set_speed(self.speed(), self.speed_mode()).await;
+        }
+
+        let scrub = take_turns(Dial::Primary);
+        if scrub != 0
+            && let Some(position) = self.cry.position()
         {
-            let cry = &CRIES[cry_index];
-            SPEAKER_CHANNEL
-                .send(SpeakerCommand::PlayPcmWithVolume {
-                    samples: cry.samples,
-                    volume_multiplier: CRIES_VOLUME_MULTIPLIER,
-                })
-                .await;
+            let frame = (position.frame as i32
+                + scrub * SCRUB_STEP_FRAMES)
+                .clamp(0, position.length as i32);
+            self.cry.seek(frame as u32).await;
         }
 
-        let (previous, next) = match take_one_rotary_turn() {
-            Some(Direction::Anticlockwise) => (1, 0),
-            Some(Direction::Clockwise) => (0, 1),
-            Some(Direction::None) => unreachable!(),
-            None => (
-                take_total(Input::DpadLeft),
-                take_total(Input::DpadRight),
-            ),
-        };
+        let previous = take_total(Action::Previous);
+        let next = take_total(Action::Next);
         if previous != 0 || next != 0 {
             let count = CRIES.len();
-            let next_index = (cry_index + next % count + count
+            self.cry_index = (self.cry_index + next % count + count
                 - previous % count)
                 % count;
-            self.state = State::Cries(Stage::Execution, next_index);
-            show_cry(next_index).await;
+            self.cry.stop().await;
+            show_cry(self.cry_index).await;
+            self.progress = None;
         }
+
+        self.show_progress().await;
     }
 
-    async fn shutdown_cries(&mut self) {
+    async fn shutdown(&mut self, _ctx: &mut AppContext<'_>) {
+        self.cry.stop().await;
         LARGE_DISPLAY_CH
             .send(LargeDisplayCommand::StopAnimation)
             .await;
         LED_SHIFTER_CHANNEL.send(LedCommand::SetAllLow).await;
-        self.state = self.next_state.take().unwrap_or(State::Menu(
-            Stage::Setup,
-            MenuState::default(),
-        ));
     }
 }
 
-fn take_total(input: Input) -> usize {
-    InputListener::take_input(input, true)
+impl CriesState {
+    async fn play_cry(&mut self) {
+        let cry = &CRIES[self.cry_index];
+        self.cry
+            .play(Sound::Adpcm(cry.samples), CRIES_VOLUME_MULTIPLIER)
+            .await;
+        if self.looping {
+            self.cry.set_looping(true).await;
+        }
+        if self.semitones != 0 {
+            self.cry.set_speed(self.speed(), self.speed_mode()).await;
+        }
+    }
+
+    /// How many times the normal speed the cry plays at.
+    fn speed(&self) -> f32 {
+        2.0.powf(self.semitones as f32 / 12.0)
+    }
+
+    fn speed_mode(&self) -> SpeedMode {
+        if self.stretch {
+            SpeedMode::TimeStretch
+        } else {
+            SpeedMode::Turntable(Interpolation::Cubic)
+        }
+    }
+
+    /// Redraws the mono display if the progress has changed. The
+    /// sound is only sent to the speaker, so for a moment after it is
+    /// played or paused it may not show yet.
+    async fn show_progress(&mut self) {
+        let cry = &CRIES[self.cry_index];
+        let progress = Progress::new(self, self.cry.position());
+        if Some(progress) == self.progress {
+            return;
+        }
+        self.progress = Some(progress);
+
+        let name = String::try_from(cry.name).unwrap();
+        let mut natdex = String::new();
+        write!(natdex, " \nNatDex#: {}\n", cry.pokemon_id).unwrap();
+
+        let mut bar: String<MONO_DISPLAY_LINE_WIDTH> = String::new();
+        let _ = bar.push('[');
+        for cell in 0..PROGRESS_CELLS {
+            let _ = bar.push(if cell < progress.filled {
+                '#'
+            } else {
+                '.'
+            });
+        }
+        let _ = bar.push_str("]\n");
+
+        let mut time: String<MONO_DISPLAY_LINE_WIDTH> = String::new();
+        let _ = writeln!(
+            time,
+            "{}.{}s / {}.{}s",
+            progress.elapsed / 10,
+            progress.elapsed % 10,
+            progress.length / 10,
+            progress.length % 10,
+        );
+
+        let mut state: String<MONO_DISPLAY_LINE_WIDTH> =
+            String::new();
+        let _ = state.push_str(
+            match (progress.playing, progress.paused) {
+                (false, _) => "Stopped",
+                (true, true) => "Paused",
+                (true, false) => "Playing",
+            },
+        );
+        if progress.looping {
+            let _ = state.push_str(" (loop)");
+        }
+
+        let hundredths = (self.speed() * 100.0).round() as u32;
+        let mut speed: String<MONO_DISPLAY_LINE_WIDTH> =
+            String::new();
+        let _ = write!(
+            speed,
+            "\n{}.{:02}x {}",
+            hundredths / 100,
+            hundredths % 100,
+            if progress.stretch {
+                "stretch"
+            } else {
+                "turntable"
+            },
+        );
+
+        MONO_DISPLAY_CH.send(MonoDisplayCommand::Clear).await;
+        for line in [name, natdex, bar, time, state, speed] {
+            MONO_DISPLAY_CH
+                .send(MonoDisplayCommand::WriteStr(line))
+                .await;
+        }
+    }
+}
+
+fn take_total(action: Action) -> usize {
+    InputListener::take_action(action, true)
         .ok()
         .flatten()
         .unwrap_or(0) as usize
 }
 
-fn take_one_rotary_turn() -> Option<Direction> {
-    const ROTARY_INPUTS: [(Input, Direction); 4] = [
-        (
-            Input::RotaryEncoderRotateLeft(Direction::Anticlockwise),
-            Direction::Anticlockwise,
-        ),
-        (
-            Input::RotaryEncoderRotateRight(Direction::Anticlockwise),
-            Direction::Anticlockwise,
-        ),
-        (
-            Input::RotaryEncoderRotateLeft(Direction::Clockwise),
-            Direction::Clockwise,
-        ),
-        (
-            Input::RotaryEncoderRotateRight(Direction::Clockwise),
-            Direction::Clockwise,
-        ),
-    ];
-
-    ROTARY_INPUTS.iter().find_map(|(input, direction)| {
-        InputListener::take_input(*input, false)
-            .ok()
-            .flatten()
-            .map(|_| *direction)
-    })
+/// How many steps `dial` has been turned clockwise, less the steps
+/// anticlockwise.
+fn take_turns(dial: Dial) -> i32 {
+    InputListener::take_dial(dial, RotationMode::Raw).unwrap_or(0)
 }
 
+/// Starts the animation of the Pokemon. The mono display is drawn by
+/// `show_progress`.
 async fn show_cry(cry_index: usize) {
     let cry = &CRIES[cry_index];
-    let name = String::try_from(cry.name).unwrap();
-    let mut natdex = String::new();
-    write!(natdex, " \nNatDex#: {}", cry.pokemon_id).unwrap();
-
-    MONO_DISPLAY_CH.send(MonoDisplayCommand::Clear).await;
-    MONO_DISPLAY_CH
-        .send(MonoDisplayCommand::WriteStr(name))
-        .await;
-    MONO_DISPLAY_CH
-        .send(MonoDisplayCommand::WriteStr(natdex))
-        .await;
     LARGE_DISPLAY_CH
         .send(LargeDisplayCommand::PlayPokemon(cry.pokemon_id))
         .await;
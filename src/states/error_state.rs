@@ -3,9 +3,24 @@ use defmt::{error, info, warn};
 use embassy_time::{Duration, Timer};
 
 use super::Meowbox;
-use crate::states::State;
+use crate::{
+    hardware::sequencer::{find_song, play_system_song},
+    states::{ErrorStateType, State},
+};
 
 impl Meowbox {
+    /// Goes into the error state and plays the `error` song.
+    pub(super) fn enter_error_state(
+        &mut self,
+        etype: ErrorStateType,
+    ) {
+        self.state = State::ErrorState(etype);
+
+        if let Some(song) = find_song("error") {
+            play_system_song(song);
+        }
+    }
+
     pub(super) async fn tick_error_state(&mut self) {
         if let State::ErrorState(etype) = self.state {
             //RED_LED.lock().await.as_mut().unwrap().toggle();
@@ -2,11 +2,8 @@ use core::fmt::Write;
 
 use embassy_time::{Duration, Timer};
 use heapless::String;
-use rotary_encoder_embedded::Direction;
 
-use super::{
-    LangtonDirection, LangtonState, MenuState, Meowbox, Stage, State,
-};
+use super::app::{App, AppContext, MenuPlacement};
 use crate::{
     hardware::{
         buzzer::{BUZZER_2K3_CH, BuzzerCommand},
@@ -20,7 +17,12 @@ use crate::{
             MonoDisplayCommand,
         },
     },
-    input_listener::{Input, InputListener},
+    input_listener::{
+        InputListener,
+        acceleration::RotationMode,
+        bindings::{Action, Dial, active_bindings},
+    },
+    seeds::seeds,
 };
 
 const BLACK: u16 = 0x0000;
@@ -87,28 +89,56 @@ const LANGTON_RULES: [LangtonRule; LANGTON_RULE_COUNT] = [
     },
 ];
 
-impl Meowbox {
-    pub(super) async fn tick_langton(&mut self) {
-        let State::Langton(stage, langton_state) = self.state else {
-            return;
-        };
+#[derive(Clone, Copy, Debug)]
+pub enum LangtonDirection {
+    Up,
+    Right,
+    Down,
+    Left,
+}
 
-        match stage {
-            Stage::Setup => self.setup_langton(langton_state).await,
-            Stage::Execution => {
-                self.execute_langton(langton_state).await
-            }
-            Stage::Shutdown => self.shutdown_langton().await,
+#[derive(Clone, Copy, Debug)]
+pub struct LangtonState {
+    pub x: usize,
+    pub y: usize,
+    pub direction: LangtonDirection,
+    pub rule_index: usize,
+    pub palette_index: i32,
+    pub cells: [u64; 75],
+    pub cells_high: [u64; 75],
+}
+
+impl Default for LangtonState {
+    fn default() -> Self {
+        Self {
+            x: 30,
+            y: 40,
+            direction: LangtonDirection::Up,
+            rule_index: 0,
+            palette_index: 0,
+            cells: [0; 75],
+            cells_high: [0; 75],
         }
     }
+}
+
+impl App for LangtonState {
+    const NAME: &'static str = "Langton";
+    const MENU_PLACEMENT: MenuPlacement = MenuPlacement::Root;
 
-    async fn setup_langton(&mut self, langton_state: LangtonState) {
+    async fn setup(&mut self, _ctx: &mut AppContext<'_>) {
+        let langton_state = *self;
+        let bindings = active_bindings();
         LED_SHIFTER_CHANNEL.send(LedCommand::SetAllLow).await;
         LED_SHIFTER_CHANNEL
-            .send(LedCommand::SetHigh(LED::ButtonLeft))
+            .send(LedCommand::SetHigh(LED::of_control(
+                bindings.control(Action::Back),
+            )))
             .await;
         LED_SHIFTER_CHANNEL
-            .send(LedCommand::SetHigh(LED::ButtonRight))
+            .send(LedCommand::SetHigh(LED::of_control(
+                bindings.control(Action::Confirm),
+            )))
             .await;
 
         MONO_DISPLAY_CH
@@ -133,27 +163,22 @@ impl Meowbox {
             colors.ant,
         )
         .await;
-
-        self.state = State::Langton(Stage::Execution, langton_state);
     }
 
-    async fn execute_langton(
-        &mut self,
-        mut langton_state: LangtonState,
-    ) {
-        if InputListener::take_input(Input::ButtonLeft, true)
+    async fn execute(&mut self, ctx: &mut AppContext<'_>) {
+        if InputListener::take_action(Action::Back, true)
             .ok()
             .flatten()
             .is_some()
         {
-            self.next_state =
-                Some(State::Menu(Stage::Setup, MenuState::default()));
-            self.needs_to_shutdown = true;
+            ctx.exit_to_menu();
             return;
         }
 
+        let mut langton_state = *self;
+
         let right_pressed =
-            InputListener::take_input(Input::ButtonRight, true)
+            InputListener::take_action(Action::Confirm, true)
                 .ok()
                 .flatten()
                 .is_some();
@@ -205,18 +230,13 @@ impl Meowbox {
             }
         }
 
-        self.state = State::Langton(Stage::Execution, langton_state);
+        *self = langton_state;
         Timer::after(Duration::from_millis(10)).await;
     }
 
-    async fn shutdown_langton(&mut self) {
+    async fn shutdown(&mut self, _ctx: &mut AppContext<'_>) {
         LED_SHIFTER_CHANNEL.send(LedCommand::SetAllLow).await;
         BACKLIGHT_CH.send(BacklightCommand::SetLow).await;
-
-        self.state = self.next_state.take().unwrap_or(State::Menu(
-            Stage::Setup,
-            MenuState::default(),
-        ));
     }
 }
 
@@ -380,53 +400,21 @@ async fn write_langton_label(langton_state: LangtonState) {
 }
 
 fn take_rule_delta() -> i16 {
-    let left_cw = take_input_count(Input::RotaryEncoderRotateLeft(
-        Direction::Clockwise,
-    ));
-    let left_ccw = take_input_count(Input::RotaryEncoderRotateLeft(
-        Direction::Anticlockwise,
-    ));
-
-    left_cw as i16 - left_ccw as i16
+    InputListener::take_dial(Dial::Primary, RotationMode::Raw)
+        .unwrap_or_default() as i16
 }
 
 fn take_palette_delta() -> i16 {
-    let right_cw = take_input_count(Input::RotaryEncoderRotateRight(
-        Direction::Clockwise,
-    ));
-    let right_ccw = take_input_count(
-        Input::RotaryEncoderRotateRight(Direction::Anticlockwise),
-    );
-
-    right_cw as i16 - right_ccw as i16
+    InputListener::take_dial(Dial::Secondary, RotationMode::Raw)
+        .unwrap_or_default() as i16
 }
 
 fn drain_langton_inputs() {
-    let _ = take_input_count(Input::RotaryEncoderRotateLeft(
-        Direction::Clockwise,
-    ));
-    let _ = take_input_count(Input::RotaryEncoderRotateLeft(
-        Direction::Anticlockwise,
-    ));
-    let _ = take_input_count(Input::RotaryEncoderRotateRight(
-        Direction::Clockwise,
-    ));
-    let _ = take_input_count(Input::RotaryEncoderRotateRight(
-        Direction::Anticlockwise,
-    ));
-    let _ = take_input_count(Input::ButtonLeft);
-    let _ = take_input_count(Input::ButtonRight);
-    let _ = take_input_count(Input::DpadLeft);
-    let _ = take_input_count(Input::DpadRight);
-    let _ = take_input_count(Input::DpadTop);
-    let _ = take_input_count(Input::DpadBottom);
-}
-
-fn take_input_count(input: Input) -> u16 {
-    InputListener::take_input(input, true)
-        .ok()
-        .flatten()
-        .unwrap_or_default()
+    let _ = take_rule_delta();
+    let _ = take_palette_delta();
+    for action in Action::ALL {
+        let _ = InputListener::take_action(action, true);
+    }
 }
 
 fn current_rule(langton_state: &LangtonState) -> LangtonRule {
@@ -453,7 +441,7 @@ fn langton_colors(index: i32) -> LangtonColors {
         };
     }
 
-    let seed = index as u32;
+    let seed = index as u32 ^ seeds().palette;
     let background = random_rgb565(seed ^ 0x4c41_4e47);
     let state_one = random_rgb565(seed ^ 0x544f_4e31);
     let state_two = random_rgb565(seed ^ 0x544f_4e32);
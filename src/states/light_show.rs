@@ -1,10 +1,6 @@
 use embassy_time::{Duration, Timer};
-use rotary_encoder_embedded::Direction;
 
-use super::{
-    LightShowDirection, LightShowMode, LightShowState, MenuState,
-    Meowbox, Stage, State,
-};
+use super::app::{App, AppContext, MenuPlacement};
 use crate::{
     hardware::{
         large_display::{
@@ -14,7 +10,12 @@ use crate::{
         led_shifter::{LED, LED_SHIFTER_CHANNEL, LedCommand},
         mono_display::{MONO_DISPLAY_CH, MonoDisplayCommand},
     },
-    input_listener::{Input, InputListener},
+    input_listener::{
+        Input, InputListener,
+        acceleration::RotationMode,
+        bindings::{Action, Dial},
+    },
+    seeds::seeds,
 };
 
 const RANDOM_LIGHT_SHOW_LEDS: [LED; 16] = [
@@ -65,28 +66,48 @@ const RING_STEP_CHANGE_MS: u64 = 1;
 const RING_STEP_MIN_MS: u64 = 5;
 const RING_STEP_MAX_MS: u64 = 250;
 
-impl Meowbox {
-    pub(super) async fn tick_light_show(&mut self) {
-        let State::LightShow(stage, light_show_state) = self.state
-        else {
-            return;
-        };
+#[derive(Clone, Copy, Debug)]
+pub enum LightShowMode {
+    RandomBlink,
+    RingTrail,
+}
 
-        match stage {
-            Stage::Setup => {
-                self.setup_light_show(light_show_state).await
-            }
-            Stage::Execution => {
-                self.execute_light_show(light_show_state).await
-            }
-            Stage::Shutdown => self.shutdown_light_show().await,
+#[derive(Clone, Copy, Debug)]
+pub enum LightShowDirection {
+    Clockwise,
+    Counterclockwise,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LightShowState {
+    pub mode: LightShowMode,
+    pub random_seed: u16,
+    pub random_interval_ms: u64,
+    pub random_light_count: u8,
+    pub ring_index: usize,
+    pub ring_step_ms: u64,
+    pub ring_direction: LightShowDirection,
+}
+
+impl Default for LightShowState {
+    fn default() -> Self {
+        Self {
+            mode: LightShowMode::RandomBlink,
+            random_seed: seeds().light_show,
+            random_interval_ms: 250,
+            random_light_count: 4,
+            ring_index: 0,
+            ring_step_ms: 10,
+            ring_direction: LightShowDirection::Clockwise,
         }
     }
+}
+
+impl App for LightShowState {
+    const NAME: &'static str = "LightShow";
+    const MENU_PLACEMENT: MenuPlacement = MenuPlacement::Root;
 
-    async fn setup_light_show(
-        &mut self,
-        light_show_state: LightShowState,
-    ) {
+    async fn setup(&mut self, _ctx: &mut AppContext<'_>) {
         MONO_DISPLAY_CH.send(MonoDisplayCommand::Clear).await;
         MONO_DISPLAY_CH
             .send(MonoDisplayCommand::SetDisplayOn(false))
@@ -96,49 +117,33 @@ impl Meowbox {
         LED_SHIFTER_CHANNEL.send(LedCommand::SetAllLow).await;
 
         drain_light_show_inputs();
-
-        self.state =
-            State::LightShow(Stage::Execution, light_show_state);
     }
 
-    async fn execute_light_show(
-        &mut self,
-        mut light_show_state: LightShowState,
-    ) {
+    async fn execute(&mut self, ctx: &mut AppContext<'_>) {
         if left_button_requested_main_menu() {
-            self.next_state =
-                Some(State::Menu(Stage::Setup, MenuState::default()));
-            self.needs_to_shutdown = true;
+            ctx.exit_to_menu();
             return;
         }
 
-        handle_light_show_inputs(&mut light_show_state).await;
+        handle_light_show_inputs(self).await;
 
-        match light_show_state.mode {
+        match self.mode {
             LightShowMode::RandomBlink => {
-                run_random_blink(&mut light_show_state).await;
+                run_random_blink(self).await;
             }
             LightShowMode::RingTrail => {
-                run_ring_trail(&mut light_show_state).await;
+                run_ring_trail(self).await;
             }
         }
-
-        self.state =
-            State::LightShow(Stage::Execution, light_show_state);
     }
 
-    async fn shutdown_light_show(&mut self) {
+    async fn shutdown(&mut self, _ctx: &mut AppContext<'_>) {
         LED_SHIFTER_CHANNEL.send(LedCommand::SetAllLow).await;
         BACKLIGHT_CH.send(BacklightCommand::SetLow).await;
         LARGE_DISPLAY_CH.send(LargeDisplayCommand::DisplayOn).await;
         MONO_DISPLAY_CH
             .send(MonoDisplayCommand::SetDisplayOn(true))
             .await;
-
-        self.state = self.next_state.take().unwrap_or(State::Menu(
-            Stage::Setup,
-            MenuState::default(),
-        ));
     }
 }
 
@@ -147,12 +152,12 @@ async fn handle_light_show_inputs(
 ) {
     drain_button_inputs();
 
-    let previous_mode = take_total(Input::RotaryEncoderPressRight)
-        + take_total(Input::DpadLeft)
-        + take_total(Input::DpadTop);
-    let next_mode = take_total(Input::RotaryEncoderPressLeft)
-        + take_total(Input::DpadRight)
-        + take_total(Input::DpadBottom);
+    let previous_mode = take_total(Action::SecondaryPress)
+        + take_total(Action::Previous)
+        + take_total(Action::Up);
+    let next_mode = take_total(Action::PrimaryPress)
+        + take_total(Action::Next)
+        + take_total(Action::Down);
 
     if previous_mode != 0 || next_mode != 0 {
         scroll_mode(light_show_state, next_mode, previous_mode);
@@ -161,12 +166,7 @@ async fn handle_light_show_inputs(
 
     match light_show_state.mode {
         LightShowMode::RandomBlink => {
-            let speed_up = take_total(
-                Input::RotaryEncoderRotateRight(Direction::Clockwise),
-            );
-            let slow_down = take_total(
-                Input::RotaryEncoderRotateRight(Direction::Anticlockwise),
-            );
+            let (speed_up, slow_down) = take_turns(Dial::Secondary);
             light_show_state.random_interval_ms = adjust_u64(
                 light_show_state.random_interval_ms,
                 slow_down,
@@ -176,12 +176,8 @@ async fn handle_light_show_inputs(
                 RANDOM_INTERVAL_MAX_MS,
             );
 
-            let more_lights = take_total(
-                Input::RotaryEncoderRotateLeft(Direction::Clockwise),
-            );
-            let fewer_lights = take_total(
-                Input::RotaryEncoderRotateLeft(Direction::Anticlockwise),
-            );
+            let (more_lights, fewer_lights) =
+                take_turns(Dial::Primary);
             light_show_state.random_light_count = adjust_u8(
                 light_show_state.random_light_count,
                 more_lights,
@@ -191,12 +187,8 @@ async fn handle_light_show_inputs(
             );
         }
         LightShowMode::RingTrail => {
-            let clockwise = take_total(
-                Input::RotaryEncoderRotateLeft(Direction::Clockwise),
-            );
-            let counterclockwise = take_total(
-                Input::RotaryEncoderRotateLeft(Direction::Anticlockwise),
-            );
+            let (clockwise, counterclockwise) =
+                take_turns(Dial::Primary);
 
             if clockwise > counterclockwise {
                 light_show_state.ring_direction =
@@ -206,12 +198,7 @@ async fn handle_light_show_inputs(
                     LightShowDirection::Counterclockwise;
             }
 
-            let speed_up = take_total(
-                Input::RotaryEncoderRotateRight(Direction::Clockwise),
-            );
-            let slow_down = take_total(
-                Input::RotaryEncoderRotateRight(Direction::Anticlockwise),
-            );
+            let (speed_up, slow_down) = take_turns(Dial::Secondary);
             light_show_state.ring_step_ms = adjust_u64(
                 light_show_state.ring_step_ms,
                 slow_down,
@@ -265,45 +252,52 @@ async fn run_ring_trail(light_show_state: &mut LightShowState) {
 }
 
 fn drain_light_show_inputs() {
-    let _ = take_total(Input::ButtonLeft);
-    drain_button_inputs();
-
-    let _ = take_total(Input::RotaryEncoderPressLeft);
-    let _ = take_total(Input::RotaryEncoderPressRight);
-    let _ = take_total(Input::RotaryEncoderRotateLeft(
-        Direction::Clockwise,
-    ));
-    let _ = take_total(Input::RotaryEncoderRotateLeft(
-        Direction::Anticlockwise,
-    ));
-    let _ = take_total(Input::RotaryEncoderRotateRight(
-        Direction::Clockwise,
-    ));
-    let _ = take_total(Input::RotaryEncoderRotateRight(
-        Direction::Anticlockwise,
-    ));
-    let _ = take_total(Input::DpadLeft);
-    let _ = take_total(Input::DpadRight);
-    let _ = take_total(Input::DpadTop);
-    let _ = take_total(Input::DpadBottom);
+    for action in Action::ALL {
+        let _ = take_total(action);
+    }
+    let _ = take_total_input(Input::ButtonRightReleased);
+    for dial in Dial::ALL {
+        let _ = take_turns(dial);
+    }
 }
 
 fn drain_button_inputs() {
-    let _ = take_total(Input::ButtonRight);
-    let _ = take_total(Input::ButtonRightReleased);
+    let _ = take_total(Action::Confirm);
+    let _ = take_total_input(Input::ButtonRightReleased);
 }
 
 fn left_button_requested_main_menu() -> bool {
-    take_total(Input::ButtonLeft) != 0
+    take_total(Action::Back) != 0
+}
+
+fn take_total(action: Action) -> u16 {
+    InputListener::take_action(action, true)
+        .ok()
+        .flatten()
+        .unwrap_or(0)
 }
 
-fn take_total(input: Input) -> u16 {
+fn take_total_input(input: Input) -> u16 {
     InputListener::take_input(input, true)
         .ok()
         .flatten()
         .unwrap_or(0)
 }
 
+/// How far `dial` has been turned, as the steps clockwise and the
+/// steps anticlockwise. Only the difference between the two is kept.
+fn take_turns(dial: Dial) -> (u16, u16) {
+    let turns = InputListener::take_dial(dial, RotationMode::Raw)
+        .unwrap_or(0)
+        .clamp(-(u16::MAX as i32), u16::MAX as i32);
+
+    if turns >= 0 {
+        (turns as u16, 0)
+    } else {
+        (0, turns.unsigned_abs() as u16)
+    }
+}
+
 fn scroll_mode(
     light_show_state: &mut LightShowState,
     next: u16,
@@ -3,6 +3,8 @@ use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst};
 use heapless::Vec;
 use static_cell::StaticCell;
 
+use crate::states::app::{APPS, AppId, MenuPlacement};
+
 static _MENU_TREE: StaticCell<MenuTree> = StaticCell::new();
 
 pub static _MENU_SCROLL: AtomicUsize = AtomicUsize::new(0);
@@ -68,7 +70,7 @@ impl MenuStatusHandle {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MenuFolder {
     Tests,
 }
@@ -81,50 +83,9 @@ impl MenuFolder {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub enum MenuProgram {
-    LightShow,
-    Cries,
-    Beeper,
-    FlowField,
-    Seizure,
-    Polyhedra,
-    BadDecision,
-    Wireframe,
-    Sounds,
-    Conway,
-    Automata,
-    Langton,
-    Synth,
-
-    // Exists under folder Test
-    BuzzerTest,
-}
-
-impl MenuProgram {
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            MenuProgram::LightShow => "LightShow",
-            MenuProgram::Cries => "Cries",
-            MenuProgram::Beeper => "Beeper",
-            MenuProgram::FlowField => "FlowField",
-            MenuProgram::Seizure => "Seizure",
-            MenuProgram::Polyhedra => "Polyhedra",
-            MenuProgram::BadDecision => "BadDecision",
-            MenuProgram::Wireframe => "Wireframe",
-            MenuProgram::Sounds => "Sounds",
-            MenuProgram::Conway => "Conway",
-            MenuProgram::Automata => "Automata",
-            MenuProgram::Langton => "Langton",
-            MenuProgram::Synth => "Synth",
-            MenuProgram::BuzzerTest => "BuzzerTest",
-        }
-    }
-}
-
 #[derive(Debug, Clone, Copy)]
 pub enum MenuGeneralItem {
-    MenuProgram(MenuProgram),
+    App(AppId),
     MenuFolder(MenuFolder),
 }
 
@@ -136,34 +97,30 @@ pub struct MenuTree {
     pub layer_1: Vec<MenuGeneralItem, 20>,
 }
 
-/// This is where the definition of the menu tree for this program
-/// exists.
+/// Builds the menu tree out of the app registry, keeping the order
+/// the apps are registered in.
 fn generate_menu_definition() -> MenuTree {
-    //MenuTree { inner: arena }
-
     let mut layer_0 = Vec::new();
-
-    layer_0
-        .push(MenuGeneralItem::MenuProgram(MenuProgram::LightShow))
-        .unwrap();
-    layer_0
-        .push(MenuGeneralItem::MenuProgram(MenuProgram::Cries))
-        .unwrap();
-    layer_0
-        .push(MenuGeneralItem::MenuProgram(MenuProgram::Automata))
-        .unwrap();
-    layer_0
-        .push(MenuGeneralItem::MenuProgram(MenuProgram::Langton))
-        .unwrap();
-    layer_0
-        .push(MenuGeneralItem::MenuProgram(MenuProgram::Synth))
-        .unwrap();
-
     let mut layer_1 = Vec::new();
 
-    layer_1
-        .push(MenuGeneralItem::MenuProgram(MenuProgram::BuzzerTest))
-        .unwrap();
+    for app in APPS {
+        match app.menu_placement {
+            MenuPlacement::Root => {
+                layer_0.push(MenuGeneralItem::App(app.id)).unwrap()
+            }
+            // There is only one folder for now, which is layer 1.
+            MenuPlacement::Folder(MenuFolder::Tests) => {
+                layer_1.push(MenuGeneralItem::App(app.id)).unwrap()
+            }
+            MenuPlacement::Hidden => {}
+        }
+    }
+
+    if !layer_1.is_empty() {
+        layer_0
+            .push(MenuGeneralItem::MenuFolder(MenuFolder::Tests))
+            .unwrap();
+    }
 
     // update the layer length
     let handle = MenuStatusHandle::new();
@@ -182,6 +139,15 @@ impl MenuTree {
     pub fn new() -> Self {
         generate_menu_definition()
     }
+
+    /// The items of `layer`, 0 being the first layer and 1 the
+    /// folder.
+    pub fn layer(&self, layer: usize) -> &[MenuGeneralItem] {
+        match layer {
+            0 => &self.layer_0,
+            _ => &self.layer_1,
+        }
+    }
 }
 
 pub struct MenuResources {
@@ -1,17 +1,14 @@
 use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst};
 
-use defmt::{dbg, info};
+use defmt::println;
 use embassy_time::{Duration, Timer};
-use esp_println::println;
 use heapless::String;
-use menu::{MenuGeneralItem, MenuProgram, MenuStatusHandle};
-use rotary_encoder_embedded::Direction;
+use menu::{MenuGeneralItem, MenuStatusHandle};
 
 use super::{Meowbox, State};
 use crate::{
     hardware::{
-        buttons::DPAD_DEBOUNCE,
-        buzzer::{BUZZER_2K3_CH, BUZZER_400_CH, BuzzerCommand},
+        buzzer::{BUZZER_2K3_CH, BuzzerCommand},
         large_display::{
             BACKLIGHT_CH, BacklightCommand, LARGE_DISPLAY_CH,
             LargeDisplayCommand,
@@ -21,15 +18,15 @@ use crate::{
             MONO_DISPLAY_CH, MONO_DISPLAY_LINE_WIDTH,
             MonoDisplayCommand,
         },
-        speaker::{MEOW_PCM, SPEAKER_CHANNEL, SpeakerCommand},
-        thumbwheel::ThumbwheelHandle,
+        speaker::system::{SystemSound, play_system_sound},
     },
-    input_listener::{Input, InputListener, KillSignal},
-    states::{
-        AutomataState, ErrorStateType, LangtonState, LightShowState,
-        MenuState, Stage, SynthState,
+    input_listener::{
+        InputListener, KillSignal,
+        acceleration::RotationMode,
+        bindings::{Action, Dial, active_bindings},
+        thumbwheel::Thumbwheel,
     },
-    tasks::all_leds_off,
+    states::{ErrorStateType, MenuState, Stage},
 };
 
 pub mod menu;
@@ -39,8 +36,6 @@ static RIGHT_ROTARY_DISPLAY_INDEX: AtomicUsize = AtomicUsize::new(0);
 static RIGHT_ROTARY_SNAKE_INDEX: AtomicUsize = AtomicUsize::new(0);
 static RIGHT_ROTARY_DISPLAY_INITIALIZED: AtomicBool =
     AtomicBool::new(false);
-static LAST_RIGHT_THUMBWHEEL_BACKLIGHT: AtomicUsize =
-    AtomicUsize::new(usize::MAX);
 
 // Light Ring
 impl Meowbox {
@@ -63,20 +58,30 @@ impl Meowbox {
             .send(LargeDisplayCommand::Clear(LARGE_DISPLAY_BLACK))
             .await;
 
+        let bindings = active_bindings();
+
         LED_SHIFTER_CHANNEL
-            .send(LedCommand::SetHigh(LED::ButtonRight))
+            .send(LedCommand::SetHigh(LED::of_control(
+                bindings.control(Action::Confirm),
+            )))
             .await;
 
         LED_SHIFTER_CHANNEL
-            .send(LedCommand::SetHigh(LED::AmberLeft))
+            .send(LedCommand::SetHigh(LED::of_encoder(
+                bindings.encoder(Dial::Primary),
+            )))
             .await;
 
         LED_SHIFTER_CHANNEL
-            .send(LedCommand::SetHigh(LED::DpadBottom))
+            .send(LedCommand::SetHigh(LED::of_control(
+                bindings.control(Action::Down),
+            )))
             .await;
 
         LED_SHIFTER_CHANNEL
-            .send(LedCommand::SetHigh(LED::DpadTop))
+            .send(LedCommand::SetHigh(LED::of_control(
+                bindings.control(Action::Up),
+            )))
             .await;
 
         MONO_DISPLAY_CH
@@ -116,11 +121,11 @@ impl Meowbox {
             //info!("display menu");
         }
 
-        poll_right_thumbwheel_backlight().await;
+        print_right_thumbwheel();
 
         let _ = handle_inputs().await;
 
-        if InputListener::take_input(Input::ButtonRight, true)
+        if InputListener::take_action(Action::Confirm, true)
             .ok()
             .flatten()
             .is_some()
@@ -130,39 +135,20 @@ impl Meowbox {
                 .resources
                 .menu_resoures
                 .menu_tree
-                .layer_0
+                .layer(menu_status_handle.layer())
                 .get(menu_status_handle.scroll())
                 .copied();
 
             self.next_state = match selected {
-                Some(MenuGeneralItem::MenuProgram(
-                    MenuProgram::LightShow,
-                )) => Some(State::LightShow(
-                    Stage::Setup,
-                    LightShowState::default(),
-                )),
-                Some(MenuGeneralItem::MenuProgram(
-                    MenuProgram::Cries,
-                )) => Some(State::Cries(Stage::Setup, 0)),
-                Some(MenuGeneralItem::MenuProgram(
-                    MenuProgram::Automata,
-                )) => Some(State::Automata(
-                    Stage::Setup,
-                    AutomataState::default(),
-                )),
-                Some(MenuGeneralItem::MenuProgram(
-                    MenuProgram::Langton,
-                )) => Some(State::Langton(
-                    Stage::Setup,
-                    LangtonState::default(),
-                )),
-                Some(MenuGeneralItem::MenuProgram(
-                    MenuProgram::Synth,
-                )) => Some(State::Synth(
-                    Stage::Setup,
-                    SynthState::default(),
-                )),
-                Some(_) => Some(State::Unimplemented(Stage::Setup)),
+                Some(MenuGeneralItem::App(app_id)) => {
+                    Some(State::App(Stage::Setup, app_id))
+                }
+                Some(MenuGeneralItem::MenuFolder(_)) => {
+                    menu_status_handle.set_layer(1);
+                    menu_status_handle.set_scroll(0);
+                    menu_status_handle.set_needs_update(true);
+                    None
+                }
                 None => None,
             };
 
@@ -171,9 +157,7 @@ impl Meowbox {
                 return;
             }
 
-            SPEAKER_CHANNEL
-                .send(SpeakerCommand::PlayPcm(MEOW_PCM))
-                .await;
+            play_system_sound(SystemSound::Confirm).await;
         }
 
         let menu_status_handle = MenuStatusHandle::new();
@@ -203,7 +187,7 @@ impl Meowbox {
             .resources
             .menu_resoures
             .menu_tree
-            .layer_0
+            .layer(menu_status_handle.layer())
             .iter()
             .skip(scroll)
             // we only show the first 7, because this is all that fits
@@ -223,10 +207,10 @@ impl Meowbox {
 
             let name: String<MONO_DISPLAY_LINE_WIDTH> =
                 match general_item {
-                    MenuGeneralItem::MenuProgram(x) => {
+                    MenuGeneralItem::App(x) => {
                         let mut combined: String<16> = String::new();
                         combined.push_str("#").unwrap();
-                        combined.push_str(&x.as_str()).unwrap();
+                        combined.push_str(x.name()).unwrap();
                         combined
                     }
                     MenuGeneralItem::MenuFolder(x) => {
@@ -273,27 +257,14 @@ impl Meowbox {
     }
 }
 
-const THUMBWHEEL_PRINT_THRESHOLD: usize = 16;
-
-async fn poll_right_thumbwheel_backlight() {
-    let Some(right_thumbwheel) = ThumbwheelHandle::right_raw().await
+fn print_right_thumbwheel() {
+    let Ok(Some(position)) =
+        InputListener::take_thumbwheel(Thumbwheel::Right)
     else {
         return;
     };
 
-    let backlight = usize::from(right_thumbwheel);
-    let last_backlight = LAST_RIGHT_THUMBWHEEL_BACKLIGHT.load(SeqCst);
-
-    if last_backlight != usize::MAX
-        && last_backlight.abs_diff(backlight)
-            < THUMBWHEEL_PRINT_THRESHOLD
-    {
-        return;
-    }
-
-    LAST_RIGHT_THUMBWHEEL_BACKLIGHT.store(backlight, SeqCst);
-
-    println!("right thumbwheel changed: adc={}", right_thumbwheel);
+    println!("right thumbwheel changed: position={}", position);
 }
 
 #[allow(dead_code)]
@@ -359,30 +330,26 @@ async fn test_text_on_each_line() {
 
 /// Returns Err(KillSignal) if the kill signal was given
 async fn handle_inputs() -> Result<(), KillSignal> {
-    let left_rotary_encoder_pressed = InputListener::take_input(
-        Input::RotaryEncoderPressLeft,
-        true,
-    )
-    .unwrap()
-    .unwrap_or_default()
-        != 0;
+    let bindings = active_bindings();
+
+    let primary_pressed =
+        InputListener::take_action(Action::PrimaryPress, true)?
+            .unwrap_or_default()
+            != 0;
 
-    if left_rotary_encoder_pressed {
+    if primary_pressed {
         //println!("AAAA WHY THIS TRIGGER");
         BUZZER_2K3_CH
             .send(BuzzerCommand::Play(Duration::from_millis(50)))
             .await;
     }
 
-    let right_rotary_encoder_pressed = InputListener::take_input(
-        Input::RotaryEncoderPressRight,
-        true,
-    )
-    .unwrap()
-    .unwrap_or_default()
-        != 0;
+    let secondary_pressed =
+        InputListener::take_action(Action::SecondaryPress, true)?
+            .unwrap_or_default()
+            != 0;
 
-    if right_rotary_encoder_pressed {
+    if secondary_pressed {
         //println!("AAAA WHY THIS TRIGGER");
         // BUZZER_400_CH
         //     .send(BuzzerCommand::Play(Duration::from_millis(2000)))
@@ -391,17 +358,10 @@ async fn handle_inputs() -> Result<(), KillSignal> {
         draw_right_rotary_display_press().await;
     }
 
-    let right_rotary_encoder_cw = InputListener::take_input(
-        Input::RotaryEncoderRotateRight(Direction::Clockwise),
-        true,
-    )?
-    .unwrap_or_default();
-
-    let right_rotary_encoder_ccw = InputListener::take_input(
-        Input::RotaryEncoderRotateRight(Direction::Anticlockwise),
-        true,
-    )?
-    .unwrap_or_default();
+    let secondary_dial =
+        InputListener::take_dial(Dial::Secondary, RotationMode::Raw)?;
+    let right_rotary_encoder_cw = secondary_dial.max(0) as u16;
+    let right_rotary_encoder_ccw = (-secondary_dial).max(0) as u16;
 
     if right_rotary_encoder_cw != 0 || right_rotary_encoder_ccw != 0 {
         BACKLIGHT_CH.send(BacklightCommand::SetHigh).await;
@@ -413,15 +373,13 @@ async fn handle_inputs() -> Result<(), KillSignal> {
         .await;
     }
 
-    let left_rotary_encoder_cw = InputListener::take_input(
-        Input::RotaryEncoderRotateLeft(Direction::Clockwise),
-        true,
-    )?
-    .unwrap_or_default();
+    let primary_dial =
+        InputListener::take_dial(Dial::Primary, RotationMode::Raw)?;
+    let left_rotary_encoder_cw = primary_dial.max(0) as u16;
+    let left_rotary_encoder_ccw = (-primary_dial).max(0) as u16;
 
-    let dpad_bottom =
-        InputListener::take_input(Input::DpadBottom, true)?
-            .unwrap_or_default();
+    let dpad_bottom = InputListener::take_action(Action::Down, true)?
+        .unwrap_or_default();
 
     let scroll_down_amount = left_rotary_encoder_cw + dpad_bottom;
 
@@ -430,7 +388,7 @@ async fn handle_inputs() -> Result<(), KillSignal> {
 
         LED_SHIFTER_CHANNEL
             .send(LedCommand::TemporaryToggle(
-                LED::AmberLeft,
+                LED::of_encoder(bindings.encoder(Dial::Primary)),
                 Duration::from_millis(200),
             ))
             .await;
@@ -441,19 +399,13 @@ async fn handle_inputs() -> Result<(), KillSignal> {
 
         LED_SHIFTER_CHANNEL
             .send(LedCommand::TemporaryToggle(
-                LED::DpadBottom,
+                LED::of_control(bindings.control(Action::Down)),
                 Duration::from_millis(200),
             ))
             .await;
     }
 
-    let left_rotary_encoder_ccw = InputListener::take_input(
-        Input::RotaryEncoderRotateLeft(Direction::Anticlockwise),
-        true,
-    )?
-    .unwrap_or_default();
-
-    let dpad_top = InputListener::take_input(Input::DpadTop, true)?
+    let dpad_top = InputListener::take_action(Action::Up, true)?
         .unwrap_or_default();
 
     let scroll_up_amount = left_rotary_encoder_ccw + dpad_top;
@@ -462,7 +414,7 @@ async fn handle_inputs() -> Result<(), KillSignal> {
         menu_scroll_up().await;
         LED_SHIFTER_CHANNEL
             .send(LedCommand::TemporaryToggle(
-                LED::AmberLeft,
+                LED::of_encoder(bindings.encoder(Dial::Primary)),
                 Duration::from_millis(200),
             ))
             .await;
@@ -473,7 +425,7 @@ async fn handle_inputs() -> Result<(), KillSignal> {
 
         LED_SHIFTER_CHANNEL
             .send(LedCommand::TemporaryToggle(
-                LED::DpadTop,
+                LED::of_control(bindings.control(Action::Up)),
                 Duration::from_millis(200),
             ))
             .await;
@@ -507,14 +459,24 @@ async fn handle_inputs() -> Result<(), KillSignal> {
 
     LED_SCROLL_INDEX.store(new_led_scroll_index, SeqCst);
 
-    let button_left =
-        InputListener::take_input(Input::ButtonLeft, true)?;
+    let button_left = InputListener::take_action(Action::Back, true)?;
 
     if button_left.is_some() {
-        SPEAKER_CHANNEL
-            .send(SpeakerCommand::Sine440Hz(Duration::from_secs(1)))
-            .await;
-        println!("hit left button");
+        let menu_status_handle = MenuStatusHandle::new();
+
+        if menu_status_handle.layer() == 0 {
+            // the menu has nowhere to go back to
+            play_system_sound(SystemSound::Error).await;
+            println!("hit left button");
+        } else {
+            // out of the folder, which is the last item of the first
+            // layer
+            menu_status_handle.set_layer(0);
+            menu_status_handle
+                .set_scroll(menu_status_handle.layer_0_size() - 1);
+            menu_status_handle.set_needs_update(true);
+            play_system_sound(SystemSound::Click).await;
+        }
     }
 
     Ok(())
@@ -771,6 +733,7 @@ async fn menu_scroll_down() {
     scroll = (scroll + 1) % menu_status_handle.current_layer_size();
     menu_status_handle.set_scroll(scroll);
     menu_status_handle.set_needs_update(true);
+    play_system_sound(SystemSound::Click).await;
 
     LED_SHIFTER_CHANNEL
         .send(LedCommand::TemporaryToggle(
@@ -792,4 +755,5 @@ async fn menu_scroll_up() {
     }
     menu_status_handle.set_scroll(scroll);
     menu_status_handle.set_needs_update(true);
+    play_system_sound(SystemSound::Click).await;
 }
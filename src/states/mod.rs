@@ -1,15 +1,38 @@
 use defmt::Format;
 #[allow(unused_imports)]
 use defmt::{error, info, warn};
-use embassy_executor::task;
 use embassy_sync::{
     blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal,
 };
 use menu_state::menu::MenuResources;
 
-use crate::{leds::LightRingState, physics::PhysicsResources};
+pub use self::{
+    app::{App, AppContext, AppId, AppState},
+    automata::AutomataState,
+    controls::ControlsState,
+    cries::CriesState,
+    langton::LangtonState,
+    light_show::LightShowState,
+    recorder::RecorderState,
+    synth::SynthState,
+};
+use crate::{
+    input_listener::{
+        InputListener,
+        auto_repeat::reset_auto_repeat,
+        bindings::set_active_bindings,
+        events::{INPUT_EVENTS, InputEvents},
+        gestures::{GESTURES, Gestures},
+    },
+    leds::LightRingState,
+    physics::PhysicsResources,
+    settings,
+};
 
+pub mod app;
 pub mod automata;
+pub mod buzzer_test;
+pub mod controls;
 pub mod cries;
 pub mod error_state;
 pub mod flow_field;
@@ -17,6 +40,8 @@ pub mod langton;
 pub mod light_ring_loop;
 pub mod light_show;
 pub mod menu_state;
+pub mod recorder;
+pub mod ringtones;
 pub mod synth;
 pub mod unimplemented;
 
@@ -29,6 +54,12 @@ pub mod unimplemented;
 pub struct Resources {
     pub physics_resources: PhysicsResources,
     pub menu_resoures: MenuResources,
+    /// Press and release edges, for states that need more than the
+    /// input counters. Drained whenever the state changes.
+    pub input_events: InputEvents,
+    /// Long presses, double presses and chords. Drained whenever the
+    /// state changes.
+    pub gestures: Gestures,
     //pub particles: &'static mut [physics::Particle; 5],
 }
 
@@ -56,6 +87,8 @@ impl Meowbox {
         let resources = Resources {
             physics_resources: PhysicsResources::new(),
             menu_resoures: MenuResources::new(),
+            input_events: InputEvents::subscribe(&INPUT_EVENTS),
+            gestures: Gestures::subscribe(&GESTURES),
         };
 
         Meowbox {
@@ -67,8 +100,21 @@ impl Meowbox {
     }
 
     pub async fn tick(&mut self) {
+        if let Some(state) = STATE_CHANGE_REQUEST.try_take() {
+            self.next_state = Some(state);
+            self.needs_to_shutdown = true;
+        }
+
         self.check_for_shutdown_transition();
 
+        // Picked every tick so that changes to the settings take
+        // effect straight away.
+        let running_app = match self.state {
+            State::App(_, id) => Some(id),
+            _ => None,
+        };
+        set_active_bindings(settings::bindings_for(running_app));
+
         //*self.resources.foo += 1;
         //info!("{}", self.resources.foo);
 
@@ -79,21 +125,15 @@ impl Meowbox {
             State::FlowField(_, _) => self.tick_flow_field().await,
             State::ErrorState(_) => self.tick_error_state().await,
             State::Menu(_, _) => self.tick_menu_state().await,
-            State::LightShow(_, _) => self.tick_light_show().await,
-            State::Cries(_, _) => self.tick_cries().await,
-            State::Automata(_, _) => self.tick_automata().await,
-            State::Langton(_, _) => self.tick_langton().await,
-            State::Synth(_, _) => self.tick_synth().await,
+            State::App(_, _) => self.tick_app().await,
             State::Unimplemented(_) => {
                 self.tick_unimplemented().await
             }
             // If we dont yet have the state implemented, go to the
             // error state.
-            _ => {
-                self.state = State::ErrorState(
-                    ErrorStateType::StateNotImplemented,
-                )
-            }
+            _ => self.enter_error_state(
+                ErrorStateType::StateNotImplemented,
+            ),
         }
     }
 
@@ -105,6 +145,14 @@ impl Meowbox {
 
         self.needs_to_shutdown = false;
 
+        // The state has stopped running by now, so a kill signal that
+        // was used to get here is no longer needed. Shutdown has to
+        // be able to use the input listener as normal.
+        InputListener::clear();
+        self.resources.input_events.drain();
+        self.resources.gestures.drain();
+        reset_auto_repeat();
+
         // If there is a need to shutdown, then set to shutdown.
         // Annoying, a match tree has to be used here.
         match self.state {
@@ -123,38 +171,44 @@ impl Meowbox {
                     flow_field_state,
                 );
             }
-            State::LightShow(_, light_show_state) => {
-                self.state = State::LightShow(
-                    Stage::Shutdown,
-                    light_show_state,
-                );
-            }
-            State::Cries(_, cry_index) => {
-                self.state = State::Cries(Stage::Shutdown, cry_index);
-            }
-            State::Automata(_, automata_state) => {
-                self.state =
-                    State::Automata(Stage::Shutdown, automata_state);
-            }
-            State::Langton(_, langton_state) => {
-                self.state =
-                    State::Langton(Stage::Shutdown, langton_state);
-            }
-            State::Synth(_, synth_state) => {
-                self.state =
-                    State::Synth(Stage::Shutdown, synth_state);
+            State::App(_, id) => {
+                self.state = State::App(Stage::Shutdown, id);
             }
             State::Unimplemented(_) => {
                 self.state = State::Unimplemented(Stage::Shutdown);
             }
             State::Debug(_, _, _) => {
-                self.state =
-                    State::ErrorState(ErrorStateType::Unknown);
+                self.enter_error_state(ErrorStateType::Unknown);
             }
             // If we hit errorr state, dont change anything
             State::ErrorState(_) => {}
         }
     }
+
+    /// Runs the current stage of the app, then moves it on to the
+    /// next stage. Apps never touch `self.state` themselves.
+    async fn tick_app(&mut self) {
+        let State::App(stage, id) = self.state else {
+            return;
+        };
+
+        let mut ctx = AppContext::new(
+            &mut self.resources,
+            &mut self.next_state,
+            &mut self.needs_to_shutdown,
+        );
+
+        app::run_app(id, stage, &mut ctx).await;
+
+        self.state = match stage {
+            Stage::Setup | Stage::Execution => {
+                State::App(Stage::Execution, id)
+            }
+            Stage::Shutdown => self.next_state.take().unwrap_or(
+                State::Menu(Stage::Setup, MenuState::default()),
+            ),
+        };
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -173,11 +227,9 @@ pub enum State {
     Menu(Stage, MenuState),
     LightRing(Stage, LightRingState),
     FlowField(Stage, FlowFieldState),
-    LightShow(Stage, LightShowState),
-    Cries(Stage, usize),
-    Automata(Stage, AutomataState),
-    Langton(Stage, LangtonState),
-    Synth(Stage, SynthState),
+    /// Any of the apps in the registry, see `app.rs`. The state of
+    /// the app itself is kept in a static.
+    App(Stage, AppId),
     Unimplemented(Stage),
     /// Does both the light ring and the flow field. This is a good
     /// way to see if the device is still "running" properly
@@ -218,122 +270,18 @@ pub enum FlowFieldState {
     Fast,
 }
 
-#[derive(Clone, Copy, Debug)]
-pub enum LightShowMode {
-    RandomBlink,
-    RingTrail,
-}
-
-#[derive(Clone, Copy, Debug)]
-pub enum LightShowDirection {
-    Clockwise,
-    Counterclockwise,
-}
-
-#[derive(Clone, Copy, Debug)]
-pub struct LightShowState {
-    pub mode: LightShowMode,
-    pub random_seed: u16,
-    pub random_interval_ms: u64,
-    pub random_light_count: u8,
-    pub ring_index: usize,
-    pub ring_step_ms: u64,
-    pub ring_direction: LightShowDirection,
-}
-
-impl Default for LightShowState {
-    fn default() -> Self {
-        Self {
-            mode: LightShowMode::RandomBlink,
-            random_seed: 0xace1,
-            random_interval_ms: 250,
-            random_light_count: 4,
-            ring_index: 0,
-            ring_step_ms: 10,
-            ring_direction: LightShowDirection::Clockwise,
-        }
-    }
-}
-
-#[derive(Clone, Copy, Debug)]
-pub struct AutomataState {
-    pub rule: u8,
-    pub palette_index: i32,
-    pub kernel_index: usize,
-    pub kernel_running: bool,
-    pub kernel_pass: usize,
-}
-
-impl Default for AutomataState {
-    fn default() -> Self {
-        Self {
-            rule: 1,
-            palette_index: 0,
-            kernel_index: 0,
-            kernel_running: false,
-            kernel_pass: 0,
-        }
-    }
-}
-
-#[derive(Clone, Copy, Debug)]
-pub enum LangtonDirection {
-    Up,
-    Right,
-    Down,
-    Left,
-}
-
-#[derive(Clone, Copy, Debug)]
-pub struct LangtonState {
-    pub x: usize,
-    pub y: usize,
-    pub direction: LangtonDirection,
-    pub rule_index: usize,
-    pub palette_index: i32,
-    pub cells: [u64; 75],
-    pub cells_high: [u64; 75],
-}
-
-#[derive(Clone, Copy, Debug)]
-pub struct SynthState {
-    pub frequency_hz: u16,
-    pub waveform: crate::hardware::speaker::Waveform,
-    pub playing: bool,
-}
-
-impl Default for SynthState {
-    fn default() -> Self {
-        Self {
-            frequency_hz: 440,
-            waveform: crate::hardware::speaker::Waveform::Sine,
-            playing: false,
-        }
-    }
-}
-
-impl Default for LangtonState {
-    fn default() -> Self {
-        Self {
-            x: 30,
-            y: 40,
-            direction: LangtonDirection::Up,
-            rule_index: 0,
-            palette_index: 0,
-            cells: [0; 75],
-            cells_high: [0; 75],
-        }
-    }
-}
-
+/// Lets tasks outside of the state machine change the state. Use
+/// `request_state_change` instead of signalling this directly.
 pub static STATE_CHANGE_REQUEST: Signal<
     CriticalSectionRawMutex,
     State,
 > = Signal::new();
 
-#[task]
-pub async fn left_button_event() {
-    loop {
-        let _next_state = STATE_CHANGE_REQUEST.wait().await;
-    }
+/// Asks the state machine to shut down the current state and go to
+/// `state`. Anything the current state is waiting on in the input
+/// listener is cancelled with a `KillSignal`, so this works even if
+/// the state is blocked on an input.
+pub fn request_state_change(state: State) {
+    STATE_CHANGE_REQUEST.signal(state);
+    InputListener::kill();
 }
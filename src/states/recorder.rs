@@ -0,0 +1,119 @@
+//! Records inputs and plays them back, see
+//! `input_listener::recorder`.
+//!
+//! The recording starts when this app is left, so that the first
+//! thing in it is the menu, and replays start from the menu for the
+//! same reason. Nothing done inside this app is part of a recording.
+
+use core::fmt::Write;
+
+use heapless::String;
+
+use super::app::{App, AppContext, MenuPlacement};
+use crate::{
+    hardware::{
+        led_shifter::{LED, LED_SHIFTER_CHANNEL, LedCommand},
+        mono_display::{
+            MONO_DISPLAY_CH, MONO_DISPLAY_LINE_WIDTH,
+            MonoDisplayCommand,
+        },
+    },
+    input_listener::{Input, InputListener, recorder},
+};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RecorderState {
+    /// Start recording once the app is left.
+    pub record_on_exit: bool,
+    /// Start replaying once the app is left.
+    pub replay_on_exit: bool,
+}
+
+impl App for RecorderState {
+    const NAME: &'static str = "Recorder";
+    const MENU_PLACEMENT: MenuPlacement = MenuPlacement::Root;
+
+    async fn setup(&mut self, _ctx: &mut AppContext<'_>) {
+        // the recording ends with the inputs that got here
+        recorder::stop_recording();
+        recorder::stop_replay();
+
+        LED_SHIFTER_CHANNEL.send(LedCommand::SetAllLow).await;
+        for led in [
+            LED::ButtonLeft,
+            LED::ButtonRight,
+            LED::DpadTop,
+            LED::DpadBottom,
+        ] {
+            LED_SHIFTER_CHANNEL.send(LedCommand::SetHigh(led)).await;
+        }
+
+        MONO_DISPLAY_CH
+            .send(MonoDisplayCommand::SwitchToTerminal)
+            .await;
+        MONO_DISPLAY_CH
+            .send(MonoDisplayCommand::SetDisplayOn(true))
+            .await;
+
+        let _ = InputListener::take_input(Input::ButtonRight, true);
+        let _ = InputListener::take_input(Input::DpadTop, true);
+        let _ = InputListener::take_input(Input::DpadBottom, true);
+
+        show_status(*self).await;
+    }
+
+    async fn execute(&mut self, ctx: &mut AppContext<'_>) {
+        let Ok(input) = InputListener::wait_for_any().await else {
+            return;
+        };
+
+        match input {
+            Input::ButtonLeft => ctx.exit_to_menu(),
+            Input::ButtonRight => {
+                self.record_on_exit = !self.record_on_exit;
+                show_status(*self).await;
+            }
+            Input::DpadTop => {
+                self.record_on_exit = false;
+                self.replay_on_exit = true;
+                ctx.exit_to_menu();
+            }
+            Input::DpadBottom => recorder::dump(),
+            _ => {}
+        }
+    }
+
+    async fn shutdown(&mut self, _ctx: &mut AppContext<'_>) {
+        LED_SHIFTER_CHANNEL.send(LedCommand::SetAllLow).await;
+
+        if self.record_on_exit {
+            recorder::start_recording();
+        } else if self.replay_on_exit {
+            recorder::start_replay();
+        }
+    }
+}
+
+async fn show_status(recorder_state: RecorderState) {
+    let mut status: String<MONO_DISPLAY_LINE_WIDTH> = String::new();
+    let _ = write!(status, "{} inputs", recorder::recorded_len());
+
+    MONO_DISPLAY_CH.send(MonoDisplayCommand::Clear).await;
+    for line in [
+        "Recorder",
+        status.as_str(),
+        if recorder_state.record_on_exit {
+            "R: rec on exit"
+        } else {
+            "R: arm rec"
+        },
+        "Up: replay",
+        "Down: dump",
+    ] {
+        let mut line = String::try_from(line).unwrap_or_default();
+        let _ = line.push('\n');
+        MONO_DISPLAY_CH
+            .send(MonoDisplayCommand::WriteStr(line))
+            .await;
+    }
+}
@@ -0,0 +1,267 @@
+//! Lists the ringtones bundled from `sounds/ringtones`, then the MIDI
+//! files from `sounds/midi`, and plays them on the speaker. See
+//! `hardware::rtttl` and `hardware::midi`.
+//!
+//! ```text
+//! up / down, primary dial  pick a tune
+//! confirm                  play it, or stop it if it is playing
+//! previous / next          pick the waveform it is played in
+//! back                     back to the menu
+//! ```
+
+use core::fmt::Write;
+
+use embassy_time::{Duration, Timer};
+use heapless::String;
+
+use super::app::{App, AppContext, MenuPlacement};
+use crate::{
+    hardware::{
+        led_shifter::{LED, LED_SHIFTER_CHANNEL, LedCommand},
+        midi::{
+            MIDI_FILES, MidiFile, is_midi_playing, play_midi,
+            stop_midi,
+        },
+        mono_display::{
+            MONO_DISPLAY_CH, MONO_DISPLAY_LINE_WIDTH,
+            MonoDisplayCommand,
+        },
+        rtttl::{
+            RINGTONES, Rtttl, is_ringtone_playing, play_ringtone,
+            stop_ringtone,
+        },
+        speaker::Waveform,
+    },
+    input_listener::{
+        InputListener,
+        acceleration::RotationMode,
+        bindings::{Action, Dial, active_bindings},
+    },
+};
+
+/// How many rows fit under the title.
+const VISIBLE_ROWS: usize = 6;
+const TICK_TIME: Duration = Duration::from_millis(20);
+
+#[derive(Clone, Copy, Debug)]
+pub struct RingtonesState {
+    /// Index into the ringtones followed by the MIDI files.
+    pub selected: usize,
+    pub waveform: Waveform,
+    /// The tune that was started last, until it finishes.
+    pub playing: Option<usize>,
+}
+
+impl Default for RingtonesState {
+    fn default() -> Self {
+        Self {
+            selected: 0,
+            waveform: Waveform::Square,
+            playing: None,
+        }
+    }
+}
+
+impl App for RingtonesState {
+    const NAME: &'static str = "Ringtones";
+    const MENU_PLACEMENT: MenuPlacement = MenuPlacement::Root;
+
+    async fn setup(&mut self, _ctx: &mut AppContext<'_>) {
+        self.playing = None;
+        let bindings = active_bindings();
+
+        LED_SHIFTER_CHANNEL.send(LedCommand::SetAllLow).await;
+        for led in [
+            LED::of_control(bindings.control(Action::Back)),
+            LED::of_control(bindings.control(Action::Confirm)),
+            LED::of_control(bindings.control(Action::Up)),
+            LED::of_control(bindings.control(Action::Down)),
+            LED::of_control(bindings.control(Action::Previous)),
+            LED::of_control(bindings.control(Action::Next)),
+            LED::of_encoder(bindings.encoder(Dial::Primary)),
+        ] {
+            LED_SHIFTER_CHANNEL.send(LedCommand::SetHigh(led)).await;
+        }
+
+        MONO_DISPLAY_CH
+            .send(MonoDisplayCommand::SwitchToTerminal)
+            .await;
+        MONO_DISPLAY_CH
+            .send(MonoDisplayCommand::SetDisplayOn(true))
+            .await;
+
+        for action in Action::ALL {
+            let _ = take_action_count(action);
+        }
+        let _ = InputListener::take_dial(
+            Dial::Primary,
+            RotationMode::Raw,
+        );
+
+        show_ringtones(*self).await;
+    }
+
+    async fn execute(&mut self, ctx: &mut AppContext<'_>) {
+        if take_action_count(Action::Back) != 0 {
+            ctx.exit_to_menu();
+            return;
+        }
+
+        let previous = *self;
+        let count = tune_count().max(1) as i32;
+
+        let delta = take_action_count(Action::Down) as i32
+            - take_action_count(Action::Up) as i32
+            + InputListener::take_dial(
+                Dial::Primary,
+                RotationMode::Raw,
+            )
+            .unwrap_or_default();
+        self.selected =
+            (self.selected as i32 + delta).rem_euclid(count) as usize;
+
+        let waveform_delta = take_action_count(Action::Next) as i32
+            - take_action_count(Action::Previous) as i32;
+        self.waveform = Waveform::from_index(
+            (self.waveform.index() as i32 + waveform_delta)
+                .rem_euclid(Waveform::COUNT as i32)
+                as usize,
+        );
+
+        if self.playing.is_some()
+            && !is_ringtone_playing()
+            && !is_midi_playing()
+        {
+            self.playing = None;
+        }
+
+        if take_action_count(Action::Confirm) != 0 {
+            stop_ringtone();
+            stop_midi();
+            if self.playing == Some(self.selected) {
+                self.playing = None;
+            } else if let Some(tune) = tune(self.selected) {
+                tune.play(self.waveform);
+                self.playing = Some(self.selected);
+            }
+        }
+
+        if self.selected != previous.selected
+            || self.waveform.index() != previous.waveform.index()
+            || self.playing != previous.playing
+        {
+            show_ringtones(*self).await;
+        }
+
+        Timer::after(TICK_TIME).await;
+    }
+
+    async fn shutdown(&mut self, _ctx: &mut AppContext<'_>) {
+        stop_ringtone();
+        stop_midi();
+        LED_SHIFTER_CHANNEL.send(LedCommand::SetAllLow).await;
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Tune {
+    Ringtone(&'static str),
+    Midi(&'static MidiFile),
+}
+
+impl Tune {
+    fn name(self) -> &'static str {
+        match self {
+            Tune::Ringtone(ringtone) => Rtttl::parse(ringtone)
+                .map(|rtttl| rtttl.name())
+                .unwrap_or("?"),
+            Tune::Midi(file) => file.name,
+        }
+    }
+
+    fn play(self, waveform: Waveform) {
+        match self {
+            Tune::Ringtone(ringtone) => {
+                play_ringtone(ringtone, waveform)
+            }
+            Tune::Midi(file) => play_midi(file, waveform),
+        }
+    }
+}
+
+fn tune_count() -> usize {
+    RINGTONES.len() + MIDI_FILES.len()
+}
+
+fn tune(index: usize) -> Option<Tune> {
+    match RINGTONES.get(index) {
+        Some(ringtone) => Some(Tune::Ringtone(ringtone)),
+        None => {
+            MIDI_FILES.get(index - RINGTONES.len()).map(Tune::Midi)
+        }
+    }
+}
+
+fn take_action_count(action: Action) -> u16 {
+    InputListener::take_action(action, true)
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+}
+
+async fn show_ringtones(ringtones_state: RingtonesState) {
+    let mut title: String<MONO_DISPLAY_LINE_WIDTH> = String::new();
+    let _ = write!(title, "<{}>", ringtones_state.waveform.name());
+
+    MONO_DISPLAY_CH.send(MonoDisplayCommand::Clear).await;
+    write_line(title.as_str()).await;
+
+    if tune_count() == 0 {
+        write_line("No tunes").await;
+        return;
+    }
+
+    let first_row = ringtones_state
+        .selected
+        .saturating_sub(VISIBLE_ROWS - 1)
+        .min(tune_count().saturating_sub(VISIBLE_ROWS));
+
+    for (index, tune) in (first_row..tune_count())
+        .filter_map(|index| Some((index, tune(index)?)))
+        .take(VISIBLE_ROWS)
+    {
+        let cursor = if index == ringtones_state.selected {
+            '>'
+        } else {
+            ' '
+        };
+        let playing = if ringtones_state.playing == Some(index) {
+            '*'
+        } else {
+            ' '
+        };
+        let name = tune.name();
+
+        // cut down to the width of the display by `write_line`
+        let mut line: String<32> = String::new();
+        let _ = write!(line, "{cursor}{playing}{name}");
+        write_line(line.as_str()).await;
+    }
+}
+
+async fn write_line(text: &str) {
+    let mut line: String<MONO_DISPLAY_LINE_WIDTH> = String::new();
+    for character in text.chars() {
+        if line.len() + character.len_utf8()
+            > MONO_DISPLAY_LINE_WIDTH - 1
+        {
+            break;
+        }
+        let _ = line.push(character);
+    }
+    let _ = line.push('\n');
+
+    MONO_DISPLAY_CH
+        .send(MonoDisplayCommand::WriteStr(line))
+        .await;
+}
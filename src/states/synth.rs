@@ -1,10 +1,38 @@
+//! Plays a waveform for as long as confirm is held, through a
+//! resonant filter. See `hardware::speaker::filter`.
+//!
+//! ```text
+//! confirm                        play
+//! primary dial                   pitch
+//! previous / next                waveform
+//! secondary dial                 filter cutoff
+//! up / down                      filter resonance
+//! double press secondary dial    filter off, low pass or high pass
+//! double press primary dial      keyboard mode
+//! back                           back to the menu
+//! ```
+//!
+//! In keyboard mode, the D-pad and the big buttons are keys, which
+//! play the notes of a scale in the waveform and filter that were
+//! picked. Several keys can be held at once, up to as many as there
+//! are user voices, see `hardware::speaker::polyphony`.
+//!
+//! ```text
+//! left button, D-pad left, down, up, right, right button
+//!                                the notes, from low to high
+//! primary dial                   octave
+//! secondary dial                 scale
+//! double press secondary dial    filter off, low pass or high pass
+//! double press primary dial      back out of keyboard mode
+//! ```
+
 use core::fmt::Write;
 
 use embassy_time::{Duration, Timer};
 use heapless::String;
 use rotary_encoder_embedded::Direction;
 
-use super::{MenuState, Meowbox, Stage, State, SynthState};
+use super::app::{App, AppContext, MenuPlacement};
 use crate::{
     hardware::{
         large_display::{
@@ -16,12 +44,27 @@ use crate::{
             MONO_DISPLAY_CH, MONO_DISPLAY_LINE_WIDTH,
             MonoDisplayCommand,
         },
+        sequencer::note_frequency,
         speaker::{
-            SPEAKER_CHANNEL, SpeakerCommand, Waveform,
+            Waveform,
+            envelope::Envelope,
+            filter::{
+                Filter, FilterMode, MAX_CUTOFF_HZ, MIN_CUTOFF_HZ,
+            },
+            mixer::{Sound, Voice},
+            polyphony::VoiceAllocator,
+            user::UserSound,
             waveform_sample,
         },
     },
-    input_listener::{Input, InputListener},
+    input_listener::{
+        Input, InputListener,
+        acceleration::RotationMode,
+        auto_repeat::set_auto_repeat,
+        bindings::{Action, Dial, active_bindings},
+        events::{self, Control},
+        gestures::Gesture,
+    },
 };
 
 const BLACK: u16 = 0x0000;
@@ -31,6 +74,9 @@ const WAVEFORM_COLORS: [u16; Waveform::COUNT] =
 const FREQUENCY_MIN_HZ: u16 = 20;
 const FREQUENCY_MAX_HZ: u16 = 5000;
 const FREQUENCY_SCROLL_FACTOR: f32 = 1.029_302_2;
+/// A semitone a detent.
+const CUTOFF_SCROLL_FACTOR: f32 = 1.059_463_1;
+const RESONANCE_STEP: f32 = 0.1;
 const TONE_CHUNK: Duration = Duration::from_millis(30);
 const TICK_TIME: Duration = Duration::from_millis(10);
 const INPUT_LED_TIME: Duration = Duration::from_millis(100);
@@ -42,40 +88,163 @@ const WAVEFORM_DOT_SIZE: u16 = 4;
 const WAVEFORM_Y_STEP: u16 = 2;
 const DISPLAY_MIN_WINDOW_SECONDS: f32 = 0.04 / 3.0;
 const DISPLAY_MIN_CYCLES: f32 = 2.0 / 3.0;
+/// The controls that are keys in keyboard mode, from the lowest note
+/// to the highest.
+const KEYS: [Control; 6] = [
+    Control::ButtonLeft,
+    Control::DpadLeft,
+    Control::DpadBottom,
+    Control::DpadTop,
+    Control::DpadRight,
+    Control::ButtonRight,
+];
+const POLYPHONY: usize = Voice::USER.len();
+/// Low enough that every voice playing at once does not clip.
+const KEYBOARD_GAIN: f32 = 0.5;
+const MIN_OCTAVE: u8 = 1;
+const MAX_OCTAVE: u8 = 6;
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+/// The keys are drawn as bars across the large display, the lowest
+/// at the top.
+const KEY_HEIGHT: u16 = DISPLAY_HEIGHT / KEYS.len() as u16;
+const KEY_GAP: u16 = 6;
+
+pub struct Scale {
+    pub name: &'static str,
+    /// How many semitones above the root every note of an octave is.
+    pub steps: &'static [u8],
+}
 
-impl Meowbox {
-    pub(super) async fn tick_synth(&mut self) {
-        let State::Synth(stage, synth_state) = self.state
-        else {
-            return;
-        };
+pub static SCALES: [Scale; 5] = [
+    Scale {
+        name: "Major",
+        steps: &[0, 2, 4, 5, 7, 9, 11],
+    },
+    Scale {
+        name: "Minor",
+        steps: &[0, 2, 3, 5, 7, 8, 10],
+    },
+    Scale {
+        name: "Major penta",
+        steps: &[0, 2, 4, 7, 9],
+    },
+    Scale {
+        name: "Minor penta",
+        steps: &[0, 3, 5, 7, 10],
+    },
+    Scale {
+        name: "Blues",
+        steps: &[0, 3, 5, 6, 7, 10],
+    },
+];
+
+#[derive(Clone, Copy, Debug)]
+pub struct SynthState {
+    pub frequency_hz: u16,
+    pub waveform: Waveform,
+    pub playing: bool,
+    /// What the tone goes through after the oscillator.
+    pub filter: Filter,
+    /// The tone, which is replaced every tick while it plays.
+    pub tone: UserSound,
+    /// Whether the D-pad and the big buttons are playing notes.
+    pub keyboard_mode: bool,
+    pub keyboard: Keyboard,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Keyboard {
+    /// The octave of the lowest key, 4 being the one from middle C.
+    pub octave: u8,
+    /// Index into `SCALES`.
+    pub scale: usize,
+    /// Which voice every key that is sounding has. Keys are indexes
+    /// into `KEYS`.
+    voices: VoiceAllocator<POLYPHONY>,
+    /// The note every voice plays, replaced every tick while its key
+    /// is held.
+    notes: [UserSound; POLYPHONY],
+    /// The keys that were held last tick, a bit for each of `KEYS`.
+    held: u8,
+    /// The keys that are lit up on the displays, a bit for each of
+    /// `KEYS`.
+    shown: u8,
+}
+
+impl Keyboard {
+    /// The MIDI note `key` plays.
+    fn pitch(&self, key: usize) -> u8 {
+        let steps = SCALES[self.scale].steps;
+        let octave = self.octave as usize + 1 + key / steps.len();
+
+        (octave * 12) as u8 + steps[key % steps.len()]
+    }
+
+    /// The keys that have a voice, a bit for each of `KEYS`.
+    fn sounding(&self) -> u8 {
+        (0..POLYPHONY)
+            .filter_map(|voice| self.voices.key_of(voice))
+            .fold(0, |keys, key| keys | 1 << key)
+    }
 
-        match stage {
-            Stage::Setup => self.setup_synth(synth_state).await,
-            Stage::Execution => self.execute_synth(synth_state).await,
-            Stage::Shutdown => self.shutdown_synth().await,
+    /// Lets go of every key.
+    fn release_all(&mut self) {
+        for key in 0..KEYS.len() {
+            if let Some(voice) = self.voices.release(key as u8) {
+                self.notes[voice].try_stop();
+            }
         }
+        self.held = 0;
+        self.shown = 0;
     }
+}
 
-    async fn setup_synth(&mut self, mut synth_state: SynthState) {
-        synth_state.playing = false;
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self {
+            octave: 4,
+            scale: 0,
+            voices: VoiceAllocator::new(),
+            notes: core::array::from_fn(|_| UserSound::new()),
+            held: 0,
+            shown: 0,
+        }
+    }
+}
 
-        LED_SHIFTER_CHANNEL.send(LedCommand::SetAllLow).await;
-        LED_SHIFTER_CHANNEL
-            .send(LedCommand::SetHigh(LED::ButtonLeft))
-            .await;
-        LED_SHIFTER_CHANNEL
-            .send(LedCommand::SetHigh(LED::ButtonRight))
-            .await;
-        LED_SHIFTER_CHANNEL
-            .send(LedCommand::SetHigh(LED::AmberLeft))
-            .await;
-        LED_SHIFTER_CHANNEL
-            .send(LedCommand::SetHigh(LED::DpadLeft))
-            .await;
-        LED_SHIFTER_CHANNEL
-            .send(LedCommand::SetHigh(LED::DpadRight))
-            .await;
+impl Default for SynthState {
+    fn default() -> Self {
+        Self {
+            frequency_hz: 440,
+            waveform: Waveform::Sine,
+            playing: false,
+            filter: Filter {
+                mode: FilterMode::Off,
+                cutoff_hz: 2000.0,
+                resonance: 0.0,
+            },
+            tone: UserSound::new(),
+            keyboard_mode: false,
+            keyboard: Keyboard::default(),
+        }
+    }
+}
+
+impl App for SynthState {
+    const NAME: &'static str = "Synth";
+    const MENU_PLACEMENT: MenuPlacement = MenuPlacement::Root;
+
+    async fn setup(&mut self, _ctx: &mut AppContext<'_>) {
+        self.playing = false;
+        self.keyboard_mode = false;
+        self.keyboard.release_all();
+        let synth_state = *self;
+
+        let bindings = active_bindings();
+
+        light_synth_leds().await;
 
         MONO_DISPLAY_CH
             .send(MonoDisplayCommand::SwitchToTerminal)
@@ -85,6 +254,11 @@ impl Meowbox {
             .send(MonoDisplayCommand::SetDisplayOn(true))
             .await;
 
+        // one waveform per press, holding the D-pad should not cycle
+        // through all of them
+        set_auto_repeat(bindings.control(Action::Previous), false);
+        set_auto_repeat(bindings.control(Action::Next), false);
+
         drain_synth_inputs();
         write_synth_label(synth_state).await;
 
@@ -94,32 +268,79 @@ impl Meowbox {
         LARGE_DISPLAY_CH.send(LargeDisplayCommand::DisplayOn).await;
         BACKLIGHT_CH.send(BacklightCommand::SetHigh).await;
         draw_waveform(synth_state).await;
-
-        self.state = State::Synth(Stage::Execution, synth_state);
     }
 
-    async fn execute_synth(&mut self, mut synth_state: SynthState) {
-        if take_input_count(Input::ButtonLeft) != 0 {
-            self.next_state =
-                Some(State::Menu(Stage::Setup, MenuState::default()));
-            self.needs_to_shutdown = true;
+    async fn execute(&mut self, ctx: &mut AppContext<'_>) {
+        let mut keyboard_toggles = 0;
+        let mut mode_delta = 0;
+
+        // a single press of a dial is the start of a volume gesture,
+        // so they are double pressed instead
+        while let Some(event) = ctx.resources.gestures.try_next() {
+            let bindings = active_bindings();
+            if event.gesture
+                == Gesture::DoublePress(
+                    bindings.control(Action::PrimaryPress),
+                )
+            {
+                keyboard_toggles += 1;
+            } else if event.gesture
+                == Gesture::DoublePress(
+                    bindings.control(Action::SecondaryPress),
+                )
+            {
+                mode_delta += 1;
+            }
+        }
+
+        let mut synth_state = *self;
+
+        if keyboard_toggles % 2 == 1 {
+            if synth_state.keyboard_mode {
+                leave_keyboard(&mut synth_state).await;
+            } else {
+                enter_keyboard(&mut synth_state).await;
+            }
+        }
+
+        if synth_state.keyboard_mode {
+            play_keyboard(&mut synth_state, mode_delta).await;
+            *self = synth_state;
+            Timer::after(TICK_TIME).await;
             return;
         }
 
-        if take_input_count(Input::ButtonRight) != 0 {
+        if take_action_count(Action::Back) != 0 {
+            *self = synth_state;
+            ctx.exit_to_menu();
+            return;
+        }
+
+        if take_action_count(Action::Confirm) != 0 {
             synth_state.playing = true;
             LED_SHIFTER_CHANNEL
-                .send(LedCommand::SetHigh(LED::ButtonRight))
+                .send(LedCommand::SetHigh(LED::of_control(
+                    active_bindings().control(Action::Confirm),
+                )))
                 .await;
         }
 
-        if take_input_count(Input::ButtonRightReleased) != 0 {
+        // plays for as long as the confirm control is held, whichever
+        // one it is
+        let _ = take_input_count(Input::ButtonRightReleased);
+        if synth_state.playing
+            && !events::is_held(
+                active_bindings().control(Action::Confirm),
+            )
+        {
             synth_state.playing = false;
-            SPEAKER_CHANNEL.send(SpeakerCommand::Silence).await;
+            synth_state.tone.stop().await;
         }
 
         let frequency_delta = take_frequency_delta();
         let waveform_delta = take_waveform_delta();
+        let cutoff_delta = take_cutoff_delta();
+        let resonance_delta = take_resonance_delta();
 
         if frequency_delta != 0 {
             synth_state.frequency_hz = adjust_frequency(
@@ -139,51 +360,192 @@ impl Meowbox {
             flash_waveform_led(waveform_delta).await;
         }
 
+        if cutoff_delta != 0
+            || resonance_delta != 0
+            || mode_delta != 0
+        {
+            synth_state.filter = adjust_filter(
+                synth_state.filter,
+                cutoff_delta,
+                resonance_delta,
+                mode_delta,
+            );
+            write_synth_label(synth_state).await;
+        }
+
         if synth_state.playing {
-            let _ = SPEAKER_CHANNEL.try_send(
-                SpeakerCommand::PlayWaveform {
+            synth_state.tone.try_play(
+                Sound::Waveform {
                     waveform: synth_state.waveform,
-                    frequency_hz: synth_state.frequency_hz,
+                    frequency_hz: synth_state.frequency_hz as f32,
                     duration: TONE_CHUNK,
+                    envelope: Envelope::PLUCK,
+                    filter: synth_state.filter,
                 },
+                1.0,
             );
         }
 
-        self.state = State::Synth(Stage::Execution, synth_state);
+        *self = synth_state;
         Timer::after(TICK_TIME).await;
     }
 
-    async fn shutdown_synth(&mut self) {
-        let _ = SPEAKER_CHANNEL.try_send(SpeakerCommand::Silence);
+    async fn shutdown(&mut self, _ctx: &mut AppContext<'_>) {
+        self.tone.try_stop();
+        self.keyboard.release_all();
         LARGE_DISPLAY_CH
             .send(LargeDisplayCommand::StopAnimation)
             .await;
         LED_SHIFTER_CHANNEL.send(LedCommand::SetAllLow).await;
         BACKLIGHT_CH.send(BacklightCommand::SetLow).await;
+    }
+}
 
-        self.state = self.next_state.take().unwrap_or(State::Menu(
-            Stage::Setup,
-            MenuState::default(),
-        ));
+async fn enter_keyboard(synth_state: &mut SynthState) {
+    synth_state.keyboard_mode = true;
+    if synth_state.playing {
+        synth_state.playing = false;
+        synth_state.tone.stop().await;
+    }
+    drain_actions();
+
+    let bindings = active_bindings();
+    LED_SHIFTER_CHANNEL.send(LedCommand::SetAllLow).await;
+    for dial in Dial::ALL {
+        LED_SHIFTER_CHANNEL
+            .send(LedCommand::SetHigh(LED::of_encoder(
+                bindings.encoder(dial),
+            )))
+            .await;
     }
+
+    write_keyboard_label(synth_state.keyboard).await;
+    draw_keyboard(*synth_state).await;
 }
 
-fn take_frequency_delta() -> i16 {
-    let cw = take_input_count(Input::RotaryEncoderRotateLeft(
-        Direction::Clockwise,
-    ));
-    let ccw = take_input_count(Input::RotaryEncoderRotateLeft(
-        Direction::Anticlockwise,
-    ));
+async fn leave_keyboard(synth_state: &mut SynthState) {
+    synth_state.keyboard_mode = false;
+    synth_state.keyboard.release_all();
+    drain_actions();
+
+    light_synth_leds().await;
+    write_synth_label(*synth_state).await;
+    draw_waveform(*synth_state).await;
+}
+
+/// One tick of keyboard mode: starts the notes of the keys that were
+/// pressed, lets go of the ones that were released, and keeps the
+/// rest going.
+async fn play_keyboard(
+    synth_state: &mut SynthState,
+    mode_delta: i16,
+) {
+    // the keys are read straight from the controls, so the presses
+    // are thrown away rather than left for when keyboard mode ends
+    drain_actions();
+
+    let octave_delta =
+        InputListener::take_dial(Dial::Primary, RotationMode::Raw)
+            .unwrap_or_default();
+    let scale_delta =
+        InputListener::take_dial(Dial::Secondary, RotationMode::Raw)
+            .unwrap_or_default();
+
+    if mode_delta != 0 {
+        synth_state.filter =
+            adjust_filter(synth_state.filter, 0, 0, mode_delta);
+    }
+
+    let keyboard = &mut synth_state.keyboard;
+    if octave_delta != 0 || scale_delta != 0 {
+        keyboard.octave = (keyboard.octave as i32 + octave_delta)
+            .clamp(MIN_OCTAVE as i32, MAX_OCTAVE as i32)
+            as u8;
+        keyboard.scale = (keyboard.scale as i32 + scale_delta)
+            .rem_euclid(SCALES.len() as i32)
+            as usize;
+    }
+
+    let held = KEYS
+        .iter()
+        .enumerate()
+        .filter(|(_, control)| events::is_held(**control))
+        .fold(0u8, |keys, (key, _)| keys | 1 << key);
+
+    for key in 0..KEYS.len() {
+        let bit = 1 << key;
+        if keyboard.held & bit != 0
+            && held & bit == 0
+            && let Some(voice) = keyboard.voices.release(key as u8)
+        {
+            keyboard.notes[voice].try_stop();
+        }
+    }
+    for key in 0..KEYS.len() {
+        let bit = 1 << key;
+        if keyboard.held & bit == 0 && held & bit != 0 {
+            keyboard.voices.press(key as u8);
+        }
+    }
+    keyboard.held = held;
 
-    cw as i16 - ccw as i16
+    for voice in 0..POLYPHONY {
+        let Some(key) = keyboard.voices.key_of(voice) else {
+            continue;
+        };
+
+        keyboard.notes[voice].try_play(
+            Sound::Waveform {
+                waveform: synth_state.waveform,
+                frequency_hz: note_frequency(
+                    keyboard.pitch(key as usize),
+                ),
+                duration: TONE_CHUNK,
+                envelope: Envelope::PLUCK,
+                filter: synth_state.filter,
+            },
+            KEYBOARD_GAIN,
+        );
+    }
+
+    let sounding = keyboard.sounding();
+    if octave_delta != 0
+        || scale_delta != 0
+        || mode_delta != 0
+        || sounding != keyboard.shown
+    {
+        show_keys(keyboard.shown, sounding, synth_state.waveform)
+            .await;
+        keyboard.shown = sounding;
+        write_keyboard_label(*keyboard).await;
+    }
+}
+
+fn take_frequency_delta() -> i16 {
+    InputListener::take_dial(Dial::Primary, RotationMode::Accelerated)
+        .unwrap_or_default() as i16
 }
 
 fn take_waveform_delta() -> i16 {
-    let right = take_input_count(Input::DpadRight);
-    let left = take_input_count(Input::DpadLeft);
+    let next = take_action_count(Action::Next);
+    let previous = take_action_count(Action::Previous);
 
-    right as i16 - left as i16
+    next as i16 - previous as i16
+}
+
+fn take_cutoff_delta() -> i16 {
+    InputListener::take_dial(
+        Dial::Secondary,
+        RotationMode::Accelerated,
+    )
+    .unwrap_or_default() as i16
+}
+
+fn take_resonance_delta() -> i16 {
+    let up = take_action_count(Action::Up);
+    let down = take_action_count(Action::Down);
+
+    up as i16 - down as i16
 }
 
 fn adjust_frequency(frequency_hz: u16, delta: i16) -> u16 {
@@ -202,6 +564,39 @@ fn adjust_frequency(frequency_hz: u16, delta: i16) -> u16 {
         as u16
 }
 
+fn adjust_filter(
+    filter: Filter,
+    cutoff_delta: i16,
+    resonance_delta: i16,
+    mode_delta: i16,
+) -> Filter {
+    let cutoff_hz = (0..cutoff_delta.unsigned_abs()).fold(
+        filter.cutoff_hz,
+        |cutoff_hz, _| {
+            if cutoff_delta > 0 {
+                cutoff_hz * CUTOFF_SCROLL_FACTOR
+            } else {
+                cutoff_hz / CUTOFF_SCROLL_FACTOR
+            }
+        },
+    );
+    let resonance = (filter.resonance
+        + resonance_delta as f32 * RESONANCE_STEP)
+        .clamp(0.0, 1.0);
+    let mode = FilterMode::from_index(
+        (filter.mode.index() as i16 + mode_delta)
+            .rem_euclid(FilterMode::COUNT as i16) as usize,
+    );
+
+    Filter {
+        mode,
+        cutoff_hz: cutoff_hz.clamp(MIN_CUTOFF_HZ, MAX_CUTOFF_HZ),
+        // rounded so that stepping up and down lands back on 0.0
+        resonance: ((resonance / RESONANCE_STEP + 0.5) as i32 as f32)
+            * RESONANCE_STEP,
+    }
+}
+
 fn shift_waveform(waveform: Waveform, delta: i16) -> Waveform {
     let next = (waveform.index() as i16 + delta)
         .rem_euclid(Waveform::COUNT as i16) as usize;
@@ -231,6 +626,20 @@ fn drain_synth_inputs() {
     let _ = take_input_count(Input::DpadBottom);
 }
 
+fn drain_actions() {
+    for action in Action::ALL {
+        let _ = take_action_count(action);
+    }
+    let _ = take_input_count(Input::ButtonRightReleased);
+}
+
+fn take_action_count(action: Action) -> u16 {
+    InputListener::take_action(action, true)
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+}
+
 fn take_input_count(input: Input) -> u16 {
     InputListener::take_input(input, true)
         .ok()
@@ -242,10 +651,27 @@ async fn write_synth_label(synth_state: SynthState) {
     let mut frequency: String<MONO_DISPLAY_LINE_WIDTH> =
         String::new();
     let mut waveform: String<MONO_DISPLAY_LINE_WIDTH> = String::new();
+    let mut mode: String<MONO_DISPLAY_LINE_WIDTH> = String::new();
+    let mut cutoff: String<MONO_DISPLAY_LINE_WIDTH> = String::new();
+    let mut resonance: String<MONO_DISPLAY_LINE_WIDTH> =
+        String::new();
+    let filter = synth_state.filter;
 
     write!(frequency, "{} Hz", synth_state.frequency_hz).unwrap();
-    write!(waveform, "\n{}", synth_state.waveform.name())
+    write!(waveform, "\n{}", synth_state.waveform.name()).unwrap();
+    match filter.mode {
+        FilterMode::Off => write!(mode, "\nFilter off"),
+        filter_mode => write!(mode, "\n{}", filter_mode.name()),
+    }
+    .unwrap();
+    write!(cutoff, "\nCutoff {} Hz", (filter.cutoff_hz + 0.5) as u16)
         .unwrap();
+    write!(
+        resonance,
+        "\nResonance {}%",
+        (filter.resonance * 100.0 + 0.5) as u8
+    )
+    .unwrap();
 
     MONO_DISPLAY_CH.send(MonoDisplayCommand::Clear).await;
     MONO_DISPLAY_CH
@@ -254,12 +680,95 @@ async fn write_synth_label(synth_state: SynthState) {
     MONO_DISPLAY_CH
         .send(MonoDisplayCommand::WriteStr(waveform))
         .await;
+    for line in [mode, cutoff, resonance] {
+        MONO_DISPLAY_CH
+            .send(MonoDisplayCommand::WriteStr(line))
+            .await;
+    }
+}
+
+async fn write_keyboard_label(keyboard: Keyboard) {
+    let mut octave: String<MONO_DISPLAY_LINE_WIDTH> = String::new();
+    let mut scale: String<MONO_DISPLAY_LINE_WIDTH> = String::new();
+    let mut notes: String<MONO_DISPLAY_LINE_WIDTH> = String::new();
+
+    write!(octave, "Keys, octave {}", keyboard.octave).unwrap();
+    write!(scale, "\n{}", SCALES[keyboard.scale].name).unwrap();
+    let _ = notes.push('\n');
+    for key in 0..KEYS.len() {
+        if keyboard.shown & 1 << key == 0 {
+            continue;
+        }
+
+        let pitch = keyboard.pitch(key);
+        // as many notes as fit, there are more keys than voices
+        let _ = write!(
+            notes,
+            "{}{} ",
+            NOTE_NAMES[(pitch % 12) as usize],
+            pitch / 12 - 1
+        );
+    }
+
+    MONO_DISPLAY_CH.send(MonoDisplayCommand::Clear).await;
+    for line in [octave, scale, notes] {
+        MONO_DISPLAY_CH
+            .send(MonoDisplayCommand::WriteStr(line))
+            .await;
+    }
+}
+
+async fn draw_keyboard(synth_state: SynthState) {
+    LARGE_DISPLAY_CH
+        .send(LargeDisplayCommand::Clear(BLACK))
+        .await;
+    show_keys(
+        !synth_state.keyboard.shown,
+        synth_state.keyboard.shown,
+        synth_state.waveform,
+    )
+    .await;
+}
+
+/// Redraws the keys that are not lit the way they were, in the
+/// colour of `waveform` if they are sounding. The LEDs of the keys
+/// follow them.
+async fn show_keys(shown: u8, sounding: u8, waveform: Waveform) {
+    for (key, control) in KEYS.iter().enumerate() {
+        let bit = 1 << key;
+        if (shown ^ sounding) & bit == 0 {
+            continue;
+        }
+
+        let lit = sounding & bit != 0;
+        LARGE_DISPLAY_CH
+            .send(LargeDisplayCommand::FillRect {
+                x: KEY_GAP,
+                y: key as u16 * KEY_HEIGHT + KEY_GAP / 2,
+                width: DISPLAY_WIDTH - KEY_GAP * 2,
+                height: KEY_HEIGHT - KEY_GAP,
+                color: if lit {
+                    WAVEFORM_COLORS[waveform.index()]
+                } else {
+                    DIM_GRAY
+                },
+            })
+            .await;
+        LED_SHIFTER_CHANNEL
+            .send(if lit {
+                LedCommand::SetHigh(LED::of_control(*control))
+            } else {
+                LedCommand::SetLow(LED::of_control(*control))
+            })
+            .await;
+    }
 }
 
 async fn draw_waveform(synth_state: SynthState) {
     let waveform = synth_state.waveform;
     let color = WAVEFORM_COLORS[waveform.index()];
-    let window_seconds = display_window_seconds(synth_state.frequency_hz);
+    let window_seconds =
+        display_window_seconds(synth_state.frequency_hz);
     let mut previous: Option<(u16, u16)> = None;
 
     LARGE_DISPLAY_CH
@@ -343,11 +852,30 @@ async fn draw_waveform_dot(x: u16, y: u16, color: u16) {
         .await;
 }
 
+async fn light_synth_leds() {
+    let bindings = active_bindings();
+
+    LED_SHIFTER_CHANNEL.send(LedCommand::SetAllLow).await;
+    for led in [
+        LED::of_control(bindings.control(Action::Back)),
+        LED::of_control(bindings.control(Action::Confirm)),
+        LED::of_encoder(bindings.encoder(Dial::Primary)),
+        LED::of_control(bindings.control(Action::Previous)),
+        LED::of_control(bindings.control(Action::Next)),
+        LED::of_encoder(bindings.encoder(Dial::Secondary)),
+        LED::of_control(bindings.control(Action::Up)),
+        LED::of_control(bindings.control(Action::Down)),
+    ] {
+        LED_SHIFTER_CHANNEL.send(LedCommand::SetHigh(led)).await;
+    }
+}
+
 async fn flash_frequency_led(delta: i16) {
+    let bindings = active_bindings();
     let led = if delta > 0 {
-        LED::AmberLeft
+        LED::of_encoder(bindings.encoder(Dial::Primary))
     } else {
-        LED::ButtonLeft
+        LED::of_control(bindings.control(Action::Back))
     };
 
     LED_SHIFTER_CHANNEL
@@ -356,17 +884,20 @@ async fn flash_frequency_led(delta: i16) {
 }
 
 async fn flash_waveform_led(delta: i16) {
+    let bindings = active_bindings();
+    let next = LED::of_control(bindings.control(Action::Next));
+    let previous =
+        LED::of_control(bindings.control(Action::Previous));
+
     LED_SHIFTER_CHANNEL
         .send(LedCommand::SetHigh(if delta > 0 {
-            LED::DpadRight
+            next
         } else {
-            LED::DpadLeft
+            previous
         }))
         .await;
     LED_SHIFTER_CHANNEL
-        .send(LedCommand::SetHigh(LED::DpadLeft))
-        .await;
-    LED_SHIFTER_CHANNEL
-        .send(LedCommand::SetHigh(LED::DpadRight))
+        .send(LedCommand::SetHigh(previous))
         .await;
+    LED_SHIFTER_CHANNEL.send(LedCommand::SetHigh(next)).await;
 }
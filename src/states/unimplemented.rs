@@ -65,11 +65,9 @@ impl Meowbox {
     }
 
     async fn execute_unimplemented(&mut self) {
-        if InputListener::take_input(Input::ButtonLeft, true)
-            .ok()
-            .flatten()
-            .is_some()
-        {
+        // Nothing else happens here, so there is no need to poll.
+        // If the wait is killed, the state is already being changed.
+        if InputListener::wait_for(Input::ButtonLeft).await.is_ok() {
             self.next_state =
                 Some(State::Menu(Stage::Setup, MenuState::default()));
             self.needs_to_shutdown = true;
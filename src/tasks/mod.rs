@@ -7,13 +7,17 @@ use embassy_sync::{
 };
 use embassy_time::{Duration, Timer};
 use heapless::Vec;
+#[cfg(feature = "esp32s3")]
 pub use neopixel::neopixel_command_listener;
+#[cfg(feature = "esp32s3")]
 pub use rotary::{
     left_rotary_rotation_watcher, right_rotary_rotation_watcher,
     rotary_switch_left_event, rotary_switch_right_event,
 };
 
+#[cfg(feature = "esp32s3")]
 use super::hardware;
+#[cfg(feature = "esp32s3")]
 pub use crate::hardware::{
     mono_display::display_task, speaker::speaker_task,
 };
@@ -23,7 +27,9 @@ use crate::hardware::{
 };
 
 pub mod neopixel;
+#[cfg(feature = "esp32s3")]
 pub mod rotary;
+pub mod volume;
 
 pub static BUZZER_SIGNAL: Signal<
     CriticalSectionRawMutex,
@@ -55,6 +61,7 @@ pub enum BuzzerSequence {
 //pub static DEBOUNCE_ACTIVE_LEFT: AtomicBool =
 // AtomicBool::new(false);
 
+#[cfg(feature = "esp32s3")]
 #[task]
 pub async fn left_button_event(
     button: &'static hardware::ButtonType,
@@ -86,6 +93,7 @@ pub async fn left_button_event(
     }
 }
 
+#[cfg(feature = "esp32s3")]
 #[task]
 pub async fn right_button_event(
     button: &'static hardware::ButtonType,
@@ -119,6 +127,7 @@ pub async fn right_button_event(
     }
 }
 
+#[cfg(feature = "esp32s3")]
 #[task]
 pub async fn play_sequence_listener(
     buzzer: &'static hardware::BuzzerType,
@@ -132,6 +141,7 @@ pub async fn play_sequence_listener(
     }
 }
 
+#[cfg(feature = "esp32s3")]
 async fn execute_sequence(
     buzzer: &'static hardware::BuzzerType,
     sequence: &BuzzerSequence,
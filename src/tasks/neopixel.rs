@@ -7,6 +7,7 @@ use embassy_sync::{
     blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel,
 };
 use embassy_time::{Duration, Timer};
+#[cfg(feature = "esp32s3")]
 use esp_hal_smartled::SmartLedsAdapter;
 use smart_leds::{RGB8, SmartLedsWrite};
 
@@ -84,6 +85,7 @@ impl NeoPixelHandle {
     }
 }
 
+#[cfg(feature = "esp32s3")]
 #[task]
 pub async fn neopixel_command_listener(
     mut neopixel: SmartLedsAdapter<'static, 25>,
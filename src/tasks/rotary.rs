@@ -1,14 +1,34 @@
 use embassy_executor::task;
-use embassy_time::{Duration, Timer};
+use embassy_futures::select::select;
 use esp_hal::gpio;
-use rotary_encoder_embedded::{
-    Direction, quadrature::QuadratureTableMode,
-};
+use rotary_encoder_embedded::Direction;
 
 use super::hardware;
-use crate::{input_listener, input_listener::INPUT_CHANNEL};
+use crate::{
+    hardware::{
+        buttons,
+        quadrature::{QuadratureDecoder, record_quadrature_errors},
+    },
+    input_listener,
+    input_listener::{
+        INPUT_CHANNEL, acceleration::RotaryEncoder, events::Control,
+    },
+};
 
-const ROTARY_SW_DEBOUNCE_MS: u64 = 200;
+/// Publishes the debounced edges of the switch and sends `pressed`
+/// on every press, see `buttons::listen_for_edges`. The lock is held
+/// for as long as the task runs.
+async fn listen_for_switch_edges(
+    rotary_switch: &'static hardware::RotarySwitchType,
+    control: Control,
+    pressed: input_listener::Input,
+) -> ! {
+    let mut rotary_switch = rotary_switch.lock().await;
+    let rotary_switch = rotary_switch.as_mut().unwrap();
+
+    buttons::listen_for_edges(rotary_switch, control, pressed, None)
+        .await
+}
 
 #[task]
 pub async fn rotary_switch_left_event(
@@ -18,43 +38,54 @@ pub async fn rotary_switch_left_event(
 ) {
     // TODO: basically make the buzzer beeping a separate task, that
     // waits for a message on a channel
-    loop {
-        rotary_switch
-            .lock()
-            .await
-            .as_mut()
-            .unwrap()
-            .wait_for_falling_edge()
-            .await;
-
-        INPUT_CHANNEL
-            .send(input_listener::Input::RotaryEncoderPressLeft)
-            .await;
-
-        Timer::after(Duration::from_millis(ROTARY_SW_DEBOUNCE_MS))
-            .await;
-    }
+    listen_for_switch_edges(
+        rotary_switch,
+        Control::RotarySwitchLeft,
+        input_listener::Input::RotaryEncoderPressLeft,
+    )
+    .await
 }
 
 #[task]
 pub async fn rotary_switch_right_event(
     rotary_switch: &'static hardware::RotarySwitchType,
 ) {
+    listen_for_switch_edges(
+        rotary_switch,
+        Control::RotarySwitchRight,
+        input_listener::Input::RotaryEncoderPressRight,
+    )
+    .await
+}
+
+/// Feeds the decoder every time either contact changes, and sends a
+/// rotation input for every detent.
+async fn watch_rotation(
+    mut a: gpio::Input<'static>,
+    mut b: gpio::Input<'static>,
+    encoder: RotaryEncoder,
+) -> ! {
+    let mut decoder = QuadratureDecoder::new(a.is_low(), b.is_low());
+    let mut reported_errors = 0;
+
     loop {
-        rotary_switch
-            .lock()
-            .await
-            .as_mut()
-            .unwrap()
-            .wait_for_falling_edge()
-            .await;
+        select(a.wait_for_any_edge(), b.wait_for_any_edge()).await;
 
-        INPUT_CHANNEL
-            .send(input_listener::Input::RotaryEncoderPressRight)
-            .await;
+        let direction = decoder.update(a.is_low(), b.is_low());
 
-        Timer::after(Duration::from_millis(ROTARY_SW_DEBOUNCE_MS))
-            .await;
+        if decoder.errors() != reported_errors {
+            record_quadrature_errors(
+                encoder,
+                decoder.errors().wrapping_sub(reported_errors),
+            );
+            reported_errors = decoder.errors();
+        }
+
+        if direction != Direction::None {
+            INPUT_CHANNEL
+                .send(encoder.rotation_input(direction))
+                .await;
+        }
     }
 }
 
@@ -63,40 +94,8 @@ pub async fn left_rotary_rotation_watcher(
     left_rotary_a: gpio::Input<'static>,
     left_rotary_b: gpio::Input<'static>,
 ) {
-    // start an encoder that we set the values of manually
-
-    let mut raw_encoder = QuadratureTableMode::new(3);
-    let _dir = raw_encoder.update(false, false);
-
-    loop {
-        // whenever this happens, update the state of the encoder
-        let dir = raw_encoder
-            .update(left_rotary_b.is_low(), left_rotary_a.is_low());
-
-        match dir {
-            Direction::Clockwise => {
-                INPUT_CHANNEL
-                    .send(
-                        input_listener::Input::RotaryEncoderRotateLeft(Direction::Clockwise),
-                    )
-                    .await;
-                //light_ring.forward().await;
-                //menu_scroll_down();
-            }
-            Direction::Anticlockwise => {
-                INPUT_CHANNEL
-                    .send(
-                        input_listener::Input::RotaryEncoderRotateLeft(Direction::Anticlockwise),
-                    )
-                    .await;
-                //light_ring.backward().await;
-                //menu_scroll_up();
-            }
-            Direction::None => {}
-        }
-
-        Timer::after(Duration::from_micros(1000)).await; // 1 kHz
-    }
+    watch_rotation(left_rotary_a, left_rotary_b, RotaryEncoder::Left)
+        .await
 }
 
 #[task]
@@ -104,35 +103,10 @@ pub async fn right_rotary_rotation_watcher(
     right_rotary_a: gpio::Input<'static>,
     right_rotary_b: gpio::Input<'static>,
 ) {
-    // start an encoder that we set the values of manually
-    // this used to be 4, but it would sometimes miss count.
-    // even still, any value of this usually overcounts
-    let mut raw_encoder = QuadratureTableMode::new(3);
-    let _dir = raw_encoder.update(false, false);
-
-    loop {
-        // whenever this happens, update the state of the encoder
-        let dir = raw_encoder
-            .update(right_rotary_b.is_low(), right_rotary_a.is_low());
-
-        match dir {
-            Direction::Clockwise => {
-                INPUT_CHANNEL
-                    .send(
-                        input_listener::Input::RotaryEncoderRotateRight(Direction::Clockwise),
-                    )
-                    .await;
-            }
-            Direction::Anticlockwise => {
-                INPUT_CHANNEL
-                    .send(
-                        input_listener::Input::RotaryEncoderRotateRight(Direction::Anticlockwise),
-                    )
-                    .await;
-            }
-            Direction::None => {}
-        }
-
-        Timer::after(Duration::from_micros(1000)).await; // 1 kHz
-    }
+    watch_rotation(
+        right_rotary_a,
+        right_rotary_b,
+        RotaryEncoder::Right,
+    )
+    .await
 }
@@ -0,0 +1,63 @@
+//! Global gestures for the master volume, which work in every app:
+//!
+//! ```text
+//! hold the right encoder       volume up a step
+//! hold the left encoder        volume down a step
+//! press both encoders at once  mute or unmute
+//! ```
+//!
+//! A click plays after every change, at the new volume. The settings
+//! are in `settings`, which also saves them. The presses these
+//! gestures are made of never reach the app, see
+//! `gestures::holds_back_press`.
+
+use embassy_executor::task;
+use embassy_sync::pubsub::WaitResult;
+
+use crate::{
+    hardware::speaker::system::{SystemSound, play_system_sound},
+    input_listener::{
+        events::Control,
+        gestures::{GESTURES, Gesture},
+    },
+    settings,
+};
+
+#[task]
+pub async fn volume_gestures() {
+    let Ok(mut gestures) = GESTURES.subscriber() else {
+        return;
+    };
+
+    loop {
+        let WaitResult::Message(event) =
+            gestures.next_message().await
+        else {
+            continue;
+        };
+
+        match event.gesture {
+            Gesture::LongPress(Control::RotarySwitchRight) => {
+                settings::set_volume(
+                    settings::volume().saturating_add(1),
+                );
+            }
+            Gesture::LongPress(Control::RotarySwitchLeft) => {
+                settings::set_volume(
+                    settings::volume().saturating_sub(1),
+                );
+            }
+            gesture
+                if gesture.is_chord(
+                    Control::RotarySwitchLeft,
+                    Control::RotarySwitchRight,
+                ) =>
+            {
+                settings::set_muted(!settings::muted());
+            }
+            _ => continue,
+        }
+
+        play_system_sound(SystemSound::Click).await;
+    }
+}
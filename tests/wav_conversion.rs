@@ -0,0 +1,138 @@
+//! Checks the conversion of `.wav` cries in `build/wav.rs` on made up
+//! files. The build script only runs it, as build scripts have no
+//! test harness.
+
+#[path = "../build/wav.rs"]
+mod wav;
+
+use wav::{PEAK_LIMIT, TARGET_RMS, wav_to_samples};
+
+#[test]
+fn tone_is_trimmed_and_normalized() {
+    // a 1 kHz tone with silence either side, at 48 kHz in stereo
+    let tone = (0..48_000)
+        .map(|index| {
+            let audible = (4_800..43_200).contains(&index);
+            let phase = index as f32 * 1_000.0 / 48_000.0;
+            let sample = if audible {
+                (phase * std::f32::consts::TAU).sin() * 0.5
+            } else {
+                0.0
+            };
+            (sample * 32_767.0) as i16
+        })
+        .flat_map(|sample| [sample, sample])
+        .flat_map(i16::to_le_bytes)
+        .collect::<Vec<_>>();
+    let samples =
+        wav_to_samples(&test_wav(1, 2, 48_000, 16, &tone)).unwrap();
+
+    // 0.8 seconds of tone, give or take the ringing of the filter
+    assert!(
+        samples.len().abs_diff(35_280) < 100,
+        "{}",
+        samples.len()
+    );
+    let crossings = samples
+        .windows(2)
+        .filter(|pair| pair[0] < 0 && pair[1] >= 0)
+        .count();
+    assert!(crossings.abs_diff(800) <= 2, "{crossings}");
+    let peak = samples
+        .iter()
+        .map(|sample| sample.unsigned_abs())
+        .max()
+        .unwrap();
+    let expected_peak =
+        TARGET_RMS * std::f32::consts::SQRT_2 * 32_767.0;
+    assert!((peak as f32 - expected_peak).abs() < 300.0, "{peak}");
+}
+
+#[test]
+fn lower_rates_are_resampled() {
+    // unsigned 8 bit at half the rate comes out twice as long
+    let square = (0..1_000)
+        .map(|index| if index / 50 % 2 == 0 { 64 } else { 192 })
+        .collect::<Vec<u8>>();
+    let samples =
+        wav_to_samples(&test_wav(1, 1, 22_050, 8, &square)).unwrap();
+    assert!(samples.len().abs_diff(2_000) < 4, "{}", samples.len());
+}
+
+#[test]
+fn spiky_sounds_are_held_back_by_the_peak_limit() {
+    let mut click = vec![0.0f32; 1_000];
+    click[0] = 1.0;
+    click[999] = -1.0;
+    let click = click
+        .iter()
+        .flat_map(|sample| sample.to_le_bytes())
+        .collect::<Vec<_>>();
+    let samples =
+        wav_to_samples(&test_wav(3, 1, 44_100, 32, &click)).unwrap();
+    assert_eq!(
+        samples[0],
+        (PEAK_LIMIT * i16::MAX as f32).round() as i16
+    );
+}
+
+#[test]
+fn bad_files_are_explained() {
+    for (wav, error) in [
+        (b"RIFF\0\0\0\0WAVX".to_vec(), "not a RIFF WAVE file"),
+        (test_wav(1, 1, 44_100, 12, &[0; 4]), "12 bit samples"),
+        (test_wav(2, 1, 44_100, 4, &[0; 4]), "format 0x0002"),
+        (test_wav(1, 2, 44_100, 16, &[0; 6]), "middle of a frame"),
+        (test_wav(1, 0, 44_100, 16, &[0; 4]), "no channels"),
+        (test_wav(1, 1, 44_100, 16, &[0; 4]), "nothing but silence"),
+        (
+            test_wav(1, 1, 44_100, 16, &[])[..40].to_vec(),
+            "no data chunk",
+        ),
+    ] {
+        let result = wav_to_samples(&wav);
+        assert!(
+            result
+                .as_ref()
+                .is_err_and(|message| message.contains(error)),
+            "expected an error containing {error:?}, got {:?}",
+            result.map(|samples| samples.len())
+        );
+    }
+
+    let mut truncated = test_wav(1, 1, 44_100, 16, &[1; 8]);
+    truncated.truncate(48);
+    assert!(
+        wav_to_samples(&truncated)
+            .unwrap_err()
+            .contains("past the end")
+    );
+}
+
+/// A `.wav` file with a plain `fmt ` chunk.
+fn test_wav(
+    tag: u16,
+    channels: u16,
+    sample_rate: u32,
+    bits: u16,
+    data: &[u8],
+) -> Vec<u8> {
+    let block_align = channels * bits.div_ceil(8);
+    let mut wav = Vec::new();
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&tag.to_le_bytes());
+    wav.extend_from_slice(&channels.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(
+        &(sample_rate * block_align as u32).to_le_bytes(),
+    );
+    wav.extend_from_slice(&block_align.to_le_bytes());
+    wav.extend_from_slice(&bits.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    wav.extend_from_slice(data);
+    wav
+}